@@ -0,0 +1,181 @@
+use std::io;
+use crate::proto::ProtoData;
+use crate::typedefs::{PbReaderTrait, PbSliceReader};
+use crate::view::{ScreenLine, TextStyle, MARGIN_LEFT, MARGIN_RIGHT};
+use crate::wire::*;
+
+// Binary pane: the bytes of the selected field as they are written to the file,
+// the offset, the tag (field id and wire type) and the length are explained, for example:
+// 002F8 : 0A 1:LEN 8C 35 (6796) 0A 03 61 62 63 ...
+// A value of a packed record is shown after the tag and the length of the record, with its position in the record:
+// 00003 : 12 2:LEN 03 (3) +1 80 01
+// The bytes are encoded from the current data, so the pane shows the result of edits too.
+
+pub const WIRE_TYPE_NAMES: [&str; 6] = ["VARINT", "I64", "LEN", "SGROUP", "EGROUP", "I32"];
+
+pub struct FieldBytes {
+    pub offset: Option<usize>, // position of the tag in the file, of the value for a packed value, None for new data
+    pub tag: Vec<u8>,
+    pub tag_text: String, // field id and wire type, e.g. "1:LEN"
+    pub length: Vec<u8>,  // the length prefix of LEN data
+    pub value: Vec<u8>,
+    pub record: Option<(usize, usize)>, // for a packed value: its position in the record data and the data size
+}
+
+impl FieldBytes {
+    // the field at index in the fields of the message
+    pub fn new(msg: &MessageData, index: usize, proto: &ProtoData) -> io::Result<FieldBytes> {
+        let field = &msg.fields[index];
+        if field.def.damaged() { // not written, the bytes are shown as they are in the file
+            let FieldValue::SCALAR(ScalarValue::BYTES(bytes)) = &field.value else { unreachable!() };
+            return Ok(FieldBytes { offset: Some(field.pos), tag: vec![], tag_text: "damaged".to_string(), length: vec![], value: bytes.clone(), record: None });
+        }
+        let mut buf = vec![];
+        let record = msg.packed_record(index);
+        match &record {
+            Some(range) => FieldData::write_packed(&mut buf, &msg.fields[range.clone()])?,
+            None => field.write(&mut buf, proto)?,
+        }
+        let mut limit = buf.len() as u64;
+        let mut reader = PbSliceReader::new(&buf, 0);
+        let tag = reader.read_tag(&mut limit)?;
+        let tag_len = ScalarValue::varint_size(tag.first_number as i128);
+        let value_start = reader.pos();
+        let wire_type = WIRE_TYPE_NAMES.get(tag.wire_type() as usize).copied().unwrap_or("?");
+        if let (Some(range), FieldValue::SCALAR(scalar)) = (record, &field.value) {
+            let mut skipped = vec![]; // the values before it in the record
+            for value in &msg.fields[range.start..index] {
+                if let FieldValue::SCALAR(scalar) = &value.value { value.write_value(&mut skipped, scalar)?; }
+            }
+            let mut value = vec![];
+            field.write_value(&mut value, scalar)?;
+            return Ok(FieldBytes {
+                offset: if field.pos == usize::MAX { None } else { Some(field.pos) }, // the read position of the value
+                tag: buf[..tag_len].to_vec(),
+                tag_text: format!("{}:{}", tag.field_id(), wire_type),
+                length: buf[tag_len..value_start].to_vec(),
+                value,
+                record: Some((skipped.len(), buf.len() - value_start)),
+            });
+        }
+        Ok(FieldBytes {
+            // the read position is after the tag and the length
+            offset: if field.pos == usize::MAX { None } else { field.pos.checked_sub(value_start) },
+            tag: buf[..tag_len].to_vec(),
+            tag_text: format!("{}:{}", tag.field_id(), wire_type),
+            length: buf[tag_len..value_start].to_vec(),
+            value: buf[value_start..].to_vec(),
+            record: None,
+        })
+    }
+
+    // lines of the pane, the value is cut if it does not fit
+    pub fn to_lines(&self, width: u16, height: usize) -> Vec<ScreenLine> {
+        let max_len = (width - MARGIN_RIGHT) as usize;
+        let mut words = vec![];
+        words.extend(self.tag.iter().map(|b| (format!("{:02X}", b), TextStyle::Binary)));
+        words.push((self.tag_text.clone(), TextStyle::Typename));
+        words.extend(self.length.iter().map(|b| (format!("{:02X}", b), TextStyle::Binary)));
+        match self.record {
+            Some((skipped, size)) => {
+                words.push((format!("({})", size), TextStyle::DataSize));
+                words.push((format!("+{}", skipped), TextStyle::DataSize));
+            }
+            None if !self.length.is_empty() => words.push((format!("({})", self.value.len()), TextStyle::DataSize)),
+            None => {}
+        }
+        words.extend(self.value.iter().map(|b| (format!("{:02X}", b), TextStyle::Value)));
+
+        let mut lines = vec![];
+        let mut line = Self::new_line(Some(self.offset.map_or("new".to_string(), |offset| format!("{:05X}", offset))));
+        for (index, (word, style)) in words.iter().enumerate() {
+            if line.0.len() + 1 + word.len() > max_len {
+                lines.push(line);
+                line = Self::new_line(None);
+                let rest_len: usize = words[index..].iter().map(|(word, _)| 1 + word.len()).sum();
+                if lines.len() + 1 == height && line.0.len() + rest_len > max_len { // the last line
+                    line.add_string(format!(" ... {}", words.len() - index), TextStyle::DataSize);
+                    break;
+                }
+            }
+            line.add_string(format!(" {}", word), *style);
+        }
+        lines.push(line);
+        for line in &mut lines { line.fix_length(width); }
+        lines.truncate(height);
+        lines
+    }
+
+    // the offset is shown in the first line only
+    fn new_line(offset: Option<String>) -> ScreenLine {
+        let mut line = ScreenLine(vec![]);
+        line.add_string(" ".repeat(MARGIN_LEFT as usize), TextStyle::Binary);
+        line.add_string(offset.map_or(" ".repeat(7), |offset| format!("{:<5} :", offset)), TextStyle::FieldIndex);
+        line
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod pane {
+    use super::*;
+
+    fn to_strings(bytes: &FieldBytes, width: u16, height: usize) -> Vec<String> {
+        bytes.to_lines(width, height).into_iter().map(|line| line.0.into_iter().map(|(c, _)| c).collect::<String>().trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn explain_field() {
+        let proto = ProtoData::new("message M { int32 i = 1; string s = 2; M m = 3; }").unwrap().finalize().unwrap();
+        let def = proto.get_message_definition("M").unwrap();
+        let data = [0x08, 0x96, 0x01, 0x12, 0x03, 0x61, 0x62, 0x63, 0x1a, 0x02, 0x08, 0x01];
+        let mut limit = data.len() as u64;
+        let msg = MessageData::new(&mut PbSliceReader::new(&data, 0), &proto, def, &mut limit).unwrap();
+
+        let bytes = FieldBytes::new(&msg, 0, &proto).unwrap();
+        assert_eq!(to_strings(&bytes, 40, 4), [" 00000 : 08 1:VARINT 96 01"]);
+        let bytes = FieldBytes::new(&msg, 1, &proto).unwrap();
+        assert_eq!(to_strings(&bytes, 40, 4), [" 00003 : 12 2:LEN 03 (3) 61 62 63"]);
+        let bytes = FieldBytes::new(&msg, 2, &proto).unwrap();
+        assert_eq!(bytes.offset, Some(8));
+        assert_eq!(bytes.value, [0x08, 0x01]);
+
+        let mut msg = msg;
+        msg.fields[0] = FieldData::new(msg.fields[0].def.clone(), FieldValue::SCALAR(ScalarValue::I32(1)));
+        assert_eq!(to_strings(&FieldBytes::new(&msg, 0, &proto).unwrap(), 40, 4), [" new   : 08 1:VARINT 01"]);
+    }
+
+    #[test]
+    fn packed_values() {
+        let proto = ProtoData::new("message M { repeated int32 r = 2; }").unwrap().finalize().unwrap();
+        let def = proto.get_message_definition("M").unwrap();
+        let data = [0x12, 0x03, 0x01, 0x80, 0x01];
+        let mut limit = data.len() as u64;
+        let msg = MessageData::new(&mut PbSliceReader::new(&data, 0), &proto, def, &mut limit).unwrap();
+
+        let bytes = FieldBytes::new(&msg, 0, &proto).unwrap();
+        assert_eq!(to_strings(&bytes, 40, 4), [" 00002 : 12 2:LEN 03 (3) +0 01"]);
+        let bytes = FieldBytes::new(&msg, 1, &proto).unwrap();
+        assert_eq!(to_strings(&bytes, 40, 4), [" 00003 : 12 2:LEN 03 (3) +1 80 01"]);
+    }
+
+    #[test]
+    fn wrap_lines() {
+        let bytes = FieldBytes { offset: Some(0x2f8), tag: vec![0x0a], tag_text: "1:LEN".to_string(), length: vec![0x14], value: (0..20).collect(), record: None };
+        assert_eq!(to_strings(&bytes, 30, 4), [
+            " 002F8 : 0A 1:LEN 14 (20) 00",
+            "         01 02 03 04 05 06 07",
+            "         08 09 0A 0B 0C 0D 0E",
+            "         0F 10 11 12 13"]);
+        assert_eq!(to_strings(&bytes, 30, 2), [
+            " 002F8 : 0A 1:LEN 14 (20) 00",
+            "         ... 19"]);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use crate::binary::{FieldBytes, WIRE_TYPE_NAMES};
+use crate::path::format_path;
+use crate::proto::ProtoData;
+use crate::wire::*;
+
+// Validation of the data against the schema: the problems which readers pass over silently,
+// each with the path of the field and the offset of its tag in the file.
+// Unknown fields, values of another wire type and strings with invalid UTF-8 are read as unknown fields,
+// so they are found here and still saved unchanged.
+
+pub struct Problem {
+    pub path: FieldPath,
+    pub offset: Option<usize>, // of the tag; None for new data, or the message of a missing field
+    pub text: String,
+}
+
+impl Problem {
+    // a line of the report, e.g. "m3.f5 (offset 12): unknown field 5 (VARINT)"
+    // the root message is named by its type
+    pub fn describe(&self, root: &MessageData) -> String {
+        let place = if self.path.0.is_empty() { root.def.name.clone() } else { format_path(root, &self.path) };
+        match self.offset {
+            Some(offset) => format!("{} (offset {}): {}", place, offset, self.text),
+            None => format!("{}: {}", place, self.text),
+        }
+    }
+}
+
+pub fn check(data: &MessageData, proto: &ProtoData) -> Vec<Problem> {
+    let mut res = vec![];
+    check_message(data, proto, &FieldPath::new(), None, &mut res);
+    res
+}
+
+fn wire_type_name(wire_type: u8) -> &'static str {
+    WIRE_TYPE_NAMES.get(wire_type as usize).copied().unwrap_or("?")
+}
+
+// of the field at pos in the fields of the message
+fn tag_offset(msg: &MessageData, pos: usize, proto: &ProtoData) -> Option<usize> {
+    let field = &msg.fields[pos];
+    // damaged bytes, or a value of a packed record: the read position itself
+    if field.def.damaged() || (matches!(field.encoding.packing, Packing::First | Packing::Next) && field.pos != usize::MAX) {
+        return Some(field.pos);
+    }
+    FieldBytes::new(msg, pos, proto).ok().and_then(|bytes| bytes.offset)
+}
+
+// parent is the message with the field of the message and its position, None for the root
+fn check_message(msg: &MessageData, proto: &ProtoData, prefix: &FieldPath, parent: Option<(&MessageData, usize)>, res: &mut Vec<Problem>) {
+    let is_set = |id: i32| msg.fields.iter().any(|f| f.id() == id && !matches!(f.value, FieldValue::SCALAR(ScalarValue::DELETED)));
+    for def in msg.def.fields.iter().filter(|f| f.required() && !is_set(f.id())) {
+        let offset = parent.and_then(|(parent, pos)| tag_offset(parent, pos, proto));
+        res.push(Problem { path: prefix.add((def.id(), 0).into()), offset, text: format!("required field {} is missing", def.name()) });
+    }
+
+    let mut indexes: HashMap<i32, usize> = HashMap::new();
+    let mut oneofs: HashMap<&str, i32> = HashMap::new(); // the member set last
+    for (pos, field) in msg.fields.iter().enumerate() {
+        let id = field.id();
+        let index = indexes.entry(id).or_insert(0);
+        // damaged bytes are reported by their message
+        let path = if field.def.damaged() { prefix.clone() } else { prefix.add((id, *index).into()) };
+        *index += 1;
+        let mut texts = vec![];
+        match &field.value {
+            FieldValue::SCALAR(ScalarValue::DELETED) => continue,
+            _ if field.def.damaged() => {
+                let comment = field.def.comment();
+                texts.push(format!("damaged data, {}", comment.split_once(": ").map_or(comment.as_str(), |(_, error)| error).trim_end()));
+            }
+            // an empty packed record
+            FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, bytes)) if bytes.is_empty() && tag.wire_type() == WT_LEN && msg.def.get_field(id).is_some_and(|def| def.packable()) => {}
+            FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, _)) => texts.push(match msg.def.get_field(id) {
+                Some(def) if def.typename() == "string" && tag.wire_type() == WT_LEN => format!("invalid UTF-8 in string field {}", def.name()),
+                Some(def) => format!("{} data of {} field {}, {} expected", wire_type_name(tag.wire_type()), def.typename(), def.name(), wire_type_name(def.wire_type())),
+                None => format!("unknown field {} ({})", id, wire_type_name(tag.wire_type())),
+            }),
+            FieldValue::SCALAR(ScalarValue::ENUM(value)) if field.def.get_enum_name_by_index(*value).is_none() => {
+                texts.push(format!("value {} is not declared in enum {}", value, field.def.typename()));
+            }
+            _ => {}
+        }
+        if !field.def.repeated() && *indexes.get(&id).unwrap() > 1 {
+            let result = if field.def.is_message() { "readers merge the messages" } else { "readers keep the last value" };
+            texts.push(format!("non-repeated field {} is set again, {}", field.def.name(), result));
+        }
+        if let Some(oneof) = field.def.oneof_name() {
+            if let Some(other) = oneofs.get(oneof.as_str()).filter(|other| **other != id).and_then(|other| msg.def.get_field(*other)) {
+                texts.push(format!("{} and {} of oneof {} are both set, readers keep the last one", other.name(), field.def.name(), oneof));
+            }
+            oneofs.insert(oneof, id);
+        }
+
+        if !texts.is_empty() {
+            let offset = tag_offset(msg, pos, proto);
+            res.extend(texts.into_iter().map(|text| Problem { path: path.clone(), offset, text }));
+        }
+        if let FieldValue::MESSAGE(sub) = &field.value {
+            check_message(sub, proto, &path, Some((msg, pos)), res);
+        }
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod problems {
+    use super::*;
+    use crate::typedefs::PbSliceReader;
+
+    fn check_bytes(proto: &str, bytes: &[u8]) -> Vec<String> {
+        let proto = ProtoData::new(proto).unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let mut limit = bytes.len() as u64;
+        let data = MessageData::new(&mut PbSliceReader::new(bytes, 0), &proto, def, &mut limit).unwrap();
+        check(&data, &proto).iter().map(|problem| problem.describe(&data)).collect()
+    }
+
+    #[test]
+    fn found() {
+        let proto = r#"
+syntax = "proto2";
+message M1 {
+  optional int32 f1 = 1;
+  optional string s2 = 2;
+  optional E e3 = 3;
+  oneof choice { int32 a4 = 4; int32 b5 = 5; }
+  optional M2 m6 = 6;
+}
+message M2 { required int32 r7 = 7; }
+enum E { ZERO = 0; ONE = 1; }
+"#;
+        let bytes = [
+            0x08, 0x01, 0x08, 0x02,  // f1: 1 f1: 2
+            0x12, 0x01, 0xFF,        // s2 with invalid UTF-8
+            0x18, 0x07,              // e3: 7
+            0x20, 0x01, 0x28, 0x02,  // a4: 1 b5: 2
+            0x32, 0x00,              // m6 {}
+            0x40, 0x01,              // unknown field 8
+            0x0D, 0x01, 0x00, 0x00, 0x00, // f1 as I32
+        ];
+        assert_eq!(check_bytes(proto, &bytes), [
+            "f1 (offset 2): non-repeated field f1 is set again, readers keep the last value",
+            "s2 (offset 4): invalid UTF-8 in string field s2",
+            "e3 (offset 7): value 7 is not declared in enum E",
+            "b5 (offset 11): a4 and b5 of oneof choice are both set, readers keep the last one",
+            "m6.r7 (offset 13): required field r7 is missing",
+            "8 (offset 15): unknown field 8 (VARINT)",
+            "f1 (offset 17): I32 data of int32 field f1, VARINT expected",
+        ]);
+        assert!(check_bytes(proto, &[0x08, 0x01, 0x32, 0x02, 0x38, 0x01]).is_empty());
+    }
+
+    #[test]
+    fn packed_values() {
+        let proto = r#"
+syntax = "proto2";
+message M { repeated E e = 2 [packed = true]; }
+enum E { ZERO = 0; ONE = 1; }
+"#;
+        assert_eq!(check_bytes(proto, &[0x12, 0x03, 0x01, 0x80, 0x01]), [
+            "e[1] (offset 3): value 128 is not declared in enum E",
+        ]);
+        assert_eq!(check_bytes(proto, &[0x12, 0x03, 0x80, 0x01, 0x01]), [
+            "e[0] (offset 2): value 128 is not declared in enum E",
+        ]);
+    }
+}
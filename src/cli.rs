@@ -0,0 +1,164 @@
+use crate::path::{format_path, PathQuery, StepIndex};
+use crate::proto::FieldProtoPtr;
+use crate::text_format::{message_to_text, scalar_to_text, text_to_value};
+use crate::wire::*;
+
+// Field access for the command line, fields are addressed by paths like m3.m6[1].f8 (see path.rs)
+// and the check that a file is saved as it was read
+
+fn value_to_text(def: &FieldProtoPtr, value: &FieldValue) -> String {
+    match value {
+        FieldValue::MESSAGE(msg) => message_to_text(msg),
+        FieldValue::SCALAR(scalar) => scalar_to_text(def, scalar) + "\n",
+    }
+}
+
+// the value in the text format; if the path matches several fields, each value is prefixed by its path
+pub fn get_value(data: &MessageData, path: &str) -> Result<String, String> {
+    let query = PathQuery::parse(&data.def, path)?;
+    let value_def = query.last().value_def();
+    let paths = query.resolve(data);
+    let values: Vec<(String, String)> = paths.iter().map(|p| {
+        let text = match data.get_field(&p.0) {
+            Some(field) => value_to_text(&field.def, &field.value),
+            None => value_to_text(&value_def, &value_def.default()), // the value of a map entry is not set
+        };
+        (format_path(data, p), text)
+    }).collect();
+
+    if query.is_exact() {
+        return match values.into_iter().next() {
+            Some((_, text)) => Ok(text),
+            None if query.last().index == StepIndex::At(0) && !value_def.repeated() => Ok(value_to_text(&value_def, &value_def.default())),
+            None => Err(format!("no data at {}", path)),
+        };
+    }
+    let mut res = String::new();
+    for (path, text) in values {
+        if value_def.is_message() {
+            res += &format!("{} {{\n", path);
+            text.lines().for_each(|line| res += &format!("  {}\n", line));
+            res += "}\n";
+        } else {
+            res += &format!("{}: {}", path, text);
+        }
+    }
+    Ok(res)
+}
+
+// the value is in the text format, or a plain text for string and bytes fields;
+// missing fields are added if the path is exact, otherwise all matched fields are changed
+pub fn set_value(data: &mut MessageData, path: &str, value: &str) -> Result<(), String> {
+    let query = PathQuery::parse(&data.def, path)?;
+    let value_def = query.last().value_def();
+    let quoted = value.starts_with(['"', '\'']);
+    let parse = || match value_def.default() {
+        FieldValue::SCALAR(ScalarValue::STR(_)) if !quoted => Ok(FieldValue::SCALAR(ScalarValue::STR(value.to_string()))),
+        FieldValue::SCALAR(ScalarValue::BYTES(_)) if !quoted => Ok(FieldValue::SCALAR(ScalarValue::BYTES(value.as_bytes().to_vec()))),
+        _ => text_to_value(value, &value_def),
+    };
+    parse()?; // check the value before any change
+
+    let paths = if query.is_exact() { vec![query.create(data)?] } else { query.resolve(data) };
+    if paths.is_empty() {
+        return Err(format!("no data at {}", path));
+    }
+    for p in paths {
+        if data.get_field(&p.0).is_none() {
+            data.add_field(&p.0);
+        }
+        data.get_field_mut(&p.0).unwrap().value = parse()?;
+    }
+    Ok(())
+}
+
+// the data as it would be saved is the same as the file, otherwise the first different offset
+pub fn compare_saved(input: &[u8], output: &[u8]) -> Result<(), String> {
+    let Some(offset) = input.iter().zip(output).position(|(a, b)| a != b).
+        or_else(|| (input.len() != output.len()).then(|| input.len().min(output.len()))) else { return Ok(()); };
+    let bytes = |data: &[u8]| match &data[offset.min(data.len())..data.len().min(offset + 8)] {
+        [] => "the end".to_string(),
+        part => part.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "),
+    };
+    Err(format!("saved data differs at offset {}: {} in the file, {} written ({} and {} bytes)", offset, bytes(input), bytes(output), input.len(), output.len()))
+}
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod commands {
+    use super::*;
+    use crate::proto::ProtoData;
+    use crate::text_format::text_to_message;
+
+    const PROTO: &str = r#"
+message Root {
+  int32 id = 1;
+  string name = 2;
+  repeated Item items = 3;
+  Item main = 4;
+  map<string, int32> counts = 5;
+}
+message Item {
+  repeated int32 ids = 1;
+  bytes raw = 2;
+}
+"#;
+
+    fn make_data(text: &str) -> MessageData {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        text_to_message(text, proto.get_message_definition("Root").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn get() {
+        let data = make_data("id: 5 items { ids: 1 ids: 2 } items { raw: 'x' } counts { key: 'a' value: 3 }");
+        assert_eq!(get_value(&data, "id").unwrap(), "5\n");
+        assert_eq!(get_value(&data, "1").unwrap(), "5\n");
+        assert_eq!(get_value(&data, "name").unwrap(), "\"\"\n"); // default value
+        assert_eq!(get_value(&data, "items[0].ids").unwrap(), "items[0].ids[0]: 1\nitems[0].ids[1]: 2\n");
+        assert_eq!(get_value(&data, "items[0].ids[1]").unwrap(), "2\n");
+        assert_eq!(get_value(&data, "items[1]").unwrap(), "raw: \"x\"\n");
+        assert_eq!(get_value(&data, "items[*].raw").unwrap(), "items[1].raw: \"x\"\n");
+        assert_eq!(get_value(&data, "items").unwrap(), "items[0] {\n  ids: 1\n  ids: 2\n}\nitems[1] {\n  raw: \"x\"\n}\n");
+        assert_eq!(get_value(&data, "main.raw").unwrap(), "\"\"\n");
+        assert_eq!(get_value(&data, "counts[a]").unwrap(), "3\n");
+        assert_eq!(get_value(&data, "counts").unwrap(), "counts[\"a\"]: 3\n");
+        assert_eq!(get_value(&data, "items[2]").err().unwrap(), "no data at items[2]");
+        assert_eq!(get_value(&data, "counts[\"b\"]").err().unwrap(), "no data at counts[\"b\"]");
+        assert_eq!(get_value(&data, "items[0].other").err().unwrap(), "unknown field \"other\" of Item");
+    }
+
+    #[test]
+    fn set() {
+        let mut data = make_data("id: 5 items { ids: 1 }");
+        set_value(&mut data, "id", "7").unwrap();
+        set_value(&mut data, "name", "plain text").unwrap();
+        set_value(&mut data, "items[0].ids[0]", "10").unwrap();
+        set_value(&mut data, "items[0].ids[1]", "11").unwrap(); // append
+        set_value(&mut data, "main.raw", "\"\\001\"").unwrap(); // the parent message is created
+        set_value(&mut data, "items[1]", "ids: 3").unwrap();
+        set_value(&mut data, "counts[x]", "4").unwrap();
+        assert_eq!(message_to_text(&data),
+                   "id: 7\nitems {\n  ids: 10\n  ids: 11\n}\nname: \"plain text\"\nmain {\n  raw: \"\\001\"\n}\nitems {\n  ids: 3\n}\ncounts {\n  key: \"x\"\n  value: 4\n}\n");
+
+        set_value(&mut data, "items[*].ids", "0").unwrap();
+        assert_eq!(get_value(&data, "items.ids").unwrap(), "items[0].ids[0]: 0\nitems[0].ids[1]: 0\nitems[1].ids[0]: 0\n");
+
+        assert_eq!(set_value(&mut data, "id", "x").err().unwrap(), "line 1, column 1: expected int32 for field id");
+        assert_eq!(set_value(&mut data, "items[5].ids[0]", "1").err().unwrap(), "index 5 of \"items\" is out of range, the field has 2 values");
+        assert_eq!(set_value(&mut data, "main.ids", "1").err().unwrap(), "no data at main.ids");
+    }
+
+    #[test]
+    fn verify() {
+        assert!(compare_saved(&[1, 2, 3], &[1, 2, 3]).is_ok());
+        assert_eq!(compare_saved(&[1, 2, 3], &[1, 4, 3]).err().unwrap(), "saved data differs at offset 1: 02 03 in the file, 04 03 written (3 and 3 bytes)");
+        assert_eq!(compare_saved(&[1, 2, 3], &[1, 2]).err().unwrap(), "saved data differs at offset 2: 03 in the file, the end written (3 and 2 bytes)");
+    }
+}
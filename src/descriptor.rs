@@ -0,0 +1,917 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use crate::json;
+use crate::proto::*;
+use crate::proto_parser::Features;
+use crate::text_format;
+use crate::typedefs::{CommonFieldProto, PbSliceReader};
+use crate::well_known;
+use crate::wire::*;
+
+// Compiled schemas: a google.protobuf.FileDescriptorSet as written by `protoc --descriptor_set_out`.
+// The set is decoded with the bundled descriptor.proto, each FileDescriptorProto becomes a ProtoData as a parsed proto file.
+// Map fields are declared by nested XxxEntry messages, they become "key,value" fields as in proto files.
+// Comments are read from source_code_info (`protoc --include_source_info`).
+// The parsed proto files are also written as a set, so proto files are compiled where protoc is not installed.
+
+const DESCRIPTOR_FILE: &str = "google/protobuf/descriptor.proto";
+pub const FILE_SET_NAME: &str = "google.protobuf.FileDescriptorSet";
+
+// FieldDescriptorProto.Type by number
+const TYPE_NAMES: [&str; 19] = ["", "double", "float", "int64", "uint64", "int32", "fixed64", "fixed32", "bool", "string",
+    "group", "message", "bytes", "uint32", "enum", "sfixed32", "sfixed64", "sint32", "sint64"];
+const TYPE_GROUP: i32 = 10;
+const TYPE_MESSAGE: i32 = 11;
+const TYPE_ENUM: i32 = 14;
+const LABEL_OPTIONAL: i32 = 1;
+const LABEL_REQUIRED: i32 = 2;
+const LABEL_REPEATED: i32 = 3;
+
+// the finalized definitions of descriptor.proto
+pub fn descriptor_proto() -> ProtoData {
+    ProtoData::new(well_known::bundled_file(DESCRIPTOR_FILE).unwrap()).unwrap().finalize().unwrap()
+}
+
+// a schema file which is not a proto source
+pub fn is_descriptor_set(path: &Path) -> bool {
+    path.extension().is_none_or(|ext| ext != "proto")
+}
+
+// a ProtoData for each file of the set, in the order of the set: the imported files first
+pub fn read_descriptor_set(data: &[u8]) -> io::Result<Vec<ProtoData>> {
+    let proto = descriptor_proto();
+    let def = proto.get_message_definition(FILE_SET_NAME).unwrap();
+    let mut limit = data.len() as u64;
+    let set = MessageData::new(&mut PbSliceReader::new(data, 0), &proto, def, &mut limit)?;
+    let files: Vec<&MessageData> = messages(&set, 1).collect();
+    if files.is_empty() || set.fields.iter().any(|f| f.def.id() != 1) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FileDescriptorSet"));
+    }
+    Ok(files.into_iter().map(read_file).collect())
+}
+
+/**************************************************************************************************/
+// values of the descriptor messages
+
+fn values(msg: &MessageData, id: i32) -> impl Iterator<Item=&FieldValue> {
+    msg.fields.iter().filter(move |f| f.def.id() == id).map(|f| &f.value)
+}
+
+fn messages(msg: &MessageData, id: i32) -> impl Iterator<Item=&MessageData> {
+    values(msg, id).filter_map(|value| match value {
+        FieldValue::MESSAGE(sub) => Some(&**sub),
+        FieldValue::SCALAR(_) => None,
+    })
+}
+
+fn message(msg: &MessageData, id: i32) -> Option<&MessageData> {
+    messages(msg, id).last()
+}
+
+fn string(msg: &MessageData, id: i32) -> Option<String> {
+    match values(msg, id).last() {
+        Some(FieldValue::SCALAR(ScalarValue::STR(text))) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+fn strings(msg: &MessageData, id: i32) -> Vec<String> {
+    values(msg, id).filter_map(|value| match value {
+        FieldValue::SCALAR(ScalarValue::STR(text)) => Some(text.clone()),
+        _ => None,
+    }).collect()
+}
+
+// int32 and enum values, packed or not
+fn numbers(msg: &MessageData, id: i32) -> Vec<i32> {
+    values(msg, id).filter_map(|value| match value {
+        FieldValue::SCALAR(ScalarValue::I32(v) | ScalarValue::ENUM(v)) => Some(*v),
+        _ => None,
+    }).collect()
+}
+
+fn number(msg: &MessageData, id: i32) -> Option<i32> {
+    numbers(msg, id).last().copied()
+}
+
+fn flag(msg: &MessageData, id: i32) -> bool {
+    matches!(values(msg, id).last(), Some(FieldValue::SCALAR(ScalarValue::BOOL(true))))
+}
+
+// start and end of ReservedRange or ExtensionRange, the end is exclusive unless it is an enum range
+fn ranges(msg: &MessageData, id: i32, exclusive: bool) -> Vec<(i32, i32)> {
+    messages(msg, id).map(|range| {
+        let end = number(range, 2).unwrap_or(0);
+        (number(range, 1).unwrap_or(0), if exclusive { end - 1 } else { end })
+    }).collect()
+}
+
+// the options set in the message, features are listed one by one as they are written in proto files
+fn options(msg: Option<&MessageData>) -> Vec<ProtoOption> {
+    let mut res = vec![];
+    for field in msg.iter().flat_map(|msg| msg.fields.iter()) {
+        let name = field.def.name();
+        match &field.value {
+            // custom options are unknown fields of the options, their definitions are not in descriptor.proto
+            FieldValue::SCALAR(ScalarValue::UNKNOWN(..)) => {}
+            FieldValue::SCALAR(value) => res.push(ProtoOption { name, value: text_format::scalar_to_text(&field.def, value) }),
+            FieldValue::MESSAGE(features) if name == "features" => {
+                res.extend(options(Some(features)).into_iter().map(|option| ProtoOption { name: format!("features.{}", option.name), ..option }));
+            }
+            FieldValue::MESSAGE(_) if name == "uninterpreted_option" => {}
+            FieldValue::MESSAGE(value) => {
+                let text = text_format::message_to_text(value);
+                let text: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+                res.push(ProtoOption { name, value: format!("{{ {} }}", text.join(" ")) });
+            }
+        }
+    }
+    res
+}
+
+// the default value as written in a proto file: descriptors keep strings unquoted, bytes escaped
+fn default_text(type_number: i32, value: String) -> String {
+    match TYPE_NAMES.get(type_number as usize) {
+        Some(&"string") => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")),
+        Some(&"bytes") => format!("\"{}\"", value),
+        _ => value,
+    }
+}
+
+/**************************************************************************************************/
+
+struct FileReader {
+    syntax: String,
+    comments: HashMap<Vec<i32>, String>, // by the path of the declaration in the FileDescriptorProto
+    res: ProtoData,
+}
+
+fn read_file(file: &MessageData) -> ProtoData {
+    let syntax = string(file, 12).filter(|syntax| !syntax.is_empty()).unwrap_or("proto2".to_string());
+    let edition = match number(file, 14) {
+        Some(1000) => "2023".to_string(),
+        Some(1001) => "2024".to_string(),
+        _ => String::new(),
+    };
+    let package = string(file, 2).unwrap_or_default();
+    let (public, weak) = (numbers(file, 10), numbers(file, 11));
+    let imports = strings(file, 3).into_iter().enumerate().
+        map(|(index, name)| Import { name, public: public.contains(&(index as i32)), weak: weak.contains(&(index as i32)) }).
+        collect();
+
+    // the leading comment, or else the trailing one
+    let mut comments = HashMap::new();
+    for location in message(file, 9).iter().flat_map(|info| messages(info, 1)) {
+        let comment = [3, 4].iter().filter_map(|id| string(location, *id)).find(|text| !text.is_empty());
+        if let Some(comment) = comment {
+            comments.insert(numbers(location, 1), comment.trim_end_matches('\n').to_string());
+        }
+    }
+
+    let file_options = options(message(file, 8));
+    let mut features = Features::of_syntax(&syntax);
+    file_options.iter().for_each(|option| features.apply(option));
+    let mut reader = FileReader { syntax: syntax.clone(), comments, res: ProtoData::from_messages(vec![]) };
+    for (index, msg) in messages(file, 4).enumerate() {
+        reader.read_message(msg, &package, vec![4, index as i32], features);
+    }
+    for (index, enm) in messages(file, 5).enumerate() {
+        reader.read_enum(enm, &package, vec![5, index as i32]);
+    }
+    for (index, service) in messages(file, 6).enumerate() {
+        reader.read_service(service, &package, vec![6, index as i32]);
+    }
+    for (index, field) in messages(file, 7).enumerate() {
+        reader.read_extension(field, &package, vec![7, index as i32], features);
+    }
+
+    let name = string(file, 1).unwrap_or_default();
+    reader.res.files.push(FileProto { name, syntax, edition, package, imports, options: file_options });
+    reader.res
+}
+
+impl FileReader {
+    fn comment(&self, path: &[i32]) -> String {
+        self.comments.get(path).cloned().unwrap_or_default()
+    }
+
+    fn read_message(&mut self, msg: &MessageData, scope: &str, path: Vec<i32>, mut features: Features) {
+        let name = full_name(scope, &string(msg, 1).unwrap_or_default());
+        let msg_options = options(message(msg, 7));
+        msg_options.iter().for_each(|option| features.apply(option));
+
+        // the map entries are not added as messages, their fields give the type of the map field
+        let mut entries = HashMap::new();
+        for (index, nested) in messages(msg, 3).enumerate() {
+            if message(nested, 7).is_some_and(|options| flag(options, 7)) {
+                let types: Vec<String> = messages(nested, 2).map(|field| field_type(field)).collect();
+                entries.insert(format!(".{}.{}", name, string(nested, 1).unwrap_or_default()), types.join(","));
+            } else {
+                self.read_message(nested, &name, [&path[..], &[3, index as i32]].concat(), features);
+            }
+        }
+        for (index, enm) in messages(msg, 4).enumerate() {
+            self.read_enum(enm, &name, [&path[..], &[4, index as i32]].concat());
+        }
+        for (index, field) in messages(msg, 6).enumerate() {
+            self.read_extension(field, &name, [&path[..], &[6, index as i32]].concat(), features);
+        }
+
+        let oneofs: Vec<String> = messages(msg, 8).map(|oneof| string(oneof, 1).unwrap_or_default()).collect();
+        let fields = messages(msg, 2).enumerate().map(|(index, field)| {
+            let path = [&path[..], &[2, index as i32]].concat();
+            self.read_field(field, &name, &path, &oneofs, &entries, false, features)
+        }).collect();
+
+        let comment = self.comment(&path);
+        self.res.messages.push(std::rc::Rc::new(MessageProto {
+            name, fields, comment,
+            reserved: ranges(msg, 9, true),
+            reserved_names: strings(msg, 10),
+            extension_ranges: ranges(msg, 5, true),
+            options: msg_options,
+        }));
+    }
+
+    // the scope is the message of the field or the scope of the extend block
+    fn read_field(&self, field: &MessageData, scope: &str, path: &[i32], oneofs: &[String], entries: &HashMap<String, String>, extension: bool, mut features: Features) -> FieldProtoPtr {
+        let field_options = options(message(field, 8));
+        field_options.iter().for_each(|option| features.apply(option));
+        let type_number = number(field, 5).unwrap_or_default();
+        let label = number(field, 4).unwrap_or(LABEL_OPTIONAL);
+        let mut type_name = field_type(field);
+        let map = entries.get(&type_name);
+        if let Some(map) = map {
+            type_name = map.clone();
+        }
+        // a group is named by its message declared in the scope of the field, as in proto files
+        let nested = type_name.rsplit('.').next().unwrap_or_default().to_string();
+        if type_number == TYPE_GROUP && type_name == format!(".{}", full_name(scope, &nested)) {
+            type_name = nested;
+        }
+        // proto3 optional fields are in synthetic oneofs
+        let proto3_optional = flag(field, 17);
+        let oneof_name = number(field, 9).filter(|_| !proto3_optional).and_then(|index| oneofs.get(index as usize)).cloned();
+        let name = string(field, 1).unwrap_or_default();
+        let common = CommonFieldProto {
+            extension: extension.then(|| full_name(scope, &name)),
+            name,
+            id: number(field, 3).unwrap_or_default(),
+            repeated: label == LABEL_REPEATED,
+            required: label == LABEL_REQUIRED || (features.required && label != LABEL_REPEATED && oneof_name.is_none()),
+            optional: proto3_optional || (self.syntax == "proto2" && label == LABEL_OPTIONAL),
+            group: type_number == TYPE_GROUP || (features.delimited && type_number == TYPE_MESSAGE && map.is_none()),
+            packed: features.packed,
+            default_value: string(field, 7).map(|value| default_text(type_number, value)),
+            comment: self.comment(path),
+            oneof_name,
+            options: field_options,
+            ..Default::default()
+        };
+        CommonFieldProto::with_type(common, type_name)
+    }
+
+    fn read_extension(&mut self, field: &MessageData, scope: &str, path: Vec<i32>, features: Features) {
+        let extended = string(field, 2).unwrap_or_default();
+        let field = self.read_field(field, scope, &path, &[], &HashMap::new(), true, features);
+        self.res.extensions.push((scope.to_string(), extended, vec![field]));
+    }
+
+    fn read_enum(&mut self, enm: &MessageData, scope: &str, path: Vec<i32>) {
+        let variants = messages(enm, 2).enumerate().map(|(index, value)| {
+            let comment = self.comment(&[&path[..], &[2, index as i32]].concat());
+            (string(value, 1).unwrap_or_default(), number(value, 2).unwrap_or_default(), comment)
+        }).collect();
+        self.res.enums.push(std::rc::Rc::new(EnumProto {
+            name: full_name(scope, &string(enm, 1).unwrap_or_default()),
+            variants,
+            comment: self.comment(&path),
+            options: options(message(enm, 3)),
+            reserved: ranges(enm, 4, false),
+            reserved_names: strings(enm, 5),
+        }));
+    }
+
+    fn read_service(&mut self, service: &MessageData, scope: &str, path: Vec<i32>) {
+        let methods = messages(service, 2).enumerate().map(|(index, method)| MethodProto {
+            name: string(method, 1).unwrap_or_default(),
+            input_type: string(method, 2).unwrap_or_default(),
+            output_type: string(method, 3).unwrap_or_default(),
+            client_streaming: flag(method, 5),
+            server_streaming: flag(method, 6),
+            comment: self.comment(&[&path[..], &[2, index as i32]].concat()),
+            options: options(message(method, 4)),
+        }).collect();
+        self.res.services.push(ServiceProto {
+            name: full_name(scope, &string(service, 1).unwrap_or_default()),
+            methods,
+            comment: self.comment(&path),
+            options: options(message(service, 3)),
+        });
+    }
+}
+
+// a scalar type name, or the fully qualified name of a message or an enum
+fn field_type(field: &MessageData) -> String {
+    match number(field, 5) {
+        Some(TYPE_GROUP | TYPE_MESSAGE | TYPE_ENUM) | None => string(field, 6).unwrap_or_default(),
+        Some(type_number) => TYPE_NAMES.get(type_number as usize).unwrap_or(&"").to_string(),
+    }
+}
+
+/**************************************************************************************************/
+// writing: the parsed proto files as a FileDescriptorSet, as `protoc --include_imports --include_source_info`
+// The source code locations are written for the declarations with their spans and comments, not for their parts.
+// Custom options are not written, their extensions of the options messages are not in descriptor.proto.
+
+// each ProtoData is a proto file as it is parsed, before finalize; the types are resolved in all the files
+pub fn write_descriptor_set(files: &[ProtoData], writer: &mut dyn io::Write) -> io::Result<()> {
+    let proto = descriptor_proto();
+    let def = proto.get_message_definition(FILE_SET_NAME).unwrap();
+    let messages = files.iter().flat_map(|file| file.messages.iter().map(|msg| msg.name.clone())).collect();
+    let enums = files.iter().flat_map(|file| file.enums.iter().map(|enm| enm.name.clone())).collect();
+    let mut set = MessageData { def, fields: vec![] };
+    for file in dependency_order(files) {
+        let writer = FileWriter { messages: &messages, enums: &enums, spans: &file.spans, locations: vec![] };
+        let msg = writer.write_file(file, child(&set, 1))?;
+        add(&mut set, 1, msg);
+    }
+    set.write(writer, &proto, set.def.clone())
+}
+
+// the imported files before the files importing them, as protoc writes them
+fn dependency_order(files: &[ProtoData]) -> Vec<&ProtoData> {
+    let files: Vec<(&ProtoData, &FileProto)> = files.iter().filter_map(|data| data.files.first().map(|file| (data, file))).collect();
+    let mut res: Vec<usize> = vec![];
+    while res.len() < files.len() {
+        let pending = || (0..files.len()).filter(|index| !res.contains(index));
+        let ready = pending().find(|index| files[*index].1.imports.iter().all(|import| {
+            files.iter().position(|(_, file)| file.name == import.name).is_none_or(|imported| res.contains(&imported))
+        }));
+        let next = ready.or_else(|| pending().next()).unwrap(); // the first file of an import cycle
+        res.push(next);
+    }
+    res.into_iter().map(|index| files[index].0).collect()
+}
+
+fn child(msg: &MessageData, id: i32) -> MessageData {
+    MessageData { def: msg.def.get_field(id).unwrap().message_proto().unwrap(), fields: vec![] }
+}
+
+fn add(msg: &mut MessageData, id: i32, sub: MessageData) {
+    let def = msg.def.get_field(id).unwrap();
+    msg.fields.push(FieldData::new(def, FieldValue::MESSAGE(sub.into())));
+}
+
+fn set(msg: &mut MessageData, id: i32, value: ScalarValue) {
+    let def = msg.def.get_field(id).unwrap();
+    msg.fields.push(FieldData::new(def, FieldValue::SCALAR(value)));
+}
+
+fn set_string(msg: &mut MessageData, id: i32, text: &str) {
+    set(msg, id, ScalarValue::STR(text.to_string()));
+}
+
+// the options message with the options known by descriptor.proto, the features are merged into one message
+fn options_message(parent: &MessageData, id: i32, options: &[ProtoOption]) -> Option<MessageData> {
+    let def = parent.def.get_field(id).unwrap().message_proto().unwrap();
+    let text = |option: &ProtoOption| match option.name.strip_prefix("features.") {
+        Some(feature) => format!("features {{ {}: {} }}", feature, option.value),
+        None if option.value.starts_with('{') => format!("{} {}", option.name, option.value),
+        None => format!("{}: {}", option.name, option.value),
+    };
+    let known: Vec<&ProtoOption> = options.iter().filter(|option| text_format::text_to_message(&text(option), def.clone()).is_ok()).collect();
+    let features: Vec<String> = known.iter().filter_map(|option| option.name.strip_prefix("features.").map(|name| format!("{}: {}", name, option.value))).collect();
+    let mut lines: Vec<String> = known.iter().filter(|option| !option.name.starts_with("features.")).map(|option| text(option)).collect();
+    if !features.is_empty() {
+        lines.push(format!("features {{ {} }}", features.join(" ")));
+    }
+    let mut msg = text_format::text_to_message(&lines.join("\n"), def).ok()?;
+    msg.fields.sort_by_key(|field| field.def.id()); // as protoc writes them
+    (!msg.fields.is_empty()).then_some(msg)
+}
+
+// the name of the message declared by a map field, as protoc names it
+fn map_entry_name(field_name: &str) -> String {
+    let name = json::lower_camel_case(field_name);
+    let mut chars = name.chars();
+    chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default() + "Entry"
+}
+
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text)
+}
+
+fn parent_scope(name: &str) -> &str {
+    name.rsplit_once('.').map_or("", |(scope, _)| scope)
+}
+
+struct FileWriter<'a> {
+    messages: &'a HashSet<String>, // the full names in all the files
+    enums: &'a HashSet<String>,
+    spans: &'a HashMap<String, Span>, // of the file
+    locations: Vec<(Vec<i32>, Span, String)>, // the spans and the comments by the path of the declaration
+}
+
+impl FileWriter<'_> {
+    // the declarations without a span in the proto file are not located, e.g. the ones read from a descriptor set
+    fn location(&mut self, path: &[i32], name: &str, comment: &str) {
+        if let Some(span) = self.spans.get(name) {
+            self.locations.push((path.to_vec(), *span, comment.to_string()));
+        }
+    }
+
+    // the type number and the fully qualified name of a message or an enum type
+    fn resolve(&self, scope: &str, typename: &str, group: bool) -> io::Result<(i32, String)> {
+        let scalar = TYPE_NAMES.iter().position(|name| *name == typename).filter(|n| ![0, TYPE_GROUP, TYPE_MESSAGE, TYPE_ENUM].contains(&(*n as i32)));
+        if let Some(type_number) = scalar {
+            return Ok((type_number as i32, String::new()));
+        }
+        for name in scope_candidates(scope, typename) {
+            if self.messages.contains(&name) {
+                return Ok((if group { TYPE_GROUP } else { TYPE_MESSAGE }, format!(".{}", name)));
+            }
+            if self.enums.contains(&name) {
+                return Ok((TYPE_ENUM, format!(".{}", name)));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("type \"{}\" is not found in \"{}\"", typename, scope)))
+    }
+
+    fn write_file(mut self, data: &ProtoData, mut msg: MessageData) -> io::Result<MessageData> {
+        let file = &data.files[0];
+        let package = file.package.as_str();
+        if !file.name.is_empty() {
+            set_string(&mut msg, 1, &file.name);
+        }
+        if !package.is_empty() {
+            set_string(&mut msg, 2, package);
+        }
+        for import in &file.imports {
+            set_string(&mut msg, 3, &import.name);
+        }
+        let messages: Vec<&MessageProtoPtr> = data.messages.iter().filter(|m| parent_scope(&m.name) == package).collect();
+        for (index, nested) in messages.iter().enumerate() {
+            let sub = self.write_message(data, nested, child(&msg, 4), vec![4, index as i32])?;
+            add(&mut msg, 4, sub);
+        }
+        let enums: Vec<&EnumProtoPtr> = data.enums.iter().filter(|e| parent_scope(&e.name) == package).collect();
+        for (index, enm) in enums.iter().enumerate() {
+            let sub = self.write_enum(enm, child(&msg, 5), vec![5, index as i32]);
+            add(&mut msg, 5, sub);
+        }
+        for (index, service) in data.services.iter().enumerate() {
+            let sub = self.write_service(service, child(&msg, 6), vec![6, index as i32])?;
+            add(&mut msg, 6, sub);
+        }
+        self.write_extensions(data, package, &mut msg, 7, vec![])?;
+        if let Some(options) = options_message(&msg, 8, &file.options) {
+            add(&mut msg, 8, options);
+        }
+        if !self.locations.is_empty() {
+            let mut info = child(&msg, 9);
+            for (path, span, comment) in std::mem::take(&mut self.locations) {
+                let mut location = child(&info, 1);
+                path.into_iter().for_each(|n| set(&mut location, 1, ScalarValue::I32(n)));
+                let end_line = if span.end.0 == span.start.0 { vec![] } else { vec![span.end.0] }; // left out on one line
+                let numbers = [vec![span.start.0, span.start.1], end_line, vec![span.end.1]].concat();
+                numbers.into_iter().for_each(|n| set(&mut location, 2, ScalarValue::I32(n)));
+                if !comment.is_empty() {
+                    set_string(&mut location, if span.trailing { 4 } else { 3 }, &format!("{}\n", comment));
+                }
+                add(&mut info, 1, location);
+            }
+            add(&mut msg, 9, info);
+        }
+        for (index, import) in file.imports.iter().enumerate() {
+            if import.public {
+                set(&mut msg, 10, ScalarValue::I32(index as i32));
+            }
+        }
+        for (index, import) in file.imports.iter().enumerate() {
+            if import.weak {
+                set(&mut msg, 11, ScalarValue::I32(index as i32));
+            }
+        }
+        match file.syntax.as_str() {
+            "proto3" => set_string(&mut msg, 12, "proto3"),
+            "editions" => {
+                set_string(&mut msg, 12, "editions");
+                set(&mut msg, 14, ScalarValue::ENUM(if file.edition == "2024" { 1001 } else { 1000 }));
+            }
+            _ => {} // proto2 is the default
+        }
+        Ok(msg)
+    }
+
+    // the fields of the extend blocks in the scope
+    fn write_extensions(&mut self, data: &ProtoData, scope: &str, msg: &mut MessageData, id: i32, path: Vec<i32>) -> io::Result<()> {
+        let fields = data.extensions.iter().filter(|(extension_scope, ..)| extension_scope == scope).
+            flat_map(|(_, extended, fields)| fields.iter().map(move |field| (extended, field)));
+        for (index, (extended, field)) in fields.enumerate() {
+            let path = [&path[..], &[id, index as i32]].concat();
+            let extendee = self.resolve(scope, extended, false)?.1;
+            let sub = self.write_field(field, scope, child(msg, id), Some(&extendee), &[], &path, false)?;
+            add(msg, id, sub);
+        }
+        Ok(())
+    }
+
+    fn write_message(&mut self, data: &ProtoData, def: &MessageProto, mut msg: MessageData, path: Vec<i32>) -> io::Result<MessageData> {
+        let scope = def.name.as_str();
+        self.location(&path, scope, &def.comment);
+        set_string(&mut msg, 1, scope.rsplit('.').next().unwrap_or_default());
+
+        // proto3 optional fields are in synthetic oneofs after the declared ones
+        let proto3 = data.files.first().is_some_and(|file| file.syntax == "proto3");
+        let mut oneofs: Vec<String> = vec![];
+        for field in &def.fields {
+            if let Some(name) = field.oneof_name() {
+                if !oneofs.contains(name) {
+                    oneofs.push(name.clone());
+                }
+            }
+        }
+        for field in &def.fields {
+            if proto3 && field.get_common_definition().optional {
+                oneofs.push(format!("_{}", field.name()));
+            }
+        }
+
+        let mut entries = vec![];
+        for (index, field) in def.fields.iter().enumerate() {
+            let path = [&path[..], &[2, index as i32]].concat();
+            let sub = self.write_field(field, scope, child(&msg, 2), None, &oneofs, &path, proto3)?;
+            add(&mut msg, 2, sub);
+            if let Some((key, value)) = field.typename().split_once(',') {
+                entries.push((map_entry_name(&field.name()), key.to_string(), value.to_string()));
+            }
+        }
+
+        let nested: Vec<&MessageProtoPtr> = data.messages.iter().filter(|m| parent_scope(&m.name) == scope).collect();
+        for (index, nested) in nested.iter().enumerate() {
+            let sub = self.write_message(data, nested, child(&msg, 3), [&path[..], &[3, index as i32]].concat())?;
+            add(&mut msg, 3, sub);
+        }
+        // the map entries are declared after the nested messages
+        for (name, key, value) in entries {
+            let mut entry = child(&msg, 3);
+            set_string(&mut entry, 1, &name);
+            for (id, field_name, typename) in [(1, "key", key), (2, "value", value)] {
+                let (type_number, type_name) = self.resolve(scope, &typename, false)?;
+                let mut field = child(&entry, 2);
+                set_string(&mut field, 1, field_name);
+                set(&mut field, 3, ScalarValue::I32(id));
+                set(&mut field, 4, ScalarValue::ENUM(LABEL_OPTIONAL));
+                set(&mut field, 5, ScalarValue::ENUM(type_number));
+                if !type_name.is_empty() {
+                    set_string(&mut field, 6, &type_name);
+                }
+                set_string(&mut field, 10, field_name);
+                add(&mut entry, 2, field);
+            }
+            let mut options = child(&entry, 7);
+            set(&mut options, 7, ScalarValue::BOOL(true));
+            add(&mut entry, 7, options);
+            add(&mut msg, 3, entry);
+        }
+
+        let enums: Vec<&EnumProtoPtr> = data.enums.iter().filter(|e| parent_scope(&e.name) == scope).collect();
+        for (index, enm) in enums.iter().enumerate() {
+            let sub = self.write_enum(enm, child(&msg, 4), [&path[..], &[4, index as i32]].concat());
+            add(&mut msg, 4, sub);
+        }
+        for (start, end) in &def.extension_ranges {
+            let mut range = child(&msg, 5);
+            set(&mut range, 1, ScalarValue::I32(*start));
+            set(&mut range, 2, ScalarValue::I32(end + 1));
+            add(&mut msg, 5, range);
+        }
+        self.write_extensions(data, scope, &mut msg, 6, path.clone())?;
+        if let Some(options) = options_message(&msg, 7, &def.options) {
+            add(&mut msg, 7, options);
+        }
+        for name in &oneofs {
+            let mut oneof = child(&msg, 8);
+            set_string(&mut oneof, 1, name);
+            add(&mut msg, 8, oneof);
+        }
+        for (start, end) in &def.reserved {
+            let mut range = child(&msg, 9);
+            set(&mut range, 1, ScalarValue::I32(*start));
+            set(&mut range, 2, ScalarValue::I32(end + 1));
+            add(&mut msg, 9, range);
+        }
+        for name in &def.reserved_names {
+            set_string(&mut msg, 10, name);
+        }
+        Ok(msg)
+    }
+
+    // the scope is the message of the field or the scope of the extend block
+    #[allow(clippy::too_many_arguments)]
+    fn write_field(&mut self, def: &FieldProtoPtr, scope: &str, mut msg: MessageData, extendee: Option<&str>, oneofs: &[String], path: &[i32], proto3: bool) -> io::Result<MessageData> {
+        let common = def.get_common_definition();
+        self.location(path, &full_name(scope, &common.name), &common.comment);
+        let typename = def.typename();
+        let (type_number, type_name) = match typename.split_once(',') {
+            Some(_) => (TYPE_MESSAGE, format!(".{}.{}", scope, map_entry_name(&common.name))),
+            None => self.resolve(scope, &typename, common.group)?,
+        };
+        let label = if common.repeated || typename.contains(',') { LABEL_REPEATED } else if common.required { LABEL_REQUIRED } else { LABEL_OPTIONAL };
+        let option = |name: &str| common.options.iter().find(|option| option.name == name).map(|option| option.value.clone());
+
+        set_string(&mut msg, 1, &common.name);
+        if let Some(extendee) = extendee {
+            set_string(&mut msg, 2, extendee);
+        }
+        set(&mut msg, 3, ScalarValue::I32(common.id));
+        set(&mut msg, 4, ScalarValue::ENUM(label));
+        set(&mut msg, 5, ScalarValue::ENUM(type_number));
+        if !type_name.is_empty() {
+            set_string(&mut msg, 6, &type_name);
+        }
+        // descriptors keep strings unquoted and bytes escaped
+        // the files are written before ProtoData::finalize, so the default is parsed here
+        if let Some(text) = &common.default_value {
+            let parsed = match def.default() {
+                FieldValue::SCALAR(zero) => text_format::default_to_scalar(text, &zero, |_| None),
+                FieldValue::MESSAGE(_) => None,
+            };
+            let value = match parsed {
+                _ if type_number == TYPE_ENUM => text.clone(),
+                Some(ScalarValue::STR(value)) => value,
+                Some(value @ ScalarValue::BYTES(_)) => unquote(&text_format::scalar_to_text(def, &value)).to_string(),
+                Some(value) => text_format::scalar_to_text(def, &value),
+                None => text.clone(),
+            };
+            set_string(&mut msg, 7, &value);
+        }
+        if let Some(options) = options_message(&msg, 8, &common.options) {
+            add(&mut msg, 8, options);
+        }
+        let proto3_optional = proto3 && common.optional;
+        let oneof = if proto3_optional { Some(format!("_{}", common.name)) } else { common.oneof_name.clone() };
+        if let Some(index) = oneof.and_then(|name| oneofs.iter().position(|oneof| *oneof == name)) {
+            set(&mut msg, 9, ScalarValue::I32(index as i32));
+        }
+        let json_name = match option("json_name").and_then(|value| text_format::default_to_scalar(&value, &ScalarValue::STR(String::new()), |_| None)) {
+            Some(ScalarValue::STR(name)) => name,
+            _ => json::lower_camel_case(&common.name),
+        };
+        set_string(&mut msg, 10, &json_name);
+        if proto3_optional {
+            set(&mut msg, 17, ScalarValue::BOOL(true));
+        }
+        Ok(msg)
+    }
+
+    fn write_enum(&mut self, def: &EnumProto, mut msg: MessageData, path: Vec<i32>) -> MessageData {
+        self.location(&path, &def.name, &def.comment);
+        set_string(&mut msg, 1, def.name.rsplit('.').next().unwrap_or_default());
+        for (index, (name, number, comment)) in def.variants.iter().enumerate() {
+            self.location(&[&path[..], &[2, index as i32]].concat(), &full_name(&def.name, name), comment);
+            let mut value = child(&msg, 2);
+            set_string(&mut value, 1, name);
+            set(&mut value, 2, ScalarValue::I32(*number));
+            add(&mut msg, 2, value);
+        }
+        if let Some(options) = options_message(&msg, 3, &def.options) {
+            add(&mut msg, 3, options);
+        }
+        for (start, end) in &def.reserved {
+            let mut range = child(&msg, 4);
+            set(&mut range, 1, ScalarValue::I32(*start));
+            set(&mut range, 2, ScalarValue::I32(*end)); // inclusive in enums
+            add(&mut msg, 4, range);
+        }
+        for name in &def.reserved_names {
+            set_string(&mut msg, 5, name);
+        }
+        msg
+    }
+
+    fn write_service(&mut self, def: &ServiceProto, mut msg: MessageData, path: Vec<i32>) -> io::Result<MessageData> {
+        self.location(&path, &def.name, &def.comment);
+        let scope = parent_scope(&def.name);
+        set_string(&mut msg, 1, def.name.rsplit('.').next().unwrap_or_default());
+        for (index, method) in def.methods.iter().enumerate() {
+            self.location(&[&path[..], &[2, index as i32]].concat(), &full_name(&def.name, &method.name), &method.comment);
+            let mut sub = child(&msg, 2);
+            set_string(&mut sub, 1, &method.name);
+            set_string(&mut sub, 2, &self.resolve(scope, &method.input_type, false)?.1);
+            set_string(&mut sub, 3, &self.resolve(scope, &method.output_type, false)?.1);
+            if let Some(options) = options_message(&sub, 4, &method.options) {
+                add(&mut sub, 4, options);
+            }
+            if method.client_streaming {
+                set(&mut sub, 5, ScalarValue::BOOL(true));
+            }
+            if method.server_streaming {
+                set(&mut sub, 6, ScalarValue::BOOL(true));
+            }
+            add(&mut msg, 2, sub);
+        }
+        if let Some(options) = options_message(&msg, 3, &def.options) {
+            add(&mut msg, 3, options);
+        }
+        Ok(msg)
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod descriptor_set {
+    use super::*;
+    use crate::json::is_map;
+    use crate::text_format::text_to_message;
+    use crate::wire::WT_SGROUP;
+
+    fn encode(text: &str) -> Vec<u8> {
+        let proto = descriptor_proto();
+        let set = text_to_message(text, proto.get_message_definition(FILE_SET_NAME).unwrap()).unwrap();
+        well_known::packed_value(&set)
+    }
+
+    #[test]
+    fn read() {
+        let data = encode(r#"
+file {
+  name: "old.proto" package: "old"
+  message_type {
+    name: "Item"
+    field { name: "name" number: 1 label: LABEL_OPTIONAL type: TYPE_STRING default_value: "a \"b\"" }
+    field { name: "result" number: 2 label: LABEL_REPEATED type: TYPE_GROUP type_name: ".old.Item.Result" }
+    nested_type { name: "Result" field { name: "url" number: 3 label: LABEL_REQUIRED type: TYPE_STRING } }
+    extension_range { start: 100 end: 200 }
+  }
+  extension { name: "extra" number: 100 label: LABEL_OPTIONAL type: TYPE_INT32 extendee: ".old.Item" }
+}
+file {
+  name: "shop.proto" package: "shop" dependency: "old.proto" public_dependency: 0 syntax: "proto3"
+  options { java_package: "com.shop" }
+  message_type {
+    name: "Order"
+    field { name: "id" number: 1 label: LABEL_OPTIONAL type: TYPE_INT32 }
+    field { name: "tags" number: 2 label: LABEL_REPEATED type: TYPE_INT32 options { packed: false } }
+    field { name: "counts" number: 3 label: LABEL_REPEATED type: TYPE_MESSAGE type_name: ".shop.Order.CountsEntry" }
+    field { name: "card" number: 4 label: LABEL_OPTIONAL type: TYPE_STRING oneof_index: 0 }
+    field { name: "note" number: 5 label: LABEL_OPTIONAL type: TYPE_STRING oneof_index: 1 proto3_optional: true }
+    field { name: "item" number: 6 label: LABEL_OPTIONAL type: TYPE_MESSAGE type_name: ".old.Item" }
+    nested_type {
+      name: "CountsEntry"
+      field { name: "key" number: 1 label: LABEL_OPTIONAL type: TYPE_STRING }
+      field { name: "value" number: 2 label: LABEL_OPTIONAL type: TYPE_ENUM type_name: ".shop.Status" }
+      options { map_entry: true }
+    }
+    oneof_decl { name: "payment" }
+    oneof_decl { name: "_note" }
+    reserved_range { start: 10 end: 13 }
+    reserved_name: "old"
+  }
+  enum_type { name: "Status" value { name: "NEW" number: 0 } value { name: "DONE" number: 1 } reserved_range { start: 5 end: 6 } }
+  service { name: "Orders" method { name: "Watch" input_type: ".shop.Order" output_type: ".shop.Order" server_streaming: true } }
+  source_code_info {
+    location { path: [4, 0] span: [3, 0, 10, 1] leading_comments: " An order.\n" }
+    location { path: [4, 0, 2, 0] span: [4, 2, 15] trailing_comments: " the id\n" }
+    location { path: [5, 0, 2, 1] span: [12, 2, 11] leading_comments: " finished\n" }
+  }
+}"#);
+        let mut files = read_descriptor_set(&data).unwrap();
+        assert_eq!(files.len(), 2);
+        let file = &files[1].files[0];
+        assert_eq!((file.name.as_str(), file.syntax.as_str(), file.package.as_str()), ("shop.proto", "proto3", "shop"));
+        assert_eq!(file.imports, [Import { name: "old.proto".to_string(), public: true, weak: false }]);
+        assert_eq!(file.options, [ProtoOption { name: "java_package".to_string(), value: "\"com.shop\"".to_string() }]);
+        assert_eq!(files[1].auto_detect_root_message().unwrap().name, "shop.Order");
+
+        let mut proto = files.remove(1);
+        proto.append(files.remove(0));
+        let proto = proto.finalize().unwrap();
+        let order = proto.get_message_definition("shop.Order").unwrap();
+        assert_eq!(order.comment, " An order.");
+        assert_eq!(order.fields[0].comment(), " the id");
+        assert!(order.get_field(2).unwrap().packable() && !order.get_field(2).unwrap().packed());
+        assert!(is_map(&order.get_field(3).unwrap()));
+        assert_eq!(order.get_field(4).unwrap().oneof_name().as_deref(), Some("payment"));
+        assert_eq!(order.get_field(5).unwrap().oneof_name(), &None);
+        assert!(order.get_field(5).unwrap().get_common_definition().optional);
+        assert_eq!((order.reserved.as_slice(), order.reserved_names.as_slice()), (&[(10, 12)][..], &["old".to_string()][..]));
+        assert!(proto.get_message_definition("shop.Order.CountsEntry").is_none());
+
+        let status = proto.get_enum_definition("shop.Status").unwrap();
+        assert_eq!(status.variants[1], ("DONE".to_string(), 1, " finished".to_string()));
+        assert_eq!(status.reserved, [(5, 6)]);
+        let method = &proto.services[0].methods[0];
+        assert_eq!((method.input_type.as_str(), method.client_streaming, method.server_streaming), (".shop.Order", false, true));
+
+        let item = proto.get_message_definition("old.Item").unwrap();
+        assert_eq!(item.extension_ranges, [(100, 199)]);
+        assert_eq!(item.get_field(2).unwrap().wire_type(), WT_SGROUP);
+        assert!(item.get_field(2).unwrap().message_proto().unwrap().get_field(3).unwrap().required());
+        assert_eq!(item.get_field(100).unwrap().name(), "extra");
+
+        // the data is read with the loaded schema
+        let data = text_to_message(r#"id: 1 counts { key: "a" value: DONE } item { name: "x" Result { url: "u" } }"#, order).unwrap();
+        assert_eq!(text_format::message_to_text(&data), "id: 1\ncounts {\n  key: \"a\"\n  value: DONE\n}\nitem {\n  name: \"x\"\n  Result {\n    url: \"u\"\n  }\n}\n");
+        let FieldValue::MESSAGE(item) = &data.fields[2].value else { panic!() };
+        assert_eq!(item.def.get_field(1).unwrap().default(), FieldValue::SCALAR(ScalarValue::STR("a \"b\"".to_string())));
+    }
+
+    #[test]
+    fn write() {
+        let old = crate::proto_parser::parse(r#"
+package old;
+message Item {
+  optional string name = 1 [default = "a \"b\""];
+  repeated group Result = 2 { required string url = 3; }
+  extensions 100 to 199;
+}
+extend Item { optional int32 extra = 100; }
+"#, "old.proto").unwrap();
+        let shop = crate::proto_parser::parse(r#"
+syntax = "proto3";
+package shop;
+import public "old.proto";
+option java_package = "com.shop";
+// An order.
+message Order {
+  int32 id = 1; // the id
+  repeated int32 tags = 2 [packed = false];
+  map<string, Status> counts = 3;
+  oneof payment { string card = 4; }
+  optional string note = 5;
+  old.Item item = 6;
+  reserved 10 to 12;
+  reserved "old";
+}
+enum Status {
+  NEW = 0;
+  // finished
+  DONE = 1;
+  reserved 5 to 6;
+}
+service Orders { rpc Watch(Order) returns (stream Order); }
+"#, "shop.proto").unwrap();
+
+        // the imported file first, as read by read()
+        let mut data = vec![];
+        write_descriptor_set(&[shop, old], &mut data).unwrap();
+        let proto = descriptor_proto();
+        let mut limit = data.len() as u64;
+        let set = MessageData::new(&mut PbSliceReader::new(&data, 0), &proto, proto.get_message_definition(FILE_SET_NAME).unwrap(), &mut limit).unwrap();
+        assert_eq!(data, encode(&text_format::message_to_text(&set)));
+        let text = text_format::message_to_text(&set);
+        assert!(text.starts_with("file {\n  name: \"old.proto\"\n  package: \"old\"\n"));
+        assert!(text.contains("field {\n      name: \"name\"\n      number: 1\n      label: LABEL_OPTIONAL\n      type: TYPE_STRING\n      default_value: \"a \\\"b\\\"\"\n      json_name: \"name\"\n    }"));
+        assert!(text.contains("name: \"CountsEntry\""));
+        assert!(text.contains("oneof_decl {\n      name: \"payment\"\n    }\n    oneof_decl {\n      name: \"_note\"\n    }"));
+        assert!(text.contains("leading_comments: \" An order.\\n\""));
+        let shop_text = &text[text.find("name: \"shop.proto\"").unwrap()..];
+        let location = |path: &str| shop_text.find(&format!("location {{\n      {}", path)).map(|start| shop_text[start..].split("\n    }").next().unwrap().replace("\n      ", " "));
+        assert_eq!(location("path: 4\n      path: 0\n      span").unwrap(), "location { path: 4 path: 0 span: 6 span: 0 span: 15 span: 1 leading_comments: \" An order.\\n\"");
+        assert_eq!(location("path: 4\n      path: 0\n      path: 2\n      path: 0\n").unwrap(), "location { path: 4 path: 0 path: 2 path: 0 span: 7 span: 2 span: 15 trailing_comments: \" the id\\n\"");
+        assert_eq!(location("path: 5\n      path: 0\n      path: 2\n      path: 1\n").unwrap(), "location { path: 5 path: 0 path: 2 path: 1 span: 19 span: 2 span: 11 leading_comments: \" finished\\n\"");
+
+        // the written set is read as the parsed files
+        let files = read_descriptor_set(&data).unwrap();
+        let (old, shop) = (&files[0], &files[1]);
+        assert_eq!(shop.files[0].imports, [Import { name: "old.proto".to_string(), public: true, weak: false }]);
+        assert_eq!(shop.files[0].options, [ProtoOption { name: "java_package".to_string(), value: "\"com.shop\"".to_string() }]);
+        assert_eq!(old.extensions[0].1, ".old.Item");
+        let order = shop.messages.iter().find(|m| m.name == "shop.Order").unwrap();
+        assert_eq!(order.comment, " An order.");
+        assert_eq!(order.fields[0].comment(), " the id");
+        assert_eq!(order.fields[2].typename(), "string,.shop.Status");
+        assert_eq!(order.fields[3].oneof_name().as_deref(), Some("payment"));
+        assert!(order.fields[4].get_common_definition().optional && order.fields[4].oneof_name().is_none());
+        assert_eq!(order.fields[5].typename(), ".old.Item");
+        assert_eq!((order.reserved.as_slice(), order.reserved_names.as_slice()), (&[(10, 12)][..], &["old".to_string()][..]));
+        assert_eq!(shop.enums[0].variants[1], ("DONE".to_string(), 1, " finished".to_string()));
+        assert_eq!(shop.enums[0].reserved, [(5, 6)]);
+        let method = &shop.services[0].methods[0];
+        assert_eq!((method.input_type.as_str(), method.client_streaming, method.server_streaming), (".shop.Order", false, true));
+        let item = old.messages.iter().find(|m| m.name == "old.Item").unwrap();
+        assert_eq!(item.extension_ranges, [(100, 199)]);
+        assert_eq!((item.fields[1].typename(), item.fields[1].get_common_definition().group), ("Result".to_string(), true));
+        assert_eq!(item.fields[0].get_common_definition().default_value.as_deref(), Some("\"a \\\"b\\\"\""));
+
+        let unknown = crate::proto_parser::parse("message A { B b = 1; }", "a.proto").unwrap();
+        assert!(write_descriptor_set(&[unknown], &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn not_a_set() {
+        assert!(read_descriptor_set(b"\x08\x01").is_err());
+        assert!(read_descriptor_set(b"").is_err());
+        assert!(is_descriptor_set(Path::new("schema.desc")) && !is_descriptor_set(Path::new("schema.proto")));
+    }
+}
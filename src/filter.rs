@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+use crate::format;
+use crate::json::is_map;
+use crate::path::PathQuery;
+use crate::proto::FieldProtoPtr;
+use crate::text_format::text_to_value;
+use crate::wire::*;
+use crate::wire::ScalarValue::*;
+
+// Sorting and filtering of repeated values in the view only, the data keeps the order it was read in.
+// Messages are sorted by a subfield (e.g. a table column) and filtered by comparing a subfield with a value,
+// e.g. `status == ACTIVE`, `size > 100` or `name ~ abc` (contains); scalars are compared themselves: `> 100`.
+// Map entries are compared by `key` or `value`, e.g. `key ~ abc` or `value.size > 100`.
+// The view keeps the real indexes of the values, so edits change the right data.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator { Eq, Ne, Lt, Le, Gt, Ge, Contains }
+
+// a longer operator before its prefix
+const OPERATORS: [(&str, Operator); 8] = [
+    ("==", Operator::Eq), ("!=", Operator::Ne), ("<=", Operator::Le), (">=", Operator::Ge),
+    ("=", Operator::Eq), ("<", Operator::Lt), (">", Operator::Gt), ("~", Operator::Contains)];
+
+// how the values of a repeated field are shown, kept for the field of all messages of the type
+#[derive(Default)]
+pub struct RepeatedEditorConfig {
+    pub sort_by: Option<SortKey>,
+    pub filter: Option<Filter>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SortKey {
+    pub ids: Vec<i32>, // path of the subfield in a message, empty for scalar values
+    pub descending: bool,
+}
+
+pub struct Filter {
+    pub text: String, // as typed
+    ids: Vec<i32>, // path of the compared subfield, empty for scalar values
+    operator: Operator,
+    value: ScalarValue,
+}
+
+impl RepeatedEditorConfig {
+    pub fn shows_all(&self) -> bool {
+        self.sort_by.is_none() && self.filter.is_none()
+    }
+
+    // offsets of the shown values from the first one, in the view order; equal values keep the order of the file
+    pub fn view_order(&self, parent: &MessageData, id: i32, start: usize, amount: usize) -> Vec<usize> {
+        let values: Vec<&FieldValue> = (start..start + amount).filter_map(|index| parent.get_field(&[FieldPos { id, index }])).map(|field| &field.value).collect();
+        let mut order: Vec<usize> = (0..values.len()).filter(|&offset| self.filter.as_ref().is_none_or(|filter| filter.matches(values[offset]))).collect();
+        if let Some(sort) = &self.sort_by {
+            let keys: Vec<Option<ScalarValue>> = values.iter().map(|value| subfield_value(value, &sort.ids)).collect();
+            order.sort_by(|a, b| {
+                let ordering = match (&keys[*a], &keys[*b]) {
+                    (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+                    (a, b) => a.is_some().cmp(&b.is_some()), // a value without the subfield first
+                };
+                if sort.descending { ordering.reverse() } else { ordering }
+            });
+        }
+        order
+    }
+
+    // for the top line, e.g. "name z…a [size > 100]"
+    pub fn describe(&self, def: &FieldProtoPtr) -> String {
+        let mut parts = vec![];
+        if let Some(sort) = &self.sort_by {
+            let mut names = vec![];
+            let mut field = def.clone();
+            for id in &sort.ids {
+                let Some(sub) = field.message_proto().and_then(|msg| msg.get_field(*id)) else { break; };
+                names.push(if is_map(&field) { entry_field_name(*id).to_string() } else { sub.name() });
+                field = sub;
+            }
+            let order = if sort.descending { "z…a" } else { "a…z" };
+            parts.push(if names.is_empty() { order.to_string() } else { format!("{} {}", names.join("."), order) });
+        }
+        if let Some(filter) = &self.filter {
+            parts.push(format!("[{}]", filter.text));
+        }
+        parts.join(" ")
+    }
+}
+
+impl Filter {
+    // the subfield of a message is given by names: "size > 100", "stats.count >= 2"; a scalar is compared itself: "> 100"
+    pub fn parse(text: &str, def: &FieldProtoPtr) -> Result<Filter, String> {
+        let (pos, (operator_text, operator)) = OPERATORS.iter().
+            filter_map(|(operator_text, operator)| Some((text.find(operator_text)?, (operator_text, *operator)))).
+            min_by_key(|(pos, _)| *pos).
+            ok_or("expected a comparison: == != < <= > >= or ~ (contains)")?;
+        let (field, value) = (text[..pos].trim(), text[pos + operator_text.len()..].trim());
+        let (ids, field_def) = match def.message_proto() {
+            Some(msg_def) => {
+                let field = match field.split_once('.') { // the fields of map entries are named @1 and @2
+                    Some((name, rest)) if is_map(def) => format!("{}.{}", entry_field_id(name), rest),
+                    None if is_map(def) => entry_field_id(field).to_string(),
+                    _ => field.to_string(),
+                };
+                let query = PathQuery::parse(&msg_def, &field)?;
+                (query.0.iter().map(|step| step.def.id()).collect(), query.last().def.clone())
+            }
+            None if field.is_empty() => (vec![], def.clone()),
+            None => return Err(format!("\"{}\" has no fields, type the comparison only, e.g. \"> 100\"", def.name())),
+        };
+        let value = match text_to_value(value, &field_def) {
+            _ if field_def.is_message() => return Err(format!("\"{}\" is a message, it cannot be compared", field_def.name())),
+            Ok(FieldValue::SCALAR(value)) => value,
+            // a text is typed without quotes
+            _ if operator == Operator::Contains || matches!(field_def.default(), FieldValue::SCALAR(STR(_))) => STR(value.to_string()),
+            Err(e) => return Err(e),
+            Ok(FieldValue::MESSAGE(_)) => unreachable!(),
+        };
+        Ok(Filter { text: text.trim().to_string(), ids, operator, value })
+    }
+
+    fn matches(&self, element: &FieldValue) -> bool {
+        let Some(value) = subfield_value(element, &self.ids) else { return false; };
+        if self.operator == Operator::Contains {
+            return match (&value, &self.value) {
+                (STR(text), STR(part)) => text.to_lowercase().contains(&part.to_lowercase()),
+                _ => false,
+            };
+        }
+        let Some(ordering) = compare(&value, &self.value) else { return false; };
+        match self.operator {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::Ne => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::Le => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::Ge => ordering != Ordering::Less,
+            Operator::Contains => unreachable!(),
+        }
+    }
+}
+
+fn entry_field_name(id: i32) -> &'static str {
+    if id == 1 { "key" } else { "value" }
+}
+
+fn entry_field_id(name: &str) -> &str {
+    match name {
+        "key" => "1",
+        "value" => "2",
+        _ => name,
+    }
+}
+
+// the last value of the subfield (the default one if it is not set), or the scalar value itself
+fn subfield_value(value: &FieldValue, ids: &[i32]) -> Option<ScalarValue> {
+    let Some((id, rest)) = ids.split_first() else {
+        return match value {
+            FieldValue::SCALAR(value) => Some(value.clone()),
+            FieldValue::MESSAGE(_) => None,
+        };
+    };
+    let FieldValue::MESSAGE(msg) = value else { return None; };
+    match msg.fields.iter().rfind(|field| field.def.id() == *id) {
+        Some(field) => subfield_value(&field.value, rest),
+        None if rest.is_empty() => subfield_value(&msg.def.get_field(*id)?.default(), rest),
+        None => None,
+    }
+}
+
+// numbers are compared by value, enums by number
+fn compare(a: &ScalarValue, b: &ScalarValue) -> Option<Ordering> {
+    match (a, b) {
+        (STR(a), STR(b)) => Some(a.cmp(b)),
+        (BYTES(a), BYTES(b)) => Some(a.cmp(b)),
+        (BOOL(a), BOOL(b)) => Some(a.cmp(b)),
+        (ENUM(a), ENUM(b)) => Some(a.cmp(b)),
+        _ => match (format::integer(a), format::integer(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => real(a)?.partial_cmp(&real(b)?),
+        },
+    }
+}
+
+fn real(value: &ScalarValue) -> Option<f64> {
+    match value {
+        F32(v) => Some(*v as f64),
+        F64(v) => Some(*v),
+        _ => format::integer(value).map(|v| v as f64),
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod view_order {
+    use super::*;
+    use crate::proto::ProtoData;
+    use crate::text_format::text_to_message;
+
+    fn make_data() -> MessageData {
+        let proto = ProtoData::new("message M { repeated P p = 1; repeated int32 n = 2; }\nmessage P { string name = 1; int64 size = 2; S status = 3; }\nenum S { NEW = 0; ACTIVE = 1; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        text_to_message("p { name: \"b\" size: 300 status: ACTIVE } p { name: \"c\" size: 5 } p { name: \"a\" size: 120 status: ACTIVE } n: 3 n: -1 n: 20", def).unwrap()
+    }
+
+    #[test]
+    fn sort() {
+        let data = make_data();
+        let mut config = RepeatedEditorConfig { sort_by: Some(SortKey { ids: vec![1], descending: false }), filter: None };
+        assert_eq!(config.view_order(&data, 1, 0, 3), [2, 0, 1]);
+        config.sort_by = Some(SortKey { ids: vec![2], descending: true });
+        assert_eq!(config.view_order(&data, 1, 0, 3), [0, 2, 1]);
+        config.sort_by = Some(SortKey { ids: vec![3], descending: false }); // the missing enum is the default NEW
+        assert_eq!(config.view_order(&data, 1, 0, 3), [1, 0, 2]);
+        config.sort_by = Some(SortKey { ids: vec![], descending: false });
+        assert_eq!(config.view_order(&data, 2, 0, 3), [1, 0, 2]);
+        assert_eq!(config.view_order(&data, 2, 1, 2), [0, 1]); // a group of the values
+    }
+
+    #[test]
+    fn filter() {
+        let data = make_data();
+        let def = data.def.get_field(1).unwrap();
+        let filtered = |text: &str| {
+            let config = RepeatedEditorConfig { sort_by: None, filter: Some(Filter::parse(text, &def).unwrap()) };
+            config.view_order(&data, 1, 0, 3)
+        };
+        assert_eq!(filtered("status == ACTIVE"), [0, 2]);
+        assert_eq!(filtered("status != ACTIVE"), [1]);
+        assert_eq!(filtered("size > 100"), [0, 2]);
+        assert_eq!(filtered("size<=120"), [1, 2]);
+        assert_eq!(filtered("name = a"), [2]);
+        assert_eq!(filtered("name ~ B"), [0]);
+
+        let numbers = data.def.get_field(2).unwrap();
+        let config = RepeatedEditorConfig { sort_by: Some(SortKey { ids: vec![], descending: true }), filter: Some(Filter::parse(">= 0", &numbers).unwrap()) };
+        assert_eq!(config.view_order(&data, 2, 0, 3), [2, 0]);
+        assert_eq!(config.describe(&numbers), "z…a [>= 0]");
+
+        assert!(Filter::parse("size", &def).is_err());
+        assert!(Filter::parse("weight > 1", &def).is_err());
+        assert!(Filter::parse("size > big", &def).is_err());
+        assert!(Filter::parse("size > 1", &numbers).is_err());
+    }
+
+    #[test]
+    fn map_entries() {
+        let proto = ProtoData::new("message M { map<string, int32> counts = 1; }").unwrap().finalize().unwrap();
+        let data = text_to_message("counts { key: \"ab\" value: 5 } counts { key: \"b\" value: 1 }", proto.auto_detect_root_message().unwrap()).unwrap();
+        let def = data.def.get_field(1).unwrap();
+        let config = RepeatedEditorConfig { sort_by: Some(SortKey { ids: vec![2], descending: false }), filter: Some(Filter::parse("key ~ b", &def).unwrap()) };
+        assert_eq!(config.view_order(&data, 1, 0, 2), [1, 0]);
+        assert_eq!(config.describe(&def), "value a…z [key ~ b]");
+        assert!(Filter::parse("value > 2", &def).is_ok());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::proto::FieldProtoPtr;
+use crate::text_format::text_to_value;
+use crate::view::FieldDataViewFormat;
+use crate::wire::*;
+
+// Number formats of scalar fields, chosen for each field with F3 (see LayoutConfig::field_format):
+// integers as hex, octal or binary, or seconds and milliseconds since 1970 as an ISO date (UTC),
+// floating point values with all significant digits. Typed values are read in the same format.
+
+impl FieldDataViewFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldDataViewFormat::Formated => "",
+            FieldDataViewFormat::Decimal => "dec",
+            FieldDataViewFormat::Hex => "hex",
+            FieldDataViewFormat::Octal => "oct",
+            FieldDataViewFormat::Binary => "bin",
+            FieldDataViewFormat::UnixSeconds => "unix s",
+            FieldDataViewFormat::UnixMillis => "unix ms",
+            FieldDataViewFormat::FullPrecision => "full",
+        }
+    }
+}
+
+// formats available for the field, the first one is the default
+pub fn formats(def: &FieldProtoPtr) -> Vec<FieldDataViewFormat> {
+    use FieldDataViewFormat::*;
+    match def.default() {
+        FieldValue::SCALAR(ScalarValue::ENUM(_)) => vec![Formated, Decimal, Hex],
+        FieldValue::SCALAR(ScalarValue::F32(_) | ScalarValue::F64(_)) => vec![Formated, FullPrecision],
+        FieldValue::SCALAR(value) if integer(&value).is_some() => vec![Formated, Hex, Octal, Binary, UnixSeconds, UnixMillis],
+        _ => vec![Formated],
+    }
+}
+
+// None if the format is not applicable to the value, it is shown as usual then
+pub fn format_scalar(value: &ScalarValue, format: FieldDataViewFormat) -> Option<String> {
+    let number = match value {
+        ScalarValue::ENUM(v) => Some(*v as i128),
+        _ => integer(value),
+    };
+    match (format, value, number) {
+        (FieldDataViewFormat::Decimal, _, Some(v)) => Some(v.to_string()),
+        (FieldDataViewFormat::Hex, _, Some(v)) => Some(with_sign(v, |v| format!("0x{:X}", v))),
+        (FieldDataViewFormat::Octal, _, Some(v)) => Some(with_sign(v, |v| if v == 0 { "0".to_string() } else { format!("0{:o}", v) })),
+        (FieldDataViewFormat::Binary, _, Some(v)) => Some(with_sign(v, |v| format!("0b{:b}", v))),
+        (FieldDataViewFormat::UnixSeconds, _, Some(v)) => format_date(v, 1),
+        (FieldDataViewFormat::UnixMillis, _, Some(v)) => format_date(v, 1000),
+        // the shortest text which is read back as the same value
+        (FieldDataViewFormat::FullPrecision, ScalarValue::F32(v), _) => Some(format!("{:?}", v)),
+        (FieldDataViewFormat::FullPrecision, ScalarValue::F64(v), _) => Some(format!("{:?}", v)),
+        _ => None,
+    }
+}
+
+// a value typed by the user in the format of the field
+pub fn parse_scalar(text: &str, def: &FieldProtoPtr, format: FieldDataViewFormat) -> Result<ScalarValue, String> {
+    let text = text.trim();
+    let radix = match format {
+        FieldDataViewFormat::Hex => Some((["0x", "0X"], 16)),
+        FieldDataViewFormat::Octal => Some((["0o", "0"], 8)),
+        FieldDataViewFormat::Binary => Some((["0b", "0B"], 2)),
+        _ => None,
+    };
+    let number = match (radix, format) {
+        (Some((prefixes, radix)), _) if !matches!(def.default(), FieldValue::SCALAR(ScalarValue::ENUM(_))) || !text.starts_with(char::is_alphabetic) =>
+            Some(parse_radix(text, &prefixes, radix).ok_or_else(|| format!("\"{}\" is not a number in the {} format", text, format.name()))?),
+        (_, FieldDataViewFormat::UnixSeconds) => parse_date(text, 1), // or a number of seconds
+        (_, FieldDataViewFormat::UnixMillis) => parse_date(text, 1000),
+        _ => None,
+    };
+    // the number is checked for the range of the field type as a decimal value
+    let text = number.map_or(text.to_string(), |v| v.to_string());
+    match text_to_value(&text, def)? {
+        FieldValue::SCALAR(value) => Ok(value),
+        FieldValue::MESSAGE(_) => Err("a message cannot be typed".to_string()),
+    }
+}
+
+pub(crate) fn integer(value: &ScalarValue) -> Option<i128> {
+    match *value {
+        ScalarValue::I32(v) | ScalarValue::S32(v) | ScalarValue::SF32(v) => Some(v as i128),
+        ScalarValue::U32(v) | ScalarValue::UF32(v) => Some(v as i128),
+        ScalarValue::I64(v) | ScalarValue::S64(v) | ScalarValue::SF64(v) => Some(v as i128),
+        ScalarValue::U64(v) | ScalarValue::UF64(v) => Some(v as i128),
+        _ => None,
+    }
+}
+
+// negative numbers are shown with the minus sign, not as two's complement
+fn with_sign(value: i128, to_string: impl Fn(u128) -> String) -> String {
+    if value < 0 { format!("-{}", to_string(value.unsigned_abs())) } else { to_string(value as u128) }
+}
+
+fn parse_radix(text: &str, prefixes: &[&str], radix: u32) -> Option<i128> {
+    let (negative, mut digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    if let Some(rest) = prefixes.iter().find_map(|prefix| digits.strip_prefix(prefix)).filter(|rest| !rest.is_empty()) {
+        digits = rest;
+    }
+    let value = i128::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+// years 0..=9999 only, other values are shown as numbers; per_second is a power of 10
+pub(crate) fn format_date(value: i128, per_second: i128) -> Option<String> {
+    let seconds = value.div_euclid(per_second);
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(i64::try_from(days).ok()?);
+    if !(0..=9999).contains(&year) { return None; }
+    let mut res = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60);
+    if per_second > 1 {
+        let digits = per_second.ilog10() as usize;
+        res += &format!(".{:0digits$}", value.rem_euclid(per_second));
+    }
+    Some(res + "Z")
+}
+
+// "2024-01-31T12:00:00Z", the time or its parts may be omitted, an offset like "+01:00" is converted to UTC
+pub(crate) fn parse_date(text: &str, per_second: i128) -> Option<i128> {
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00:00"));
+    let date: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = date[..] else { return None; };
+    let (year, month, day): (i64, i64, i64) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if year.to_string().len() > 4 || !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None; }
+
+    let (time, offset) = match time.find(['+', '-']) {
+        Some(pos) => (&time[..pos], parse_offset(&time[pos..])?),
+        None => (time, 0),
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut parts = [0i64; 3];
+    for (index, part) in time.split(':').enumerate() {
+        if index >= 3 { return None; }
+        parts[index] = part.parse().ok()?;
+    }
+    let [hour, minute, second] = parts;
+    if hour > 23 || minute > 59 || second > 60 { return None; }
+    let digits = per_second.ilog10() as usize;
+    let part: i128 = if fraction.is_empty() { 0 } else if fraction.len() <= digits && fraction.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{:0<digits$}", fraction).parse().ok()?
+    } else { return None; };
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some(seconds as i128 * per_second + part)
+}
+
+// "+01:00" to seconds
+fn parse_offset(text: &str) -> Option<i64> {
+    let (hours, minutes) = text[1..].split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 { return None; }
+    let offset = hours * 3600 + minutes * 60;
+    Some(if text.starts_with('-') { -offset } else { offset })
+}
+
+// days since 1970-01-01 to (year, month, day), the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod number_formats {
+    use super::*;
+    use crate::proto::ProtoData;
+    use FieldDataViewFormat::*;
+
+    fn field(name: &str) -> FieldProtoPtr {
+        let proto = ProtoData::new("message M { int32 i = 1; uint64 u = 2; sint32 s = 3; double d = 4; float f = 5; string t = 6; E e = 7; }\nenum E { A = 0; B = 1; }").unwrap().finalize().unwrap();
+        proto.get_message_definition("M").unwrap().fields.iter().find(|f| f.name() == name).unwrap().clone()
+    }
+
+    #[test]
+    fn integers() {
+        let def = field("i");
+        assert_eq!(formats(&def), [Formated, Hex, Octal, Binary, UnixSeconds, UnixMillis]);
+        assert_eq!(format_scalar(&ScalarValue::I32(255), Hex).unwrap(), "0xFF");
+        assert_eq!(format_scalar(&ScalarValue::I32(-255), Hex).unwrap(), "-0xFF");
+        assert_eq!(format_scalar(&ScalarValue::I32(8), Octal).unwrap(), "010");
+        assert_eq!(format_scalar(&ScalarValue::I32(5), Binary).unwrap(), "0b101");
+        assert_eq!(format_scalar(&ScalarValue::U64(u64::MAX), Hex).unwrap(), "0xFFFFFFFFFFFFFFFF");
+        assert_eq!(format_scalar(&ScalarValue::I32(5), Formated), None);
+
+        assert_eq!(parse_scalar("ff", &def, Hex).unwrap(), ScalarValue::I32(255));
+        assert_eq!(parse_scalar("-0xff", &def, Hex).unwrap(), ScalarValue::I32(-255));
+        assert_eq!(parse_scalar("17", &def, Octal).unwrap(), ScalarValue::I32(15));
+        assert_eq!(parse_scalar("0b101", &def, Binary).unwrap(), ScalarValue::I32(5));
+        assert_eq!(parse_scalar("101", &field("s"), Binary).unwrap(), ScalarValue::S32(5));
+        assert!(parse_scalar("fffffffff", &def, Hex).is_err()); // out of range
+        assert!(parse_scalar("12", &def, Binary).is_err());
+        assert!(parse_scalar("-1", &field("u"), Decimal).is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let def = field("u");
+        assert_eq!(format_scalar(&ScalarValue::U64(0), UnixSeconds).unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(format_scalar(&ScalarValue::U64(1709251199), UnixSeconds).unwrap(), "2024-02-29T23:59:59Z");
+        assert_eq!(format_scalar(&ScalarValue::I64(-1), UnixMillis).unwrap(), "1969-12-31T23:59:59.999Z");
+        assert_eq!(format_scalar(&ScalarValue::U64(u64::MAX), UnixSeconds), None);
+
+        assert_eq!(parse_scalar("2024-02-29T23:59:59Z", &def, UnixSeconds).unwrap(), ScalarValue::U64(1709251199));
+        assert_eq!(parse_scalar("2024-03-01", &def, UnixSeconds).unwrap(), ScalarValue::U64(1709251200));
+        assert_eq!(parse_scalar("1970-01-01 00:00:01.5", &def, UnixMillis).unwrap(), ScalarValue::U64(1500));
+        assert_eq!(parse_scalar("1709251200", &def, UnixSeconds).unwrap(), ScalarValue::U64(1709251200)); // as a number
+        assert!(parse_scalar("1969-12-31", &def, UnixSeconds).is_err()); // negative for uint64
+        assert!(parse_scalar("2024-13-01", &def, UnixSeconds).is_err());
+    }
+
+    #[test]
+    fn floats_and_enums() {
+        assert_eq!(formats(&field("d")), [Formated, FullPrecision]);
+        assert_eq!(format_scalar(&ScalarValue::F64(0.1), FullPrecision).unwrap(), "0.1");
+        assert_eq!(format_scalar(&ScalarValue::F32(0.1), FullPrecision).unwrap(), "0.1");
+        assert_eq!(format_scalar(&ScalarValue::F64(1.0 / 3.0), FullPrecision).unwrap(), "0.3333333333333333");
+        assert_eq!(format_scalar(&ScalarValue::F64(1e300), FullPrecision).unwrap(), "1e300");
+        assert_eq!(parse_scalar("0.3333333333333333", &field("d"), FullPrecision).unwrap(), ScalarValue::F64(1.0 / 3.0));
+        assert_eq!(parse_scalar("1.0000000000000001e-1", &field("d"), FullPrecision).unwrap(), ScalarValue::F64(0.1));
+
+        let def = field("e");
+        assert_eq!(formats(&def), [Formated, Decimal, Hex]);
+        assert_eq!(format_scalar(&ScalarValue::ENUM(1), Decimal).unwrap(), "1");
+        assert_eq!(parse_scalar("B", &def, Decimal).unwrap(), ScalarValue::ENUM(1));
+        assert_eq!(parse_scalar("1", &def, Hex).unwrap(), ScalarValue::ENUM(1));
+        assert_eq!(formats(&field("t")), [Formated]);
+    }
+}
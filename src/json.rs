@@ -0,0 +1,809 @@
+use std::fmt::Write;
+use crate::proto::{FieldProtoPtr, MessageProtoPtr};
+use crate::wire::*;
+use crate::well_known;
+
+// Export to the canonical proto3 JSON mapping
+// https://protobuf.dev/programming-guides/json/
+
+const INDENT: usize = 2;
+
+pub fn message_to_json(msg: &MessageData) -> String {
+    let mut out = String::new();
+    write_message(&mut out, msg, 0);
+    out.push('\n');
+    out
+}
+
+// data selected in the editor: one value, an array of values, or an object for map entries
+pub fn range_to_json(root: &MessageData, path: &FieldPath, amount: usize) -> Option<String> {
+    let def = root.get_field_definition(path)?;
+    let (last, parent_path) = path.0.split_last()?;
+    let parent = root.get_submessage(parent_path)?;
+    let default = def.default();
+    let values: Vec<&FieldValue> = if amount == 0 {
+        vec![&default]
+    } else {
+        (last.index..last.index + amount).filter_map(|index| parent.get_field(&[(last.id, index).into()])).map(|f| &f.value).collect()
+    };
+
+    let mut out = String::new();
+    if is_map(&def) || values.len() > 1 {
+        write_field(&mut out, &def, &values, 0);
+    } else {
+        write_value(&mut out, &def, values.first()?, 0);
+    }
+    out.push('\n');
+    Some(out)
+}
+
+// json_name of a field: underscores removed, the next letter capitalized
+pub fn lower_camel_case(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            res.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+// an extension is named by its fully qualified name in brackets
+fn json_name(def: &FieldProtoPtr) -> String {
+    match def.extension() {
+        Some(name) => format!("[{}]", name),
+        None => lower_camel_case(&def.name()),
+    }
+}
+
+pub fn is_map(def: &FieldProtoPtr) -> bool {
+    def.is_message() && def.typename().contains(',')
+}
+
+fn write_message(out: &mut String, msg: &MessageData, indent: usize) {
+    let mut first = true;
+    for def in &msg.def.fields {
+        // unknown fields have another definition, so they are skipped
+        let values: Vec<&FieldValue> = msg.fields.iter().
+            filter(|f| f.def.id() == def.id() && !matches!(f.value, FieldValue::SCALAR(ScalarValue::DELETED))).
+            map(|f| &f.value).collect();
+        if values.is_empty() { continue; }
+
+        out.push_str(if first { "{\n" } else { ",\n" });
+        first = false;
+        write!(out, "{:indent$}\"{}\": ", "", json_name(def), indent = indent + INDENT).unwrap();
+        write_field(out, def, &values, indent + INDENT);
+    }
+    if first {
+        out.push_str("{}");
+    } else {
+        write!(out, "\n{:indent$}}}", "", indent = indent).unwrap();
+    }
+}
+
+// all values of a field
+fn write_field(out: &mut String, def: &FieldProtoPtr, values: &[&FieldValue], indent: usize) {
+    if is_map(def) {
+        if values.is_empty() {
+            out.push_str("{}");
+            return;
+        }
+        out.push_str("{\n");
+        for (i, entry) in values.iter().enumerate() {
+            if let FieldValue::MESSAGE(entry) = entry {
+                if i > 0 { out.push_str(",\n"); }
+                let key_def = &entry.def.fields[0];
+                let value_def = &entry.def.fields[1];
+                let (key_default, value_default) = (key_def.default(), value_def.default());
+                let key = last_value(entry, key_def).unwrap_or(&key_default);
+                let value = last_value(entry, value_def).unwrap_or(&value_default);
+                write!(out, "{:indent$}", "", indent = indent + INDENT).unwrap();
+                match key {
+                    FieldValue::SCALAR(ScalarValue::STR(s)) => write_string(out, s),
+                    FieldValue::SCALAR(scalar) => write_string(out, &scalar.to_string()),
+                    FieldValue::MESSAGE(_) => write_string(out, ""),
+                }
+                out.push_str(": ");
+                write_value(out, value_def, value, indent + INDENT);
+            }
+        }
+        write!(out, "\n{:indent$}}}", "", indent = indent).unwrap();
+    } else if def.repeated() {
+        if def.is_message() && !values.is_empty() {
+            out.push_str("[\n");
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 { out.push_str(",\n"); }
+                write!(out, "{:indent$}", "", indent = indent + INDENT).unwrap();
+                write_value(out, def, value, indent + INDENT);
+            }
+            write!(out, "\n{:indent$}]", "", indent = indent).unwrap();
+        } else {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 { out.push_str(", "); }
+                write_value(out, def, value, indent);
+            }
+            out.push(']');
+        }
+    } else if let Some(value) = values.last() { // the last value wins for a non-repeated field
+        write_value(out, def, value, indent);
+    }
+}
+
+fn last_value<'a>(msg: &'a MessageData, def: &FieldProtoPtr) -> Option<&'a FieldValue> {
+    msg.fields.iter().rev().find(|f| f.def.id() == def.id()).map(|f| &f.value)
+}
+
+fn write_value(out: &mut String, def: &FieldProtoPtr, value: &FieldValue, indent: usize) {
+    match value {
+        // the decoded value of Any is kept as bytes
+        FieldValue::MESSAGE(msg) if def.any_value() || !def.is_message() => write_string(out, &base64_encode(&well_known::packed_value(msg))),
+        FieldValue::MESSAGE(msg) => write_message(out, msg, indent),
+        FieldValue::SCALAR(scalar) => write_scalar(out, def, scalar),
+    }
+}
+
+fn write_scalar(out: &mut String, def: &FieldProtoPtr, value: &ScalarValue) {
+    match value {
+        ScalarValue::I32(v) | ScalarValue::S32(v) | ScalarValue::SF32(v) => write!(out, "{}", v).unwrap(),
+        ScalarValue::U32(v) | ScalarValue::UF32(v) => write!(out, "{}", v).unwrap(),
+        // 64-bit integers are strings, javascript cannot keep them in numbers
+        ScalarValue::I64(v) | ScalarValue::S64(v) | ScalarValue::SF64(v) => write!(out, "\"{}\"", v).unwrap(),
+        ScalarValue::U64(v) | ScalarValue::UF64(v) => write!(out, "\"{}\"", v).unwrap(),
+        ScalarValue::F32(v) => write_float(out, *v as f64, v.to_string()),
+        ScalarValue::F64(v) => write_float(out, *v, v.to_string()),
+        ScalarValue::BOOL(v) => write!(out, "{}", v).unwrap(),
+        ScalarValue::ENUM(v) => {
+            if let Some(name) = def.get_enum_name_by_index(*v) {
+                write_string(out, name);
+            } else {
+                write!(out, "{}", v).unwrap();
+            }
+        }
+        ScalarValue::STR(v) => write_string(out, v),
+        ScalarValue::BYTES(v) => write_string(out, &base64_encode(v)),
+        ScalarValue::UNKNOWN(_, _) | ScalarValue::DELETED => out.push_str("null"),
+    }
+}
+
+fn write_float(out: &mut String, value: f64, text: String) {
+    if value.is_nan() {
+        out.push_str("\"NaN\"");
+    } else if value.is_infinite() {
+        out.push_str(if value > 0.0 { "\"Infinity\"" } else { "\"-Infinity\"" });
+    } else {
+        out.push_str(&text);
+    }
+}
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut res = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62, // standard and URL-safe alphabets
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(res)
+}
+
+
+// Import from the proto3 JSON format
+
+#[derive(Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String), // converted when the field type is known
+    Str(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn parse(text: &str) -> Result<JsonValue, String> {
+        let mut parser = JsonParser { text, pos: 0 };
+        let value = parser.parse_value()?;
+        parser.skip_spaces();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected data after the end"));
+        }
+        Ok(value)
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "bool",
+            JsonValue::Number(_) => "number",
+            JsonValue::Str(_) => "string",
+            JsonValue::Array(_) => "array",
+            JsonValue::Object(_) => "object",
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize, // in bytes
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("line {}, column {}: {}", line, column, message)
+    }
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+    fn skip_spaces(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_spaces();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    self.skip_spaces();
+                    if self.peek() != Some('"') { return Err(self.error("expected a member name")); }
+                    let name = self.parse_string()?;
+                    self.expect(':')?;
+                    members.push((name, self.parse_value()?));
+                    self.skip_spaces();
+                    match self.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(JsonValue::Object(members)),
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_spaces();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                loop {
+                    items.push(self.parse_value()?);
+                    self.skip_spaces();
+                    match self.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(JsonValue::Array(items)),
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('"') => Ok(JsonValue::Str(self.parse_string()?)),
+            Some('-' | '0'..='9') => {
+                let start = self.pos;
+                while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+                    self.pos += 1;
+                }
+                let text = &self.text[start..self.pos];
+                if !is_number(text) {
+                    self.pos = start;
+                    return Err(self.error(&format!("wrong number {}", text)));
+                }
+                Ok(JsonValue::Number(text.to_string()))
+            }
+            _ => {
+                for (word, value) in [("null", JsonValue::Null), ("true", JsonValue::Bool(true)), ("false", JsonValue::Bool(false))] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+        }
+    }
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.pos += 1; // opening quote
+        let mut res = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(res),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.parse_hex4()?;
+                            if (0xD800..0xDC00).contains(&code) && self.text[self.pos..].starts_with("\\u") { // surrogate pair
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xDC00..=0xDFFF).contains(&low) {
+                                    return Err(self.error("wrong surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("wrong unicode character"))?
+                        }
+                        _ => return Err(self.error("wrong escape sequence")),
+                    };
+                    res.push(c);
+                }
+                Some(c) => res.push(c),
+            }
+        }
+    }
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let hex = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error("wrong escape sequence"))?;
+        let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("wrong escape sequence"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+// the JSON number syntax: -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?
+fn is_number(text: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let mut rest = text.strip_prefix('-').unwrap_or(text);
+    let count = digits(rest);
+    if count == 0 || (count > 1 && rest.starts_with('0')) { return false; }
+    rest = &rest[count..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let count = digits(fraction);
+        if count == 0 { return false; }
+        rest = &fraction[count..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let count = digits(exponent);
+        if count == 0 { return false; }
+        rest = &exponent[count..];
+    }
+    rest.is_empty()
+}
+
+// create the message data described by JSON text, errors contain JSON path to the wrong value
+pub fn json_to_message(text: &str, def: MessageProtoPtr) -> Result<MessageData, String> {
+    let value = JsonValue::parse(text)?;
+    value_to_message(&value, def, "$")
+}
+
+fn value_to_message(value: &JsonValue, def: MessageProtoPtr, path: &str) -> Result<MessageData, String> {
+    let JsonValue::Object(members) = value else {
+        return Err(format!("{}: expected {}, found {}", path, def.name, value.kind()));
+    };
+    let mut fields = vec![];
+    for (name, value) in members {
+        let field_path = format!("{}.{}", path, name);
+        let field_def = def.fields.iter().
+            find(|f| json_name(f) == *name || f.name() == *name).
+            ok_or_else(|| format!("{}: unknown field of {}", field_path, def.name))?;
+        if *value == JsonValue::Null { continue; } // default value
+
+        if is_map(field_def) {
+            let JsonValue::Object(entries) = value else {
+                return Err(format!("{}: expected map<{}>, found {}", field_path, field_def.typename(), value.kind()));
+            };
+            let entry_def = field_def.message_proto().unwrap();
+            for (key, value) in entries {
+                let entry_path = format!("{}[\"{}\"]", field_path, key);
+                let key_def = &entry_def.fields[0];
+                let key_value = match key_def.default() { // keys are always strings in JSON
+                    FieldValue::SCALAR(ScalarValue::BOOL(_)) => match key.as_str() {
+                        "true" => ScalarValue::BOOL(true),
+                        "false" => ScalarValue::BOOL(false),
+                        _ => return Err(format!("{}: expected bool, found string", entry_path)),
+                    },
+                    _ => value_to_scalar(&JsonValue::Str(key.clone()), key_def, &entry_path)?,
+                };
+                let value_def = &entry_def.fields[1];
+                let entry_fields = vec![
+                    FieldData::new(key_def.clone(), FieldValue::SCALAR(key_value)),
+                    FieldData::new(value_def.clone(), value_to_field(value, value_def, &entry_path)?),
+                ];
+                let entry = MessageData { def: entry_def.clone(), fields: entry_fields };
+                fields.push(FieldData::new(field_def.clone(), FieldValue::MESSAGE(entry.into())));
+            }
+        } else if field_def.repeated() {
+            let JsonValue::Array(items) = value else {
+                return Err(format!("{}: expected array of {}, found {}", field_path, field_def.typename(), value.kind()));
+            };
+            for (i, item) in items.iter().enumerate() {
+                let value = value_to_field(item, field_def, &format!("{}[{}]", field_path, i))?;
+                fields.push(FieldData::new(field_def.clone(), value));
+            }
+        } else {
+            fields.push(FieldData::new(field_def.clone(), value_to_field(value, field_def, &field_path)?));
+        }
+    }
+    Ok(MessageData { def, fields })
+}
+
+fn value_to_field(value: &JsonValue, def: &FieldProtoPtr, path: &str) -> Result<FieldValue, String> {
+    if let Some(msg_def) = def.message_proto() {
+        Ok(FieldValue::MESSAGE(value_to_message(value, msg_def, path)?.into()))
+    } else {
+        Ok(FieldValue::SCALAR(value_to_scalar(value, def, path)?))
+    }
+}
+
+fn value_to_scalar(value: &JsonValue, def: &FieldProtoPtr, path: &str) -> Result<ScalarValue, String> {
+    let error = || format!("{}: expected {}, found {}", path, def.typename(), value.kind());
+    let FieldValue::SCALAR(default) = def.default() else { return Err(error()); };
+    let int = |min: i128, max: i128| integer_value(value, min, max).ok_or_else(error);
+    Ok(match default {
+        ScalarValue::I32(_) => ScalarValue::I32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::S32(_) => ScalarValue::S32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::SF32(_) => ScalarValue::SF32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::U32(_) => ScalarValue::U32(int(0, u32::MAX as i128)? as u32),
+        ScalarValue::UF32(_) => ScalarValue::UF32(int(0, u32::MAX as i128)? as u32),
+        ScalarValue::I64(_) => ScalarValue::I64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::S64(_) => ScalarValue::S64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::SF64(_) => ScalarValue::SF64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::U64(_) => ScalarValue::U64(int(0, u64::MAX as i128)? as u64),
+        ScalarValue::UF64(_) => ScalarValue::UF64(int(0, u64::MAX as i128)? as u64),
+        ScalarValue::F32(_) => ScalarValue::F32(float_value(value).ok_or_else(error)? as f32),
+        ScalarValue::F64(_) => ScalarValue::F64(float_value(value).ok_or_else(error)?),
+        ScalarValue::BOOL(_) => match value {
+            JsonValue::Bool(v) => ScalarValue::BOOL(*v),
+            _ => return Err(error()),
+        },
+        ScalarValue::ENUM(_) => match value {
+            JsonValue::Str(name) => ScalarValue::ENUM(def.get_enum_index_by_name(name).ok_or_else(error)?),
+            _ => ScalarValue::ENUM(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        },
+        ScalarValue::STR(_) => match value {
+            JsonValue::Str(v) => ScalarValue::STR(v.clone()),
+            _ => return Err(error()),
+        },
+        ScalarValue::BYTES(_) => match value {
+            JsonValue::Str(v) => ScalarValue::BYTES(base64_decode(v).ok_or_else(error)?),
+            _ => return Err(error()),
+        },
+        ScalarValue::UNKNOWN(_, _) | ScalarValue::DELETED => return Err(error()),
+    })
+}
+
+// integers can be numbers or strings, also in exponential notation (1.5e3);
+// the digits are read exactly, not through f64, so 64-bit values keep all digits
+fn integer_value(value: &JsonValue, min: i128, max: i128) -> Option<i128> {
+    let text = match value {
+        JsonValue::Number(text) | JsonValue::Str(text) => text.as_str(),
+        _ => return None,
+    };
+    if !is_number(text) { return None; }
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+        None => (text, 0),
+    };
+    let (int, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut res = format!("{}{}", int, fraction).parse::<i128>().ok()?;
+    let scale = exponent - fraction.len() as i64;
+    if res != 0 {
+        let power = 10i128.checked_pow(u32::try_from(scale.unsigned_abs()).ok()?)?;
+        res = if scale >= 0 { res.checked_mul(power)? } else if res % power == 0 { res / power } else { return None; };
+    }
+    let res = if negative { -res } else { res };
+    if res < min || res > max { None } else { Some(res) }
+}
+
+fn float_value(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(text) => text.parse().ok(),
+        JsonValue::Str(text) => match text.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            _ => text.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod export {
+    use super::*;
+    use crate::proto::ProtoData;
+    use crate::typedefs::PbReader;
+
+    fn make_data(proto_str: &str) -> (ProtoData, MessageData) {
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let root_msg = proto.auto_detect_root_message().unwrap();
+        let mut limit = 0;
+        let mut read = PbReader::new([].as_slice());
+        let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
+        (proto, data)
+    }
+
+    fn set(data: &mut MessageData, path: &[(i32, usize)], value: ScalarValue) {
+        let path: Vec<FieldPos> = path.iter().map(|p| (*p).into()).collect();
+        data.add_field(&path).unwrap().value = FieldValue::SCALAR(value);
+    }
+
+    #[test]
+    fn scalars() {
+        let (_, mut data) = make_data(r#"
+message M {
+  int32 f_int = 1;
+  int64 f_long = 2;
+  bytes f_bytes = 3;
+  E f_enum = 4;
+  string f_str = 5;
+  double f_nan = 6;
+  bool f_bool = 7;
+  repeated uint32 ids = 8;
+  sint64 unset = 9;
+}
+enum E { ZERO = 0; ONE = 1; }
+"#);
+        set(&mut data, &[(1, 0)], ScalarValue::I32(-5));
+        set(&mut data, &[(2, 0)], ScalarValue::I64(1234567890123));
+        set(&mut data, &[(3, 0)], ScalarValue::BYTES(vec![1, 2, 3, 4]));
+        set(&mut data, &[(4, 0)], ScalarValue::ENUM(1));
+        set(&mut data, &[(5, 0)], ScalarValue::STR("a\"b\n".to_string()));
+        set(&mut data, &[(6, 0)], ScalarValue::F64(f64::NAN));
+        set(&mut data, &[(7, 0)], ScalarValue::BOOL(true));
+        set(&mut data, &[(8, 0)], ScalarValue::U32(1));
+        set(&mut data, &[(8, 1)], ScalarValue::U32(2));
+
+        assert_eq!(message_to_json(&data), r#"{
+  "fInt": -5,
+  "fLong": "1234567890123",
+  "fBytes": "AQIDBA==",
+  "fEnum": "ONE",
+  "fStr": "a\"b\n",
+  "fNan": "NaN",
+  "fBool": true,
+  "ids": [1, 2]
+}
+"#);
+    }
+
+    #[test]
+    fn messages_and_maps() {
+        let (_, mut data) = make_data(r#"
+message Root {
+  repeated Item items = 1;
+  map<string, Item> by_name = 2;
+  map<int32, string> names = 3;
+}
+message Item { int32 id = 1; }
+"#);
+        data.add_field(&[(1, 0).into()]).unwrap();
+        set(&mut data, &[(1, 0), (1, 0)], ScalarValue::I32(1));
+        data.add_field(&[(1, 1).into()]).unwrap();
+        data.add_field(&[(2, 0).into()]).unwrap();
+        set(&mut data, &[(2, 0), (1, 0)], ScalarValue::STR("a".to_string()));
+        data.add_field(&[(2, 0).into(), (2, 0).into()]).unwrap();
+        set(&mut data, &[(2, 0), (2, 0), (1, 0)], ScalarValue::I32(3));
+        data.add_field(&[(3, 0).into()]).unwrap();
+        set(&mut data, &[(3, 0), (1, 0)], ScalarValue::I32(7));
+        set(&mut data, &[(3, 0), (2, 0)], ScalarValue::STR("seven".to_string()));
+        data.add_field(&[(3, 1).into()]).unwrap(); // default key and value
+
+        assert_eq!(message_to_json(&data), r#"{
+  "items": [
+    {
+      "id": 1
+    },
+    {}
+  ],
+  "byName": {
+    "a": {
+      "id": 3
+    }
+  },
+  "names": {
+    "7": "seven",
+    "0": ""
+  }
+}
+"#);
+        assert_eq!(range_to_json(&data, &[(1, 0)].into(), 1).unwrap(), "{\n  \"id\": 1\n}\n");
+        assert_eq!(range_to_json(&data, &[(1, 0), (1, 0)].into(), 1).unwrap(), "1\n");
+        assert_eq!(range_to_json(&data, &[(3, 1)].into(), 1).unwrap(), "{\n  \"0\": \"\"\n}\n");
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(lower_camel_case("foo_bar_baz"), "fooBarBaz");
+    }
+}
+
+#[cfg(test)]
+mod import {
+    use super::*;
+    use crate::proto::ProtoData;
+
+    fn proto() -> ProtoData {
+        ProtoData::new(r#"
+message Root {
+  int32 f_int = 1;
+  repeated int64 longs = 2;
+  E f_enum = 3;
+  bytes f_bytes = 4;
+  repeated Item items = 5;
+  map<string, int32> counts = 6;
+  double f_double = 7;
+}
+message Item { string name = 1; bool ok = 2; }
+enum E { ZERO = 0; ONE = 1; }
+"#).unwrap().finalize().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let proto = proto();
+        let text = r#"{
+  "fInt": -5,
+  "longs": ["1", "-9223372036854775808"],
+  "fEnum": "ONE",
+  "fBytes": "AQIDBA==",
+  "items": [
+    {
+      "name": "a\"é\n",
+      "ok": true
+    },
+    {}
+  ],
+  "counts": {
+    "x": 1,
+    "y": 2
+  },
+  "fDouble": "-Infinity"
+}
+"#;
+        let data = json_to_message(text, proto.get_message_definition("Root").unwrap()).unwrap();
+        assert_eq!(message_to_json(&data), text);
+
+        // proto names and numbers instead of strings are accepted too
+        let data = json_to_message(r#"{"f_int": "7", "longs": [1e3], "f_enum": 1, "f_bytes": "AQID-_8"}"#, proto.get_message_definition("Root").unwrap()).unwrap();
+        assert_eq!(message_to_json(&data), "{\n  \"fInt\": 7,\n  \"longs\": [\"1000\"],\n  \"fEnum\": \"ONE\",\n  \"fBytes\": \"AQID+/8=\"\n}\n");
+
+        let mut buf = vec![];
+        data.write(&mut buf, &proto, data.def.clone()).unwrap();
+        assert_eq!(buf, [0x08, 0x07, 0x10, 0xE8, 0x07, 0x18, 0x01, 0x22, 0x05, 0x01, 0x02, 0x03, 0xFB, 0xFF]);
+
+        // integers above 2^53 keep all digits, as numbers and as strings
+        let data = json_to_message(r#"{"longs": [9007199254740993, "-9007199254740995", 9.007199254740997e15, "1234567890123456780e-1"]}"#, proto.get_message_definition("Root").unwrap());
+        assert_eq!(message_to_json(&data.unwrap()), "{\n  \"longs\": [\"9007199254740993\", \"-9007199254740995\", \"9007199254740997\", \"123456789012345678\"]\n}\n");
+    }
+
+    #[test]
+    fn errors() {
+        let proto = proto();
+        let root = || proto.get_message_definition("Root").unwrap();
+        assert_eq!(json_to_message(r#"{"fInt": 1.5}"#, root()).err().unwrap(), "$.fInt: expected int32, found number");
+        assert_eq!(json_to_message(r#"{"fInt": 3000000000}"#, root()).err().unwrap(), "$.fInt: expected int32, found number");
+        assert_eq!(json_to_message(r#"{"items": [{}, {"ok": "yes"}]}"#, root()).err().unwrap(), "$.items[1].ok: expected bool, found string");
+        assert_eq!(json_to_message(r#"{"items": {}}"#, root()).err().unwrap(), "$.items: expected array of Item, found object");
+        assert_eq!(json_to_message(r#"{"counts": {"a": "b"}}"#, root()).err().unwrap(), "$.counts[\"a\"]: expected int32, found string");
+        assert_eq!(json_to_message(r#"{"fEnum": "TWO"}"#, root()).err().unwrap(), "$.fEnum: expected E, found string");
+        assert_eq!(json_to_message(r#"{"other": 1}"#, root()).err().unwrap(), "$.other: unknown field of Root");
+        assert_eq!(json_to_message("[]", root()).err().unwrap(), "$: expected Root, found array");
+        assert_eq!(json_to_message("{\n  \"fInt\": 1,\n  \"longs\" []\n}", root()).err().unwrap(), "line 3, column 11: expected ':'");
+        assert_eq!(json_to_message("{} 1", root()).err().unwrap(), "line 1, column 4: unexpected data after the end");
+        assert_eq!(json_to_message(r#"{"s": "\uD800\u0041"}"#, root()).err().unwrap(), "line 1, column 20: wrong surrogate pair");
+        assert_eq!(json_to_message(r#"{"fInt": 1-2}"#, root()).err().unwrap(), "line 1, column 10: wrong number 1-2");
+        for number in ["01", "1.", ".5", "1e", "-", "1e+-2", "2.5.1"] {
+            assert!(json_to_message(&format!(r#"{{"fDouble": {}}}"#, number), root()).is_err(), "{}", number);
+        }
+        assert_eq!(json_to_message(r#"{"longs": ["1-2"]}"#, root()).err().unwrap(), "$.longs[0]: expected int64, found string");
+        assert_eq!(json_to_message(r#"{"fInt": 1.5e0}"#, root()).err().unwrap(), "$.fInt: expected int32, found number");
+        assert!(json_to_message(r#"{"fInt": null}"#, root()).unwrap().fields.is_empty());
+    }
+
+    #[test]
+    fn extensions() {
+        let proto = ProtoData::new("syntax = \"proto2\";\npackage pk;\nmessage M { optional int32 a = 1; extensions 100 to 199; }\nextend M { optional int32 ext = 100; }").unwrap().finalize().unwrap();
+        let def = proto.get_message_definition("pk.M").unwrap();
+        let text = "{\n  \"a\": 1,\n  \"[pk.ext]\": 5\n}\n";
+        assert_eq!(message_to_json(&json_to_message(text, def.clone()).unwrap()), text);
+    }
+
+    #[test]
+    fn base64() {
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foob", &[0xFB, 0xFF]] {
+            assert_eq!(base64_decode(&base64_encode(data)).unwrap(), data);
+        }
+        assert!(base64_decode("Zm9v!").is_none());
+    }
+}
@@ -1,11 +1,26 @@
 #![allow(warnings)]
 
 mod proto;
+mod proto_parser;
+mod descriptor;
 mod wire;
 mod typedefs;
 mod view;
 mod trz;
 mod text_edit;
+mod raw;
+mod json;
+mod text_format;
+mod path;
+mod cli;
+mod search;
+mod stream;
+mod repair;
+mod binary;
+mod format;
+mod well_known;
+mod filter;
+mod check;
 
 use std::string::String;
 use crate::ScalarValue::STR;
@@ -18,9 +33,9 @@ use std::path::PathBuf;
 use std::process::exit;
 use crossterm::*;
 use crossterm::style::{Color, Colored, Colors, ContentStyle, Stylize};
-use crate::view::{CommandResult, CommentVisibility, FieldOrder, LayoutConfig, LayoutType, Layouts, ScreenLine, ScreenLines, IndentsCalc, TextStyle, UserCommand, MARGIN_LEFT, MARGIN_RIGHT};
+use crate::view::{CommandResult, CommentVisibility, FieldOrder, LayoutConfig, LayoutParams, LayoutType, Layouts, ScreenLine, ScreenLines, IndentsCalc, TextStyle, UserCommand, MARGIN_LEFT, MARGIN_RIGHT};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 //#![cfg(feature = "bracketed-paste")]
 use crossterm::{
@@ -32,9 +47,11 @@ use crossterm::{
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
-use pest::Lines;
-use crate::proto::{FieldProtoPtr, MessageProto, ProtoData, ProtoFile};
+use crate::proto::{FieldProtoPtr, MessageProto, MessageProtoPtr, ProtoData, ProtoFile};
 use crate::typedefs::{PbReader};
+use crate::trz::{Change, ChangeType, History};
+use crate::search::Search;
+use crate::filter::{Filter, RepeatedEditorConfig, SortKey};
 use crate::view::FieldOrder::Proto;
 use crate::view::UserCommand::{ChangeFieldOrder, CollapsedToggle, DeleteData, End, Home, InsertData, ScrollHorizontally, ScrollSibling, ScrollToBottom, ScrollVertically, Exit, KeyPress};
 use crate::wire::FieldValue::SCALAR;
@@ -44,17 +61,36 @@ const USE_ALTERNATIVE_SCREEN: bool = false;
 // 0-hide top line, 1-show
 const TOP_LINE: u16 = 1;
 
+// lines of the binary pane at the bottom, when shown
+const BINARY_PANE: u16 = 4;
+
+// lines of the diagnostics pane: the number of problems and the list
+const DIAGNOSTICS_PANE: u16 = 6;
 
-struct RepeatedEditorConfig {
-    sort_by: Option<i32>, // field index for sort data
-    limit: Option<usize>, // lines count available for the editor
-    vertical: bool, // field names in the left column
-    columns: u16, // 0 to autofill all available space
-}
 
 // UpperUilayer: confirmations (CtrlC exit,etc.), enum/oneof lists
 
 
+// the filter of a repeated field typed in the top line
+struct FilterPrompt {
+    path: FieldPath, // of the repeated field
+    text: String,
+    error: String,
+}
+
+// the member of a oneof picked in the top line
+struct CasePicker {
+    path: FieldPath, // of the shown member
+    cases: Vec<FieldProtoPtr>, // all members of the oneof
+    selected: usize,
+}
+
+// the problems of the data listed in a pane below the data, the selected one is shown
+struct Diagnostics {
+    problems: Vec<check::Problem>,
+    selected: usize,
+}
+
 #[derive(Default)]
 struct Selection {
     // current active layout index
@@ -78,6 +114,13 @@ struct App {
     pub layouts: Layouts,
     pub layout_config: LayoutConfig,
     pub selected: Selection,
+    pub history: History,
+    pub search: Search,
+    pub filter: Option<FilterPrompt>,
+    pub case_picker: Option<CasePicker>,
+    pub diagnostics: Option<Diagnostics>,
+    pub message: Option<String>, // shown in the top line until the next key
+    pub export: Option<(PathBuf, String)>, // the existing JSON file and the text for it, written if the user agrees
     pub need_update: bool,
     pub need_update_layout_height: bool,
 }
@@ -110,6 +153,13 @@ impl App {
             layouts,
             layout_config,
             selected: Selection::default(),
+            history: History::default(),
+            search: Search::default(),
+            filter: None,
+            case_picker: None,
+            diagnostics: None,
+            message: None,
+            export: None,
             need_update: true,
             need_update_layout_height: true,
             test_mode: false,
@@ -134,6 +184,13 @@ impl App {
             layouts,
             layout_config,
             selected: Selection::default(),
+            history: History::default(),
+            search: Search::default(),
+            filter: None,
+            case_picker: None,
+            diagnostics: None,
+            message: None,
+            export: None,
             need_update: true,
             need_update_layout_height: true,
             test_mode: true,
@@ -204,6 +261,27 @@ impl App {
 
     pub fn on_key(&mut self, event: KeyEvent) -> io::Result<bool> {
         if event.kind != KeyEventKind::Press { return Ok(true); }
+        if self.message.take().is_some() { self.need_update = true; }
+        if let Some((path, text)) = self.export.take() { // the answer to the prompt of export_selected
+            self.message = Some(if event.code == KeyCode::Char('y') { Self::write_export(&path, &text) } else { "not exported".to_string() });
+            return Ok(true);
+        }
+        if self.search.prompt {
+            self.on_search_key(event);
+            return Ok(true);
+        }
+        if self.filter.is_some() {
+            self.on_filter_key(event);
+            return Ok(true);
+        }
+        if self.case_picker.is_some() {
+            self.on_case_key(event)?;
+            return Ok(true);
+        }
+        if self.diagnostics.is_some() {
+            self.on_diagnostics_key(event);
+            return Ok(true);
+        }
 
         let quit_app =
             match event.code {
@@ -214,10 +292,7 @@ impl App {
                         self.run_command(Exit)?;
                         self.layouts.save_document(&self.data)?
                     }
-                    3 => {
-                        // change self.layout_config.format;
-                        todo!();
-                    }
+                    3 => self.run_command(UserCommand::ChangeFormat)?,
                     4 => {
                         let new_order =
                             if event.modifiers.contains(KeyModifiers::SHIFT) { self.layout_config.field_order.prev() } else { self.layout_config.field_order.next() };
@@ -231,15 +306,19 @@ impl App {
                         self.need_update_layout_height = true;
                         false
                     }
+                    7 => self.start_diagnostics()?,
                     10 => self.run_command(Exit)?,
                     _ => false
                 },
+                KeyCode::Esc if self.editing_well_known() => self.run_command(UserCommand::CancelEdit)?,
                 KeyCode::Esc => self.run_command(Exit)?,
                 KeyCode::Enter => self.run_command(CollapsedToggle)?,
+                KeyCode::Up if event.modifiers.contains(KeyModifiers::ALT) => self.run_command(UserCommand::MoveField(-1))?,
+                KeyCode::Down if event.modifiers.contains(KeyModifiers::ALT) => self.run_command(UserCommand::MoveField(1))?,
                 KeyCode::Up => self.run_command(if event.modifiers.contains(KeyModifiers::CONTROL) { ScrollSibling(-1) } else { ScrollVertically(-1) })?,
                 KeyCode::Down => self.run_command(if event.modifiers.contains(KeyModifiers::CONTROL) { ScrollSibling(1) } else { ScrollVertically(1) })?,
-                KeyCode::PageUp => self.run_command(ScrollVertically(-((self.height - TOP_LINE - 1) as isize)))?,
-                KeyCode::PageDown => self.run_command(ScrollVertically((self.height - TOP_LINE - 1) as isize))?,
+                KeyCode::PageUp => self.run_command(ScrollVertically(-((self.data_bottom() - TOP_LINE - 1) as isize)))?,
+                KeyCode::PageDown => self.run_command(ScrollVertically((self.data_bottom() - TOP_LINE - 1) as isize))?,
                 KeyCode::Home => if event.modifiers.contains(KeyModifiers::CONTROL) {
                     self.selected = Selection::default();
                     self.need_update = true;
@@ -253,6 +332,23 @@ impl App {
                 KeyCode::Backspace => self.run_command(DeleteData(true))?,
                 KeyCode::Insert => self.run_command(InsertData)?,
 
+                KeyCode::Char('z') if event.modifiers.contains(KeyModifiers::CONTROL) => self.run_command(UserCommand::Undo)?,
+                KeyCode::Char('y') if event.modifiers.contains(KeyModifiers::CONTROL) => self.run_command(UserCommand::Redo)?,
+                KeyCode::Char('e') if event.modifiers.contains(KeyModifiers::CONTROL) => self.run_command(UserCommand::ExportData)?,
+                KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => self.run_command(UserCommand::ImportData)?,
+                KeyCode::Char('b') if event.modifiers.contains(KeyModifiers::CONTROL) => self.run_command(UserCommand::BinaryVisibility)?,
+                KeyCode::Char('n') if event.modifiers.contains(KeyModifiers::CONTROL) => self.find_next(true),
+                KeyCode::Char('p') if event.modifiers.contains(KeyModifiers::CONTROL) => self.find_next(false),
+                KeyCode::Char('/') if !self.is_editing() => self.start_search(),
+                KeyCode::Char('F') if self.view_hotkeys() => self.start_filter(),
+                KeyCode::Char('O') if self.view_hotkeys() => self.start_case_picker(),
+                KeyCode::Char(c @ ('T' | 'V' | '<' | '>' | 'S')) if self.view_hotkeys() => self.run_command(match c {
+                    'T' => UserCommand::TableTreeToggle,
+                    'V' => UserCommand::TableVariant,
+                    '<' => UserCommand::ChangeColumnCount(-1),
+                    '>' => UserCommand::ChangeColumnCount(1),
+                    _ => UserCommand::SortDataView,
+                })?,
                 KeyCode::Char(c) => { self.run_command(KeyPress(c))? }
 
                 _ => false
@@ -260,6 +356,195 @@ impl App {
         Ok(!quit_app)
     }
 
+    // keys typed into the search prompt
+    fn on_search_key(&mut self, event: KeyEvent) {
+        let mut pattern = self.search.pattern.clone();
+        match event.code {
+            KeyCode::Char(c) => pattern.push(c),
+            KeyCode::Backspace => { pattern.pop(); }
+            KeyCode::Enter => self.search.prompt = false,
+            KeyCode::Esc => self.search = Search::default(), // cancel and remove the highlight
+            KeyCode::Down => { self.find_next(true); }
+            KeyCode::Up => { self.find_next(false); }
+            _ => {}
+        }
+        if pattern != self.search.pattern {
+            self.search.set_pattern(&self.data, &self.layout_config.field_order, pattern);
+            self.show_search_hit();
+        }
+        self.need_update = true;
+    }
+
+    fn is_editing(&self) -> bool {
+        self.layouts.items.get(self.selected.layout).is_some_and(|item| item.get_text_edit_cursor().is_some())
+    }
+
+    // a well-known value is typed, Esc discards it
+    fn editing_well_known(&self) -> bool {
+        let layout = self.layouts.items.get(self.selected.layout).and_then(|item| item.layout.as_ref());
+        self.is_editing() && layout.is_some_and(|layout| layout.layout_type() == LayoutType::WellKnown)
+    }
+
+    // view hotkeys are letters, they are used on a field name or on a table header, where values are not typed
+    fn view_hotkeys(&self) -> bool {
+        let table = self.layouts.items.get(self.selected.layout).and_then(|item| item.layout.as_ref()).is_some_and(|layout| layout.layout_type() == LayoutType::Table);
+        !self.is_editing() && (self.selected.x == 0 || (table && self.selected.y == 0))
+    }
+
+    // the filter of the repeated field with the selected data, the current one is edited
+    fn start_filter(&mut self) -> bool {
+        if let Some((path, _)) = self.repeated_target() {
+            let text = self.repeated_config(&path).and_then(|config| config.filter.as_ref()).map_or(String::new(), |filter| filter.text.clone());
+            self.filter = Some(FilterPrompt { path, text, error: String::new() });
+            self.need_update = true;
+        }
+        false
+    }
+
+    fn on_filter_key(&mut self, event: KeyEvent) {
+        let Some(prompt) = &mut self.filter else { return; };
+        match event.code {
+            KeyCode::Char(c) => prompt.text.push(c),
+            KeyCode::Backspace => { prompt.text.pop(); }
+            KeyCode::Enter => self.apply_filter(),
+            KeyCode::Esc => self.filter = None,
+            _ => {}
+        }
+        self.need_update = true;
+    }
+
+    // an empty text removes the filter, the prompt stays open with an invalid one
+    fn apply_filter(&mut self) {
+        let Some(mut prompt) = self.filter.take() else { return; };
+        let Some(def) = self.data.get_field_definition(&prompt.path) else { return; };
+        let filter = if prompt.text.trim().is_empty() { None } else {
+            match Filter::parse(&prompt.text, &def) {
+                Ok(filter) => Some(filter),
+                Err(e) => {
+                    prompt.error = e;
+                    self.filter = Some(prompt);
+                    return;
+                }
+            }
+        };
+        let Some(parent) = self.data.get_submessage(&prompt.path.0[..prompt.path.0.len() - 1]) else { return; };
+        self.layout_config.repeated_config_mut(parent, def.id()).filter = filter;
+        self.rebuild_layouts(None);
+    }
+
+    // the picker of the oneof member shown by the selected layout
+    fn start_case_picker(&mut self) -> bool {
+        let Some(current) = self.layouts.items.get(self.selected.layout) else { return false; };
+        let Some(def) = self.data.get_field_definition(&current.path) else { return false; };
+        let Some(oneof) = def.oneof_name() else { return false; };
+        let Some(parent) = self.data.get_submessage(&current.path.0[..current.path.0.len() - 1]) else { return false; };
+        let cases: Vec<FieldProtoPtr> = parent.def.fields.iter().filter(|f| f.oneof_name().as_ref() == Some(oneof)).cloned().collect();
+        let selected = cases.iter().position(|f| f.id() == def.id()).unwrap_or(0);
+        self.case_picker = Some(CasePicker { path: current.path.clone(), cases, selected });
+        self.need_update = true;
+        false
+    }
+
+    fn on_case_key(&mut self, event: KeyEvent) -> io::Result<()> {
+        let Some(picker) = &mut self.case_picker else { return Ok(()); };
+        match event.code {
+            KeyCode::Left | KeyCode::Up => picker.selected = picker.selected.checked_sub(1).unwrap_or(picker.cases.len() - 1),
+            KeyCode::Right | KeyCode::Down => picker.selected = (picker.selected + 1) % picker.cases.len(),
+            KeyCode::Enter => self.switch_case()?,
+            KeyCode::Esc => self.case_picker = None,
+            _ => {}
+        }
+        self.need_update = true;
+        Ok(())
+    }
+
+    // the picked member replaces the set one with its default value, the old members are kept for undo
+    fn switch_case(&mut self) -> io::Result<()> {
+        let Some(picker) = self.case_picker.take() else { return Ok(()); };
+        let (def, old) = (picker.cases[picker.selected].clone(), picker.path.0.last().unwrap().id);
+        if def.id() == old { return Ok(()); }
+        self.run_command(Exit)?; // a typed value is saved first
+        let parent_path = &picker.path.0[..picker.path.0.len() - 1];
+        let Some(parent) = self.data.get_submessage(parent_path) else { return Ok(()); };
+        let pos = parent.fields.iter().position(|f| f.def.oneof_name() == def.oneof_name()).unwrap_or(parent.fields.len());
+        let field = FieldData::new(def.clone(), def.default());
+        let path = FieldPath(parent_path.to_vec()).add(FieldPos { id: def.id(), index: 0 });
+        self.after_command(CommandResult::ChangeData(Change { path: path.clone(), action: ChangeType::SwitchOneof(vec![(pos, field)]) }))?;
+        if let Some(index) = self.layouts.find_layout(&path) {
+            self.selected = Selection { layout: index, x: 0, y: 0 };
+        }
+        Ok(())
+    }
+
+    // the problems are found again each time the pane is opened, the data may be changed
+    fn start_diagnostics(&mut self) -> io::Result<bool> {
+        self.run_command(Exit)?; // a typed value is saved first
+        let problems = check::check(&self.data, &self.layouts.proto);
+        self.diagnostics = Some(Diagnostics { problems, selected: 0 });
+        self.show_problem();
+        self.need_update = true;
+        Ok(false)
+    }
+
+    fn on_diagnostics_key(&mut self, event: KeyEvent) {
+        let Some(diagnostics) = &mut self.diagnostics else { return; };
+        match event.code {
+            KeyCode::Up => diagnostics.selected = diagnostics.selected.saturating_sub(1),
+            KeyCode::Down => diagnostics.selected = (diagnostics.selected + 1).min(diagnostics.problems.len().saturating_sub(1)),
+            KeyCode::Enter | KeyCode::Esc | KeyCode::F(7) => self.diagnostics = None,
+            _ => {}
+        }
+        self.show_problem();
+        self.need_update = true;
+    }
+
+    // move the cursor to the field of the selected problem, or to its message if the field is missing
+    fn show_problem(&mut self) {
+        let Some(problem) = self.diagnostics.as_ref().and_then(|diagnostics| diagnostics.problems.get(diagnostics.selected)) else { return; };
+        self.show_path(&problem.path.clone(), false);
+    }
+
+    fn start_search(&mut self) -> bool {
+        let from = self.layouts.items.get(self.selected.layout).map(|item| item.path.clone()).unwrap_or_default();
+        self.search.start(&self.data, &self.layout_config.field_order, &from);
+        self.need_update = true;
+        false
+    }
+
+    fn find_next(&mut self, forward: bool) -> bool {
+        if self.search.step(forward).is_some() {
+            self.show_search_hit();
+            self.need_update = true;
+        }
+        false
+    }
+
+    // move the cursor to the current search hit
+    fn show_search_hit(&mut self) {
+        let Some(hit) = self.search.current_hit() else { return; };
+        self.show_path(&hit.path.clone(), hit.value);
+    }
+
+    // move the cursor to the field name or the value, collapsed messages are expanded
+    fn show_path(&mut self, path: &FieldPath, value: bool) {
+        if let Some(index) = self.layouts.reveal(&self.data, &self.layout_config, path) {
+            let item = &self.layouts.items[index];
+            let nested = item.path.0.len() < path.0.len(); // a field of a message in a table
+            let (x, y) = if value || nested {
+                item.cursor_at_value(path.0[item.path.0.len() - 1].index - item.path.0.last().unwrap().index)
+            } else { (0, 0) };
+            self.selected = Selection { layout: index, x, y };
+        }
+    }
+
+    // hits are found again after the data or the field order changed
+    fn refresh_search(&mut self) {
+        if !self.search.pattern.is_empty() {
+            let pattern = std::mem::take(&mut self.search.pattern);
+            self.search.set_pattern(&self.data, &self.layout_config.field_order, pattern);
+        }
+    }
+
     fn run_command(&mut self, command: UserCommand) -> io::Result<(bool)> {
         let result =
             match command {
@@ -272,6 +557,7 @@ impl App {
                     let proto = std::mem::take(&mut self.layouts.proto);
 
                     self.layouts = Layouts::new(&self.data, proto, &self.layout_config, self.layouts.file_path.clone(), self.layouts.width, self.layouts.height);
+                    self.refresh_search();
                     CommandResult::Redraw
                 }
                 ScrollVertically(delta) => {
@@ -282,6 +568,41 @@ impl App {
                     }
                     self.layouts.run_command(command, &self.data, &self.layout_config, &mut self.selected)
                 }
+                UserCommand::Undo => {
+                    self.run_command(Exit)?; // finish an active editor first, its changes also go to the history
+                    return self.step_history(true);
+                }
+                UserCommand::Redo => {
+                    self.run_command(Exit)?;
+                    return self.step_history(false);
+                }
+                UserCommand::ExportData => {
+                    self.run_command(Exit)?;
+                    return self.export_selected();
+                }
+                UserCommand::ImportData => {
+                    self.run_command(Exit)?;
+                    return self.import_selected();
+                }
+                UserCommand::ChangeFormat => {
+                    self.run_command(Exit)?; // a typed value is read in the previous format
+                    self.change_format();
+                    CommandResult::Redraw
+                }
+                UserCommand::SortDataView => {
+                    self.run_command(Exit)?;
+                    self.sort_view();
+                    CommandResult::Redraw
+                }
+                UserCommand::TableTreeToggle | UserCommand::TableVariant | UserCommand::ChangeColumnCount(_) => {
+                    self.run_command(Exit)?; // a typed value is saved first
+                    self.switch_table(command);
+                    CommandResult::Redraw
+                }
+                UserCommand::BinaryVisibility => {
+                    self.layout_config.show_binary = !self.layout_config.show_binary;
+                    CommandResult::Redraw
+                }
                 _ => self.layouts.run_command(command, &self.data, &self.layout_config, &mut self.selected)
             };
 
@@ -295,8 +616,17 @@ impl App {
                 Ok(false)
             }
             CommandResult::ChangeData(mut change) => {
-                self.data.apply(&mut change);
+                let applied = self.data.apply(&mut change).is_some();
                 self.layouts.update_after_data_changed(&self.data, &self.layout_config, self.selected.layout);
+                if applied {
+                    if let ChangeType::Move(_) = change.action { // the selection follows the moved value
+                        if let Some(index) = self.layouts.find_layout(&change.path) {
+                            self.selected.layout = index;
+                        }
+                    }
+                    self.history.push(change); // after apply the change contains the reverse action
+                }
+                self.refresh_search();
                 self.need_update_layout_height = true;
                 Ok(false)
             }
@@ -306,14 +636,241 @@ impl App {
         }
         //Ok(())
     }
+
+    // undo (or redo) one change and move the cursor to the changed data
+    fn step_history(&mut self, undo: bool) -> io::Result<bool> {
+        let change = if undo { self.history.undo.pop() } else { self.history.redo.pop() };
+        if let Some(mut change) = change {
+            let changed_layout = self.layouts.find_layout(&change.path).unwrap_or(usize::MAX);
+            if self.data.apply(&mut change).is_some() {
+                self.layouts.update_after_data_changed(&self.data, &self.layout_config, changed_layout);
+                self.selected = Selection::default();
+                if let Some(index) = self.layouts.find_layout(&change.path) {
+                    self.selected.layout = index;
+                }
+                if undo { self.history.redo.push(change) } else { self.history.undo.push(change) }
+                self.refresh_search();
+            }
+            self.need_update_layout_height = true;
+        }
+        Ok(false)
+    }
+    // the repeated message type of the selected field, it may be shown as a table
+    fn selected_table_type(&self) -> Option<MessageProtoPtr> {
+        let current = self.layouts.items.get(self.selected.layout)?;
+        let def = self.data.get_field_definition(&current.path)?;
+        def.message_proto().filter(|msg_def| def.repeated() && !well_known::is_well_known(msg_def))
+    }
+    // the message type of the selected table and the field id of the selected column (a row in the vertical variant),
+    // for a map the type of the entries and the selected key or value
+    fn selected_table_column(&self) -> Option<(MessageProtoPtr, Option<i32>)> {
+        let current = self.layouts.items.get(self.selected.layout)?;
+        match current.layout.as_ref()?.layout_type() {
+            LayoutType::Table => {}
+            LayoutType::Map => {
+                let def = self.selected_table_type()?;
+                return Some((def, (self.selected.y > 0).then_some(self.selected.x as i32 + 1)));
+            }
+            _ => return None,
+        }
+        let def = self.selected_table_type()?;
+        let column = if self.layout_config.table_view(&def)? { self.selected.y } else { self.selected.x as usize };
+        let id = column.checked_sub(1).and_then(|column| self.layout_config.table_columns(&def).get(column).copied());
+        Some((def, id))
+    }
+    // switch the table view of the selected repeated message, kept for all fields of the type
+    fn switch_table(&mut self, command: UserCommand) {
+        let Some(def) = self.selected_table_type() else { return; };
+        let cursor = match command {
+            UserCommand::TableTreeToggle => {
+                self.layout_config.switch_table(&def, false);
+                None
+            }
+            UserCommand::TableVariant if self.layout_config.switch_table(&def, true) => Some((self.selected.y as u16, self.selected.x as usize)), // transposed
+            UserCommand::ChangeColumnCount(delta) if self.layout_config.table_view(&def).is_some() => {
+                let Some((_, id)) = self.selected_table_column() else { return; };
+                self.layout_config.change_column_count(&def, id, delta);
+                Some((self.selected.x, self.selected.y))
+            }
+            _ => return,
+        };
+        self.rebuild_layouts(cursor);
+    }
+    // the repeated field with the selected data and the ids of the selected subfield of its values:
+    // a column of a table, a field of a repeated message, none for a repeated scalar itself
+    fn repeated_target(&self) -> Option<(FieldPath, Vec<i32>)> {
+        let current = self.layouts.items.get(self.selected.layout)?;
+        if let Some((_, id)) = self.selected_table_column() {
+            return Some((current.path.clone(), id.into_iter().collect()));
+        }
+        let path = &current.path.0;
+        let repeated = (0..path.len()).rev().find(|&k| self.data.get_field_definition(&FieldPath(path[..=k].to_vec())).is_some_and(|def| def.repeated()))?;
+        Some((FieldPath(path[..=repeated].to_vec()), path[repeated + 1..].iter().map(|pos| pos.id).collect()))
+    }
+    fn repeated_config(&self, path: &FieldPath) -> Option<&RepeatedEditorConfig> {
+        let parent = self.data.get_submessage(&path.0[..path.0.len() - 1])?;
+        self.layout_config.repeated_config(parent, path.0.last().unwrap().id)
+    }
+    // the next view order of the repeated values by the selected subfield: a…z, z…a, as read from the file
+    fn sort_view(&mut self) {
+        let Some((path, ids)) = self.repeated_target() else { return; };
+        let Some(def) = self.data.get_field_definition(&path) else { return; };
+        let Some(parent) = self.data.get_submessage(&path.0[..path.0.len() - 1]) else { return; };
+        let config = self.layout_config.repeated_config_mut(parent, def.id());
+        let ids = if ids.is_empty() && json::is_map(&def) { vec![1] } else { ids }; // map entries by the key
+        config.sort_by = match config.sort_by.take() {
+            _ if ids.is_empty() && def.is_message() => None, // messages are not compared themselves
+            Some(sort) if sort.ids == ids && !sort.descending => Some(SortKey { ids, descending: true }),
+            Some(sort) if sort.ids == ids => None,
+            _ => Some(SortKey { ids, descending: false }),
+        };
+        self.rebuild_layouts(Some((self.selected.x, self.selected.y)));
+    }
+    // the layouts are created again after the view config changed, the selected data stays selected
+    fn rebuild_layouts(&mut self, cursor: Option<(u16, usize)>) {
+        let Some(current) = self.layouts.items.get(self.selected.layout) else { return; };
+        let path = current.path.with_last_index(current.path.0.last().unwrap().index + current.value_at_cursor(self.selected.x, self.selected.y));
+        let proto = std::mem::take(&mut self.layouts.proto);
+        self.layouts = Layouts::new(&self.data, proto, &self.layout_config, self.layouts.file_path.clone(), self.layouts.width, self.layouts.height);
+        self.selected = Selection::default();
+        if let Some(index) = self.layouts.reveal(&self.data, &self.layout_config, &path) {
+            let item = &self.layouts.items[index];
+            let level = item.path.0.len() - 1; // the layout may show a parent of a filtered value
+            let value = item.cursor_at_value(path.0[level].index.saturating_sub(item.path.0[level].index));
+            let (x, y) = if level + 1 == path.0.len() { cursor.unwrap_or(value) } else { (0, 0) };
+            self.selected = Selection { layout: index, x, y };
+        }
+        self.need_update_layout_height = true;
+    }
+    // the next number format of the selected field, for this field of all messages of the type
+    fn change_format(&mut self) {
+        let Some(current) = self.layouts.items.get(self.selected.layout) else { return; };
+        let (def, parent) = if let Some((_, id)) = self.selected_table_column() { // the field of the selected cell
+            let element = current.path.with_last_index(current.path.0.last().unwrap().index + current.value_at_cursor(self.selected.x, self.selected.y));
+            let Some(parent) = self.data.get_submessage(&element.0) else { return; };
+            let Some(def) = id.and_then(|id| parent.def.get_field(id)) else { return; };
+            (def, parent)
+        } else {
+            let Some(def) = self.data.get_field_definition(&current.path) else { return; };
+            let Some(parent) = self.data.get_submessage(&current.path.0[..current.path.0.len() - 1]) else { return; };
+            (def, parent)
+        };
+        let formats = format::formats(&def);
+        let pos = formats.iter().position(|f| *f == self.layout_config.field_format(parent, def.id())).unwrap_or(0);
+        self.layout_config.set_field_format(parent, def.id(), formats[(pos + 1) % formats.len()]);
+        self.need_update_layout_height = true;
+    }
+    // the name of the number format of the selected field, empty for the default one
+    fn get_format_name(&self, current: &LayoutParams) -> &'static str {
+        let def = self.data.get_field_definition(&current.path);
+        let parent = self.data.get_submessage(&current.path.0[..current.path.0.len() - 1]);
+        match (def, parent) {
+            (Some(def), Some(parent)) => self.layout_config.field_format(parent, def.id()).name(),
+            _ => "",
+        }
+    }
+    // the sorting and filtering of the repeated field with the selected data, empty if it is shown as read
+    fn get_view_order_name(&self) -> String {
+        let Some((path, _)) = self.repeated_target() else { return String::new(); };
+        match (self.repeated_config(&path), self.data.get_field_definition(&path)) {
+            (Some(config), Some(def)) => config.describe(&def),
+            _ => String::new(),
+        }
+    }
+    // write the selected data to the json file near the data file, an existing file is overwritten if the user agrees;
+    // the written file is shown in the top line
+    fn export_selected(&mut self) -> io::Result<bool> {
+        if let Some(item) = self.layouts.items.get(self.selected.layout) {
+            if let Some(text) = json::range_to_json(&self.data, &item.path, item.amount) {
+                let mut path = self.layouts.file_path.clone();
+                path.set_extension("json");
+                if path.exists() {
+                    self.message = Some(format!("overwrite {}? (y/n)", path.display()));
+                    self.export = Some((path, text));
+                } else {
+                    self.message = Some(Self::write_export(&path, &text));
+                }
+                self.need_update = true;
+            }
+        }
+        Ok(false)
+    }
+    // the result for the top line
+    fn write_export(path: &std::path::Path, text: &str) -> String {
+        match std::fs::write(path, text) {
+            Ok(()) => format!("exported to {}", path.display()),
+            Err(e) => format!("{}: {}", path.display(), e),
+        }
+    }
+    // replace the selected message by the message in the json file near the data file, as export_selected writes it;
+    // the result or the error is shown in the top line
+    fn import_selected(&mut self) -> io::Result<bool> {
+        let Some(current) = self.layouts.items.get(self.selected.layout).filter(|current| current.amount > 0) else { return Ok(false); };
+        let path = current.path.with_last_index(current.path.0.last().unwrap().index + current.value_at_cursor(self.selected.x, self.selected.y));
+        self.need_update = true;
+        let def = self.data.get_field_definition(&path).filter(|def| !json::is_map(def)).and_then(|def| def.message_proto());
+        let Some(def) = def.filter(|_| self.data.get_submessage(&path.0).is_some()) else {
+            self.message = Some("only a message can be imported from JSON".to_string());
+            return Ok(false);
+        };
+        let mut file = self.layouts.file_path.clone();
+        file.set_extension("json");
+        match std::fs::read_to_string(&file).map_err(|e| e.to_string()).and_then(|text| json::json_to_message(&text, def)) {
+            Ok(msg) => {
+                self.message = Some(format!("imported {}", file.display()));
+                self.after_command(CommandResult::ChangeData(Change { path, action: ChangeType::Overwrite(FieldValue::MESSAGE(msg.into())) }))
+            }
+            Err(e) => {
+                self.message = Some(format!("{}: {}", file.display(), e));
+                Ok(false)
+            }
+        }
+    }
+    // the path of the selected data in the query syntax
+    fn get_path_string(&self, current: &LayoutParams) -> String {
+        if let Some(field) = self.data.get_field(&current.path.0).filter(|field| field.def.damaged()) {
+            return format!("damaged data at {}", field.def.comment()); // instead of the path
+        }
+        let mut res = path::format_path(&self.data, &current.path);
+        if current.amount > 1 { // several values in one layout, the index is in the status string
+            if let Some(pos) = res.rfind('[') { res.truncate(pos); }
+        }
+        res
+    }
+
     fn get_top_line(&self, width: u16, config: &LayoutConfig) -> String {
+        let prompt = if self.search.prompt {
+            let info = match self.search.current {
+                Some(current) => format!("{}/{}", current + 1, self.search.hits.len()),
+                None if self.search.pattern.is_empty() => String::new(),
+                None => "not found".to_string(),
+            };
+            Some((format!("/{}", self.search.pattern), info))
+        } else if let Some(filter) = &self.filter {
+            let name = self.data.get_field_definition(&filter.path).map_or(String::new(), |def| def.name());
+            Some((format!("filter {}: {}", name, filter.text), filter.error.clone()))
+        } else if let Some(picker) = &self.case_picker {
+            let oneof = picker.cases[0].oneof_name().clone().unwrap_or_default();
+            let cases: Vec<String> = picker.cases.iter().enumerate().map(|(index, def)| if index == picker.selected { format!("[{}]", def.name()) } else { def.name() }).collect();
+            Some((format!("oneof {}: {}", oneof, cases.join(" ")), String::new()))
+        } else {
+            self.message.as_ref().map(|message| (message.clone(), String::new()))
+        };
+        if let Some((prompt, info)) = prompt {
+            let avail_len = (width - MARGIN_LEFT - MARGIN_RIGHT) as usize;
+            let mut text = format!("{:<w$}{}", prompt, info, w = avail_len.saturating_sub(info.len()));
+            text = text.chars().take(avail_len).collect();
+            return " ".repeat(MARGIN_LEFT as usize) + &text + &" ".repeat(MARGIN_RIGHT as usize);
+        }
         let mut parts = Vec::with_capacity(3);
 
         parts.push(self.layouts.file_name());
         if let Some(current) = self.layouts.items.get(self.selected.layout) {
             debug_assert!(current.layout.is_some());
             let percent = 100.0 * self.layouts.calc_relative_pos(self.selected.layout);
-            parts.push(current.get_status_string(self.selected.x, self.selected.y));
+            let status = current.get_status_string(self.selected.x, self.selected.y);
+            let path = [self.get_path_string(current), status, self.get_format_name(current).to_string(), self.get_view_order_name()];
+            parts.push(path.iter().filter(|s| !s.is_empty()).cloned().collect::<Vec<_>>().join(" "));
             parts.push(format!("{:.0}% {}", percent, config.field_order.first_letter()));
         }
 
@@ -346,6 +903,68 @@ impl App {
         }
     }
 
+    // the first screen line below the data, where the diagnostics or the binary pane starts
+    fn data_bottom(&self) -> u16 {
+        let pane = if self.diagnostics.is_some() { DIAGNOSTICS_PANE } else if self.layout_config.show_binary { BINARY_PANE } else { 0 };
+        if pane > 0 && self.height > TOP_LINE + pane {
+            self.height - pane
+        } else {
+            self.height
+        }
+    }
+
+    // the diagnostics pane replaces the binary pane while it is open
+    fn bottom_pane(&self) -> Vec<ScreenLine> {
+        match &self.diagnostics {
+            Some(diagnostics) => self.diagnostics_pane(diagnostics),
+            None => self.binary_pane(),
+        }
+    }
+
+    fn diagnostics_pane(&self, diagnostics: &Diagnostics) -> Vec<ScreenLine> {
+        let height = (self.height - self.data_bottom()) as usize;
+        if height == 0 { return vec![]; }
+        let count = diagnostics.problems.len();
+        let title = match count {
+            0 => "no problems found, Esc closes".to_string(),
+            1 => "1 problem, Esc closes".to_string(),
+            _ => format!("{} problems, Up/Down select, Esc closes", count),
+        };
+        let mut lines = vec![binary_pane_message(title)];
+        let first = (diagnostics.selected + 1).saturating_sub(height - 1); // the selected one is visible
+        for (index, problem) in diagnostics.problems.iter().enumerate().skip(first).take(height - 1) {
+            let mut line = ScreenLine(vec![]);
+            let style = if index == diagnostics.selected { TextStyle::SelectedValue } else { TextStyle::Value };
+            line.add_string(" ".repeat(MARGIN_LEFT as usize) + &problem.describe(&self.data), style);
+            lines.push(line);
+        }
+        for line in &mut lines { line.fix_length(self.width); }
+        lines.resize_with(height, || { let mut line = ScreenLine(vec![]); line.fix_length(self.width); line });
+        lines
+    }
+
+    // the bytes of the data item at the cursor
+    fn binary_pane(&self) -> Vec<ScreenLine> {
+        let height = (self.height - self.data_bottom()) as usize;
+        if height == 0 { return vec![]; }
+        let mut lines = match self.layouts.items.get(self.selected.layout) {
+            Some(current) if current.amount > 0 => {
+                let index = current.path.0.last().unwrap().index + current.value_at_cursor(self.selected.x, self.selected.y);
+                let place = self.data.get_field_place(&current.path.with_last_index(index).0);
+                match place.map(|(msg, pos)| binary::FieldBytes::new(msg, pos, &self.layouts.proto)) {
+                    Some(Ok(bytes)) => bytes.to_lines(self.width, height),
+                    Some(Err(e)) => vec![binary_pane_message(format!("cannot encode: {}", e))],
+                    None => vec![],
+                }
+            }
+            Some(_) => vec![binary_pane_message("no data, the default value is not written".to_string())],
+            None => vec![],
+        };
+        for line in &mut lines { line.fix_length(self.width); }
+        lines.resize_with(height, || { let mut line = ScreenLine(vec![]); line.fix_length(self.width); line });
+        lines
+    }
+
     // find out the line number with active cursor
     fn calc_scroll_pos(&self) -> usize { // move to layouts
         let mut selected_line = 0;
@@ -360,8 +979,8 @@ impl App {
             y += item.height;
         }
         // correct scroll position if active cursor is above/below visible window
-        if selected_line + 1 >= self.layouts.scroll + (self.height - TOP_LINE) as usize {
-            return selected_line + 1 - (self.height - TOP_LINE) as usize;
+        if selected_line + 1 >= self.layouts.scroll + (self.data_bottom() - TOP_LINE) as usize {
+            return selected_line + 1 - (self.data_bottom() - TOP_LINE) as usize;
         }
         if selected_line < self.layouts.scroll {
             return selected_line;
@@ -420,10 +1039,12 @@ impl App {
         self.layouts.ensure_loaded(&self.data, &self.layout_config, layout_index, 0, self.height as usize + skip_lines, &mut self.selected);
 
         self.print_top_line()?;
+        let bottom = self.data_bottom();
         let mut y = TOP_LINE;
 
         let mut text_edit_cursor = None;
         let mut current_style = TextStyle::Unknown;
+        let pattern = self.search.pattern.to_lowercase();
         for index in layout_index..self.layouts.items.len() {
             let item = &mut self.layouts.items[index];
             let indent = self.layouts.indents[item.level() - 1];
@@ -440,7 +1061,8 @@ impl App {
                 skip_lines = 0;
             }
 
-            for line in lines.0 {
+            for mut line in lines.0 {
+                line.highlight(&pattern);
                 let mut text = String::new();
                 for (c, s) in line.0 {
                     if s != current_style {
@@ -459,9 +1081,9 @@ impl App {
                 }
                 self.stdout.queue(cursor::MoveToNextLine(1))?;
                 y += 1;
-                if y >= self.height { break; }
+                if y >= bottom { break; }
             }
-            if y >= self.height { break; }
+            if y >= bottom { break; }
         }
         if y < self.height { // fill the free space below if any
             self.stdout.queue(style::ResetColor)?;
@@ -469,6 +1091,17 @@ impl App {
 
             self.stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
         }
+        self.stdout.queue(cursor::MoveTo(0, bottom))?;
+        for line in self.bottom_pane() {
+            for (c, s) in line.0 {
+                if s != current_style {
+                    self.stdout.queue(s.activate())?;
+                    current_style = s;
+                }
+                self.stdout.queue(style::Print(c))?;
+            }
+            self.stdout.queue(cursor::MoveToNextLine(1))?;
+        }
 
         if let Some((x, y)) = text_edit_cursor {
             self.stdout.queue(cursor::MoveTo(x, y));
@@ -482,6 +1115,7 @@ impl App {
 
     #[cfg(test)]
     fn to_strings(&mut self) -> Vec<String> {
+        let bottom = self.data_bottom();
         let mut y = TOP_LINE;
         let mut res = vec![];
 
@@ -508,11 +1142,12 @@ impl App {
             for line in lines.0 {
                 res.push(line.0.into_iter().map(|v| v.0).collect());
                 y += 1;
-                if y >= self.height { break; }
+                if y >= bottom { break; }
             }
-            if y >= self.height { break; }
+            if y >= bottom { break; }
         }
 
+        res.extend(self.bottom_pane().into_iter().map(|line| line.0.into_iter().map(|v| v.0).collect()));
         if let Some((x, y)) = text_edit_cursor {
             res.push(format!("cursor: {x},{y}"));
         }
@@ -532,7 +1167,13 @@ impl Drop for App {
     }
 }
 
-fn exit_with_error<T: std::fmt::Display>(message: T, code: i32) {
+fn binary_pane_message(text: String) -> ScreenLine {
+    let mut line = ScreenLine(vec![]);
+    line.add_string(" ".repeat(MARGIN_LEFT as usize) + &text, TextStyle::Comment);
+    line
+}
+
+fn exit_with_error<T: std::fmt::Display>(message: T, code: i32) -> ! {
     let _ = io::stderr().execute(style::SetForegroundColor(Color::Red));
     eprint!("error");
     let _ = io::stderr().execute(style::ResetColor);
@@ -546,23 +1187,107 @@ fn exit_with_error<T: std::fmt::Display>(message: T, code: i32) {
 #[command(
     version,
     about,
-    long_about = "\nTerminal-based protobuf data files editor.\nhttps://github.com/friend2025/protoedit"
+    long_about = "\nTerminal-based protobuf data files editor.\nhttps://github.com/friend2025/protoedit",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
 )]
 struct Args {
-    /// Input file: data.pb{;format.proto{;message_name}}
-    file: String,
+    /// Input file: data.pb{;format.proto{;message_name}}, or text format data.textproto (.pbtxt, .txtpb); format.desc is a compiled FileDescriptorSet
+    #[arg(required = true)]
+    file: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 
     /// Set of directories for proto files search
-    #[arg(short = 'I', long = "proto_path")]
+    #[arg(short = 'I', long = "proto_path", global = true)]
     proto_path: Vec<PathBuf>,
+
+    /// Decode the data using only wire types, without a proto file
+    #[arg(long, global = true)]
+    raw: bool,
+
+    /// Map the data file to memory and decode submessages when they are shown (always for files over 64 MiB);
+    /// the top level fields are decoded when the file is opened, decoded submessages are kept
+    #[arg(long, global = true)]
+    lazy: bool,
+
+    /// Read the data file as a stream of size-prefixed messages; the framing is detected if not given, e.g. --stream=varint
+    #[arg(long, global = true, value_name = "FRAMING", num_args = 0..=1, require_equals = true)]
+    stream: Option<Option<stream::Framing>>,
+
+    /// Keep the data read before errors in a damaged file; the damaged bytes are shown, but not saved
+    #[arg(long, global = true)]
+    repair: bool,
+
+    /// Print the data in the proto3 JSON format instead of opening the editor
+    #[arg(long)]
+    json: bool,
+
+    /// Create the data file from the JSON file instead of opening the editor
+    #[arg(long, value_name = "JSON_FILE")]
+    from_json: Option<PathBuf>,
+}
+
+/// Commands run without the editor, the file argument is the same as for the editor
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the data in the text format
+    Decode {
+        file: String,
+    },
+    /// Create the data file from the text format read from the standard input
+    Encode {
+        file: String,
+    },
+    /// Print the value of a field, for example m3.m6[1].f8
+    Get {
+        file: String,
+        path: String,
+    },
+    /// Change the value of a field and save the file; the value is in the text format
+    Set {
+        file: String,
+        path: String,
+        value: String,
+    },
+    /// Check that the file is saved byte by byte as it was read, without saving it
+    Verify {
+        file: String,
+    },
+    /// List the problems of the data: unknown fields, values of another wire type, undeclared enum values, fields set several times, several members of a oneof, invalid UTF-8, missing required fields
+    Check {
+        file: String,
+    },
+    /// Write the proto file and its imports as a FileDescriptorSet, as protoc --include_imports --descriptor_set_out
+    Compile {
+        proto: String,
+        output: String,
+    },
 }
 
 
 fn main() -> io::Result<()> {
     let args = Args::parse();
 
-    let mut it = args.file.split(";");
-    let binary_file = it.next().unwrap();
+    match &args.command {
+        Some(command) => run_command(&args, command),
+        None => start(&args, args.file.as_ref().unwrap()),
+    }
+}
+
+// the data and its definitions
+struct Document {
+    binary_file: String,
+    proto: ProtoData,
+    data: MessageData,
+}
+
+// file: data.pb{;format.proto{;message_name}}
+// a new document has no data, the data file may not exist
+fn load(args: &Args, file: &str, new_document: bool) -> io::Result<Document> {
+    let mut it = file.split(";");
+    let binary_file = it.next().unwrap().to_string();
     let mut proto_file = String::new();
     let mut root_message_name = String::new();
     if let Some(path) = it.next() {
@@ -574,30 +1299,38 @@ fn main() -> io::Result<()> {
     }
 
     // if no proto file provided, use the file with the same name as data file but with proto extension
+    let mut raw = args.raw;
     if proto_file.is_empty() {
-        proto_file = binary_file.trim_end_matches(".pb").to_string() + ".proto";
+        proto_file = if text_format::is_text_format(binary_file.as_ref()) {
+            PathBuf::from(&binary_file).with_extension("proto").to_string_lossy().into_owned()
+        } else {
+            binary_file.trim_end_matches(".pb").to_string() + ".proto"
+        };
+        raw = raw || (!new_document && !std::fs::exists(&proto_file)?); // no schema available
     }
 
-    if !std::fs::exists(&binary_file)? {
+    if !new_document && !std::fs::exists(&binary_file)? {
         exit_with_error(format!("file \"{}\" is not available", binary_file), 101);
     }
-    if !std::fs::exists(&proto_file)? {
-        exit_with_error(format!("proto definitions file \"{}\" is not available", proto_file), 102);
+    if raw && new_document {
+        exit_with_error("a new data file requires a proto file", 102);
     }
-
-    for dir in &args.proto_path {
-        if !dir.is_absolute() {
-            eprintln!("The proto_path argument should contain an absolute path.");
-            break;
-        }
-        if !dir.is_dir() {
-            eprintln!("The proto_path is not a directory: {}", dir.display());
-        }
+    if raw && text_format::is_text_format(binary_file.as_ref()) {
+        exit_with_error("the text format requires a proto file", 102);
     }
-
-    let mut proto_files = ProtoFile::new_with_imports(proto_file.into(), args.proto_path);
-
-    let mut proto = ProtoData::new(&proto_files.remove(0).content)?;
+    if raw {
+        let buf = std::fs::read(&binary_file)?;
+        let proto = match raw::guess_proto(&buf) {
+            Ok(proto) => proto,
+            Err(e) => exit_with_error(format!("cannot decode \"{}\": {}", binary_file, e), 105),
+        };
+        let root_msg = proto.get_message_definition(raw::RAW_ROOT_NAME).unwrap();
+        let mut limit = buf.len() as u64;
+        let data = MessageData::new(&mut PbReader::new(buf.as_slice()), &proto, root_msg, &mut limit)?;
+        return Ok(Document { binary_file, proto, data });
+    }
+    let mut schema = load_schema(args, &proto_file)?;
+    let mut proto = schema.remove(0);
 
     let mut root_msg = None;
     if root_message_name.is_empty() {
@@ -608,10 +1341,10 @@ fn main() -> io::Result<()> {
     }
 
     // merge imported proto files
-    for file in proto_files.into_iter() {
-        proto.append(ProtoData::new(&file.content)?);
+    for file in schema {
+        proto.append(file);
     }
-    proto = proto.finalize()?;
+    proto = proto.finalize().unwrap_or_else(|e| exit_with_error(e, 110));
 
     if root_msg.is_none() {
         root_msg = proto.get_message_definition(&root_message_name);
@@ -619,14 +1352,173 @@ fn main() -> io::Result<()> {
             exit_with_error(format!("root message \"{}\" not found", root_message_name), 104);
         }
     }
+    let root_msg = root_msg.unwrap();
 
-    println!("loading...");
-    let file = std::fs::File::open(binary_file)?;
-    let mut limit = file.metadata()?.len() as u32;
-    let mut reader = PbReader::new(file);
-    let data = MessageData::new(&mut reader, &proto, root_msg.unwrap(), &mut limit)?;
+    if new_document {
+        // a new stream is written as by writeDelimitedTo if the framing is not given
+        let def = match args.stream {
+            Some(framing) => stream::new_stream(&mut proto, &root_msg, framing.unwrap_or(stream::Framing::Varint)),
+            None => root_msg,
+        };
+        return Ok(Document { binary_file, proto, data: MessageData { def, fields: vec![] } });
+    }
+    let options = text_format::ReadOptions { lazy: args.lazy, stream: args.stream, repair: args.repair };
+    let mut data = match text_format::read_document(binary_file.as_ref(), &mut proto, root_msg, options) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData && text_format::is_text_format(binary_file.as_ref()) => exit_with_error(format!("{}: {}", binary_file, e), 107),
+        Err(e) if matches!(e.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) => {
+            exit_with_error(format!("{}: {} (--repair keeps the data read before the error)", binary_file, e), 107)
+        }
+        Err(e) => return Err(e),
+    };
+    well_known::unpack_any(&mut data, &proto);
+    for damaged in repair::find_damaged(&data) {
+        eprintln!("warning: damaged data at {}", damaged);
+    }
+    Ok(Document { binary_file, proto, data })
+}
 
-    App::new(data, proto, binary_file.into())?.run()
+// the parsed schema files: the main file first, then the imported ones
+fn load_schema(args: &Args, proto_file: &str) -> io::Result<Vec<ProtoData>> {
+    if !std::fs::exists(proto_file)? {
+        exit_with_error(format!("proto definitions file \"{}\" is not available", proto_file), 102);
+    }
+    Ok(if descriptor::is_descriptor_set(proto_file.as_ref()) {
+        let mut files = descriptor::read_descriptor_set(&std::fs::read(proto_file)?).unwrap_or_else(|e| exit_with_error(format!("{}: {}", proto_file, e), 110));
+        files.reverse(); // the imported files are before the main one in the set
+        files
+    } else {
+        for dir in &args.proto_path {
+            if !dir.is_absolute() {
+                eprintln!("The proto_path argument should contain an absolute path.");
+                break;
+            }
+            if !dir.is_dir() {
+                eprintln!("The proto_path is not a directory: {}", dir.display());
+            }
+        }
+        ProtoFile::new_with_imports(proto_file.into(), args.proto_path.clone()).iter().
+            map(|file| ProtoData::from_file(file).unwrap_or_else(|e| exit_with_error(e, 110))).
+            collect()
+    })
+}
+
+// the data may be read lazily from the same file, so it is replaced only after writing
+fn save(doc: &Document) -> io::Result<()> {
+    let temp_path = PathBuf::from(format!("{}.tmp", doc.binary_file)); // next to the file, so the rename does not copy it
+    {
+        let mut output = io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        text_format::write_document(&mut output, doc.binary_file.as_ref(), &doc.data, &doc.proto)?;
+        output.flush()?;
+    }
+    std::fs::rename(temp_path, &doc.binary_file)
+}
+
+fn start(args: &Args, file: &str) -> io::Result<()> {
+    if let Some(json_file) = &args.from_json {
+        let mut doc = load(args, file, true)?;
+        let text = std::fs::read_to_string(json_file)?;
+        match json::json_to_message(&text, doc.data.def.clone()) {
+            Ok(data) => doc.data = data,
+            Err(e) => exit_with_error(format!("{}: {}", json_file.display(), e), 106),
+        }
+        return save(&doc);
+    }
+
+    if !args.json { println!("loading..."); }
+    let doc = load(args, file, false)?;
+    if args.json {
+        print!("{}", json::message_to_json(&doc.data));
+        return Ok(());
+    }
+    let damaged = repair::find_damaged(&doc.data);
+    let mut app = App::new(doc.data, doc.proto, doc.binary_file.into())?;
+    app.message = damaged_message(&damaged);
+    app.run()
+}
+
+// the first damaged data in the top line of the editor, the warnings are behind its screen
+fn damaged_message(damaged: &[String]) -> Option<String> {
+    let first = damaged.first()?;
+    let more = if damaged.len() > 1 { format!(" and {} more", damaged.len() - 1) } else { String::new() };
+    Some(format!("damaged data at {}{}", first, more))
+}
+
+// proto2 required fields, the data is still usable without them
+fn warn_missing_required(data: &MessageData) {
+    for path in data.missing_required() {
+        eprintln!("warning: required field {} is missing", path::format_path(data, &path));
+    }
+}
+
+fn run_command(args: &Args, command: &Command) -> io::Result<()> {
+    match command {
+        Command::Decode { file } => {
+            let doc = load(args, file, false)?;
+            print!("{}", text_format::message_to_text(&doc.data));
+            warn_missing_required(&doc.data);
+        }
+        Command::Encode { file } => {
+            let mut doc = load(args, file, true)?;
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            match text_format::text_to_message(&text, doc.data.def.clone()) {
+                Ok(data) => doc.data = data,
+                Err(e) => exit_with_error(format!("stdin: {}", e), 107),
+            }
+            warn_missing_required(&doc.data);
+            save(&doc)?;
+        }
+        Command::Get { file, path } => {
+            let doc = load(args, file, false)?;
+            match cli::get_value(&doc.data, path) {
+                Ok(text) => print!("{}", text),
+                Err(e) => exit_with_error(format!("{}: {}", path, e), 108),
+            }
+        }
+        Command::Set { file, path, value } => {
+            let mut doc = load(args, file, false)?;
+            if let Err(e) = cli::set_value(&mut doc.data, path, value) {
+                exit_with_error(format!("{}: {}", path, e), 108);
+            }
+            save(&doc)?;
+        }
+        Command::Verify { file } => {
+            let doc = load(args, file, false)?;
+            let mut output = vec![];
+            text_format::write_document(&mut output, doc.binary_file.as_ref(), &doc.data, &doc.proto)?;
+            match cli::compare_saved(&std::fs::read(&doc.binary_file)?, &output) {
+                Ok(()) => println!("{}: saved unchanged, {} bytes", doc.binary_file, output.len()),
+                Err(e) => exit_with_error(format!("{}: {}", doc.binary_file, e), 109),
+            }
+        }
+        Command::Check { file } => {
+            let doc = load(args, file, false)?;
+            let problems = check::check(&doc.data, &doc.proto);
+            for problem in &problems {
+                println!("{}", problem.describe(&doc.data));
+            }
+            match problems.len() {
+                0 => println!("{}: no problems found", doc.binary_file),
+                count => exit_with_error(format!("{}: {} problem{} found", doc.binary_file, count, if count == 1 { "" } else { "s" }), 111),
+            }
+        }
+        Command::Compile { proto, output } => {
+            // linked first, so the errors are reported at the declarations
+            let mut linked = ProtoData::default();
+            for file in load_schema(args, proto)? {
+                linked.append(file);
+            }
+            linked.finalize().unwrap_or_else(|e| exit_with_error(e, 110));
+            let schema = load_schema(args, proto)?;
+            let mut buf = vec![];
+            if let Err(e) = descriptor::write_descriptor_set(&schema, &mut buf) {
+                exit_with_error(format!("{}: {}", proto, e), 110);
+            }
+            std::fs::write(output, buf)?;
+        }
+    }
+    Ok(())
 }
 
 
@@ -698,7 +1590,7 @@ mod app_tests {
     fn make_minimal_test_data() -> MessageData {
         let binary_input = [];
         let proto = ProtoData::new("message M { int32 f1 = 1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap()
@@ -731,7 +1623,7 @@ message M6 { int32 f8 = 8; int32 f9 = 9; }
         ];
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
 
@@ -744,7 +1636,7 @@ message M6 { int32 f8 = 8; int32 f9 = 9; }
     fn make_no_field_data(proto: &str) -> (MessageData, ProtoData) {
         let binary_input = [];
         let proto = ProtoData::new(proto).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -783,7 +1675,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         ];
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -906,6 +1798,61 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         assert_eq!(app.to_strings(), expected);
     }
 
+    #[test]
+    fn move_repeated_message() {
+        let mut data = make_repeated_message_data(2);
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 30, 25).unwrap();
+        let expected_start = app.to_strings();
+
+        app.run_command(UserCommand::MoveField(1)).unwrap();
+        app.after_event().unwrap();
+        let expected = [
+            " m1:                      M2* ",
+            "   i2: 4                int32 ",
+            "   i3: 5                int32 ",
+            " m1:                      M2* ",
+            "   i2: 2                int32 ",
+            "   i3: 3                int32 "];
+        assert_eq!(app.to_strings(), expected);
+        assert_eq!(app.selected.layout, 3); // the cursor follows the moved message
+
+        app.run_command(UserCommand::MoveField(1)).unwrap(); // already the last one
+        app.after_event().unwrap();
+        assert_eq!(app.selected.layout, 3);
+
+        app.run_command(UserCommand::Undo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_start);
+    }
+
+    #[test]
+    fn damaged_data() {
+        let expected_proto = [
+            "      f1: 1             int32 ",
+            "      f2: ''          -string ",
+            " damaged: 12 05 61   damaged* "]; // the string is cut
+        let expected_wire = [
+            "      f1: 1             int32 ",
+            " damaged: 12 05 61   damaged* "];
+        for (order, expected) in [(FieldOrder::Proto, &expected_proto[..]), (FieldOrder::Wire, &expected_wire[..])] {
+            let proto = ProtoData::new("message M { int32 f1 = 1; string f2 = 2; }").unwrap().finalize().unwrap();
+            let data = repair::repair_message(&[0x08, 0x01, 0x12, 0x05, 0x61], &proto, proto.get_message_definition("M").unwrap());
+            let mut app = App::for_tests(data, proto, order, 30, 25).unwrap();
+            assert_eq!(app.to_strings(), expected);
+        }
+
+        // shown in the top line of the editor until a key is pressed
+        let proto = ProtoData::new("message M { int32 f1 = 1; string f2 = 2; }").unwrap().finalize().unwrap();
+        let data = repair::repair_message(&[0x08, 0x01, 0x12, 0x05, 0x61], &proto, proto.get_message_definition("M").unwrap());
+        let message = damaged_message(&repair::find_damaged(&data));
+        assert_eq!(message.as_deref(), Some("damaged data at offset 2: read data out of limit"));
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 60, 25).unwrap();
+        app.message = message;
+        assert_eq!(app.get_top_line(60, &app.layout_config).trim(), "damaged data at offset 2: read data out of limit");
+        app.on_key(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE)).unwrap();
+        assert!(app.get_top_line(60, &app.layout_config).starts_with(" test_data.pb"));
+    }
+
     #[test]
     fn delete_message_field() {
         let mut data = make_repeated_message_data(1);
@@ -1056,7 +2003,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
             0x0A, 0x03, 0x61, 0x62, 0x63,
             0x0A, 0x03, 0x64, 0x65, 0x66];
         let proto = ProtoData::new("message M { repeated string f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1075,7 +2022,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
             0x0A, 0x02, 0x01, 0x02,
             0x0A, 0x03, 0x03, 0x04, 0x05];
         let proto = ProtoData::new("message M { repeated bytes f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1353,7 +2300,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn delete_in_proto_order() {
         let binary_input = [0x08, 0x01, 0x10, 0x02, 0x18, 0x03];
         let proto = ProtoData::new("message M { int32 f1=1; int32 f2=2; int32 f3=3; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1390,7 +2337,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn delete_in_wire_order() {
         let binary_input = [0x08, 0x01, 0x10, 0x02, 0x18, 0x03];
         let proto = ProtoData::new("message M { int32 f1=1; int32 f2=2; int32 f3=3; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1464,6 +2411,295 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         //app.after_event().unwrap();
     }
 
+    #[test]
+    fn binary_pane() {
+        let mut data = make_one_field_data("message M { repeated int32 i1=1; }", I32(1));
+        data.0.add_field(&[(1, 1).into()]).unwrap().value = FieldValue::SCALAR(I32(300));
+        data.0.fields[0].pos = 1; // as read from the file
+
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 30, 3 + BINARY_PANE).unwrap();
+        app.run_command(UserCommand::BinaryVisibility).unwrap();
+        app.after_event().unwrap();
+        let empty = " ".repeat(30);
+        assert_eq!(app.to_strings(), [" i1: 1 300             int32* ", &format!("{:<30}", " 00000 : 08 1:VARINT 01"), &empty, &empty, &empty]);
+
+        app.run_command(ScrollHorizontally(1)).unwrap();
+        app.run_command(ScrollHorizontally(1)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1], format!("{:<30}", " new   : 08 1:VARINT AC 02"));
+
+        app.run_command(UserCommand::BinaryVisibility).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" i1: 1 300             int32* "]);
+    }
+
+    #[test]
+    fn number_format() {
+        let data = make_one_field_data("message M { repeated int32 i1=1; }", I32(10));
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 30, 25).unwrap();
+        app.run_command(UserCommand::ChangeFormat).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" i1: 0xA               int32* "]);
+        assert_eq!(app.get_top_line(30, &app.layout_config), " test_data.pb i1[0] hex  0% P ");
+
+        app.run_command(ScrollHorizontally(1)).unwrap();
+        for c in "1g".chars() { app.run_command(KeyPress(c)).unwrap(); }
+        app.run_command(DeleteData(true)).unwrap(); // not a hex digit
+        app.run_command(KeyPress('f')).unwrap();
+        assert_eq!(app.to_strings(), [" i1: 1f                int32* ", "cursor: 7,1"]);
+        app.run_command(Exit).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.data.fields[0].value, SCALAR(I32(31)));
+        assert_eq!(app.to_strings(), [" i1: 0x1F              int32* "]);
+
+        app.run_command(UserCommand::ChangeFormat).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" i1: 037               int32* "]);
+        for _ in 0..4 { app.run_command(UserCommand::ChangeFormat).unwrap(); }
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" i1: 31                int32* "]);
+    }
+
+    #[test]
+    fn well_known_type() {
+        let data = make_no_field_data("package google.protobuf;\nmessage M { Timestamp t = 1; }\nmessage Timestamp { int64 seconds = 1; int32 nanos = 2; }");
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 50, 25).unwrap();
+        assert_eq!(app.to_strings(), [" t: 1970-01-01T00:00:00Z               -Timestamp "]);
+
+        for c in "2024-02-29T12:00:00.5".chars() { app.run_command(KeyPress(c)).unwrap(); }
+        assert_eq!(app.to_strings(), [" t: 2024-02-29T12:00:00.5              -Timestamp ", "cursor: 25,1"]);
+        app.run_command(CollapsedToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" t: 2024-02-29T12:00:00.500Z            Timestamp "]);
+        let t = app.data.get_submessage(&[(1, 0).into()]).unwrap();
+        assert_eq!(text_format::message_to_text(t), "seconds: 1709208000\nnanos: 500000000\n");
+
+        // Esc discards the typed text
+        for c in "2000".chars() { app.on_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)).unwrap(); }
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[0], " t: 2000                                Timestamp ");
+        app.on_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" t: 2024-02-29T12:00:00.500Z            Timestamp "]);
+        assert_eq!(text_format::message_to_text(app.data.get_submessage(&[(1, 0).into()]).unwrap()), "seconds: 1709208000\nnanos: 500000000\n");
+    }
+
+    #[test]
+    fn table_view() {
+        let proto = ProtoData::new("message M { repeated P p = 1; }\nmessage P { int32 id = 1; string name = 2; repeated int32 tags = 3; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let data = text_format::text_to_message("p { id: 1 name: \"first\" tags: 5 tags: 6 } p { id: 22 }", def).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 25).unwrap();
+        app.run_command(UserCommand::TableTreeToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [
+            " p: id name  tags                    P* ",
+            " 0: 1  first 5 6                        ",
+            " 1: 22       -                          "]);
+
+        // type a name into the empty cell
+        app.run_command(ScrollVertically(2)).unwrap();
+        app.run_command(ScrollHorizontally(2)).unwrap();
+        for c in "second".chars() { app.run_command(KeyPress(c)).unwrap(); }
+        assert_eq!(app.to_strings()[3], "cursor: 13,3");
+        app.run_command(CollapsedToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[2], " 1: 22 second -                         ");
+        assert_eq!(text_format::message_to_text(&app.data), "p {\n  id: 1\n  name: \"first\"\n  tags: 5\n  tags: 6\n}\np {\n  id: 22\n  name: \"second\"\n}\n");
+
+        app.run_command(UserCommand::TableVariant).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [
+            "    p: 0     1                       P* ",
+            "   id: 1     22                         ",
+            " name: first second                     ",
+            " tags: 5 6   -                          "]);
+        assert_eq!((app.selected.x, app.selected.y), (2, 2));
+
+        // hide the selected row of the vertical table, then show it again
+        app.selected = Selection { layout: 0, x: 0, y: 1 };
+        app.run_command(UserCommand::ChangeColumnCount(-1)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1], " name: first second                     ");
+        app.run_command(UserCommand::ChangeColumnCount(1)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1], "   id: 1     22                         ");
+
+        app.run_command(UserCommand::TableTreeToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[..2], [" p:                                  P* ".to_string(), "   id: 1                          int32 ".to_string()]);
+    }
+
+    #[test]
+    fn table_scroll() {
+        let proto = ProtoData::new("message M { repeated P p = 1; }\nmessage P { string a = 1; int32 b = 2; int32 c = 3; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let data = text_format::text_to_message("p { a: \"a long text shown cut in the cell\" b: 2 c: 3 }", def).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 30, 25).unwrap();
+        app.on_key(KeyEvent::new(KeyCode::Char('T'), KeyModifiers::NONE)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [
+            " p: a                    … P* ",
+            " 0: a long text shown c… …    "]);
+        app.run_command(End).unwrap();
+        assert_eq!(app.to_strings(), [
+            " p: b c                    P* ",
+            " 0: 2 3                       "]);
+        assert_eq!(app.get_top_line(30, &LayoutConfig::default()).trim(), "test_data.pb  p[0] 0/1  0% P");
+    }
+
+    #[test]
+    fn sort_and_filter() {
+        let proto = ProtoData::new("message M { repeated P p = 1; repeated int32 n = 2; }\nmessage P { string name = 1; int64 size = 2; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let data = text_format::text_to_message("p { name: \"b\" size: 300 } p { name: \"c\" size: 5 } p { name: \"a\" size: 120 } n: 3 n: -1 n: 20", def).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 25).unwrap();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        app.on_key(key('T')).unwrap();
+        app.selected = Selection { layout: 0, x: 2, y: 0 };
+        app.on_key(key('S')).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[..4], [
+            " p: name size                        P* ",
+            " 1: c    5                              ",
+            " 2: a    120                            ",
+            " 0: b    300                            "]);
+        assert!(app.get_top_line(40, &LayoutConfig::default()).contains("size a…z"));
+
+        // the edit changes the message shown in the row
+        app.run_command(ScrollVertically(1)).unwrap();
+        app.run_command(ScrollHorizontally(-1)).unwrap();
+        app.run_command(KeyPress('z')).unwrap();
+        app.run_command(CollapsedToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.data.get_field(&[(1, 1).into(), (1, 0).into()]).unwrap().value, SCALAR(STR("z".to_string())));
+        assert_eq!(app.to_strings()[1], " 1: z    5                              ");
+
+        app.selected = Selection { layout: 0, x: 0, y: 0 };
+        app.on_key(key('F')).unwrap();
+        for c in "size > 100".chars() { app.on_key(key(c)).unwrap(); }
+        assert_eq!(app.get_top_line(40, &LayoutConfig::default()), " filter p: size > 100                   ");
+        app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[..4], [
+            " p: name size                        P* ",
+            " 2: a    120                            ",
+            " 0: b    300                            ",
+            " n: 3 -1 20                      int32* "]);
+
+        // repeated scalars sorted by value, the selected value is deleted
+        app.selected = Selection { layout: 1, x: 0, y: 0 };
+        app.on_key(key('S')).unwrap();
+        app.on_key(key('S')).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[3], " n: 20 3 -1                      int32* ");
+        app.run_command(ScrollHorizontally(3)).unwrap();
+        app.run_command(DeleteData(false)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[3], " n: 20 3                         int32* ");
+        assert_eq!(app.data.fields.iter().filter(|f| f.def.id() == 2).count(), 2);
+    }
+
+    #[test]
+    fn diagnostics_pane() {
+        let proto = ProtoData::new("message M { int32 i1 = 1; E e2 = 2; } enum E { A = 0; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let bytes = [0x08, 0x01, 0x10, 0x05, 0x18, 0x01]; // e2: 5, unknown field 3
+        let mut limit = bytes.len() as u64;
+        let data = MessageData::new(&mut typedefs::PbSliceReader::new(&bytes, 0), &proto, def, &mut limit).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 3 + DIAGNOSTICS_PANE).unwrap();
+        let key = |c| KeyEvent::new(c, KeyModifiers::NONE);
+        app.on_key(key(KeyCode::F(7))).unwrap();
+        app.after_event().unwrap();
+        let empty = " ".repeat(40);
+        assert_eq!(app.to_strings(), [
+            " i1: 1                            int32 ",
+            " e2: ?5                               E ",
+            " 2 problems, Up/Down select, Esc closes ",
+            " e2 (offset 2): value 5 is not declared ",
+            " 3 (offset 4): unknown field 3 (VARINT) ",
+            &empty, &empty, &empty]);
+        assert_eq!(app.selected.layout, 1); // the field of the first problem
+
+        let selected = |app: &App| app.bottom_pane().iter().position(|line| line.0[1].1 == TextStyle::SelectedValue);
+        assert_eq!(selected(&app), Some(1));
+        app.on_key(key(KeyCode::Down)).unwrap();
+        app.on_key(key(KeyCode::Down)).unwrap();
+        assert_eq!(selected(&app), Some(2));
+        app.on_key(key(KeyCode::Esc)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [" i1: 1                            int32 ", " e2: ?5                               E "]);
+    }
+
+    #[test]
+    fn oneof_case() {
+        let proto = ProtoData::new("message M { float length = 1; oneof kind { string name = 2; int32 number = 3; } }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let data = text_format::text_to_message("name: \"abc\" length: 1 number: 100", def).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 25).unwrap();
+        app.after_event().unwrap();
+        // the last member read is shown, the name is marked because it replaced another member
+        assert_eq!(app.to_strings(), [
+            " length: 1                        float ",
+            " number: 100                 kind:int32 "]);
+        let line = &app.layouts.items[1].get_screen(&app.data, 40, app.layouts.indents[0], &app.layout_config, None).0[0];
+        assert_eq!(line.0[1], ('n', TextStyle::Overridden));
+
+        let key = |c| KeyEvent::new(c, KeyModifiers::NONE);
+        app.selected = Selection { layout: 1, x: 0, y: 0 };
+        app.on_key(key(KeyCode::Char('O'))).unwrap();
+        app.on_key(key(KeyCode::Left)).unwrap();
+        assert_eq!(app.get_top_line(40, &LayoutConfig::default()), " oneof kind: [name] number              ");
+        app.on_key(key(KeyCode::Enter)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1], "   name: ''                 kind:string ");
+        assert_eq!(text_format::message_to_text(&app.data), "name: \"\"\nlength: 1\n");
+
+        // both old members come back
+        app.run_command(UserCommand::Undo).unwrap();
+        assert_eq!(text_format::message_to_text(&app.data), "name: \"abc\"\nlength: 1\nnumber: 100\n");
+    }
+
+    #[test]
+    fn map_view() {
+        let proto = ProtoData::new("message M { map<string, int32> counts = 1; }").unwrap().finalize().unwrap();
+        let def = proto.auto_detect_root_message().unwrap();
+        let data = text_format::text_to_message("counts { key: \"b\" value: 2 } counts { key: \"a\" value: 1 } counts { key: \"b\" value: 3 }", def).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 25).unwrap();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        app.on_key(key('S')).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), [
+            " counts:                  string,int32* ",
+            "      a: 1                              ",
+            "      b: 2                              ",
+            "      b: 3                              "]);
+        assert!(app.get_top_line(40, &LayoutConfig::default()).contains("counts key a…z"));
+
+        // the first entry with the key "b" is replaced by the last one, its key cannot be changed to a used one
+        app.selected = Selection { layout: 0, x: 0, y: 2 };
+        app.after_event().unwrap();
+        assert_eq!(app.get_top_line(60, &LayoutConfig::default()), " test_data.pb   counts 1/3 (duplicate key) key a…z   0% P ");
+        app.run_command(CollapsedToggle).unwrap();
+        app.run_command(DeleteData(true)).unwrap();
+        app.run_command(KeyPress('a')).unwrap();
+        assert_eq!(app.to_strings()[2..], ["      b: a (new key)                    ", "      b: 3                              ", "cursor: 10,3"]);
+        app.run_command(DeleteData(true)).unwrap();
+        app.run_command(KeyPress('c')).unwrap();
+        app.run_command(CollapsedToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1..], ["      a: 1                              ", "      b: 3                              ", "      c: 2                              "]);
+
+        // a new entry gets an unused key
+        app.run_command(InsertData).unwrap();
+        app.selected = Selection { layout: 0, x: 1, y: 1 };
+        app.run_command(KeyPress('7')).unwrap();
+        app.run_command(CollapsedToggle).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[1], "       : 7                              ");
+        assert_eq!(text_format::message_to_text(&app.data), "counts {\n  key: \"c\"\n  value: 2\n}\ncounts {\n  key: \"a\"\n  value: 1\n}\ncounts {\n  key: \"b\"\n  value: 3\n}\ncounts {\n  key: \"\"\n  value: 7\n}\n");
+    }
+
     fn make_repeated_int_data() -> App {
         let mut data = make_no_field_data("message M { repeated int32 i1=1; }");
         for v in 1..=6 {
@@ -1619,7 +2855,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         ];
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1642,7 +2878,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         ];
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1664,7 +2900,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
         ];
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -2057,7 +3293,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn start_edit_string() {
         let binary_input = [0x0A, 0x05, 'a' as u8, 'b' as u8, '\n' as u8, 'd' as u8, 'e' as u8];
         let proto = ProtoData::new("message M { string f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -2074,7 +3310,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn move_cursor_in_string() {
         let binary_input = [0x0A, 0x07, 'a' as u8, 'b' as u8, 'c' as u8, '\n' as u8, 'd' as u8, 'e' as u8, 'f' as u8];
         let proto = ProtoData::new("message M { string f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -2096,7 +3332,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn type_bytes_overwrite() {
         let binary_input = [0x0A, 0x02, 0x01, 0x02];
         let proto = ProtoData::new("message M { bytes f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -2127,7 +3363,7 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     fn type_bytes_from_empty() {
         let binary_input = [0x0A, 0x00];
         let proto = ProtoData::new("message M { bytes f1=1; }").unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -2143,6 +3379,277 @@ message M2 { int32 i2 = 2; int32 i3 = 3; }
     }
 
 
+    #[test]
+    fn undo_redo_delete() {
+        let binary_input = [0x08, 0x01, 0x10, 0x02, 0x18, 0x03];
+        let proto = ProtoData::new("message M { int32 f1=1; int32 f2=2; int32 f3=3; }").unwrap().finalize().unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.auto_detect_root_message().unwrap();
+        let mut read = PbReader::new(binary_input.as_slice());
+        let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
+
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 30, 25).unwrap();
+        let expected_start = [
+            " f1: 1                  int32 ",
+            " f2: 2                  int32 ",
+            " f3: 3                  int32 "];
+        let expected_deleted = [
+            " f1: 1                  int32 ",
+            " f2: 0                 -int32 ",
+            " f3: 3                  int32 "];
+        assert_eq!(app.to_strings(), expected_start);
+
+        app.run_command(UserCommand::ScrollVertically(1)).unwrap();
+        app.after_event().unwrap();
+        app.run_command(UserCommand::DeleteData(false)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_deleted);
+
+        app.run_command(UserCommand::ScrollVertically(-1)).unwrap();
+        app.after_event().unwrap();
+        app.run_command(UserCommand::Undo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_start);
+        assert_eq!(app.selected.layout, 1); // cursor moved to the restored field
+
+        app.run_command(UserCommand::Undo).unwrap(); // nothing to undo
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_start);
+
+        app.run_command(UserCommand::Redo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_deleted);
+
+        app.run_command(UserCommand::Redo).unwrap(); // nothing to redo
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_deleted);
+
+        app.run_command(UserCommand::Undo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_start);
+    }
+
+    #[test]
+    fn top_line_path() {
+        let mut data = make_repeated_message_data(2);
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 40, 25).unwrap();
+        assert_eq!(app.get_top_line(40, &app.layout_config), " test_data.pb        m1[0]         0% P ");
+        app.run_command(UserCommand::ScrollVertically(5)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.get_top_line(40, &app.layout_config), " test_data.pb      m1[1].i3       83% P ");
+    }
+
+    #[test]
+    fn search() {
+        let data = make_repeated_message_data(3);
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 30, 3 + TOP_LINE).unwrap();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        app.on_key(key('/')).unwrap();
+        app.on_key(key('7')).unwrap();
+        app.after_event().unwrap();
+        // the third message was not loaded before
+        assert_eq!(app.get_path_string(&app.layouts.items[app.selected.layout]), "m1[2].i3");
+        assert_eq!((app.selected.x, app.selected.y), (1, 0));
+        assert_eq!(app.get_top_line(30, &app.layout_config), " /7                       1/1 ");
+
+        app.on_key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE)).unwrap();
+        app.on_key(key('i')).unwrap();
+        app.on_key(key('3')).unwrap();
+        app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+        assert!(!app.search.prompt);
+        assert_eq!(app.search.hits.len(), 3);
+        app.on_key(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.get_path_string(&app.layouts.items[app.selected.layout]), "m1[1].i3");
+        assert_eq!((app.selected.x, app.selected.y), (0, 0));
+        app.on_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL)).unwrap();
+        app.on_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL)).unwrap(); // wrap around
+        app.after_event().unwrap();
+        assert_eq!(app.get_path_string(&app.layouts.items[app.selected.layout]), "m1[2].i3");
+
+        let mut line = ScreenLine::new(20);
+        line.add_string("I3: 13".to_string(), TextStyle::Value);
+        line.add_string("i3".to_string(), TextStyle::Typename);
+        line.highlight("i3");
+        let styles: Vec<TextStyle> = line.0.iter().map(|(_, s)| *s).collect();
+        assert_eq!(styles[..2], [TextStyle::Found, TextStyle::Found]);
+        assert!(styles[2..].iter().all(|s| *s != TextStyle::Found));
+    }
+
+    #[test]
+    fn undo_redo_nested() {
+        let mut data = make_repeated_message_data(2);
+        let mut app = App::for_tests(data.0, data.1, FieldOrder::Proto, 30, 25).unwrap();
+        let expected_start = [
+            " m1:                      M2* ",
+            "   i2: 2                int32 ",
+            "   i3: 3                int32 ",
+            " m1:                      M2* ",
+            "   i2: 4                int32 ",
+            "   i3: 5                int32 "];
+        assert_eq!(app.to_strings(), expected_start);
+
+        // delete a field of the second submessage
+        app.run_command(UserCommand::ScrollVertically(5)).unwrap();
+        app.after_event().unwrap();
+        app.run_command(UserCommand::DeleteData(false)).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings()[5], "   i3: 0               -int32 ");
+
+        // insert a new submessage
+        app.run_command(UserCommand::ScrollVertically(-5)).unwrap();
+        app.after_event().unwrap();
+        app.run_command(UserCommand::InsertData).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings().len(), 9);
+
+        app.run_command(UserCommand::Undo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings().len(), 6);
+        assert_eq!(app.selected.layout, 0);
+
+        app.run_command(UserCommand::Undo).unwrap();
+        app.after_event().unwrap();
+        assert_eq!(app.to_strings(), expected_start);
+        assert_eq!(app.selected.layout, 5);
+
+        app.run_command(UserCommand::Redo).unwrap();
+        app.after_event().unwrap();
+        app.run_command(UserCommand::Redo).unwrap();
+        app.after_event().unwrap();
+        let expected_end = [
+            " m1:                      M2* ",
+            "   i2: 0               -int32 ",
+            "   i3: 0               -int32 ",
+            " m1:                      M2* ",
+            "   i2: 2                int32 ",
+            "   i3: 3                int32 ",
+            " m1:                      M2* ",
+            "   i2: 4                int32 ",
+            "   i3: 0               -int32 "];
+        assert_eq!(app.to_strings(), expected_end);
+    }
+
+    #[test]
+    fn undo_bytes_typing() {
+        let binary_input = [0x0A, 0x02, 0x01, 0x02];
+        let proto = ProtoData::new("message M { bytes f1=1; }").unwrap().finalize().unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.auto_detect_root_message().unwrap();
+        let mut read = PbReader::new(binary_input.as_slice());
+        let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
+
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 25, 25).unwrap();
+        app.run_command(ScrollHorizontally(1)).unwrap();
+        app.to_strings();
+        app.run_command(KeyPress('f')).unwrap();
+        app.run_command(KeyPress('e')).unwrap();
+        app.run_command(KeyPress('2')).unwrap();
+        app.run_command(KeyPress('2')).unwrap();
+        assert_eq!(app.to_strings()[0], " f1: FE 22         bytes ");
+
+        app.run_command(UserCommand::Undo).unwrap();
+        assert_eq!(app.to_strings()[0], " f1: FE 02         bytes ");
+        app.run_command(UserCommand::Undo).unwrap();
+        assert_eq!(app.to_strings()[0], " f1: 01 02         bytes ");
+        app.run_command(UserCommand::Redo).unwrap();
+        assert_eq!(app.to_strings()[0], " f1: FE 02         bytes ");
+    }
+
+    #[test]
+    fn raw_tree() {
+        let binary_input = [0x08, 0x96, 0x01, 0x12, 0x05, 0x0A, 0x03, 0x61, 0x62, 0x63, 0x12, 0x02, 0x10, 0x01];
+        let proto = raw::guess_proto(&binary_input).unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.get_message_definition(raw::RAW_ROOT_NAME).unwrap();
+        let mut read = PbReader::new(binary_input.as_slice());
+        let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
+
+        let mut app = App::for_tests(data, proto, FieldOrder::Wire, 30, 25).unwrap();
+        assert_eq!(app.to_strings(), [
+            " 1: 150                 int64 ",
+            " 2:                    raw.2* ",
+            "   1: 'abc'            string ",
+            " 2:                    raw.2* ",
+            "   2: 1                 int64 "]);
+    }
+
+    #[test]
+    fn save_next_to_file() {
+        let dir = std::env::temp_dir().join(format!("protoedit_save_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tmp"), "other file").unwrap();
+        let proto = ProtoData::new("message M { int32 f1 = 1; }").unwrap().finalize().unwrap();
+        let root_msg = proto.auto_detect_root_message().unwrap();
+        for name in ["a.pb", "b.tmp"] {
+            let mut data = MessageData { def: root_msg.clone(), fields: vec![] };
+            data.add_field(&[(1, 0).into()]).unwrap().value = SCALAR(I32(5));
+            let binary_file = dir.join(name).display().to_string();
+            save(&Document { binary_file: binary_file.clone(), proto: ProtoData::new("message M { int32 f1 = 1; }").unwrap().finalize().unwrap(), data }).unwrap();
+            assert_eq!(std::fs::read(&binary_file).unwrap(), [0x08, 0x05]);
+        }
+        assert_eq!(std::fs::read_to_string(dir.join("a.tmp")).unwrap(), "other file");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_json() {
+        let dir = std::env::temp_dir().join(format!("protoedit_import_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proto = ProtoData::new("message M { int32 f1 = 1; S s = 2; } message S { int32 a = 1; string b = 2; }").unwrap().finalize().unwrap();
+        let data = text_format::text_to_message("f1: 1 s { a: 2 }", proto.get_message_definition("M").unwrap()).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 10).unwrap();
+        app.layouts.file_path = dir.join("data.pb");
+        let key = |app: &mut App, code: KeyCode, modifiers: KeyModifiers| { app.on_key(KeyEvent::new(code, modifiers)).unwrap(); };
+
+        key(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert_eq!(app.message.as_deref(), Some("only a message can be imported from JSON"));
+        key(&mut app, KeyCode::Down, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert!(app.message.as_ref().unwrap().starts_with(&dir.join("data.json").display().to_string()));
+
+        std::fs::write(dir.join("data.json"), r#"{"a": 1-2}"#).unwrap();
+        key(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert_eq!(app.message, Some(format!("{}: line 1, column 7: wrong number 1-2", dir.join("data.json").display())));
+
+        std::fs::write(dir.join("data.json"), r#"{"a": 7, "b": "x"}"#).unwrap();
+        key(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert_eq!(app.message, Some(format!("imported {}", dir.join("data.json").display())));
+        assert_eq!(app.data.get_submessage(&[(2, 0).into()]).unwrap().to_string(), "message S {\n  a = 7\n  b = x\n}\n");
+        key(&mut app, KeyCode::Char('z'), KeyModifiers::CONTROL); // undo
+        assert_eq!(app.data.get_submessage(&[(2, 0).into()]).unwrap().to_string(), "message S {\n  a = 2\n}\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn export_json() {
+        let dir = std::env::temp_dir().join(format!("protoedit_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proto = ProtoData::new("message M { int32 f1 = 1; }").unwrap().finalize().unwrap();
+        let data = text_format::text_to_message("f1: 5", proto.get_message_definition("M").unwrap()).unwrap();
+        let mut app = App::for_tests(data, proto, FieldOrder::Proto, 40, 10).unwrap();
+        app.layouts.file_path = dir.join("data.pb");
+        let file = dir.join("data.json");
+        let key = |app: &mut App, code: KeyCode, modifiers: KeyModifiers| { app.on_key(KeyEvent::new(code, modifiers)).unwrap(); };
+
+        key(&mut app, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        assert_eq!(app.message, Some(format!("exported to {}", file.display())));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "5\n");
+
+        std::fs::write(&file, "other").unwrap();
+        key(&mut app, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        assert_eq!(app.message, Some(format!("overwrite {}? (y/n)", file.display())));
+        key(&mut app, KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(app.message.as_deref(), Some("not exported"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "other");
+
+        key(&mut app, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        key(&mut app, KeyCode::Char('y'), KeyModifiers::NONE);
+        assert_eq!(app.message, Some(format!("exported to {}", file.display())));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "5\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     // TODO unknown field layout
     // TODO delete a field of a submessage
 }
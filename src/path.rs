@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use crate::json::is_map;
+use crate::proto::{FieldProtoPtr, MessageProtoPtr};
+use crate::text_format::{scalar_to_text, text_to_value};
+use crate::wire::*;
+
+// Textual field paths: field names separated by dots, with an index for repeated fields and a key for maps,
+// e.g. m3.m6[2].f8, items[*].name or counts["key"]; a field may be given by its number.
+// A repeated field without an index means all its values, a map key addresses the value of the entry.
+// If the data has several entries with the same key, the last one is used, as by readers of the wire format.
+
+pub(crate) const MAP_KEY_ID: i32 = 1;
+pub(crate) const MAP_VALUE_ID: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum StepIndex {
+    All,
+    At(usize),
+    Key(ScalarValue),
+}
+
+pub struct PathStep {
+    pub def: FieldProtoPtr,
+    pub index: StepIndex,
+}
+
+impl PathStep {
+    // the field with the data, it is the value of the entry for maps
+    pub fn value_def(&self) -> FieldProtoPtr {
+        if is_map(&self.def) {
+            self.def.message_proto().and_then(|entry| entry.get_field(MAP_VALUE_ID)).unwrap()
+        } else {
+            self.def.clone()
+        }
+    }
+}
+
+pub struct PathQuery(pub Vec<PathStep>);
+
+impl PathQuery {
+    pub fn parse(def: &MessageProtoPtr, text: &str) -> Result<PathQuery, String> {
+        let mut steps: Vec<PathStep> = vec![];
+        let mut msg_def = Some(def.clone());
+        let mut rest = text;
+        loop {
+            let name_len = rest.find(['.', '[']).unwrap_or(rest.len());
+            let name = &rest[..name_len];
+            rest = &rest[name_len..];
+
+            let Some(parent) = msg_def else {
+                return Err(format!("\"{}\" is not a message field", steps.last().unwrap().def.name()));
+            };
+            if name.is_empty() { return Err(format!("field name expected at \"{}\"", rest)); }
+            let field_def = parent.fields.iter().find(|f| f.name() == name).cloned()
+                .or_else(|| parent.get_field(name.parse().ok()?))
+                .ok_or_else(|| format!("unknown field \"{}\" of {}", name, parent.name))?;
+
+            let mut index = if field_def.repeated() { StepIndex::All } else { StepIndex::At(0) };
+            if let Some(after_bracket) = rest.strip_prefix('[') {
+                let (index_text, after_index) = split_index(after_bracket)
+                    .ok_or_else(|| format!("\"]\" expected after \"{}\"", rest))?;
+                rest = after_index;
+                index = parse_index(&field_def, index_text)?;
+            }
+
+            msg_def = if is_map(&field_def) {
+                field_def.message_proto().and_then(|entry| entry.get_field(MAP_VALUE_ID)).and_then(|v| v.message_proto())
+            } else {
+                field_def.message_proto()
+            };
+            steps.push(PathStep { def: field_def, index });
+
+            if rest.is_empty() { break; }
+            rest = rest.strip_prefix('.').ok_or_else(|| format!("\".\" expected at \"{}\"", rest))?;
+        }
+        Ok(PathQuery(steps))
+    }
+
+    // a single place in the data, which may not exist yet
+    pub fn is_exact(&self) -> bool {
+        self.0.iter().all(|step| step.index != StepIndex::All)
+    }
+
+    pub fn last(&self) -> &PathStep {
+        self.0.last().unwrap()
+    }
+
+    // paths of the existing data; a map value may be absent in its entry
+    pub fn resolve(&self, root: &MessageData) -> Vec<FieldPath> {
+        let mut res = vec![];
+        resolve_steps(root, &self.0, FieldPath::new(), &mut res);
+        res
+    }
+
+    // the path to the data, missing fields and map entries are added; only for exact paths
+    pub fn create(&self, root: &mut MessageData) -> Result<FieldPath, String> {
+        debug_assert!(self.is_exact());
+        let mut path = FieldPath::new();
+        for step in &self.0 {
+            // the parser checks that parents are messages, a damaged one is not changed
+            let msg = root.get_submessage_mut(&path.0).ok_or_else(|| "the data is damaged and cannot be changed".to_string())?;
+            let id = step.def.id();
+            let count = msg.fields.iter().filter(|f| f.id() == id).count();
+            match &step.index {
+                StepIndex::Key(key) => {
+                    let index = match find_entries(msg, id, key).last() {
+                        Some(index) => *index,
+                        None => {
+                            let FieldValue::MESSAGE(entry) = &mut msg.add_field(&[FieldPos { id, index: count }]).unwrap().value else { unreachable!() };
+                            entry.add_field(&[FieldPos { id: MAP_KEY_ID, index: 0 }]).unwrap().value = FieldValue::SCALAR(key.clone());
+                            count
+                        }
+                    };
+                    path.push(FieldPos { id, index });
+                    path.push(FieldPos { id: MAP_VALUE_ID, index: 0 });
+                    let entry = msg.get_submessage_mut(&[FieldPos { id, index }]).unwrap();
+                    if entry.get_field(&[FieldPos { id: MAP_VALUE_ID, index: 0 }]).is_none() {
+                        entry.add_field(&[FieldPos { id: MAP_VALUE_ID, index: 0 }]);
+                    }
+                }
+                StepIndex::At(index) => {
+                    if *index > count {
+                        return Err(format!("index {} of \"{}\" is out of range, the field has {} values", index, step.def.name(), count));
+                    }
+                    if *index == count { msg.add_field(&[FieldPos { id, index: count }]); }
+                    path.push(FieldPos { id, index: *index });
+                }
+                StepIndex::All => unreachable!(),
+            }
+        }
+        Ok(path)
+    }
+}
+
+// the index text and the rest after "]", the closing bracket may be in a quoted key
+fn split_index(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, c) in text.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ']' => return Some((&text[..pos], &text[pos + 1..])),
+            None => {}
+        }
+    }
+    None
+}
+
+fn parse_index(def: &FieldProtoPtr, text: &str) -> Result<StepIndex, String> {
+    if text == "*" {
+        return Ok(StepIndex::All);
+    }
+    if is_map(def) {
+        let key_def = def.message_proto().and_then(|entry| entry.get_field(MAP_KEY_ID)).unwrap();
+        let key = match key_def.default() {
+            FieldValue::SCALAR(ScalarValue::STR(_)) if !text.starts_with(['"', '\'']) => FieldValue::SCALAR(ScalarValue::STR(text.to_string())),
+            _ => text_to_value(text, &key_def).map_err(|_| format!("wrong key \"{}\" of \"{}\"", text, def.name()))?,
+        };
+        let FieldValue::SCALAR(key) = key else { unreachable!() };
+        return Ok(StepIndex::Key(key));
+    }
+    let index = text.parse::<usize>().map_err(|_| format!("wrong index \"{}\" of \"{}\"", text, def.name()))?;
+    if index > 0 && !def.repeated() {
+        return Err(format!("field \"{}\" is not repeated", def.name()));
+    }
+    Ok(StepIndex::At(index))
+}
+
+pub(crate) fn map_key(entry: &MessageData) -> ScalarValue {
+    match entry.get_field(&[FieldPos { id: MAP_KEY_ID, index: 0 }]).map(|f| &f.value) {
+        Some(FieldValue::SCALAR(key)) => key.clone(),
+        _ => match entry.def.get_field(MAP_KEY_ID).map(|def| def.default()) {
+            Some(FieldValue::SCALAR(key)) => key,
+            _ => ScalarValue::DELETED,
+        },
+    }
+}
+
+// indexes of map entries with the key
+pub(crate) fn find_entries(msg: &MessageData, id: i32, key: &ScalarValue) -> Vec<usize> {
+    msg.fields.iter().filter(|f| f.id() == id).enumerate()
+        .filter(|(_, f)| matches!(&f.value, FieldValue::MESSAGE(entry) if map_key(entry) == *key))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn resolve_steps(msg: &MessageData, steps: &[PathStep], prefix: FieldPath, res: &mut Vec<FieldPath>) {
+    let Some((step, rest)) = steps.split_first() else { return; };
+    let id = step.def.id();
+    let count = msg.fields.iter().filter(|f| f.id() == id).count();
+    let indexes: Vec<usize> = match &step.index {
+        StepIndex::All => (0..count).collect(),
+        StepIndex::At(index) => if *index < count { vec![*index] } else { vec![] },
+        StepIndex::Key(key) => find_entries(msg, id, key).pop().into_iter().collect(), // the last entry wins
+    };
+    for index in indexes {
+        let mut path = prefix.add(FieldPos { id, index });
+        if is_map(&step.def) {
+            path.push(FieldPos { id: MAP_VALUE_ID, index: 0 });
+        }
+        if rest.is_empty() {
+            res.push(path);
+        } else if let Some(sub) = msg.get_submessage(&path.0[prefix.0.len()..]) {
+            resolve_steps(sub, rest, path, res);
+        }
+    }
+}
+
+// indexes of map entries replaced by a later entry with the same key, in one pass from the end
+pub(crate) fn overridden_entries(msg: &MessageData, id: i32) -> HashSet<usize> {
+    let mut seen = HashSet::new(); // map keys are integers, strings or bools, so their texts are unique
+    let entries: Vec<&FieldData> = msg.fields.iter().filter(|f| f.id() == id).collect();
+    (0..entries.len()).rev().filter(|&index| {
+        let key = match &entries[index].value {
+            FieldValue::MESSAGE(entry) => map_key(entry),
+            FieldValue::SCALAR(_) => ScalarValue::DELETED,
+        };
+        !seen.insert(format!("{:?}", key))
+    }).collect()
+}
+
+// the reverse of PathQuery::parse, map entries are shown by keys
+pub fn format_path(root: &MessageData, path: &FieldPath) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut msg_def = Some(root.def.clone());
+    let mut i = 0;
+    while i < path.0.len() {
+        let pos = &path.0[i];
+        let Some(def) = msg_def.as_ref().and_then(|m| m.get_field(pos.id)) else {
+            parts.push(pos.id.to_string()); // unknown field
+            break;
+        };
+        if is_map(&def) {
+            let key = root.get_submessage(&path.0[..=i]).map(map_key).unwrap_or(ScalarValue::DELETED);
+            let key_def = def.message_proto().and_then(|entry| entry.get_field(MAP_KEY_ID)).unwrap();
+            parts.push(format!("{}[{}]", def.name(), scalar_to_text(&key_def, &key)));
+            let value_def = def.message_proto().and_then(|entry| entry.get_field(MAP_VALUE_ID));
+            msg_def = value_def.and_then(|v| v.message_proto());
+            i += 1; // the key or value of the entry is shown as the entry
+        } else {
+            if def.repeated() {
+                parts.push(format!("{}[{}]", def.name(), pos.index));
+            } else {
+                parts.push(def.name());
+            }
+            msg_def = def.message_proto();
+        }
+        i += 1;
+    }
+    parts.join(".")
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod query {
+    use super::*;
+    use crate::proto::ProtoData;
+    use crate::text_format::text_to_message;
+
+    const PROTO: &str = r#"
+message Root {
+  int32 id = 1;
+  repeated Item items = 2;
+  map<string, Item> named = 3;
+  map<int32, string> codes = 4;
+}
+message Item {
+  string name = 1;
+  repeated int32 ids = 2;
+}
+"#;
+
+    fn make_data(text: &str) -> MessageData {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        text_to_message(text, proto.get_message_definition("Root").unwrap()).unwrap()
+    }
+
+    fn resolve(data: &MessageData, query: &str) -> Vec<String> {
+        PathQuery::parse(&data.def, query).unwrap().resolve(data).iter().map(|p| format_path(data, p)).collect()
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let data = make_data(r#"id: 1 items { name: "a" ids: 5 ids: 6 } items { name: "b" }
+            named { key: "x.y]" value { name: "c" } } codes { key: 7 value: "seven" } codes { key: 8 }"#);
+        assert_eq!(resolve(&data, "id"), ["id"]);
+        assert_eq!(resolve(&data, "2[1].1"), ["items[1].name"]);
+        assert_eq!(resolve(&data, "items[*].name"), ["items[0].name", "items[1].name"]);
+        assert_eq!(resolve(&data, "items.ids"), ["items[0].ids[0]", "items[0].ids[1]"]);
+        assert_eq!(resolve(&data, "items[2]"), Vec::<String>::new());
+        assert_eq!(resolve(&data, r#"named["x.y]"].name"#), [r#"named["x.y]"].name"#]);
+        assert_eq!(resolve(&data, "codes[8]"), ["codes[8]"]); // the value is not set
+        assert_eq!(resolve(&data, "codes"), ["codes[7]", "codes[8]"]);
+
+        let path = PathQuery::parse(&data.def, "codes[7]").unwrap().resolve(&data).remove(0);
+        assert_eq!(path.0, [FieldPos { id: 4, index: 0 }, FieldPos { id: 2, index: 0 }]);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let mut data = make_data("codes { key: 7 value: \"a\" } codes { key: 8 } codes { key: 7 value: \"b\" }");
+        let path = PathQuery::parse(&data.def, "codes[7]").unwrap().resolve(&data).remove(0);
+        assert_eq!(path.0[0], FieldPos { id: 4, index: 2 });
+        assert_eq!(overridden_entries(&data, 4), HashSet::from([0]));
+        let created = PathQuery::parse(&data.def, "codes[7]").unwrap().create(&mut data).unwrap();
+        assert_eq!(created.0, path.0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let data = make_data("");
+        let error = |query: &str| PathQuery::parse(&data.def, query).err().unwrap();
+        assert_eq!(error("other"), "unknown field \"other\" of Root");
+        assert_eq!(error("id.x"), "\"id\" is not a message field");
+        assert_eq!(error("id[1]"), "field \"id\" is not repeated");
+        assert_eq!(error("items[x]"), "wrong index \"x\" of \"items\"");
+        assert_eq!(error("items[0"), "\"]\" expected after \"[0\"");
+        assert_eq!(error("items[0]name"), "\".\" expected at \"name\"");
+        assert_eq!(error("items..name"), "field name expected at \".name\"");
+        assert_eq!(error("codes[a]"), "wrong key \"a\" of \"codes\"");
+    }
+
+    #[test]
+    fn create() {
+        let mut data = make_data("items { name: \"a\" }");
+        for query in ["items[0].ids[0]", "items[1].name", "named[new].ids[0]", "codes[-1]", "codes[-1]"] {
+            PathQuery::parse(&data.def, query).unwrap().create(&mut data).unwrap();
+        }
+        assert_eq!(crate::text_format::message_to_text(&data),
+                   "items {\n  name: \"a\"\n  ids: 0\n}\nitems {\n  name: \"\"\n}\nnamed {\n  key: \"new\"\n  value {\n    ids: 0\n  }\n}\ncodes {\n  key: -1\n  value: \"\"\n}\n");
+        let error = PathQuery::parse(&data.def, "items[3]").unwrap().create(&mut data).err().unwrap();
+        assert_eq!(error, "index 3 of \"items\" is out of range, the field has 2 values");
+    }
+}
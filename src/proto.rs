@@ -1,56 +1,118 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::{io, rc};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::str::FromStr;
-use pest::iterators::{Pairs};
 use crate::typedefs::*;
-
-use pest::Parser;
-use pest_derive::Parser;
-
-#[derive(Parser)]
-#[grammar = "pb.pest"]
-pub struct PBParser;
+use crate::stream::Framing;
+use crate::{proto_parser, well_known};
+use crate::text_format::default_to_scalar;
+use crate::wire::FieldValue;
 
 
 pub struct ProtoFile {
     path: PathBuf,
+    pub name: String, // as imported, or as given for the main file
     pub content: String,
 }
 
 pub struct ProtoData {
-    messages: Vec<MessageProtoPtr>,
-    enums: Vec<EnumProtoPtr>,
+    pub(crate) messages: Vec<MessageProtoPtr>,
+    pub(crate) enums: Vec<EnumProtoPtr>,
+    pub(crate) extensions: Vec<(String, String, Vec<FieldProtoPtr>)>, // scope of the extend block, extended message, fields
+    pub services: Vec<ServiceProto>,
+    pub files: Vec<FileProto>, // the parsed files, their definitions are in the lists above
     pub unknown_field: FieldProtoPtr, //UnknownFieldDefinition,
+    pub stream: Option<Framing>, // the root message is a stream of size-prefixed messages (see stream.rs)
+    pub(crate) places: HashMap<String, String>, // "file: line L, column C" of the declarations by full name, for errors
+    pub(crate) spans: HashMap<String, Span>, // of the declarations by full name, enum values and methods in the scope of their type
+}
+
+// where a declaration is in its proto file, for the source_code_info of a descriptor set
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: (i32, i32), // line and column, from 0
+    pub end: (i32, i32), // after the declaration
+    pub trailing: bool, // the comment of the declaration is the one after it on its line
 }
 
 pub type FieldProtoPtr = Rc<dyn FieldProto>;
 pub type MessageProtoPtr = Rc<MessageProto>;
 pub type EnumProtoPtr = Rc<EnumProto>;
 
+#[derive(Default)]
 pub struct MessageProto {
     pub name: String,
     pub fields: Vec<FieldProtoPtr>,
     pub comment: String,
+    pub reserved: Vec<(i32, i32)>, // field numbers, inclusive ranges
+    pub reserved_names: Vec<String>,
+    pub extension_ranges: Vec<(i32, i32)>, // inclusive
+    pub options: Vec<ProtoOption>,
 }
 
 pub struct EnumProto {
     pub name: String,
     pub variants: Vec<(String, i32, String)>, // name, id, comment
     pub comment: String,
+    pub options: Vec<ProtoOption>,
+    pub reserved: Vec<(i32, i32)>, // values, inclusive ranges
+    pub reserved_names: Vec<String>,
+}
+
+pub struct ServiceProto {
+    pub name: String,
+    pub methods: Vec<MethodProto>,
+    pub comment: String,
+    pub options: Vec<ProtoOption>,
+}
+
+pub struct MethodProto {
+    pub name: String,
+    pub input_type: String, // as written, resolved in the package of the service
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub comment: String,
+    pub options: Vec<ProtoOption>,
+}
+
+// the declarations of a proto file other than types
+#[derive(Default, Debug)]
+pub struct FileProto {
+    pub name: String,
+    pub syntax: String, // proto2, proto3 or editions
+    pub edition: String, // e.g. 2023
+    pub package: String,
+    pub imports: Vec<Import>,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Import {
+    pub name: String,
+    pub public: bool,
+    pub weak: bool,
+}
+
+// an option as written in the proto file, e.g. name "(my.ext).flag" and value "true", or a message in braces
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProtoOption {
+    pub name: String,
+    pub value: String,
 }
 
 impl ProtoData {
     pub fn new(input: &str) -> io::Result<ProtoData> {
-        match PBParser::parse(Rule::file, input) {
-            Ok(rules_pairs) => {
-                let proto_data = ProtoData::from_pairs(rules_pairs);
-                Ok(proto_data)
-            }
-            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
-        }
+        proto_parser::parse(input, "").map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // errors show the path of the file
+    pub fn from_file(file: &ProtoFile) -> io::Result<ProtoData> {
+        let mut res = proto_parser::parse(&file.content, &file.path.display().to_string()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        res.files[0].name = file.name.clone();
+        Ok(res)
     }
     pub fn auto_detect_root_message(&self) -> Option<MessageProtoPtr> {
 
@@ -58,16 +120,19 @@ impl ProtoData {
         let all_msg_names: HashSet<String> = self.messages.iter().map(|m| m.name.clone()).collect();
 
         // remove auto-created messages for map fields
-        let all_msg_names = all_msg_names.into_iter().filter(|m| !m.contains(",")).collect();
+        let all_msg_names: HashSet<String> = all_msg_names.into_iter().filter(|m| !m.contains(",")).collect();
 
         let mut sub_msg_names = vec![];
-        for msg in &self.messages {
-            for fld in &msg.fields {
-                //                if fld.is_message() { // unless the proto data finalized we do not know is it a message
-                if fld.typename() != msg.name {
-                    sub_msg_names.push(fld.typename());
+        let message_fields = self.messages.iter().flat_map(|m| m.fields.iter().map(move |f| (m.name.as_str(), f)));
+        let extension_fields = self.extensions.iter().flat_map(|(scope, _, fields)| fields.iter().map(move |f| (scope.as_str(), f)));
+        for (scope, fld) in message_fields.chain(extension_fields) {
+            // unless the proto data finalized we do not know is it a message, so resolve the name here
+            let typename = fld.typename();
+            let typename = typename.rsplit(',').next().unwrap(); // value type of a map
+            if let Some(name) = scope_candidates(scope, typename).into_iter().find(|n| all_msg_names.contains(n)) {
+                if name != scope {
+                    sub_msg_names.push(name);
                 }
-                //                }
             }
         }
 
@@ -84,11 +149,12 @@ impl ProtoData {
         None
     }
 
+    // search by the fully qualified name, or by a short name if it is unique
     pub fn get_message_definition(&self, name: &str) -> Option<MessageProtoPtr> {
         if let Ok(index) = self.messages.binary_search_by(|m| m.name.as_str().cmp(name)) {
             Some(self.messages[index].clone())
         } else {
-            None
+            Self::find_unique(&self.messages, name, |m| &m.name).cloned()
         }
     }
 
@@ -96,208 +162,80 @@ impl ProtoData {
         if let Ok(index) = self.enums.binary_search_by(|m| m.name.as_str().cmp(name)) {
             Some(&self.enums[index])
         } else {
-            None
+            Self::find_unique(&self.enums, name, |e| &e.name).map(|e| e.as_ref())
         }
     }
 
+    fn find_unique<'a, T>(items: &'a [Rc<T>], short_name: &str, full_name: impl Fn(&T) -> &str) -> Option<&'a Rc<T>> {
+        let suffix = format!(".{}", short_name);
+        let mut found = items.iter().filter(|item| full_name(item).ends_with(&suffix));
+        let first = found.next();
+        if found.next().is_none() { first } else { None }
+    }
+
     pub fn append(&mut self, mut other: ProtoData) {
         self.messages.append(&mut other.messages);
         self.enums.append(&mut other.enums);
+        self.extensions.append(&mut other.extensions);
+        self.services.append(&mut other.services);
+        self.files.append(&mut other.files);
+        self.places.extend(other.places); // a name declared again is reported at its last declaration
+        self.spans.extend(other.spans);
     }
 
-    fn add_message(pairs: Pairs<Rule>, comment: String) -> ProtoData {
-        let mut it = pairs.into_iter(); // first get the message name
-        let name_rule = it.next().unwrap();
-        debug_assert_eq!(name_rule.as_rule(), Rule::name);
-        let name = name_rule.as_span().as_str().to_string();
-        let mut field_comment = String::new();
-
-        let mut fields: Vec<Rc<dyn FieldProto>> = Vec::new(); // read message fields and other content
-        let mut res = ProtoData { messages: vec![], enums: vec![], unknown_field: Rc::new(UnknownFieldDefinition::new()) };
-        for pair in it {
-            match pair.as_rule() {
-                Rule::msg_field => {
-                    fields.push(Self::field_from_pair(field_comment.clone(), pair.into_inner(), None));
-                    field_comment.clear();
-                }
-                Rule::enum1 => {
-                    res.enums.push(Self::add_enum(pair.into_inner(), field_comment.clone()));
-                    field_comment.clear();
-                }
-                Rule::message => {
-                    res.append(Self::add_message(pair.into_inner(), field_comment.clone()));
-                    field_comment.clear();
-                }
-                Rule::one_of => {
-                    let mut it = pair.into_inner().into_iter();
-                    let name_rule = it.next().unwrap();
-                    debug_assert_eq!(name_rule.as_rule(), Rule::name);
-                    let oneof_name = Some(name_rule.as_span().as_str().to_string());
-
-                    for pair in it {
-                        match pair.as_rule() {
-                            Rule::msg_field => {
-                                fields.push(Self::field_from_pair(field_comment.clone(), pair.into_inner(), oneof_name.clone()));
-                                field_comment.clear();
-                            }
-                            Rule::COMMENT => {
-                                if !field_comment.is_empty() { field_comment += "\n"; }
-                                field_comment += pair.as_span().as_str().trim_start_matches("//");
-                            }
-                            //Rule::option | Rule::EOI
-                            _ => { panic!("Unknown oneof rule: {:?}", pair.as_rule()); }
-                        }
-                    }
-                }
-                Rule::COMMENT => {
-                    if !field_comment.is_empty() { field_comment += "\n"; }
-                    field_comment += pair.as_span().as_str().trim_start_matches("//");
-                }
-                Rule::mapname |
-                Rule::option | Rule::EOI => {}
-                _ => { panic!("Unknown message rule: {:?}", pair.as_rule()); }
-            };
-        }
-
-        res.messages.push(Rc::new(MessageProto { name, fields, comment }));
-        return res;
-    }
-
-    fn add_enum(pairs: Pairs<Rule>, comment: String) -> EnumProtoPtr {
-        let mut variants = Vec::new();
-        let mut field_comment = String::new();
-
-        let mut it = pairs.into_iter();
-        let name_rule = it.next().unwrap();
-        debug_assert_eq!(name_rule.as_rule(), Rule::name);
-        let name = name_rule.as_span().as_str().to_string();
-
-        for pair in it {
-            match pair.as_rule() {
-                Rule::enum_field => {
-                    let mut it = pair.into_inner();
-                    let name = it.next().unwrap().as_str().to_string();
-                    let value = it.next().unwrap().as_str().to_string();
-                    variants.push((name, value.parse().unwrap(), field_comment.clone()));
-                    field_comment.clear();
-                    if let Some(r) = it.next() {
-                        if r.as_rule() == Rule::COMMENT {
-                            if !field_comment.is_empty() { field_comment += "\n"; }
-                            field_comment += r.as_span().as_str().trim_start_matches("//");
-                        }
-                    }
-                }
-                Rule::option | Rule::EOI => {}
-                _ => {
-                    panic!("Unknown enum rule: {:?}", pair.as_rule());
-                }
-            };
-        }
-
-        Rc::new(EnumProto { name, variants, comment })
+    // definitions created without a proto file (see raw.rs)
+    pub fn from_messages(messages: Vec<MessageProto>) -> ProtoData {
+        let messages = messages.into_iter().map(Rc::new).collect();
+        ProtoData { messages, unknown_field: Rc::new(UnknownFieldDefinition::new()), ..Default::default() }
     }
 
-    fn field_from_pair(comment: String, pairs: Pairs<Rule>, oneof_name: Option<String>) -> Rc<dyn FieldProto> {
-        let mut name = String::new();
-        let mut repeated = false;
-        let mut type_name = String::new();
-        let mut id = 0;
-        //        let mut map_types : Option<(String, String)> = None;
-
-        for pair in pairs {
-            match pair.as_rule() {
-                Rule::cardinality => {
-                    repeated = match pair.as_span().as_str() {
-                        "repeated" => true,
-                        _ => false,
-                    }
-                }
-                Rule::mapname => {
-                    let mut it = pair.into_inner();
-                    let key_type = it.next().unwrap().as_str().to_string();
-                    let value_type = it.next().unwrap().as_str().to_string();
-                    type_name = format!("{},{}", key_type, value_type);
-                    //if repeated { warn!("map field ({}) cannot be repeated", name); }
-                    repeated = true;
-                }
-                Rule::typename => {
-                    type_name = pair.as_str().to_string();
-                }
-                Rule::name => {
-                    name = pair.as_span().as_str().to_string();
-                }
-                Rule::integer => {
-                    id = pair.as_span().as_str().parse().unwrap();
-                }
-                Rule::COMMENT | //=> { comments = comments + pair.as_span().as_str(); }
-                Rule::option | Rule::EOI => {}
-                _ => {
-                    panic!("Unknown field rule: {:?}", pair.as_rule());
-                }
-            }
-        };
-
-        return CommonFieldProto::new_field(name, type_name, id, repeated, comment, oneof_name);
-    }
-
-    fn from_pairs(pairs: Pairs<Rule>) -> ProtoData {
-        let mut res = ProtoData { messages: vec![], enums: vec![], unknown_field: Rc::new(UnknownFieldDefinition::new()) };
-        let mut comments = String::new();
-        for pair in pairs {
-            for inner_pair in pair.into_inner() {
-                match inner_pair.as_rule() {
-                    //                    Rule::file => { return Self::from_pairs(inner_pair.into_inner()); }
-                    Rule::message => {
-                        res.append(Self::add_message(inner_pair.into_inner(), comments.clone()));
-                        comments.clear();
-                    }
-                    Rule::enum1 => {
-                        res.enums.push(Self::add_enum(inner_pair.into_inner(), comments.clone()));
-                        comments.clear();
-                    }
-                    Rule::COMMENT => {
-                        if !comments.is_empty() { comments += "\n"; }
-                        comments += inner_pair.as_span().as_str().trim_start_matches("//");
-                    }
-                    Rule::option | Rule::EOI => {}
-                    _ => {
-                        panic!("Unknown rule: {:?}", inner_pair.as_rule());
-                    }
-                };
-            }
+    // a definition added after finalize, e.g. the root message of a stream
+    pub fn insert_message(&mut self, msg: MessageProto) -> MessageProtoPtr {
+        let msg = Rc::new(msg);
+        for field in &msg.fields {
+            field.link_user_types(&msg.name, &self.enums, &self.messages);
         }
-        //        res.create_map_messages();
-        //        res.messages.sort_by(|a, b| a.name.cmp(&b.name));
-        //        res.enums.sort_by(|a, b| a.name.cmp(&b.name));
-        //        res.link_user_types();
-        res
+        match self.messages.binary_search_by(|m| m.name.cmp(&msg.name)) {
+            Ok(index) => self.messages[index] = msg.clone(),
+            Err(index) => self.messages.insert(index, msg.clone()),
+        }
+        msg
     }
 
-    fn create_map_messages(&mut self) {
-        let mut map_names = vec![]; // collect maps fields from all messages
+    // returns the scope for each map message, the value type is resolved in the scope of the map field
+    // a map message is named by the map type in the message of the field, e.g. "p.M.string,Status",
+    // so the field finds it first and the same map type in other messages resolves its own value type
+    fn create_map_messages(&mut self) -> HashMap<String, String> {
+        let mut map_scopes = HashMap::new(); // collect maps fields from all messages
+        let mut places = HashMap::new();
         for msg in &self.messages {
             for field in &msg.fields {
                 if field.typename().contains(',') {
-                    map_names.push(field.typename());
+                    let name = full_name(&msg.name, &field.typename());
+                    if let Some(place) = self.places.get(&full_name(&msg.name, &field.name())) {
+                        places.insert(name.clone(), place.clone()); // errors of the map types are reported at the field
+                    }
+                    map_scopes.insert(name, msg.name.clone());
                 }
             }
         }
-        // remove duplicated map types
-        let map_names_hashset: HashSet<String> = map_names.into_iter().collect();
+        self.places.extend(places);
 
         // add new messages types for each found map type
-        for name in map_names_hashset {
+        for name in map_scopes.keys().cloned() {
             let mut fields = vec![];
             let mut id = 1;
-            for field_type in name.split(",") {
+            let types = &name[map_scopes[&name].len() + 1..];
+            for field_type in types.split(",") {
                 fields.push(CommonFieldProto::new_field(format!("@{}", id),
                                                         field_type.to_string(), id,
                                                         false,
                                                         String::new(), None));
                 id += 1;
             }
-            self.messages.push(Rc::new(MessageProto { name, fields, comment: String::new() }));
+            self.messages.push(Rc::new(MessageProto { name, fields, ..Default::default() }));
         }
+        map_scopes
     }
 
     //    fn link_user_types(&mut self) {
@@ -310,14 +248,53 @@ impl ProtoData {
 
 
     pub fn finalize(mut self) -> io::Result<ProtoData> {
-        self.create_map_messages();
+        self.check_duplicates()?;
+        let map_scopes = self.create_map_messages();
         self.messages.sort_by(|a, b| a.name.cmp(&b.name));
         self.enums.sort_by(|a, b| a.name.cmp(&b.name));
         //self.link_user_types();
 
+        // extension fields are added to the extended messages before any link makes the messages shared,
+        // and linked in the scope of their extend block
+        let mut extension_fields = vec![];
+        for (scope, extended, fields) in std::mem::take(&mut self.extensions) {
+            let index = scope_candidates(&scope, &extended).iter().find_map(|name| self.messages.binary_search_by(|m| m.name.cmp(name)).ok());
+            let Some(msg) = index.and_then(|index| Rc::get_mut(&mut self.messages[index])) else {
+                // e.g. options without the import of descriptor.proto
+                let Some(field) = fields.first() else { continue; };
+                return Err(self.declaration_error(&full_name(&scope, &field.name()), format!("extend {}: the message is not found in \"{}\"", extended, scope)));
+            };
+            msg.fields.extend(fields.iter().cloned());
+            extension_fields.extend(fields.into_iter().map(|field| (scope.clone(), field)));
+        }
+        let mut unresolved = vec![]; // (field, its scope)
+        for (scope, field) in &extension_fields {
+            if !field.link_user_types(scope, &self.enums, &self.messages) {
+                unresolved.push((full_name(scope, &field.name()), field.clone(), scope.clone()));
+            }
+        }
+
+        for msg in &self.messages {
+            let scope = map_scopes.get(&msg.name).unwrap_or(&msg.name);
+            for field in &msg.fields {
+                if !field.link_user_types(scope, &self.enums, &self.messages) {
+                    unresolved.push((full_name(&msg.name, &field.name()), field.clone(), scope.clone()));
+                }
+            }
+        }
+        if let Some((name, field, scope)) = unresolved.first() {
+            return Err(self.declaration_error(name, format!("type \"{}\" is not found in \"{}\"", field.typename(), scope)));
+        }
+
+        // the [default = ...] options are parsed once, enum values after the link
         for msg in &self.messages {
             for field in &msg.fields {
-                field.link_user_types(&self.enums, &self.messages);
+                let common = field.get_common_definition();
+                if let (Some(text), FieldValue::SCALAR(zero)) = (&common.default_value, field.default()) {
+                    if let Some(value) = default_to_scalar(text, &zero, |name| field.get_enum_index_by_name(name)) {
+                        let _ = common.default.set(value);
+                    }
+                }
             }
         }
 
@@ -326,11 +303,58 @@ impl ProtoData {
 
         Ok(self)
     }
+
+    // a type name is declared once in all the files
+    fn check_duplicates(&self) -> io::Result<()> {
+        let mut names: Vec<&str> = self.messages.iter().map(|m| m.name.as_str()).chain(self.enums.iter().map(|e| e.name.as_str())).collect();
+        names.sort();
+        match names.windows(2).find(|pair| pair[0] == pair[1]) {
+            Some(pair) => Err(self.declaration_error(pair[0], format!("\"{}\" is already defined", pair[0]))),
+            None => Ok(()),
+        }
+    }
+
+    // the error at the declaration of the name, or else of its message
+    fn declaration_error(&self, name: &str, message: String) -> io::Error {
+        let mut scope = name;
+        let place = loop {
+            if let Some(place) = self.places.get(scope) { break Some(place); }
+            match scope.rsplit_once('.') {
+                Some((parent, _)) => scope = parent,
+                None => break None,
+            }
+        };
+        let text = match place {
+            Some(place) => format!("{}: {}", place, message),
+            None => message,
+        };
+        io::Error::new(io::ErrorKind::InvalidData, text)
+    }
+}
+
+pub fn full_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() { name.to_string() } else { format!("{}.{}", scope, name) }
+}
+
+// fully qualified names to search a type name used in the scope, from the innermost scope outwards
+// https://protobuf.dev/programming-guides/proto3/#name-resolution
+pub fn scope_candidates(scope: &str, name: &str) -> Vec<String> {
+    if let Some(name) = name.strip_prefix('.') {
+        return vec![name.to_string()]; // already fully qualified
+    }
+    let mut res = vec![full_name(scope, name)];
+    let mut scope = scope;
+    while let Some(pos) = scope.rfind('.') {
+        scope = &scope[..pos];
+        res.push(full_name(scope, name));
+    }
+    if !scope.is_empty() { res.push(name.to_string()); }
+    res
 }
 
 impl Default for ProtoData {
     fn default() -> Self {
-        ProtoData { messages: vec![], enums: vec![], unknown_field: Rc::new(FixedInt32FieldProto { 0: CommonFieldProto::default() }) }
+        ProtoData { messages: vec![], enums: vec![], extensions: vec![], services: vec![], files: vec![], unknown_field: Rc::new(FixedInt32FieldProto { 0: CommonFieldProto::default() }), stream: None, places: HashMap::new(), spans: HashMap::new() }
     }
 }
 
@@ -407,15 +431,15 @@ impl Debug for EnumProto {
 
 
 impl ProtoFile {
-    fn new(path: PathBuf) -> ProtoFile {
+    fn new(path: PathBuf, name: String) -> ProtoFile {
         let content = std::fs::read_to_string(&path).unwrap();
-        ProtoFile { path, content }
+        ProtoFile { path, name, content }
     }
 
     // https://protobuf.dev/programming-guides/proto3/#importing
     pub fn new_with_imports(name: PathBuf, proto_path: Vec<PathBuf>) -> Vec<ProtoFile> {
         let mut all_files = vec![];
-        let mut files: Vec<ProtoFile> = vec![ProtoFile::new(name)];
+        let mut files: Vec<ProtoFile> = vec![ProtoFile::new(name.clone(), name.display().to_string())];
         loop {
             // add children, all for the top level and only public children for others
             let new_files: Vec<ProtoFile> = files.iter().
@@ -431,23 +455,10 @@ impl ProtoFile {
         }
     }
 
+    // the imports of the parsed file; a file with errors has none here, its errors are reported when it is loaded
     fn extract_imports(&self) -> Vec<(String, bool)> { // (file_name, is_public)
-        let mut res = vec![];
-        for line in self.content.lines() {
-            let lise_string = line.to_string();
-            let s = lise_string.trim();
-            if s.starts_with("import") { // import "file_path.proto";
-                let s = s.trim_end_matches(';');
-                let s = s.trim_start_matches("import");
-                let s = s.trim();
-                let s1 = s.trim_start_matches("public");
-                let is_public = s1.len() != s.len();
-                let s = s1.trim();
-                let s = s.trim_matches('\"');
-                res.push((s.to_string(), is_public));
-            }
-        }
-        res
+        let Ok(proto) = proto_parser::parse(&self.content, &self.name) else { return vec![] };
+        proto.files.into_iter().flat_map(|file| file.imports).map(|import| (import.name, import.public)).collect()
     }
 
     // search file by name in all possible locations
@@ -479,7 +490,6 @@ impl ProtoFile {
                 }
             }
         }
-        eprintln!("Imported file {name} not found");
         None
     }
 
@@ -488,8 +498,13 @@ impl ProtoFile {
         for import_name in self.extract_imports().into_iter() {
             if all || import_name.1 {
                 if let Some(path) = self.resolve_path(&import_name.0, &proto_path) {
-                    let new = Self::new(path);
+                    let new = Self::new(path, import_name.0);
                     res.push(new);
+                } else if let Some(content) = well_known::bundled_file(&import_name.0) {
+                    // a file of the protobuf distribution is used if it is not found on the disk
+                    res.push(ProtoFile { path: import_name.0.clone().into(), name: import_name.0, content: content.to_string() });
+                } else {
+                    eprintln!("Imported file {} not found", import_name.0);
                 }
             }
         }
@@ -539,6 +554,7 @@ impl ProtoFile {
 #[cfg(test)]
 mod parsing {
     use super::*;
+    use crate::wire::{FieldValue, ScalarValue, WT_SGROUP};
 
     #[test]
     fn conformance() {
@@ -552,7 +568,9 @@ mod parsing {
             let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
             d.push("resources/test");
             d.push(path);
-            assert!(ProtoData::new(std::fs::read_to_string(d).unwrap().as_str()).unwrap().finalize().is_ok());
+            let mut proto = ProtoData::default();
+            for file in ProtoFile::new_with_imports(d, vec![]) { proto.append(ProtoData::from_file(&file).unwrap()); }
+            assert!(proto.finalize().is_ok());
         }
     }
 
@@ -585,13 +603,25 @@ mod parsing {
         let proto_str = r#"message TestMessage {
           map<int32, string> f1 = 1;
           map<int32, string> f2 = 2;
-          map<int32, fixed32> f2 = 3;
+          map<int32, fixed32> f3 = 3;
         }"#;
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
         assert_eq!(proto.messages.len(), 3);
         assert!(proto.get_message_definition("TestMessage").is_some());
         assert!(proto.get_message_definition("int32,string").is_some());
         assert!(proto.get_message_definition("int32,fixed32").is_some());
+
+        // the same map type in other scopes
+        let proto = ProtoData::new(r#"package p;
+message Status { string text = 1; }
+message First { map<string, Status> m = 1; }
+message Inner { message Status { int32 code = 1; } map<string, Status> m = 1; }
+"#).unwrap().finalize().unwrap();
+        let value_type = |name: &str| proto.get_message_definition(name).unwrap().fields[0].message_proto().unwrap().fields[1].message_proto().unwrap().name.clone();
+        assert_eq!(value_type("p.First"), "p.Status");
+        assert_eq!(value_type("p.Inner"), "p.Inner.Status");
+        let inner = proto.get_message_definition("p.Inner").unwrap();
+        assert!(crate::text_format::text_to_message(r#"m { key: "a" value { code: 1 } }"#, inner).is_ok());
     }
 
 
@@ -624,6 +654,59 @@ enum NestedEnum {
     }
 
 
+    #[test]
+    fn proto2() {
+        let proto = ProtoData::new(r#"syntax = "proto2";
+package p;
+message Request {
+  required int32 id = 1;
+  optional string name = 2 [default = "no \"name\""];
+  optional Kind kind = 3 [default = SECOND];
+  optional double ratio = 4 [deprecated = true, default = -inf];
+  optional Kind first = 5;
+  repeated group Result = 6 {
+    required string url = 7;
+    optional uint32 code = 8 [default = 0x10];
+  }
+  extensions 100 to 199;
+}
+enum Kind { FIRST = 1; SECOND = 2; }
+extend Request {
+  optional Kind extra = 100;
+  optional Request.Result last = 101;
+}
+"#).unwrap();
+        assert_eq!(proto.auto_detect_root_message().unwrap().name, "p.Request");
+        let proto = proto.finalize().unwrap();
+        let request = proto.get_message_definition("p.Request").unwrap();
+        let scalar = |id: i32| match request.get_field(id).unwrap().default() {
+            FieldValue::SCALAR(value) => value,
+            FieldValue::MESSAGE(_) => panic!("not a scalar"),
+        };
+        assert!(request.get_field(1).unwrap().required());
+        assert!(!request.get_field(2).unwrap().required());
+        assert_eq!(scalar(1), ScalarValue::I32(0));
+        assert_eq!(scalar(2), ScalarValue::STR("no \"name\"".to_string()));
+        assert_eq!(scalar(3), ScalarValue::ENUM(2));
+        assert_eq!(scalar(4), ScalarValue::F64(f64::NEG_INFINITY));
+        assert_eq!(scalar(5), ScalarValue::ENUM(1)); // the first value of the enum
+        assert!(request.get_field(3).unwrap().get_common_definition().default.get().is_some()); // parsed by finalize
+
+        let result = request.get_field(6).unwrap();
+        assert_eq!(result.name(), "result");
+        assert!(result.repeated());
+        assert_eq!(result.wire_type(), WT_SGROUP);
+        let result_msg = result.message_proto().unwrap();
+        assert_eq!(result_msg.name, "p.Request.Result");
+        assert_eq!(result_msg.get_field(8).unwrap().default(), FieldValue::SCALAR(ScalarValue::U32(16)));
+
+        // extension fields are resolved by number in the extended message
+        assert_eq!(request.get_field(100).unwrap().name(), "extra");
+        assert_eq!(scalar(100), ScalarValue::ENUM(1));
+        assert_eq!(request.get_field(101).unwrap().message_proto().unwrap().name, "p.Request.Result");
+    }
+
+
     #[test]
     fn auto_detect_root_message() {
         {
@@ -649,12 +732,43 @@ enum NestedEnum {
     }
 
 
+    #[test]
+    fn packages() {
+        let mut proto = ProtoData::new(r#"syntax = "proto3";
+package a.b;
+message Status { int32 code = 1; }
+message Root {
+  message Status { string text = 1; }
+  Status s1 = 1;
+  .a.b.Status s2 = 2;
+  b.Status s3 = 3;
+  c.Status s4 = 4;
+  map<int32, Status> m = 5;
+}
+"#).unwrap();
+        assert_eq!(proto.auto_detect_root_message().unwrap().name, "a.b.Root");
+
+        proto.append(ProtoData::new("package c;\nmessage Status { bool ok = 1; }").unwrap());
+        let proto = proto.finalize().unwrap();
+
+        let root = proto.get_message_definition("a.b.Root").unwrap();
+        let linked: Vec<String> = root.fields.iter().map(|f| f.message_proto().unwrap().name.clone()).collect();
+        assert_eq!(linked, ["a.b.Root.Status", "a.b.Status", "a.b.Status", "c.Status", "a.b.Root.int32,Status"]);
+
+        // map value type is resolved in the scope of the map field
+        let map = proto.get_message_definition("a.b.Root.int32,Status").unwrap();
+        assert_eq!(map.fields[1].message_proto().unwrap().name, "a.b.Root.Status");
+
+        assert!(proto.get_message_definition("Status").is_none()); // ambiguous short name
+        assert_eq!(proto.get_message_definition("Root").unwrap().name, "a.b.Root");
+    }
+
     #[test]
     fn import_files() {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         d.push("resources/test/test_messages_proto3.proto");
 
-        let proto_file = ProtoFile::new(d);
+        let proto_file = ProtoFile::new(d, String::new());
         assert_eq!(proto_file.extract_imports(), [
             ("google/protobuf/any.proto".to_string(), false),
             ("google/protobuf/duration.proto".to_string(), false),
@@ -665,11 +779,38 @@ enum NestedEnum {
         ]);
     }
 
+    #[test]
+    fn import_syntax() {
+        let content = r#"syntax = "proto3"; import "google/protobuf/timestamp.proto";
+import weak "x.proto";
+/* import "commented.proto"; */
+// import "commented.proto";
+"#;
+        let proto_file = ProtoFile { path: PathBuf::from("a.proto"), name: "a.proto".to_string(), content: content.to_string() };
+        assert_eq!(proto_file.extract_imports(), [
+            ("google/protobuf/timestamp.proto".to_string(), false),
+            ("x.proto".to_string(), false),
+        ]);
+    }
+
+    #[test]
+    fn import_bundled_files() { // google/protobuf/*.proto are not on the disk
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test/test_messages_proto3.proto");
+        let files = ProtoFile::new_with_imports(d, vec![]);
+        assert_eq!(files.len(), 7);
+        assert_eq!(files[4].path, PathBuf::from("google/protobuf/struct.proto"));
+        let mut proto = ProtoData::default();
+        for file in files { proto.append(ProtoData::new(&file.content).unwrap()); }
+        let proto = proto.finalize().unwrap();
+        assert!(proto.get_message_definition("google.protobuf.ListValue").is_some());
+    }
+
     #[test]
     fn import_files_public() {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         d.push("resources/test/import_tests/1.proto");
-        let proto_file = ProtoFile::new(d);
+        let proto_file = ProtoFile::new(d, String::new());
         assert_eq!(proto_file.extract_imports(), [
             ("2.proto".to_string(), false),
             ("3.proto".to_string(), true),
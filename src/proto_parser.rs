@@ -0,0 +1,1286 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::proto::*;
+use crate::typedefs::CommonFieldProto;
+
+// Parser of proto2, proto3 and editions files:
+// https://protobuf.dev/reference/protobuf/proto2-spec/, https://protobuf.dev/reference/protobuf/edition-2023-spec/
+// The comment right above a declaration (or else the one on its first line) is kept for messages, fields, enums,
+// their values, services and methods; a blank line between them detaches the comment.
+// Options are kept as written, only the ones changing the encoding are used: packed, default and the features.
+// Errors are "file: line L, column C: message".
+
+const MAX_FIELD_NUMBER: i32 = 536_870_911; // 2^29 - 1
+const IMPLEMENTATION_NUMBERS: std::ops::RangeInclusive<i32> = 19000..=19999; // reserved for the protobuf implementation
+const MAP_KEY_TYPES: [&str; 12] = ["int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32", "fixed64", "sfixed32", "sfixed64", "bool", "string"];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String), // an identifier or a keyword
+    Int(u64), // decimal, hex or octal
+    Float(f64),
+    Str(String), // the escapes resolved
+    Symbol(char),
+    End,
+}
+
+// the features of editions, set by the syntax and the options in proto2 and proto3
+#[derive(Clone, Copy)]
+pub struct Features {
+    pub packed: bool, // repeated_field_encoding = PACKED
+    pub required: bool, // field_presence = LEGACY_REQUIRED
+    pub delimited: bool, // message_encoding = DELIMITED, the message is written as a group
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    file_name: &'a str,
+    pos: usize,
+    start: usize, // of the last token, for errors
+    comment: String, // above the next declaration
+    file: FileProto,
+    features: Features, // of the file
+    res: ProtoData,
+}
+
+// parses one file, its types are linked by ProtoData::finalize after the imported files are appended
+pub fn parse(text: &str, file_name: &str) -> Result<ProtoData, String> {
+    let features = Features::of_syntax("proto2"); // without the syntax statement
+    let file = FileProto { name: file_name.to_string(), syntax: "proto2".to_string(), ..Default::default() };
+    let mut parser = Parser { text, file_name, pos: 0, start: 0, comment: String::new(), file, features, res: ProtoData::from_messages(vec![]) };
+    parser.file.package = parser.find_package();
+    parser.parse_file()?;
+    parser.res.files.push(parser.file);
+    Ok(parser.res)
+}
+
+// the text of /* */ without the frame and the stars at the line starts
+fn block_comment_text(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(|line| {
+        let line = line.trim_end();
+        line.trim_start().strip_prefix('*').unwrap_or(line)
+    }).collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Name(name) => format!("\"{}\"", name),
+        Token::Int(value) => format!("number {}", value),
+        Token::Float(value) => format!("number {}", value),
+        Token::Str(_) => "a string".to_string(),
+        Token::Symbol(c) => format!("\"{}\"", c),
+        Token::End => "the end of the file".to_string(),
+    }
+}
+
+impl Features {
+    // the defaults of proto2, proto3 or editions
+    pub fn of_syntax(syntax: &str) -> Features {
+        Features { packed: syntax != "proto2", required: false, delimited: false }
+    }
+
+    pub fn apply(&mut self, option: &ProtoOption) {
+        match (option.name.as_str(), option.value.as_str()) {
+            ("packed", value) => self.packed = value == "true",
+            ("features.repeated_field_encoding", value) => self.packed = value == "PACKED",
+            ("features.field_presence", value) => self.required = value == "LEGACY_REQUIRED",
+            ("features.message_encoding", value) => self.delimited = value == "DELIMITED",
+            _ => {}
+        }
+    }
+}
+
+impl Parser<'_> {
+    // line and column from 0
+    fn line_column(&self, pos: usize) -> (i32, i32) {
+        let line_start = self.text[..pos].rfind('\n').map_or(0, |n| n + 1);
+        (self.text[..line_start].matches('\n').count() as i32, self.text[line_start..pos].chars().count() as i32)
+    }
+
+    // "file: line L, column C"
+    fn place(&self, pos: usize) -> String {
+        let (line, column) = self.line_column(pos);
+        let place = format!("line {}, column {}", line + 1, column + 1);
+        if self.file_name.is_empty() { place } else { format!("{}: {}", self.file_name, place) }
+    }
+
+    fn error_at(&self, pos: usize, message: impl AsRef<str>) -> String {
+        format!("{}: {}", self.place(pos), message.as_ref())
+    }
+
+    // the place of a declaration, for the errors of ProtoData::finalize
+    fn declared(&mut self, name: String, pos: usize) {
+        let place = self.place(pos);
+        self.res.places.insert(name, place);
+    }
+
+    // the span of a declaration, for the source_code_info of a descriptor set
+    fn spanned(&mut self, name: String, start: usize, end: usize, trailing: bool) {
+        let span = Span { start: self.line_column(start), end: self.line_column(end), trailing };
+        self.res.spans.insert(name, span);
+    }
+
+    // the comment above a declaration, or else the one after the last token on its line; true for the latter
+    fn declaration_comment(&mut self, comment: String) -> (String, bool) {
+        let trailing = self.trailing_comment();
+        if comment.is_empty() { (trailing.clone(), !trailing.is_empty()) } else { (comment, false) }
+    }
+
+    // at the next token
+    fn error(&mut self, message: impl AsRef<str>) -> String {
+        if let Err(e) = self.skip_space() {
+            return e;
+        }
+        self.error_at(self.pos, message)
+    }
+
+    fn unexpected(&mut self, expected: &str) -> String {
+        match self.peek() {
+            Ok(token) => self.error(format!("expected {}, found {}", expected, describe(&token))),
+            Err(e) => e,
+        }
+    }
+
+    /**********************************************************************************************/
+    // tokens
+
+    // spaces and comments, a blank line after a comment detaches it from the next declaration
+    fn skip_space(&mut self) -> Result<(), String> {
+        let bytes = self.text.as_bytes();
+        let mut newlines = 0;
+        loop {
+            match bytes.get(self.pos) {
+                Some(b'\n') => {
+                    newlines += 1;
+                    self.pos += 1;
+                }
+                Some(c) if c.is_ascii_whitespace() => self.pos += 1,
+                Some(b'/') if bytes.get(self.pos + 1) == Some(&b'/') => {
+                    let end = self.text[self.pos..].find('\n').map_or(self.text.len(), |n| self.pos + n);
+                    let text = self.text[self.pos + 2..end].trim_end_matches('\r').to_string();
+                    self.add_comment(&text, newlines);
+                    newlines = 0;
+                    self.pos = end;
+                }
+                Some(b'/') if bytes.get(self.pos + 1) == Some(&b'*') => {
+                    let Some(length) = self.text[self.pos + 2..].find("*/") else {
+                        return Err(self.error_at(self.pos, "the comment is not closed"));
+                    };
+                    let text = block_comment_text(&self.text[self.pos + 2..self.pos + 2 + length]);
+                    self.add_comment(&text, newlines);
+                    newlines = 0;
+                    self.pos += length + 4;
+                }
+                _ => break,
+            }
+        }
+        if newlines > 1 {
+            self.comment.clear();
+        }
+        Ok(())
+    }
+
+    fn add_comment(&mut self, text: &str, newlines: usize) {
+        if newlines > 1 {
+            self.comment.clear();
+        }
+        if !self.comment.is_empty() {
+            self.comment.push('\n');
+        }
+        self.comment.push_str(text);
+    }
+
+    // the comment above the next token
+    fn take_comment(&mut self) -> Result<String, String> {
+        self.skip_space()?;
+        Ok(std::mem::take(&mut self.comment))
+    }
+
+    // a comment on the line of the last token
+    fn trailing_comment(&mut self) -> String {
+        let rest = &self.text[self.pos..];
+        let spaces = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let rest = &rest[spaces..];
+        if rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            self.pos += spaces + end;
+            rest[2..end].trim_end_matches('\r').to_string()
+        } else if let Some(length) = rest.strip_prefix("/*").and_then(|rest| rest.find("*/")) {
+            self.pos += spaces + length + 4;
+            block_comment_text(&rest[2..length + 2])
+        } else {
+            String::new()
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        self.skip_space()?;
+        self.comment.clear();
+        self.start = self.pos;
+        let rest = &self.text[self.pos..];
+        let Some(c) = rest.chars().next() else {
+            return Ok(Token::End);
+        };
+        if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            self.pos += length;
+            return Ok(Token::Name(rest[..length].to_string()));
+        }
+        if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            return self.number();
+        }
+        if c == '"' || c == '\'' {
+            return self.string(c);
+        }
+        self.pos += c.len_utf8();
+        Ok(Token::Symbol(c))
+    }
+
+    fn peek(&mut self) -> Result<Token, String> {
+        self.peek_nth(0)
+    }
+
+    // the token after the next n ones
+    fn peek_nth(&mut self, n: usize) -> Result<Token, String> {
+        self.skip_space()?;
+        let (pos, start, comment) = (self.pos, self.start, self.comment.clone());
+        let mut token = self.next()?;
+        for _ in 0..n {
+            token = self.next()?;
+        }
+        (self.pos, self.start, self.comment) = (pos, start, comment);
+        Ok(token)
+    }
+
+    fn number(&mut self) -> Result<Token, String> {
+        let rest = &self.text[self.pos..];
+        let bytes = rest.as_bytes();
+        let mut length = 0;
+        let mut real = false;
+        let hex = rest.starts_with("0x") || rest.starts_with("0X");
+        if hex {
+            length = 2;
+            while bytes.get(length).is_some_and(|c| c.is_ascii_hexdigit()) { length += 1; }
+        } else {
+            while bytes.get(length).is_some_and(|c| c.is_ascii_digit()) { length += 1; }
+            if bytes.get(length) == Some(&b'.') {
+                real = true;
+                length += 1;
+                while bytes.get(length).is_some_and(|c| c.is_ascii_digit()) { length += 1; }
+            }
+            if matches!(bytes.get(length), Some(b'e' | b'E')) {
+                real = true;
+                length += 1;
+                if matches!(bytes.get(length), Some(b'+' | b'-')) { length += 1; }
+                while bytes.get(length).is_some_and(|c| c.is_ascii_digit()) { length += 1; }
+            }
+        }
+        let text = &rest[..length];
+        if bytes.get(length).is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_') || (hex && length == 2) {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.').unwrap_or(rest.len());
+            return Err(self.error_at(self.pos, format!("invalid number \"{}\"", &rest[..end])));
+        }
+        let token = if real {
+            text.parse().map(Token::Float).map_err(|_| format!("invalid number \"{}\"", text))
+        } else {
+            let parsed = if hex {
+                u64::from_str_radix(&text[2..], 16)
+            } else if text.len() > 1 && text.starts_with('0') {
+                u64::from_str_radix(&text[1..], 8)
+            } else {
+                text.parse()
+            };
+            parsed.map(Token::Int).map_err(|e| match e.kind() {
+                std::num::IntErrorKind::PosOverflow => format!("integer {} is too big", text),
+                _ => format!("invalid number \"{}\"", text),
+            })
+        };
+        let token = token.map_err(|e| self.error_at(self.pos, e))?;
+        self.pos += length;
+        Ok(token)
+    }
+
+    fn string(&mut self, quote: char) -> Result<Token, String> {
+        let start = self.pos;
+        let mut chars = self.text[self.pos + 1..].char_indices();
+        let mut bytes = vec![];
+        loop {
+            let Some((offset, c)) = chars.next() else {
+                return Err(self.error_at(start, "the string is not closed"));
+            };
+            match c {
+                '\n' => return Err(self.error_at(start, "the string is not closed")),
+                c if c == quote => {
+                    self.pos += offset + 2;
+                    return Ok(Token::Str(String::from_utf8_lossy(&bytes).into_owned()));
+                }
+                '\\' => {
+                    let escape_pos = self.pos + 1 + offset;
+                    let Some((_, c)) = chars.next() else { continue; };
+                    match c {
+                        'a' => bytes.push(7),
+                        'b' => bytes.push(8),
+                        'f' => bytes.push(12),
+                        'n' => bytes.push(b'\n'),
+                        'r' => bytes.push(b'\r'),
+                        't' => bytes.push(b'\t'),
+                        'v' => bytes.push(11),
+                        '0'..='7' => {
+                            let mut value = c.to_digit(8).unwrap();
+                            for _ in 0..2 {
+                                match chars.clone().next() {
+                                    Some((_, c)) if c.is_digit(8) => {
+                                        value = value * 8 + c.to_digit(8).unwrap();
+                                        chars.next();
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        'x' | 'X' | 'u' | 'U' => {
+                            let digits = match c { 'u' => 4, 'U' => 8, _ => 2 };
+                            let mut value = 0;
+                            let mut count = 0;
+                            while count < digits {
+                                match chars.clone().next() {
+                                    Some((_, c)) if c.is_ascii_hexdigit() => {
+                                        value = value * 16 + c.to_digit(16).unwrap();
+                                        chars.next();
+                                        count += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            match c {
+                                'x' | 'X' if count > 0 => bytes.push(value as u8),
+                                'u' | 'U' if count == digits => match char::from_u32(value) {
+                                    Some(c) => bytes.extend_from_slice(c.to_string().as_bytes()),
+                                    None => return Err(self.error_at(escape_pos, format!("invalid character code {:X}", value))),
+                                },
+                                _ => return Err(self.error_at(escape_pos, "invalid escape sequence")),
+                            }
+                        }
+                        '\\' | '\'' | '"' | '?' => bytes.push(c as u8),
+                        _ => return Err(self.error_at(escape_pos, "invalid escape sequence")),
+                    }
+                }
+                c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+    }
+
+    fn is_symbol(&mut self, symbol: char) -> Result<bool, String> {
+        Ok(self.peek()? == Token::Symbol(symbol))
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> Result<bool, String> {
+        Ok(matches!(self.peek()?, Token::Name(name) if name == keyword))
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> Result<bool, String> {
+        let found = self.is_symbol(symbol)?;
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> Result<bool, String> {
+        let found = self.is_keyword(keyword)?;
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat_symbol(symbol)? { Ok(()) } else { Err(self.unexpected(&format!("\"{}\"", symbol))) }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword)? { Ok(()) } else { Err(self.unexpected(&format!("\"{}\"", keyword))) }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.peek()? {
+            Token::Name(name) => {
+                self.next()?;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    // a.b.c
+    fn full_ident(&mut self) -> Result<String, String> {
+        let mut name = self.ident()?;
+        while self.eat_symbol('.')? {
+            name.push('.');
+            name += &self.ident()?;
+        }
+        Ok(name)
+    }
+
+    // a type name, fully qualified if it starts with a dot
+    fn type_name(&mut self) -> Result<String, String> {
+        let dot = if self.eat_symbol('.')? { "." } else { "" };
+        Ok(format!("{}{}", dot, self.full_ident()?))
+    }
+
+    fn string_value(&mut self) -> Result<String, String> {
+        match self.peek()? {
+            Token::Str(mut text) => {
+                self.next()?;
+                while let Token::Str(more) = self.peek()? { // adjacent strings are joined
+                    self.next()?;
+                    text += &more;
+                }
+                Ok(text)
+            }
+            _ => Err(self.unexpected("a string")),
+        }
+    }
+
+    // a decimal, hex or octal integer with an optional sign, within the range
+    fn int_value(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        self.skip_space()?;
+        let start = self.pos;
+        let negative = self.eat_symbol('-')?;
+        let Token::Int(value) = self.peek()? else {
+            return Err(self.unexpected("an integer"));
+        };
+        self.next()?;
+        let value = if negative { -(value as i128) } else { value as i128 };
+        if value < min as i128 || value > max as i128 {
+            return Err(self.error_at(start, format!("{} is out of range, expected {} to {}", &self.text[start..self.pos], min, max)));
+        }
+        Ok(value as i64)
+    }
+
+    /**********************************************************************************************/
+    // options
+
+    // name, (extension.name).field or features.name
+    fn option_name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        loop {
+            if self.eat_symbol('(')? {
+                name += &format!("({})", self.type_name()?);
+                self.expect_symbol(')')?;
+            } else {
+                name += &self.ident()?;
+            }
+            if !self.eat_symbol('.')? {
+                return Ok(name);
+            }
+            name.push('.');
+        }
+    }
+
+    // the value as written: a number, a name, a string or a message in braces
+    fn constant(&mut self) -> Result<String, String> {
+        self.skip_space()?;
+        let start = self.pos;
+        match self.next()? {
+            Token::Symbol('{') => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next()? {
+                        Token::Symbol('{') => depth += 1,
+                        Token::Symbol('}') => depth -= 1,
+                        Token::End => return Err(self.error_at(start, "the option value is not closed")),
+                        _ => {}
+                    }
+                }
+            }
+            Token::Symbol('-' | '+') => match self.peek()? {
+                Token::Int(_) | Token::Float(_) | Token::Name(_) => { self.next()?; }
+                _ => return Err(self.unexpected("a number")),
+            },
+            Token::Int(_) | Token::Float(_) => {}
+            Token::Name(_) => {
+                while self.eat_symbol('.')? {
+                    self.ident()?;
+                }
+            }
+            Token::Str(_) => {
+                while let Token::Str(_) = self.peek()? {
+                    self.next()?;
+                }
+            }
+            token => return Err(self.error_at(self.start, format!("expected a value, found {}", describe(&token)))),
+        }
+        Ok(self.text[start..self.pos].to_string())
+    }
+
+    fn option_value(&mut self) -> Result<ProtoOption, String> {
+        let name = self.option_name()?;
+        self.expect_symbol('=')?;
+        Ok(ProtoOption { name, value: self.constant()? })
+    }
+
+    // option name = value;
+    fn option_statement(&mut self, features: &mut Features) -> Result<ProtoOption, String> {
+        self.expect_keyword("option")?;
+        let option = self.option_value()?;
+        self.expect_symbol(';')?;
+        features.apply(&option);
+        Ok(option)
+    }
+
+    // [name = value, ...] of fields, enum values and extension ranges
+    fn option_list(&mut self) -> Result<Vec<ProtoOption>, String> {
+        let mut options = vec![];
+        if self.eat_symbol('[')? {
+            loop {
+                options.push(self.option_value()?);
+                if !self.eat_symbol(',')? {
+                    break;
+                }
+            }
+            self.expect_symbol(']')?;
+        }
+        Ok(options)
+    }
+
+    /**********************************************************************************************/
+    // declarations
+
+    // the package is the scope of all declarations of the file, wherever it is written
+    fn find_package(&self) -> String {
+        let mut scan = Parser { text: self.text, file_name: self.file_name, pos: 0, start: 0, comment: String::new(), file: FileProto::default(), features: self.features, res: ProtoData::default() };
+        let mut depth = 0;
+        let mut statement_start = true;
+        while let Ok(token) = scan.next() {
+            match token {
+                Token::End => break,
+                Token::Name(name) if name == "package" && depth == 0 && statement_start => return scan.full_ident().unwrap_or_default(),
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') => depth -= 1,
+                _ => {}
+            }
+            statement_start = matches!(token, Token::Symbol(';' | '{' | '}'));
+        }
+        String::new()
+    }
+
+    fn parse_file(&mut self) -> Result<(), String> {
+        let mut package_declared = false;
+        let mut first = true;
+        loop {
+            let comment = self.take_comment()?;
+            let token = self.peek()?;
+            let Token::Name(keyword) = &token else {
+                match token {
+                    Token::End => return Ok(()),
+                    Token::Symbol(';') => { self.next()?; }
+                    _ => return Err(self.unexpected("a top-level statement (e.g. \"message\")")),
+                }
+                continue;
+            };
+            let package = self.file.package.clone();
+            match keyword.as_str() {
+                "syntax" | "edition" if first => self.syntax()?,
+                "syntax" | "edition" => return Err(self.error("the syntax must be the first statement of the file")),
+                "package" if package_declared => return Err(self.error("the package is already declared")),
+                "package" => {
+                    self.next()?;
+                    self.full_ident()?;
+                    self.expect_symbol(';')?;
+                    package_declared = true;
+                }
+                "import" => self.import()?,
+                "option" => {
+                    let mut features = self.features;
+                    let option = self.option_statement(&mut features)?;
+                    self.features = features;
+                    self.file.options.push(option);
+                }
+                "message" => self.message(&package, comment, self.features)?,
+                "enum" => self.enumeration(&package, comment)?,
+                "service" => self.service(&package, comment)?,
+                "extend" => self.extend(&package, self.features)?,
+                _ => return Err(self.unexpected("a top-level statement (e.g. \"message\")")),
+            }
+            first = false;
+        }
+    }
+
+    // syntax = "proto3"; or edition = "2023";
+    fn syntax(&mut self) -> Result<(), String> {
+        let keyword = self.ident()?;
+        self.expect_symbol('=')?;
+        self.skip_space()?;
+        let value_pos = self.pos;
+        let value = self.string_value()?;
+        match (keyword.as_str(), value.as_str()) {
+            ("syntax", "proto2") => {}
+            ("syntax", "proto3") => self.features.packed = true,
+            ("syntax", _) => return Err(self.error_at(value_pos, format!("unknown syntax \"{}\", expected \"proto2\" or \"proto3\"", value))),
+            (_, "2023" | "2024") => {
+                self.features.packed = true;
+                self.file.edition = value.clone();
+            }
+            _ => return Err(self.error_at(value_pos, format!("unknown edition \"{}\"", value))),
+        }
+        self.file.syntax = if keyword == "syntax" { value } else { "editions".to_string() };
+        self.expect_symbol(';')
+    }
+
+    // import [public | weak] "file";
+    fn import(&mut self) -> Result<(), String> {
+        self.expect_keyword("import")?;
+        let public = self.eat_keyword("public")?;
+        let weak = !public && self.eat_keyword("weak")?;
+        let name = self.string_value()?;
+        self.expect_symbol(';')?;
+        self.file.imports.push(Import { name, public, weak });
+        Ok(())
+    }
+
+    fn message(&mut self, scope: &str, comment: String, features: Features) -> Result<(), String> {
+        self.skip_space()?;
+        let start = self.pos;
+        self.expect_keyword("message")?;
+        self.skip_space()?;
+        let name_pos = self.pos;
+        let name = full_name(scope, &self.ident()?);
+        self.declared(name.clone(), name_pos);
+        self.message_body(name, start, comment, features)
+    }
+
+    // { ... } of a message or a group, the declaration starts at start
+    fn message_body(&mut self, name: String, start: usize, comment: String, mut features: Features) -> Result<(), String> {
+        self.expect_symbol('{')?;
+        let (comment, trailing) = self.declaration_comment(comment);
+        let mut msg = MessageProto { name, comment, ..Default::default() };
+        let mut places = vec![];
+        loop {
+            let comment = self.take_comment()?;
+            let token = self.peek()?;
+            let keyword = match &token {
+                Token::Symbol('}') => break,
+                Token::Symbol(';') => {
+                    self.next()?;
+                    continue;
+                }
+                Token::Name(keyword) => keyword.as_str(),
+                Token::Symbol('.') => "", // a fully qualified type
+                _ => return Err(self.unexpected("a field or a declaration")),
+            };
+            match keyword {
+                "message" => self.message(&msg.name.clone(), comment, features)?,
+                "enum" => self.enumeration(&msg.name.clone(), comment)?,
+                "extend" => self.extend(&msg.name.clone(), features)?,
+                "option" => msg.options.push(self.option_statement(&mut features)?),
+                "oneof" => self.oneof(&mut msg, &mut places, comment, features)?,
+                "reserved" => self.reserved(1, MAX_FIELD_NUMBER, &mut msg.reserved, &mut msg.reserved_names)?,
+                "extensions" => {
+                    self.next()?;
+                    self.ranges(1, MAX_FIELD_NUMBER, &mut msg.extension_ranges)?;
+                    self.option_list()?;
+                    self.expect_symbol(';')?;
+                }
+                _ => {
+                    let (field, place) = self.field(&msg.name, comment, None, false, features)?;
+                    msg.fields.push(field);
+                    places.push(place);
+                }
+            }
+        }
+        self.next()?;
+        self.spanned(msg.name.clone(), start, self.pos, trailing);
+        self.trailing_comment();
+        self.check_fields(&msg, &places)?;
+        self.res.messages.push(Rc::new(msg));
+        Ok(())
+    }
+
+    // numbers and names are unique and not reserved
+    fn check_fields(&self, msg: &MessageProto, places: &[(usize, usize)]) -> Result<(), String> {
+        let mut numbers = HashMap::new();
+        let mut names = HashMap::new();
+        for (field, &(name_pos, number_pos)) in msg.fields.iter().zip(places) {
+            let (name, id) = (field.name(), field.id());
+            if let Some(other) = numbers.insert(id, name.clone()) {
+                return Err(self.error_at(number_pos, format!("field number {} is already used by \"{}\"", id, other)));
+            }
+            if names.insert(name.clone(), id).is_some() {
+                return Err(self.error_at(name_pos, format!("field \"{}\" is already declared in \"{}\"", name, msg.name)));
+            }
+            if msg.reserved.iter().any(|(first, last)| (*first..=*last).contains(&id)) {
+                return Err(self.error_at(number_pos, format!("field number {} is reserved", id)));
+            }
+            if msg.reserved_names.contains(&name) {
+                return Err(self.error_at(name_pos, format!("field name \"{}\" is reserved", name)));
+            }
+            if msg.extension_ranges.iter().any(|(first, last)| (*first..=*last).contains(&id)) {
+                return Err(self.error_at(number_pos, format!("field number {} is in an extension range", id)));
+            }
+        }
+        Ok(())
+    }
+
+    // a field of a message, oneof or extend block, returns the positions of its name and number for errors;
+    // the message of a group is added to the scope
+    fn field(&mut self, scope: &str, comment: String, oneof_name: Option<String>, extension: bool, mut features: Features) -> Result<(FieldProtoPtr, (usize, usize)), String> {
+        let editions = self.file.syntax == "editions";
+        let mut common = CommonFieldProto { comment, ..Default::default() };
+        self.skip_space()?;
+        let label_pos = self.pos;
+        let label = match self.peek()? {
+            Token::Name(label) if matches!(label.as_str(), "optional" | "required" | "repeated") => {
+                self.next()?;
+                label
+            }
+            _ => String::new(),
+        };
+        match label.as_str() {
+            "" => {}
+            _ if oneof_name.is_some() => return Err(self.error_at(label_pos, "fields of a oneof cannot have labels")),
+            "required" if self.file.syntax == "proto3" => return Err(self.error_at(label_pos, "required fields are not allowed in proto3")),
+            "optional" | "required" if editions => return Err(self.error_at(label_pos, format!("the {} label is not allowed in editions, use features.field_presence", label))),
+            _ => {}
+        }
+
+        self.skip_space()?;
+        let type_pos = self.pos;
+        let is_map = self.is_keyword("map")? && self.peek_nth(1)? == Token::Symbol('<');
+        let mut type_name = if is_map {
+            if !label.is_empty() {
+                return Err(self.error_at(label_pos, "map fields cannot have labels"));
+            }
+            self.next()?;
+            self.expect_symbol('<')?;
+            self.skip_space()?;
+            let key_pos = self.pos;
+            let key = self.ident()?;
+            if !MAP_KEY_TYPES.contains(&key.as_str()) {
+                return Err(self.error_at(key_pos, format!("\"{}\" cannot be a map key, expected an integer, bool or string type", key)));
+            }
+            self.expect_symbol(',')?;
+            let value = self.type_name()?;
+            self.expect_symbol('>')?;
+            format!("{},{}", key, value)
+        } else {
+            self.type_name()?
+        };
+
+        self.skip_space()?;
+        let name_pos = self.pos;
+        common.name = self.ident()?;
+        self.expect_symbol('=')?;
+        self.skip_space()?;
+        let number_pos = self.pos;
+        let number = self.int_value(i64::MIN, i64::MAX)?;
+        if !(1..=MAX_FIELD_NUMBER as i64).contains(&number) {
+            return Err(self.error_at(number_pos, format!("expected a field number from 1 to {}", MAX_FIELD_NUMBER)));
+        }
+        common.id = number as i32;
+        if IMPLEMENTATION_NUMBERS.contains(&common.id) {
+            return Err(self.error_at(number_pos, "field numbers 19000 to 19999 are reserved for the protobuf implementation"));
+        }
+        self.skip_space()?;
+        let options_pos = self.pos;
+        common.options = self.option_list()?;
+        for option in &common.options {
+            features.apply(option);
+            if option.name == "default" {
+                if self.file.syntax == "proto3" {
+                    return Err(self.error_at(options_pos, "explicit default values are not allowed in proto3"));
+                }
+                common.default_value = Some(option.value.clone());
+            }
+        }
+        common.repeated = is_map || label == "repeated";
+        common.required = label == "required" || (features.required && label.is_empty() && !is_map && oneof_name.is_none());
+        common.optional = label == "optional";
+        common.packed = features.packed;
+        common.group = features.delimited && !is_map;
+        common.oneof_name = oneof_name;
+        common.extension = extension.then(|| full_name(scope, &common.name));
+
+        if type_name == "group" && self.is_symbol('{')? {
+            if self.file.syntax != "proto2" {
+                return Err(self.error_at(type_pos, "groups are supported only in proto2, use features.message_encoding = DELIMITED"));
+            }
+            // the message is named by the field, and the field by the message in lowercase
+            type_name = common.name.clone();
+            common.name = common.name.to_lowercase();
+            common.group = true;
+            common.extension = extension.then(|| full_name(scope, &common.name));
+            self.declared(full_name(scope, &common.name), type_pos);
+            self.declared(full_name(scope, &type_name), name_pos);
+            let field_name = full_name(scope, &common.name);
+            let field = CommonFieldProto::with_type(common, type_name.clone());
+            self.message_body(full_name(scope, &type_name), label_pos, String::new(), features)?;
+            let span = self.res.spans[&full_name(scope, &type_name)];
+            self.res.spans.insert(field_name, Span { trailing: false, ..span }); // the comment after '{' is the one of the message
+            return Ok((field, (name_pos, number_pos)));
+        }
+        self.expect_symbol(';')?;
+        let end = self.pos;
+        self.declared(full_name(scope, &common.name), type_pos);
+        let trailing;
+        (common.comment, trailing) = self.declaration_comment(common.comment);
+        self.spanned(full_name(scope, &common.name), label_pos, end, trailing);
+        Ok((CommonFieldProto::with_type(common, type_name), (name_pos, number_pos)))
+    }
+
+    fn oneof(&mut self, msg: &mut MessageProto, places: &mut Vec<(usize, usize)>, comment: String, mut features: Features) -> Result<(), String> {
+        self.expect_keyword("oneof")?;
+        self.skip_space()?;
+        let name_pos = self.pos;
+        let name = self.ident()?;
+        self.expect_symbol('{')?;
+        self.trailing_comment();
+        let count = msg.fields.len();
+        // the comment of the oneof goes to its first field if it has none
+        let mut comment = Some(comment);
+        loop {
+            let field_comment = self.take_comment()?;
+            match self.peek()? {
+                Token::Symbol('}') => break,
+                Token::Symbol(';') => { self.next()?; }
+                Token::Name(keyword) if keyword == "option" => { self.option_statement(&mut features)?; }
+                _ => {
+                    let field_comment = match comment.take() {
+                        Some(comment) if field_comment.is_empty() => comment,
+                        _ => field_comment,
+                    };
+                    let (field, place) = self.field(&msg.name, field_comment, Some(name.clone()), false, features)?;
+                    msg.fields.push(field);
+                    places.push(place);
+                }
+            }
+        }
+        if msg.fields.len() == count {
+            return Err(self.error_at(name_pos, format!("oneof \"{}\" has no fields", name)));
+        }
+        self.next()?;
+        self.trailing_comment();
+        Ok(())
+    }
+
+    // reserved 2, 15, 9 to 11; or reserved "foo", "bar"; (names without quotes in editions)
+    fn reserved(&mut self, min: i32, max: i32, ranges: &mut Vec<(i32, i32)>, names: &mut Vec<String>) -> Result<(), String> {
+        self.expect_keyword("reserved")?;
+        match self.peek()? {
+            Token::Str(_) | Token::Name(_) => loop {
+                let name = if let Token::Str(_) = self.peek()? { self.string_value()? } else { self.ident()? };
+                names.push(name);
+                if !self.eat_symbol(',')? {
+                    break;
+                }
+            },
+            _ => self.ranges(min, max, ranges)?,
+        }
+        self.expect_symbol(';')
+    }
+
+    // 2, 9 to 11, 100 to max; the ranges are inclusive
+    fn ranges(&mut self, min: i32, max: i32, ranges: &mut Vec<(i32, i32)>) -> Result<(), String> {
+        loop {
+            self.skip_space()?;
+            let start = self.pos;
+            let first = self.int_value(min as i64, max as i64)? as i32;
+            let last = if !self.eat_keyword("to")? {
+                first
+            } else if self.eat_keyword("max")? {
+                max
+            } else {
+                self.int_value(min as i64, max as i64)? as i32
+            };
+            if last < first {
+                return Err(self.error_at(start, "the end of the range is less than its start"));
+            }
+            if let Some((other_first, other_last)) = ranges.iter().find(|(other_first, other_last)| first <= *other_last && *other_first <= last) {
+                return Err(self.error_at(start, format!("the range overlaps the range {} to {} declared before", other_first, other_last)));
+            }
+            ranges.push((first, last));
+            if !self.eat_symbol(',')? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn enumeration(&mut self, scope: &str, comment: String) -> Result<(), String> {
+        self.skip_space()?;
+        let start = self.pos;
+        self.expect_keyword("enum")?;
+        self.skip_space()?;
+        let name_pos = self.pos;
+        let name = full_name(scope, &self.ident()?);
+        self.declared(name.clone(), name_pos);
+        self.expect_symbol('{')?;
+        let (comment, trailing) = self.declaration_comment(comment);
+        let mut enm = EnumProto { name, variants: vec![], comment, options: vec![], reserved: vec![], reserved_names: vec![] };
+        let mut places = vec![];
+        let mut features = self.features;
+        loop {
+            let comment = self.take_comment()?;
+            match self.peek()? {
+                Token::Symbol('}') => break,
+                Token::Symbol(';') => { self.next()?; }
+                Token::Name(keyword) if keyword == "option" => enm.options.push(self.option_statement(&mut features)?),
+                Token::Name(keyword) if keyword == "reserved" => self.reserved(i32::MIN, i32::MAX, &mut enm.reserved, &mut enm.reserved_names)?,
+                Token::Name(_) => {
+                    self.skip_space()?;
+                    let value_pos = self.pos;
+                    let name = self.ident()?;
+                    self.expect_symbol('=')?;
+                    let value = self.int_value(i32::MIN as i64, i32::MAX as i64)? as i32;
+                    self.option_list()?;
+                    self.expect_symbol(';')?;
+                    let end = self.pos;
+                    let (comment, trailing) = self.declaration_comment(comment);
+                    self.spanned(full_name(&enm.name, &name), value_pos, end, trailing);
+                    enm.variants.push((name, value, comment));
+                    places.push(value_pos);
+                }
+                _ => return Err(self.unexpected("an enum value")),
+            }
+        }
+        self.next()?;
+        self.spanned(enm.name.clone(), start, self.pos, trailing);
+        self.trailing_comment();
+
+        if enm.variants.is_empty() {
+            return Err(self.error_at(name_pos, format!("enum \"{}\" has no values", enm.name)));
+        }
+        if self.file.syntax == "proto3" && enm.variants[0].1 != 0 {
+            return Err(self.error_at(places[0], "the first value of a proto3 enum must be zero"));
+        }
+        let allow_alias = enm.options.iter().any(|option| option.name == "allow_alias" && option.value == "true");
+        for (index, ((name, value, _), pos)) in enm.variants.iter().zip(&places).enumerate() {
+            let earlier = &enm.variants[..index];
+            if earlier.iter().any(|other| &other.0 == name) {
+                return Err(self.error_at(*pos, format!("enum value \"{}\" is already declared", name)));
+            }
+            if let Some(other) = earlier.iter().find(|other| other.1 == *value).filter(|_| !allow_alias) {
+                return Err(self.error_at(*pos, format!("{} is already used by \"{}\", set option allow_alias = true", value, other.0)));
+            }
+            if enm.reserved.iter().any(|(first, last)| (*first..=*last).contains(value)) {
+                return Err(self.error_at(*pos, format!("enum value {} is reserved", value)));
+            }
+            if enm.reserved_names.contains(name) {
+                return Err(self.error_at(*pos, format!("enum value name \"{}\" is reserved", name)));
+            }
+        }
+        self.res.enums.push(Rc::new(enm));
+        Ok(())
+    }
+
+    // fields added to another message, the groups are nested in the scope of the block
+    fn extend(&mut self, scope: &str, features: Features) -> Result<(), String> {
+        self.expect_keyword("extend")?;
+        let extended = self.type_name()?;
+        self.expect_symbol('{')?;
+        self.trailing_comment();
+        let mut fields = vec![];
+        loop {
+            let comment = self.take_comment()?;
+            match self.peek()? {
+                Token::Symbol('}') => break,
+                Token::Symbol(';') => { self.next()?; }
+                _ => fields.push(self.field(scope, comment, None, true, features)?.0),
+            }
+        }
+        self.next()?;
+        self.trailing_comment();
+        self.res.extensions.push((scope.to_string(), extended, fields));
+        Ok(())
+    }
+
+    fn service(&mut self, scope: &str, comment: String) -> Result<(), String> {
+        self.skip_space()?;
+        let start = self.pos;
+        self.expect_keyword("service")?;
+        let name = full_name(scope, &self.ident()?);
+        self.expect_symbol('{')?;
+        let (comment, trailing) = self.declaration_comment(comment);
+        let mut service = ServiceProto { name, methods: vec![], comment, options: vec![] };
+        let mut features = self.features;
+        loop {
+            let comment = self.take_comment()?;
+            match self.peek()? {
+                Token::Symbol('}') => break,
+                Token::Symbol(';') => { self.next()?; }
+                Token::Name(keyword) if keyword == "option" => service.options.push(self.option_statement(&mut features)?),
+                Token::Name(keyword) if keyword == "rpc" => service.methods.push(self.method(&service.name, comment)?),
+                _ => return Err(self.unexpected("\"rpc\"")),
+            }
+        }
+        self.next()?;
+        self.spanned(service.name.clone(), start, self.pos, trailing);
+        self.trailing_comment();
+        self.res.services.push(service);
+        Ok(())
+    }
+
+    // rpc Name (Request) returns (stream Response) { option ...; }
+    fn method(&mut self, service: &str, comment: String) -> Result<MethodProto, String> {
+        self.skip_space()?;
+        let start = self.pos;
+        self.expect_keyword("rpc")?;
+        let name = self.ident()?;
+        let (client_streaming, input_type) = self.method_type()?;
+        self.expect_keyword("returns")?;
+        let (server_streaming, output_type) = self.method_type()?;
+        let mut options = vec![];
+        if self.eat_symbol('{')? {
+            let mut features = self.features;
+            loop {
+                match self.peek()? {
+                    Token::Symbol('}') => break,
+                    Token::Symbol(';') => { self.next()?; }
+                    _ => options.push(self.option_statement(&mut features)?),
+                }
+            }
+            self.next()?;
+        } else {
+            self.expect_symbol(';')?;
+        }
+        let end = self.pos;
+        let (comment, trailing) = self.declaration_comment(comment);
+        self.spanned(full_name(service, &name), start, end, trailing);
+        Ok(MethodProto { name, input_type, output_type, client_streaming, server_streaming, comment, options })
+    }
+
+    // ([stream] Type)
+    fn method_type(&mut self) -> Result<(bool, String), String> {
+        self.expect_symbol('(')?;
+        // not a type named stream or stream.Name
+        let stream = self.is_keyword("stream")? && self.peek_nth(1)? != Token::Symbol(')') && !self.text[self.pos + "stream".len()..].starts_with('.');
+        if stream {
+            self.next()?;
+        }
+        let type_name = self.type_name()?;
+        self.expect_symbol(')')?;
+        Ok((stream, type_name))
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod parser {
+    use super::*;
+    use crate::wire::{FieldValue, ScalarValue, WT_SGROUP};
+
+    fn error(text: &str) -> String {
+        parse(text, "test.proto").err().unwrap()
+    }
+
+    #[test]
+    fn literals() {
+        let proto = parse(r#"syntax = "proto2";
+message M {
+  optional int64 big = 536870911 [default = 0x7FFFFFFFFFFFFFFF];
+  optional int32 octal = 2 [default = 017];
+  optional double real = 3 [default = 1.5e3];
+  optional string text = 4 [default = "a\x41\101é" 'b'];
+}
+enum E { MIN = -2147483648; MAX = 0x7fffffff; }
+"#, "").unwrap().finalize().unwrap();
+        let msg = proto.get_message_definition("M").unwrap();
+        let scalar = |id: i32| match msg.get_field(id).unwrap().default() {
+            FieldValue::SCALAR(value) => value,
+            FieldValue::MESSAGE(_) => panic!("not a scalar"),
+        };
+        assert_eq!(scalar(536870911), ScalarValue::I64(i64::MAX));
+        assert_eq!(scalar(2), ScalarValue::I32(15));
+        assert_eq!(scalar(3), ScalarValue::F64(1500.0));
+        assert_eq!(msg.get_field(4).unwrap().get_common_definition().options[0].value, r#""a\x41\101é" 'b'"#);
+        assert_eq!(proto.get_enum_definition("E").unwrap().variants[0].1, i32::MIN);
+
+        let mut parser = Parser { text: r#""a\x41\101é" 'b\'' 0x1F 010 .5 1e2"#, file_name: "", pos: 0, start: 0, comment: String::new(), file: FileProto::default(), features: Features::of_syntax("proto2"), res: ProtoData::default() };
+        assert_eq!(parser.next().unwrap(), Token::Str("aAAé".to_string()));
+        assert_eq!(parser.next().unwrap(), Token::Str("b'".to_string()));
+        assert_eq!(parser.next().unwrap(), Token::Int(31));
+        assert_eq!(parser.next().unwrap(), Token::Int(8));
+        assert_eq!(parser.next().unwrap(), Token::Float(0.5));
+        assert_eq!(parser.next().unwrap(), Token::Float(100.0));
+        assert_eq!(parser.next().unwrap(), Token::End);
+    }
+
+    #[test]
+    fn field_number_range() {
+        // the top field numbers do not fit in i32 tags
+        let proto = parse("syntax = \"proto3\";\nmessage M { int32 top = 536870911; sint64 next = 536870910; repeated int32 list = 268435456; M sub = 268435455; }", "").unwrap().finalize().unwrap();
+        let def = proto.get_message_definition("M").unwrap();
+        let text = "top: 5\nnext: -3\nlist: 1\nlist: 2\nsub {\n  top: 7\n}\n";
+        let data = crate::text_format::text_to_message(text, def.clone()).unwrap();
+        let mut bytes = vec![];
+        data.write(&mut bytes, &proto, def.clone()).unwrap();
+        assert_eq!(bytes[..6], [0xF8, 0xFF, 0xFF, 0xFF, 0x0F, 0x05]);
+        let mut limit = bytes.len() as u64;
+        let read = crate::wire::MessageData::new(&mut crate::typedefs::PbSliceReader::new(&bytes, 0), &proto, def, &mut limit).unwrap();
+        assert_eq!(crate::text_format::message_to_text(&read), text);
+    }
+
+    #[test]
+    fn declarations() {
+        let proto = parse(r#"
+/* The file header,
+   a detached comment */
+
+edition = "2023";
+package shop.v1;
+import public "other.proto";
+import weak "old.proto";
+option java_package = "com.shop";
+option features.field_presence = EXPLICIT;
+option (my.file_option) = { name: "x" nested { value: 1 } list: [1, 2] };
+
+/**
+ * An order.
+ */
+message Order {
+  option (my.message_option).flag = true;
+  reserved 5, 10 to 12, 1000 to max;
+  reserved "old", removed;
+  extensions 100 to 199 [declaration = { number: 100 full_name: ".shop.v1.note" type: "string" }];
+  int32 id = 1; // the id
+  repeated int32 tags = 2 [features.repeated_field_encoding = EXPANDED];
+  Item item = 3 [features.message_encoding = DELIMITED];
+  string code = 4 [features.field_presence = LEGACY_REQUIRED, (my.field_option) = -1];
+  oneof payment {
+    option (my.oneof_option) = 1;
+    string card = 6;
+    string cash = 7;
+  }
+  message Item { string name = 1; }
+}
+enum Status {
+  option allow_alias = true;
+  reserved 3 to 5, -1;
+  reserved "LOST";
+  NEW = 0;
+  CREATED = 0 [deprecated = true];
+  DONE = 2;
+}
+extend Order { string note = 100; }
+// Orders
+service Orders {
+  option deprecated = true;
+  rpc Get(Order) returns (Order);
+  rpc Watch(stream .shop.v1.Order) returns (stream Order) {
+    option idempotency_level = NO_SIDE_EFFECTS;
+  }
+  rpc Stream(stream) returns (stream);
+}
+"#, "shop.proto").unwrap();
+
+        let file = &proto.files[0];
+        assert_eq!((file.name.as_str(), file.syntax.as_str(), file.edition.as_str(), file.package.as_str()), ("shop.proto", "editions", "2023", "shop.v1"));
+        assert_eq!(file.imports, [Import { name: "other.proto".to_string(), public: true, weak: false }, Import { name: "old.proto".to_string(), public: false, weak: true }]);
+        assert_eq!(file.options.len(), 3);
+        assert_eq!(file.options[2].name, "(my.file_option)");
+        assert_eq!(file.options[2].value, r#"{ name: "x" nested { value: 1 } list: [1, 2] }"#);
+
+        let proto = proto.finalize().unwrap();
+        let order = proto.get_message_definition("shop.v1.Order").unwrap();
+        assert_eq!(order.comment, " An order.");
+        assert_eq!(order.options, [ProtoOption { name: "(my.message_option).flag".to_string(), value: "true".to_string() }]);
+        assert_eq!(order.reserved, [(5, 5), (10, 12), (1000, MAX_FIELD_NUMBER)]);
+        assert_eq!(order.reserved_names, ["old", "removed"]);
+        assert_eq!(order.extension_ranges, [(100, 199)]);
+        assert_eq!(order.get_field(1).unwrap().comment(), " the id");
+        assert!(order.get_field(2).unwrap().packable());
+        assert!(!order.get_field(2).unwrap().packed());
+        assert_eq!(order.get_field(3).unwrap().wire_type(), WT_SGROUP);
+        assert!(order.get_field(4).unwrap().required());
+        assert_eq!(order.get_field(7).unwrap().oneof_name().as_deref(), Some("payment"));
+        assert_eq!(order.get_field(100).unwrap().name(), "note");
+
+        let status = proto.get_enum_definition("shop.v1.Status").unwrap();
+        assert_eq!(status.reserved, [(3, 5), (-1, -1)]);
+        assert_eq!(status.reserved_names, ["LOST"]);
+        assert_eq!(status.variants.len(), 3);
+
+        let service = &proto.services[0];
+        assert_eq!((service.name.as_str(), service.comment.as_str(), service.options.len()), ("shop.v1.Orders", " Orders", 1));
+        let methods: Vec<_> = service.methods.iter().map(|m| (m.name.as_str(), m.input_type.as_str(), m.client_streaming, m.output_type.as_str(), m.server_streaming)).collect();
+        assert_eq!(methods, [
+            ("Get", "Order", false, "Order", false),
+            ("Watch", ".shop.v1.Order", true, "Order", true),
+            ("Stream", "stream", false, "stream", false),
+        ]);
+        assert_eq!(service.methods[1].options[0].value, "NO_SIDE_EFFECTS");
+    }
+
+    #[test]
+    fn comments() {
+        let proto = parse(r#"message M { // trailing of M
+  // detached
+
+  // leading of a
+  int32 a = 1; // trailing of a
+  int32 b = 2; /* trailing of b */
+  /* leading
+   * of c */
+  int32 c = 3;
+  oneof kind {  // not kept
+    int32 d = 4;
+  }
+} // not kept
+enum E { A = 0; /* trailing of A */ }
+"#, "").unwrap();
+        let msg = proto.get_message_definition("M").unwrap();
+        assert_eq!(msg.comment, " trailing of M");
+        let comments: Vec<String> = msg.fields.iter().map(|f| f.comment()).collect();
+        assert_eq!(comments, [" leading of a", " trailing of b", " leading\n of c", ""]);
+        assert_eq!(proto.get_enum_definition("E").unwrap().variants[0].2, " trailing of A");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("message M {\n  int32 a = 1\n}"), "test.proto: line 3, column 1: expected \";\", found \"}\"");
+        assert_eq!(error("message M { int32 a = 0; }"), "test.proto: line 1, column 23: expected a field number from 1 to 536870911");
+        assert_eq!(error("message M { int32 a = 536870912; }"), "test.proto: line 1, column 23: expected a field number from 1 to 536870911");
+        assert_eq!(error("message M { int32 a = 19000; }"), "test.proto: line 1, column 23: field numbers 19000 to 19999 are reserved for the protobuf implementation");
+        assert_eq!(error("message M { int32 a = 1; int32 b = 1; }"), "test.proto: line 1, column 36: field number 1 is already used by \"a\"");
+        assert_eq!(error("message M { int32 a = 1; string a = 2; }"), "test.proto: line 1, column 33: field \"a\" is already declared in \"M\"");
+        assert_eq!(error("message M {\n  reserved 2 to 4;\n  int32 a = 3;\n}"), "test.proto: line 3, column 13: field number 3 is reserved");
+        assert_eq!(error("message M { reserved \"a\"; int32 a = 3; }"), "test.proto: line 1, column 33: field name \"a\" is reserved");
+        assert_eq!(error("message M { extensions 10 to 20; int32 a = 15; }"), "test.proto: line 1, column 44: field number 15 is in an extension range");
+        assert_eq!(error("message M { reserved 5 to 2; }"), "test.proto: line 1, column 22: the end of the range is less than its start");
+        assert_eq!(error("message M { map<float, int32> m = 1; }"), "test.proto: line 1, column 17: \"float\" cannot be a map key, expected an integer, bool or string type");
+        assert_eq!(error("enum E { A = 1; B = 1; }"), "test.proto: line 1, column 17: 1 is already used by \"A\", set option allow_alias = true");
+        assert_eq!(error("enum E { A = 2147483648; }"), "test.proto: line 1, column 14: 2147483648 is out of range, expected -2147483648 to 2147483647");
+        assert_eq!(error("syntax = \"proto3\";\nenum E { A = 1; }"), "test.proto: line 2, column 10: the first value of a proto3 enum must be zero");
+        assert_eq!(error("syntax = \"proto3\";\nmessage M { required int32 a = 1; }"), "test.proto: line 2, column 13: required fields are not allowed in proto3");
+        assert_eq!(error("edition = \"2023\";\nmessage M { optional int32 a = 1; }"), "test.proto: line 2, column 13: the optional label is not allowed in editions, use features.field_presence");
+        assert_eq!(error("syntax = \"proto3\";\nmessage M { repeated group G = 1 {} }"), "test.proto: line 2, column 22: groups are supported only in proto2, use features.message_encoding = DELIMITED");
+        assert_eq!(error("message M { int32 a = 99999999999999999999; }"), "test.proto: line 1, column 23: integer 99999999999999999999 is too big");
+        assert_eq!(error("/* not closed\nmessage M {}"), "test.proto: line 1, column 1: the comment is not closed");
+        assert_eq!(error("message M { string a = 1 [default = \"abc]; }"), "test.proto: line 1, column 37: the string is not closed");
+        assert_eq!(error("package a;\nmessage M {}\npackage b;"), "test.proto: line 3, column 1: the package is already declared");
+        assert_eq!(error("message M {}\nsyntax = \"proto2\";"), "test.proto: line 2, column 1: the syntax must be the first statement of the file");
+        assert_eq!(error("service S { int32 a = 1; }"), "test.proto: line 1, column 13: expected \"rpc\", found \"int32\"");
+        assert_eq!(parse("message M {", "").err().unwrap(), "line 1, column 12: expected a field or a declaration, found the end of the file");
+        assert_eq!(error("message M { reserved 1; reserved 1; }"), "test.proto: line 1, column 34: the range overlaps the range 1 to 1 declared before");
+        assert_eq!(error("message M { extensions 10 to 20, 15 to max; }"), "test.proto: line 1, column 34: the range overlaps the range 10 to 20 declared before");
+        assert_eq!(error("syntax = \"proto3\";\nmessage M { int32 a = 1 [default = 5]; }"), "test.proto: line 2, column 25: explicit default values are not allowed in proto3");
+
+        // errors of linking the files
+        let link_error = |text: &str| parse(text, "t.proto").unwrap().finalize().err().unwrap().to_string();
+        assert_eq!(link_error("message M { message Nested {} }\nmessage N {\n  M.Nested a = 1;\n  Nested b = 2;\n}"), "t.proto: line 4, column 3: type \"Nested\" is not found in \"N\"");
+        assert_eq!(link_error("message N { map<string, Value> m = 1; }"), "t.proto: line 1, column 13: type \"Value\" is not found in \"N\"");
+        assert_eq!(link_error("message M {}\nextend M { optional Value v = 1; }"), "t.proto: line 2, column 21: type \"Value\" is not found in \"\"");
+        assert_eq!(link_error("package p;\nextend google.protobuf.FieldOptions { optional int32 o = 50000; }"), "t.proto: line 2, column 48: extend google.protobuf.FieldOptions: the message is not found in \"p\"");
+        assert_eq!(link_error("message M {}\nenum E { A = 0; }\nmessage M {}"), "t.proto: line 3, column 9: \"M\" is already defined");
+        let mut proto = parse("package p;\nmessage M {}", "a.proto").unwrap();
+        proto.append(parse("package p;\n\nenum M { A = 0; }", "b.proto").unwrap());
+        assert_eq!(proto.finalize().err().unwrap().to_string(), "b.proto: line 3, column 6: \"p.M\" is already defined");
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::io;
+use crate::proto::{MessageProto, ProtoData};
+use crate::typedefs::{CommonFieldProto, PbReader, PbReaderTrait};
+use crate::wire::*;
+
+// Schema-less decoding: message definitions are guessed using only wire types of the data.
+// A LEN value is tried as a nested message, then as an UTF-8 string, otherwise it is bytes.
+// Fields are named by their numbers, messages by the path from the root message.
+
+pub const RAW_ROOT_NAME: &str = "raw";
+
+enum RawType {
+    Varint,
+    I32,
+    I64,
+    Empty, // zero length, may be a message, a string or bytes
+    Message(RawMessage),
+    Str,
+    Bytes,
+    Mixed, // different wire types for the same field number, read as unknown data
+}
+
+#[derive(Default)]
+struct RawMessage {
+    fields: BTreeMap<i32, (RawType, bool)>, // field number -> (type, repeated)
+}
+
+impl RawType {
+    fn from_len_data(data: &[u8]) -> RawType {
+        if data.is_empty() {
+            RawType::Empty
+        } else if let Some(msg) = RawMessage::parse(data) {
+            RawType::Message(msg)
+        } else if std::str::from_utf8(data).is_ok() {
+            RawType::Str
+        } else {
+            RawType::Bytes
+        }
+    }
+
+    fn is_len(&self) -> bool {
+        matches!(self, RawType::Empty | RawType::Message(_) | RawType::Str | RawType::Bytes)
+    }
+
+    // combine types guessed for different values of the same field
+    fn merge(self, other: RawType) -> RawType {
+        match (self, other) {
+            (RawType::Empty, other) | (other, RawType::Empty) => other,
+            (RawType::Varint, RawType::Varint) => RawType::Varint,
+            (RawType::I32, RawType::I32) => RawType::I32,
+            (RawType::I64, RawType::I64) => RawType::I64,
+            (RawType::Str, RawType::Str) => RawType::Str,
+            (RawType::Message(m1), RawType::Message(m2)) => RawType::Message(m1.merge(m2)),
+            (t1, t2) if t1.is_len() && t2.is_len() => RawType::Bytes, // any length delimited data can be shown as bytes
+            _ => RawType::Mixed,
+        }
+    }
+}
+
+impl RawMessage {
+    // None if the data is not a valid message
+    fn parse(data: &[u8]) -> Option<RawMessage> {
+        let mut reader = PbReader::new(data);
+        let mut limit = data.len() as u64;
+        let mut res = RawMessage::default();
+        while limit > 0 {
+            let tag = reader.read_tag(&mut limit).ok()?;
+            if tag.field_id() <= 0 { return None; }
+            let field_type = match tag.wire_type() {
+                WT_VARINT => {
+                    reader.read_varint(&mut limit).ok()?;
+                    RawType::Varint
+                }
+                WT_I32 => {
+                    reader.read_len(tag.length, &mut limit).ok()?;
+                    RawType::I32
+                }
+                WT_I64 => {
+                    reader.read_len(tag.length, &mut limit).ok()?;
+                    RawType::I64
+                }
+                WT_SGROUP | WT_EGROUP => return None, // groups are not guessed
+                _ => RawType::from_len_data(&reader.read_len(tag.length, &mut limit).ok()?),
+            };
+            res.add(tag.field_id(), field_type);
+        }
+        Some(res)
+    }
+
+    // a field found twice in the same message is repeated
+    fn add(&mut self, id: i32, field_type: RawType) {
+        if let Some((old_type, _)) = self.fields.remove(&id) {
+            self.fields.insert(id, (old_type.merge(field_type), true));
+        } else {
+            self.fields.insert(id, (field_type, false));
+        }
+    }
+
+    fn merge(mut self, other: RawMessage) -> RawMessage {
+        for (id, (field_type, repeated)) in other.fields {
+            if let Some((old_type, old_repeated)) = self.fields.remove(&id) {
+                self.fields.insert(id, (old_type.merge(field_type), repeated || old_repeated));
+            } else {
+                self.fields.insert(id, (field_type, repeated));
+            }
+        }
+        self
+    }
+
+    // create message definitions for the message and all its submessages
+    fn collect(self, name: String, messages: &mut Vec<MessageProto>) {
+        let mut fields = vec![];
+        for (id, (field_type, repeated)) in self.fields {
+            let type_name = match field_type {
+                RawType::Varint => "int64".to_string(),
+                RawType::I32 => "fixed32".to_string(),
+                RawType::I64 => "fixed64".to_string(),
+                RawType::Str => "string".to_string(),
+                RawType::Empty | RawType::Bytes => "bytes".to_string(),
+                RawType::Message(msg) => {
+                    let msg_name = format!("{}.{}", name, id);
+                    msg.collect(msg_name.clone(), messages);
+                    msg_name
+                }
+                RawType::Mixed => continue,
+            };
+            fields.push(CommonFieldProto::new_field(id.to_string(), type_name, id, repeated, String::new(), None));
+        }
+        messages.push(MessageProto { name, fields, ..Default::default() });
+    }
+}
+
+// guess definitions for the data, the root message is named RAW_ROOT_NAME
+pub fn guess_proto(data: &[u8]) -> io::Result<ProtoData> {
+    if let Some(root) = RawMessage::parse(data) {
+        let mut messages = vec![];
+        root.collect(RAW_ROOT_NAME.to_string(), &mut messages);
+        ProtoData::from_messages(messages).finalize()
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "the data is not a protobuf message"))
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod guess {
+    use super::*;
+    use crate::wire::MessageData;
+
+    fn decode(binary_input: &[u8]) -> (ProtoData, MessageData) {
+        let proto = guess_proto(binary_input).unwrap();
+        let root_msg = proto.get_message_definition(RAW_ROOT_NAME).unwrap();
+        let mut limit = binary_input.len() as u64;
+        let mut read = PbReader::new(binary_input);
+        let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
+        (proto, data)
+    }
+
+    #[test]
+    fn scalars() {
+        let binary_input = [
+            0x08, 0x96, 0x01, // 1: 150
+            0x15, 0x01, 0x00, 0x00, 0x00, // 2: fixed32
+            0x19, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 3: fixed64
+            0x22, 0x03, 0x61, 0x62, 0x63, // 4: "abc"
+            0x2A, 0x02, 0xFF, 0xFE, // 5: not a message and not UTF-8
+            0x32, 0x00]; // 6: empty
+        let (proto, data) = decode(&binary_input);
+        let root = proto.get_message_definition(RAW_ROOT_NAME).unwrap();
+        let types: Vec<String> = root.fields.iter().map(|f| format!("{}:{}", f.name(), f.typename())).collect();
+        assert_eq!(types, ["1:int64", "2:fixed32", "3:fixed64", "4:string", "5:bytes", "6:bytes"]);
+        assert_eq!(data.to_string(), "message raw {\n  1 = 150\n  2 = 1\n  3 = 2\n  4 = abc\n  5 = ff fe\n  6 = \n}\n");
+
+        let mut buf = vec![];
+        data.write(&mut buf, &proto, root).unwrap();
+        assert_eq!(buf, binary_input);
+    }
+
+    #[test]
+    fn nested() {
+        let binary_input = [
+            0x0A, 0x04, 0x08, 0x01, 0x10, 0x02, // 1: { 1: 1, 2: 2 }
+            0x0A, 0x05, 0x12, 0x03, 0x0A, 0x01, 0x03]; // 1: { 2: { 1: 3 } }, the field 2 has different wire types
+        let (proto, data) = decode(&binary_input);
+
+        let root = proto.get_message_definition(RAW_ROOT_NAME).unwrap();
+        assert_eq!(root.fields.len(), 1);
+        assert!(root.fields[0].repeated());
+        assert_eq!(root.fields[0].typename(), "raw.1");
+
+        let sub = proto.get_message_definition("raw.1").unwrap();
+        assert_eq!(sub.fields.len(), 1); // the field 2 is unknown
+        assert_eq!(sub.fields[0].name(), "1");
+        assert!(!sub.fields[0].repeated());
+
+        let mut buf = vec![];
+        data.write(&mut buf, &proto, root).unwrap();
+        assert_eq!(buf, binary_input);
+    }
+
+    #[test]
+    fn not_a_message() {
+        assert!(guess_proto(&[0x0A, 0x05, 0x01]).is_err());
+        assert!(guess_proto(&[0x00, 0x01]).is_err()); // zero field number
+        assert!(guess_proto(&[]).is_ok());
+    }
+}
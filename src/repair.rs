@@ -0,0 +1,158 @@
+use std::io;
+use std::rc::Rc;
+use crate::proto::{FieldProtoPtr, MessageProtoPtr, ProtoData};
+use crate::typedefs::{DamagedFieldDefinition, PbReaderTrait, PbSliceReader};
+use crate::wire::*;
+
+// Repair mode: damaged data is decoded as far as possible instead of failing.
+// Fields read before an error are kept, the bytes which cannot be read become a "damaged" field
+// with the offset and the error, and reading resumes at the next offset where a known field can be read.
+// Nested messages are repaired separately, a cut message keeps the fields read before its end.
+
+
+pub fn repair_message(data: &[u8], proto: &ProtoData, def: MessageProtoPtr) -> MessageData {
+    read_message(data, 0, proto, def)
+}
+
+// offsets and errors of the damaged data; lazy submessages are not parsed for it,
+// their damaged data is shown when they are opened
+pub fn find_damaged(msg: &MessageData) -> Vec<String> {
+    let mut res = vec![];
+    for field in &msg.fields {
+        match &field.value {
+            _ if field.def.damaged() => res.push(field.def.comment()),
+            FieldValue::MESSAGE(sub) if sub.is_parsed() => res.extend(find_damaged(sub)),
+            _ => {}
+        }
+    }
+    res
+}
+
+// offset is the position of the data in the file
+fn read_message(data: &[u8], offset: usize, proto: &ProtoData, def: MessageProtoPtr) -> MessageData {
+    let mut fields = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let count = fields.len();
+        match read_field(data, pos, offset, proto, &def, &mut fields) {
+            Ok(end) => pos = end,
+            Err(e) => {
+                fields.truncate(count); // values of a packed field read before the error
+                let next = (pos + 1..data.len()).find(|&p| is_field_start(data, p, offset, proto, &def)).unwrap_or(data.len());
+                fields.push(damaged(offset + pos, &data[pos..next], &e.to_string()));
+                pos = next;
+            }
+        }
+    }
+    MessageData { def, fields }
+}
+
+// returns the position after the field
+fn read_field(data: &[u8], pos: usize, offset: usize, proto: &ProtoData, def: &MessageProtoPtr, flds: &mut Vec<FieldData>) -> io::Result<usize> {
+    let mut limit = (data.len() - pos) as u64;
+    let mut reader = PbSliceReader::new(&data[pos..], offset + pos);
+    let tag = reader.read_tag(&mut limit)?;
+    match def.get_field(tag.field_id()) {
+        Some(field_def) if field_def.is_message() && tag.wire_type() == WT_LEN => {
+            let start = reader.pos() - offset;
+            let end = start + tag.length.min(limit) as usize;
+            let mut msg = read_message(&data[start..end], offset + start, proto, field_def.message_proto().unwrap());
+            if tag.length > limit {
+                msg.fields.push(damaged(offset + end, &[], &format!("the message is cut, {} bytes are missing", tag.length - limit)));
+            }
+            flds.push(FieldData { def: field_def, pos: offset + start, value: FieldValue::MESSAGE(msg.into()), encoding: Encoding::default() });
+            Ok(end)
+        }
+        _ => {
+            let mut limit = (data.len() - pos) as u64;
+            let mut reader = PbSliceReader::new(&data[pos..], offset + pos);
+            MessageData::read_field(&mut reader, &proto.unknown_field, def, &mut limit, None, None, flds)?;
+            Ok(reader.pos() - offset)
+        }
+    }
+}
+
+// a field of the message with the expected wire type can be read at the position
+fn is_field_start(data: &[u8], pos: usize, offset: usize, proto: &ProtoData, def: &MessageProtoPtr) -> bool {
+    let mut limit = (data.len() - pos) as u64;
+    let mut reader = PbSliceReader::new(&data[pos..], offset + pos);
+    let Ok(tag) = reader.read_tag(&mut limit) else { return false; };
+    let Some(field_def) = def.get_field(tag.field_id()) else { return false; };
+    if tag.wire_type() != field_def.wire_type() && !(tag.wire_type() == WT_LEN && field_def.repeated()) { // packed
+        return false;
+    }
+    let mut limit = (data.len() - pos) as u64;
+    let mut reader = PbSliceReader::new(&data[pos..], offset + pos);
+    MessageData::read_field(&mut reader, &proto.unknown_field, def, &mut limit, None, None, &mut vec![]).is_ok()
+}
+
+pub(crate) fn damaged(pos: usize, bytes: &[u8], error: &str) -> FieldData {
+    let def = Rc::new(DamagedFieldDefinition::new(format!("offset {}: {}", pos, error)));
+    FieldData { def, pos, value: FieldValue::SCALAR(ScalarValue::BYTES(bytes.to_vec())), encoding: Encoding::default() }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod damaged {
+    use super::*;
+    use crate::text_format::message_to_text;
+
+    const DATA: [u8; 24] = [
+        0x08, 0x01,                    // f1: 1
+        0x10, 0x14, 0x10, 0x15,        // f2: 20 f2: 21
+        0x1a, 0x10,                    // m3 {
+        0x28, 0x05,                    //   f5: 5
+        0x32, 0x04, 0x40, 0x08, 0x48, 0x09, // m6 { f8: 8 f9: 9 }
+        0x32, 0x04, 0x40, 0x0a, 0x48, 0x0b, // m6 { f8: 10 f9: 11 }
+        0x38, 0x07];                   //   f7: 7 }
+
+    fn repair(data: &[u8]) -> (MessageData, ProtoData) {
+        let proto = ProtoData::new(r#"
+message M1 { int32 f1 = 1; repeated int32 f2 = 2; M3 m3 = 3; }
+message M3 { int32 f5 = 5; repeated M6 m6 = 6; int32 f7 = 7; }
+message M6 { int32 f8 = 8; int32 f9 = 9; }
+"#).unwrap().finalize().unwrap();
+        let data = repair_message(data, &proto, proto.get_message_definition("M1").unwrap());
+        (data, proto)
+    }
+
+    #[test]
+    fn cut_data() {
+        let (data, proto) = repair(&DATA[..20]);
+        assert_eq!(message_to_text(&data), "f1: 1\nf2: 20\nf2: 21\nm3 {\n  f5: 5\n  m6 {\n    f8: 8\n    f9: 9\n  }\n  m6 {\n    f8: 10\n    \
+            # damaged data at offset 20: the message is cut, 2 bytes are missing\n  }\n  # damaged data at offset 20: the message is cut, 4 bytes are missing\n}\n");
+        assert_eq!(find_damaged(&data).len(), 2);
+
+        let mut output = vec![];
+        data.write(&mut output, &proto, data.def.clone()).unwrap();
+        assert_eq!(output, [0x08, 0x01, 0x10, 0x14, 0x10, 0x15, 0x1a, 0x0c, 0x28, 0x05, 0x32, 0x04, 0x40, 0x08, 0x48, 0x09, 0x32, 0x02, 0x40, 0x0a]);
+    }
+
+    #[test]
+    fn wrong_bytes() {
+        let mut input = DATA;
+        input[10] = 0xff; // wrong tag of the first m6
+        input[11] = 0xff;
+        let (data, proto) = repair(&input);
+        assert_eq!(find_damaged(&data), ["offset 10: Unsupported length type (7) "]);
+        let m3 = data.get_submessage(&[(3, 0).into()]).unwrap();
+        assert_eq!(m3.fields[1].def.typename(), "damaged");
+        assert_eq!(m3.fields[1].pos, 10);
+        assert_eq!(m3.fields[1].value, FieldValue::SCALAR(ScalarValue::BYTES(input[10..16].to_vec()))); // up to the second m6
+
+        let mut output = vec![];
+        data.write(&mut output, &proto, data.def.clone()).unwrap();
+        assert_eq!(output, [&DATA[..7], &[0x0a], &DATA[8..10], &DATA[16..]].concat());
+
+        let (data, _) = repair(&[0xff; 3]);
+        assert_eq!(data.fields.len(), 1); // nothing to read
+        assert_eq!(data.fields[0].value, FieldValue::SCALAR(ScalarValue::BYTES(vec![0xff; 3])));
+    }
+}
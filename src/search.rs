@@ -0,0 +1,177 @@
+use crate::proto::FieldProtoPtr;
+use crate::view::FieldOrder;
+use crate::wire::*;
+
+// Incremental search of field names and values, case-insensitive.
+// Hits are collected in the order of the lines on the screen, so next/previous follow the screen.
+
+pub struct SearchHit {
+    pub path: FieldPath,
+    pub value: bool, // the value matched, else the field name
+    key: Vec<(usize, usize)>, // position on the screen, see display_key
+}
+
+#[derive(Default)]
+pub struct Search {
+    pub pattern: String,
+    pub prompt: bool, // the pattern is being typed
+    pub hits: Vec<SearchHit>,
+    pub current: Option<usize>,
+    origin: Vec<(usize, usize)>, // where the search started
+}
+
+impl Search {
+    pub fn start(&mut self, root: &MessageData, order: &FieldOrder, from: &FieldPath) {
+        *self = Search { prompt: true, origin: display_key(root, order, from), ..Search::default() };
+    }
+
+    // collect hits for the new pattern, the current hit is the first one from the search start
+    pub fn set_pattern(&mut self, root: &MessageData, order: &FieldOrder, pattern: String) {
+        self.pattern = pattern;
+        self.hits.clear();
+        if !self.pattern.is_empty() {
+            search_message(root, order, &self.pattern.to_lowercase(), &FieldPath::new(), &mut vec![], &mut self.hits);
+        }
+        self.current = self.hits.iter().position(|hit| hit.key >= self.origin).or(if self.hits.is_empty() { None } else { Some(0) });
+    }
+
+    pub fn step(&mut self, forward: bool) -> Option<&SearchHit> {
+        if self.hits.is_empty() { return None; }
+        let count = self.hits.len();
+        self.current = Some(match self.current {
+            Some(current) if forward => (current + 1) % count,
+            Some(current) => (current + count - 1) % count,
+            None => 0,
+        });
+        self.current_hit()
+    }
+
+    pub fn current_hit(&self) -> Option<&SearchHit> {
+        self.current.and_then(|index| self.hits.get(index))
+    }
+}
+
+// position of the field in the sorted fields and the value index for each level of the path
+fn display_key(root: &MessageData, order: &FieldOrder, path: &FieldPath) -> Vec<(usize, usize)> {
+    let mut key = vec![];
+    let mut msg = Some(root);
+    for pos in &path.0 {
+        let Some(current) = msg else { break; };
+        let sorted_pos = current.get_sorted_fields(order).iter()
+            .position(|(p, amount)| p.id == pos.id && p.index <= pos.index && pos.index < p.index + amount.max(&1))
+            .unwrap_or(usize::MAX);
+        key.push((sorted_pos, pos.index));
+        msg = current.get_submessage(&[pos.clone()]);
+    }
+    key
+}
+
+pub fn value_matches(def: &FieldProtoPtr, value: &ScalarValue, pattern: &str) -> bool {
+    let text = match value {
+        ScalarValue::ENUM(v) => def.get_enum_name_by_index(*v).map_or(v.to_string(), |name| name.to_string()),
+        ScalarValue::BYTES(v) => format!("{} {}", value, String::from_utf8_lossy(v)), // hex and text
+        ScalarValue::DELETED => return false,
+        _ => value.to_string(),
+    };
+    text.to_lowercase().contains(pattern)
+}
+
+fn search_message(msg: &MessageData, order: &FieldOrder, pattern: &str, prefix: &FieldPath, key: &mut Vec<(usize, usize)>, hits: &mut Vec<SearchHit>) {
+    for (sorted_pos, (pos, amount)) in msg.get_sorted_fields(order).into_iter().enumerate() {
+        let path = prefix.add(pos.clone());
+        let Some(def) = msg.get_field(&[pos.clone()]).map(|f| f.def.clone()).or_else(|| msg.def.get_field(pos.id)) else { continue; };
+        let unknown = matches!(msg.get_field(&[pos.clone()]).map(|f| &f.value), Some(FieldValue::SCALAR(ScalarValue::UNKNOWN(_, _))));
+        if !unknown && def.name().to_lowercase().contains(pattern) {
+            key.push((sorted_pos, pos.index));
+            hits.push(SearchHit { path: path.clone(), value: false, key: key.clone() });
+            key.pop();
+        }
+        for index in pos.index..pos.index + amount {
+            let Some(field) = msg.get_field(&[(pos.id, index).into()]) else { continue; };
+            key.push((sorted_pos, index));
+            match &field.value {
+                FieldValue::SCALAR(value) => {
+                    if value_matches(&field.def, value, pattern) {
+                        hits.push(SearchHit { path: path.with_last_index(index), value: true, key: key.clone() });
+                    }
+                }
+                FieldValue::MESSAGE(sub) => search_message(sub, order, pattern, &path.with_last_index(index), key, hits),
+            }
+            key.pop();
+        }
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod hits {
+    use super::*;
+    use crate::path::format_path;
+    use crate::proto::ProtoData;
+    use crate::text_format::text_to_message;
+
+    fn make_data() -> MessageData {
+        let proto = ProtoData::new(r#"
+message Root {
+  int32 count = 1;
+  repeated Item items = 2;
+  bytes raw = 3;
+}
+message Item {
+  string title = 1;
+  Kind kind = 2;
+  repeated int32 counts = 3;
+}
+enum Kind { PLAIN = 0; COUNTED = 1; }
+"#).unwrap().finalize().unwrap();
+        let text = "count: 12 items { title: 'Account' kind: COUNTED counts: 1 counts: 112 } items { title: 'x' } raw: 'COUNT\\377'";
+        text_to_message(text, proto.get_message_definition("Root").unwrap()).unwrap()
+    }
+
+    fn found(search: &Search, data: &MessageData) -> Vec<String> {
+        search.hits.iter().map(|hit| format!("{}{}", format_path(data, &hit.path), if hit.value { "=" } else { "" })).collect()
+    }
+
+    #[test]
+    fn names_and_values() {
+        let data = make_data();
+        let mut search = Search::default();
+        search.start(&data, &FieldOrder::Proto, &FieldPath::new());
+        search.set_pattern(&data, &FieldOrder::Proto, "Count".to_string());
+        assert_eq!(found(&search, &data), ["count", "items[0].title=", "items[0].kind=", "items[0].counts[0]", "items[1].counts[0]", "raw="]);
+        assert_eq!(search.current, Some(0));
+
+        search.set_pattern(&data, &FieldOrder::Proto, "12".to_string());
+        assert_eq!(found(&search, &data), ["count=", "items[0].counts[1]="]);
+
+        search.set_pattern(&data, &FieldOrder::ById, "ff".to_string()); // bytes are matched as hex
+        assert_eq!(found(&search, &data), ["raw="]);
+
+        search.set_pattern(&data, &FieldOrder::Proto, "none".to_string());
+        assert!(search.hits.is_empty());
+        assert!(search.step(true).is_none());
+    }
+
+    #[test]
+    fn navigation() {
+        let data = make_data();
+        let mut search = Search::default();
+        search.start(&data, &FieldOrder::Proto, &[(2, 1)].into()); // the search starts at the second item
+        search.set_pattern(&data, &FieldOrder::Proto, "t".to_string());
+        assert_eq!(found(&search, &data), ["count", "items[0]", "items[0].title", "items[0].title=", "items[0].kind=", "items[0].counts[0]",
+            "items[1].title", "items[1].counts[0]", "raw="]);
+        assert_eq!(search.current, Some(6));
+
+        assert_eq!(format_path(&data, &search.step(true).unwrap().path), "items[1].counts[0]");
+        assert_eq!(format_path(&data, &search.step(true).unwrap().path), "raw");
+        assert_eq!(format_path(&data, &search.step(true).unwrap().path), "count"); // wrap around
+        assert_eq!(format_path(&data, &search.step(false).unwrap().path), "raw");
+    }
+}
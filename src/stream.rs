@@ -0,0 +1,228 @@
+use std::io;
+use clap::ValueEnum;
+use crate::proto::{MessageProto, MessageProtoPtr, ProtoData};
+use crate::typedefs::{CommonFieldProto, PbReaderTrait, PbSliceReader};
+use crate::wire::*;
+
+// Stream files: a sequence of messages, each one prefixed by its size,
+// as written by writeDelimitedTo (varint size) or with a fixed-width size.
+// The stream is shown as a message with a repeated field of records and saved with the same framing.
+
+pub const STREAM_ROOT_NAME: &str = "(stream)";
+pub const RECORD_FIELD_NAME: &str = "record";
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Framing {
+    /// varint size, as written by writeDelimitedTo
+    Varint,
+    /// 4 bytes little-endian size
+    #[value(name = "fixed32le")]
+    Fixed32Le,
+    /// 4 bytes big-endian size
+    #[value(name = "fixed32be")]
+    Fixed32Be,
+}
+
+impl Framing {
+    pub fn name(&self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+}
+
+// the root message of a stream with records of the message def
+pub fn new_stream(proto: &mut ProtoData, def: &MessageProtoPtr, framing: Framing) -> MessageProtoPtr {
+    proto.stream = Some(framing);
+    stream_message(proto, def)
+}
+
+fn stream_message(proto: &mut ProtoData, def: &MessageProtoPtr) -> MessageProtoPtr {
+    let field = CommonFieldProto::new_field(RECORD_FIELD_NAME.to_string(), format!(".{}", def.name), 1, true, String::new(), None);
+    proto.insert_message(MessageProto { name: STREAM_ROOT_NAME.to_string(), fields: vec![field], ..Default::default() })
+}
+
+// the framing is detected if not given: the sizes must match the data and the records must be valid,
+// the framing with fewer records is preferred (zero bytes may be read as empty records);
+// lazy records are parsed on first access, only their tags are checked here (see check_tags)
+pub fn read_stream(source: DataSource, proto: &mut ProtoData, def: MessageProtoPtr, framing: Option<Framing>, lazy: bool) -> io::Result<MessageData> {
+    let data = (*source).as_ref();
+    let stream_def = stream_message(proto, &def);
+    let record_def = stream_def.fields[0].clone();
+    let framings = framing.map_or(Framing::value_variants().to_vec(), |framing| vec![framing]);
+    let mut candidates: Vec<_> = framings.into_iter().filter_map(|framing| Some((framing, split_records(data, framing)?))).collect();
+    candidates.sort_by_key(|(_, records)| records.len());
+    let mut error = io::Error::new(io::ErrorKind::InvalidData, "the sizes of records do not match the data");
+    for (framing, records) in candidates {
+        let mut fields = vec![];
+        for (index, &(pos, start, length)) in records.iter().enumerate() {
+            let bytes = &data[start..start + length as usize];
+            let value = if lazy && index > 0 {
+                check_tags(bytes, start).map(|_| SubMessage::new_lazy(source.clone(), proto, def.clone(), start, length))
+            } else {
+                let mut limit = length;
+                MessageData::new(&mut PbSliceReader::new(bytes, start), proto, def.clone(), &mut limit).map(SubMessage::from)
+            };
+            let value = match value {
+                Ok(value) => value,
+                Err(e) => {
+                    error = io::Error::new(e.kind(), format!("record {}: {}", index, e));
+                    break;
+                }
+            };
+            fields.push(FieldData { def: record_def.clone(), pos, value: FieldValue::MESSAGE(value), encoding: Encoding::default() });
+        }
+        if fields.len() == records.len() {
+            proto.stream = Some(framing);
+            return Ok(MessageData { def: stream_def, fields });
+        }
+    }
+    Err(error)
+}
+
+// the tags of a record follow one another up to its end and the groups are closed,
+// the values are passed over
+fn check_tags(bytes: &[u8], pos: usize) -> io::Result<()> {
+    let mut limit = bytes.len() as u64;
+    let mut reader = PbSliceReader::new(bytes, pos);
+    let mut groups = vec![];
+    while limit > 0 {
+        let tag = reader.read_tag(&mut limit)?;
+        match tag.wire_type() {
+            WT_VARINT => { reader.read_varint(&mut limit)?; }
+            WT_SGROUP => groups.push(tag.field_id()),
+            WT_EGROUP if groups.last() == Some(&tag.field_id()) => { groups.pop(); }
+            WT_EGROUP => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected end of group {}", tag.field_id()))),
+            _ => reader.skip(tag.length, &mut limit)?,
+        }
+    }
+    if !groups.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "not completed group"));
+    }
+    Ok(())
+}
+
+// (position of the size, start, length) of each record, None if the sizes do not match the data
+fn split_records(data: &[u8], framing: Framing) -> Option<Vec<(usize, usize, u64)>> {
+    let mut records = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, start) = match framing {
+            Framing::Varint => {
+                let mut limit = (data.len() - pos) as u64;
+                let length = PbSliceReader::new(&data[pos..], pos).read_varint(&mut limit).ok()?;
+                (u64::try_from(length).ok()?, data.len() - limit as usize)
+            }
+            Framing::Fixed32Le => (u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as u64, pos + 4),
+            Framing::Fixed32Be => (u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as u64, pos + 4),
+        };
+        if length > (data.len() - start) as u64 { return None; }
+        records.push((pos, start, length));
+        pos = start + length as usize;
+    }
+    Some(records)
+}
+
+pub fn write_stream(writer: &mut dyn io::Write, data: &MessageData, proto: &ProtoData, framing: Framing) -> io::Result<()> {
+    let mut buf = vec![];
+    for field in &data.fields {
+        let FieldValue::MESSAGE(msg) = &field.value else { continue; };
+        buf.clear();
+        msg.write(&mut buf, proto)?;
+        match framing {
+            Framing::Varint => CommonFieldProto::write_varint(writer, buf.len() as i128)?,
+            Framing::Fixed32Le | Framing::Fixed32Be => {
+                let Ok(length) = u32::try_from(buf.len()) else {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "the record is too big for the 32-bit size"));
+                };
+                writer.write_all(&if framing == Framing::Fixed32Le { length.to_le_bytes() } else { length.to_be_bytes() })?;
+            }
+        }
+        writer.write_all(&buf)?;
+    }
+    Ok(())
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod framing {
+    use std::rc::Rc;
+    use super::*;
+    use crate::text_format::message_to_text;
+    use crate::trz::{Change, ChangeType};
+
+    fn proto() -> ProtoData {
+        ProtoData::new("message Event { int32 id = 1; string name = 2; }").unwrap().finalize().unwrap()
+    }
+
+    const VARINT_STREAM: [u8; 13] = [
+        0x05, 0x08, 0x01, 0x12, 0x01, 0x61, // { id: 1 name: "a" }
+        0x00,                               // {}
+        0x05, 0x08, 0x03, 0x12, 0x01, 0x63, // { id: 3 name: "c" }
+    ];
+
+    fn read(bytes: &[u8], framing: Option<Framing>, lazy: bool) -> io::Result<(MessageData, ProtoData)> {
+        let mut proto = proto();
+        let def = proto.get_message_definition("Event").unwrap();
+        let data = read_stream(Rc::new(bytes.to_vec()), &mut proto, def, framing, lazy)?;
+        Ok((data, proto))
+    }
+
+    fn write(data: &MessageData, proto: &ProtoData) -> Vec<u8> {
+        let mut output = vec![];
+        write_stream(&mut output, data, proto, proto.stream.unwrap()).unwrap();
+        output
+    }
+
+    #[test]
+    fn round_trip() {
+        for lazy in [false, true] {
+            let (data, proto) = read(&VARINT_STREAM, None, lazy).unwrap();
+            assert_eq!(proto.stream, Some(Framing::Varint));
+            assert_eq!(data.def.name, STREAM_ROOT_NAME);
+            assert_eq!(message_to_text(&data), "record {\n  id: 1\n  name: \"a\"\n}\nrecord {\n}\nrecord {\n  id: 3\n  name: \"c\"\n}\n");
+            assert_eq!(write(&data, &proto), VARINT_STREAM);
+        }
+
+        let fixed = [0, 0, 0, 2, 0x08, 0x07, 0, 0, 0, 0];
+        let (data, proto) = read(&fixed, None, false).unwrap();
+        assert_eq!(proto.stream, Some(Framing::Fixed32Be));
+        assert_eq!(message_to_text(&data), "record {\n  id: 7\n}\nrecord {\n}\n");
+        assert_eq!(write(&data, &proto), fixed);
+
+        let (data, proto) = read(&[2, 0, 0, 0, 0x08, 0x07], Some(Framing::Fixed32Le), false).unwrap();
+        assert_eq!(message_to_text(&data), "record {\n  id: 7\n}\n");
+        assert_eq!(write(&data, &proto), [2, 0, 0, 0, 0x08, 0x07]);
+    }
+
+    #[test]
+    fn wrong_data() {
+        assert!(read(&[0x05, 0x08, 0x01], None, false).is_err()); // the record is cut
+        assert!(read(&[0x02, 0x08, 0x01], Some(Framing::Fixed32Le), false).is_err());
+        let error = read(&[0x01, 0x08, 0x02, 0x08, 0x01], Some(Framing::Varint), false).unwrap_err();
+        assert!(error.to_string().starts_with("record 0: "));
+        // lazy records after the first one are checked too
+        let error = read(&[0x00, 0x01, 0x08, 0x02, 0x08, 0x01], Some(Framing::Varint), true).unwrap_err();
+        assert!(error.to_string().starts_with("record 1: "));
+        assert!(read(&[0x00, 0x02, 0x0B, 0x14], Some(Framing::Varint), true).is_err()); // the end of another group
+        assert!(read(&[0x00, 0x02, 0x0B, 0x0C], Some(Framing::Varint), true).is_ok());
+    }
+
+    #[test]
+    fn edit_records() {
+        let (mut data, proto) = read(&VARINT_STREAM, None, true).unwrap();
+        let mut change = Change { path: [(1, 2)].into(), action: ChangeType::Move(0) };
+        data.apply(&mut change).unwrap();
+        data.apply(&mut Change::delete_value([(1, 2)].into())).unwrap();
+        data.apply(&mut Change::insert_message([(1, 1)].into(), MessageData { def: proto.get_message_definition("Event").unwrap(), fields: vec![] })).unwrap();
+        assert_eq!(write(&data, &proto), [0x05, 0x08, 0x03, 0x12, 0x01, 0x63, 0x00, 0x05, 0x08, 0x01, 0x12, 0x01, 0x61]);
+
+        data.apply(&mut change).unwrap(); // move back
+        assert_eq!(write(&data, &proto), [0x00, 0x05, 0x08, 0x01, 0x12, 0x01, 0x61, 0x05, 0x08, 0x03, 0x12, 0x01, 0x63]);
+    }
+}
@@ -0,0 +1,693 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use crate::proto::{FieldProtoPtr, MessageProto, MessageProtoPtr, ProtoData};
+use crate::repair;
+use crate::stream::{self, Framing};
+use crate::typedefs::{CommonFieldProto, PbSliceReader, UnknownFieldDefinition};
+use crate::well_known;
+use crate::wire::*;
+
+// Protobuf text format (textproto)
+// https://protobuf.dev/reference/protobuf/textformat-spec/
+
+const INDENT: usize = 2;
+const LAZY_FILE_SIZE: u64 = 64 << 20;
+
+pub fn is_text_format(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("textproto" | "pbtxt" | "txtpb"))
+}
+
+// how a binary file is read, given by the command line arguments
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReadOptions {
+    pub lazy: bool, // big files are always lazy
+    pub stream: Option<Option<Framing>>, // Some(None) to detect the framing
+    pub repair: bool, // damaged data is kept, see repair.rs
+}
+
+// binary or text format is chosen by the file extension
+// big binary files (or any with lazy) are mapped to memory and parsed on demand
+// a binary file is read as a stream if asked, or if it is not a message
+pub fn read_document(path: &Path, proto: &mut ProtoData, def: MessageProtoPtr, options: ReadOptions) -> io::Result<MessageData> {
+    if is_text_format(path) {
+        let text = std::fs::read_to_string(path)?;
+        return text_to_message(&text, def).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let mut file = std::fs::File::open(path)?;
+    let lazy = options.lazy || file.metadata()?.len() >= LAZY_FILE_SIZE;
+    let source: DataSource = if lazy {
+        // the file is expected to be unchanged by other programs while it is open
+        Rc::new(unsafe { memmap2::Mmap::map(&file)? })
+    } else {
+        let mut buf = vec![];
+        io::Read::read_to_end(&mut file, &mut buf)?;
+        Rc::new(buf)
+    };
+    if let Some(framing) = options.stream {
+        return stream::read_stream(source, proto, def, framing, lazy);
+    }
+    if options.repair {
+        return Ok(repair::repair_message((*source).as_ref(), proto, def));
+    }
+    let single = if lazy {
+        MessageData::new_lazy(source.clone(), proto, def.clone())
+    } else {
+        let data = (*source).as_ref();
+        let mut limit = data.len() as u64;
+        MessageData::new(&mut PbSliceReader::new(data, 0), proto, def.clone(), &mut limit)
+    };
+    single.or_else(|e| stream::read_stream(source, proto, def, None, lazy).map_err(|_| e))
+}
+
+pub fn write_document(writer: &mut dyn io::Write, path: &Path, data: &MessageData, proto: &ProtoData) -> io::Result<()> {
+    if is_text_format(path) {
+        writer.write_all(message_to_text(data).as_bytes())
+    } else if let Some(framing) = proto.stream {
+        stream::write_stream(writer, data, proto, framing)
+    } else {
+        data.write(writer, proto, data.def.clone())
+    }
+}
+
+
+pub fn message_to_text(msg: &MessageData) -> String {
+    let mut out = String::new();
+    write_fields(&mut out, msg.fields.iter(), 0, false);
+    out
+}
+
+pub fn fields_to_text<'a>(fields: impl Iterator<Item=&'a FieldData>) -> String {
+    let mut out = String::new();
+    write_fields(&mut out, fields, 0, false);
+    out
+}
+
+pub fn scalar_to_text(def: &FieldProtoPtr, value: &ScalarValue) -> String {
+    let mut out = String::new();
+    write_scalar(&mut out, def, value);
+    out
+}
+
+// map entries are messages with "key" and "value" fields
+fn field_name(field: &FieldData, map_entry: bool) -> String {
+    match field.id() {
+        1 if map_entry => "key".to_string(),
+        2 if map_entry => "value".to_string(),
+        id if matches!(field.value, FieldValue::SCALAR(ScalarValue::UNKNOWN(_, _))) => id.to_string(),
+        _ if field.def.extension().is_some() => format!("[{}]", field.def.extension().unwrap()),
+        _ if field.def.get_common_definition().group => field.def.typename(), // as declared, not in lowercase
+        _ => field.def.name(),
+    }
+}
+
+fn write_fields<'a>(out: &mut String, fields: impl Iterator<Item=&'a FieldData>, indent: usize, map_entry: bool) {
+    for field in fields {
+        if field.def.damaged() {
+            writeln!(out, "{:indent$}# damaged data at {}", "", field.def.comment(), indent = indent).unwrap();
+            continue;
+        }
+        let name = field_name(field, map_entry);
+        match &field.value {
+            FieldValue::MESSAGE(sub) if field.def.any_value() => { // the bytes as they are written
+                write!(out, "{:indent$}{}: ", "", name, indent = indent).unwrap();
+                write_bytes(out, &well_known::packed_value(sub), false);
+                out.push('\n');
+            }
+            FieldValue::MESSAGE(sub) => {
+                writeln!(out, "{:indent$}{} {{", "", name, indent = indent).unwrap();
+                write_fields(out, sub.fields.iter(), indent + INDENT, is_map_entry(&sub.def));
+                writeln!(out, "{:indent$}}}", "", indent = indent).unwrap();
+            }
+            FieldValue::SCALAR(ScalarValue::DELETED) => {}
+            FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, bytes)) if tag.wire_type() == WT_SGROUP => {
+                writeln!(out, "{:indent$}{} {{", "", name, indent = indent).unwrap();
+                match unknown_group(tag, bytes) {
+                    Some(group) => write_fields(out, group.fields.iter(), indent + INDENT, false),
+                    None => writeln!(out, "{:indent$}# the group cannot be read", "", indent = indent + INDENT).unwrap(),
+                }
+                writeln!(out, "{:indent$}}}", "", indent = indent).unwrap();
+            }
+            FieldValue::SCALAR(scalar) => {
+                write!(out, "{:indent$}{}: ", "", name, indent = indent).unwrap();
+                write_scalar(out, &field.def, scalar);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+// the fields of an unknown group, all unknown
+fn unknown_group(tag: &Tag, bytes: &[u8]) -> Option<MessageData> {
+    let unknown_field: FieldProtoPtr = Rc::new(UnknownFieldDefinition::new());
+    let mut limit = bytes.len() as u64;
+    MessageData::read(&mut PbSliceReader::new(bytes, 0), &unknown_field, Rc::new(MessageProto::default()), &mut limit, Some(tag.field_id()), None).ok()
+}
+
+pub(crate) fn is_map_entry(def: &MessageProtoPtr) -> bool {
+    def.name.contains(',')
+}
+
+fn write_scalar(out: &mut String, def: &FieldProtoPtr, value: &ScalarValue) {
+    match value {
+        ScalarValue::F32(v) => write_float(out, *v as f64, v.to_string()),
+        ScalarValue::F64(v) => write_float(out, *v, v.to_string()),
+        ScalarValue::ENUM(v) => {
+            if let Some(name) = def.get_enum_name_by_index(*v) {
+                out.push_str(name);
+            } else {
+                write!(out, "{}", v).unwrap();
+            }
+        }
+        ScalarValue::STR(v) => write_bytes(out, v.as_bytes(), true),
+        ScalarValue::BYTES(v) => write_bytes(out, v, false),
+        // fixed32 and fixed64 in hex with all the digits, so they are read back as they are written
+        ScalarValue::UNKNOWN(tag, bytes) => {
+            let mut buf = [0u8; 16];
+            buf[..bytes.len().min(16)].copy_from_slice(&bytes[..bytes.len().min(16)]);
+            match tag.wire_type() {
+                WT_VARINT => write!(out, "{}", u128::from_le_bytes(buf)).unwrap(),
+                WT_I32 => write!(out, "0x{:08x}", u128::from_le_bytes(buf)).unwrap(),
+                WT_I64 => write!(out, "0x{:016x}", u128::from_le_bytes(buf)).unwrap(),
+                _ => write_bytes(out, bytes, false),
+            }
+        }
+        other => write!(out, "{}", other).unwrap(),
+    }
+}
+
+fn write_float(out: &mut String, value: f64, text: String) {
+    if value.is_nan() {
+        out.push_str("nan");
+    } else if value.is_infinite() {
+        out.push_str(if value > 0.0 { "inf" } else { "-inf" });
+    } else {
+        out.push_str(&text);
+    }
+}
+
+// C-style escaping, UTF-8 is kept as is for strings
+fn write_bytes(out: &mut String, data: &[u8], utf8: bool) {
+    out.push('"');
+    if utf8 {
+        for c in String::from_utf8_lossy(data).chars() {
+            if c.is_ascii() { escape_byte(out, c as u8) } else { out.push(c) }
+        }
+    } else {
+        for b in data { escape_byte(out, *b); }
+    }
+    out.push('"');
+}
+
+fn escape_byte(out: &mut String, b: u8) {
+    match b {
+        b'"' => out.push_str("\\\""),
+        b'\\' => out.push_str("\\\\"),
+        b'\n' => out.push_str("\\n"),
+        b'\r' => out.push_str("\\r"),
+        b'\t' => out.push_str("\\t"),
+        0x20..=0x7e => out.push(b as char),
+        _ => write!(out, "\\{:03o}", b).unwrap(),
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Name(String), // identifier or field number
+    Number(String), // also inf and nan with sign
+    Str(Vec<u8>), // adjacent strings are concatenated
+    Punct(char),
+    End,
+}
+
+struct Tokenizer<'a> {
+    text: &'a str,
+    pos: usize, // in bytes
+    token_pos: usize, // start of the last token, for error messages
+}
+
+impl Tokenizer<'_> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.text[..self.token_pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("line {}, column {}: {}", line, column, message)
+    }
+    fn peek_char(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+    fn skip_spaces(&mut self) {
+        while let Some(c) = self.peek_char() {
+            if c == '#' { // comment till the end of line
+                self.pos = self.text[self.pos..].find('\n').map_or(self.text.len(), |i| self.pos + i);
+            } else if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+    fn peek(&mut self) -> Result<Token, String> {
+        let pos = self.pos;
+        let token = self.next();
+        self.pos = pos;
+        token
+    }
+    fn next(&mut self) -> Result<Token, String> {
+        self.skip_spaces();
+        self.token_pos = self.pos;
+        let Some(c) = self.peek_char() else { return Ok(Token::End); };
+        if c == '"' || c == '\'' {
+            let mut res = vec![];
+            while let Some(quote @ ('"' | '\'')) = self.peek_char() {
+                self.pos += 1;
+                self.read_string(quote, &mut res)?;
+                self.skip_spaces();
+            }
+            return Ok(Token::Str(res));
+        }
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        if c.is_ascii_digit() || c == '-' || c == '.' {
+            let start = self.pos;
+            self.pos += 1;
+            while let Some(c) = self.peek_char() {
+                let exponent_sign = (c == '-' || c == '+') && self.text[start..self.pos].ends_with(['e', 'E']) && !self.text[start..self.pos].contains(['x', 'X']);
+                if !is_name_char(c) && !exponent_sign { break; }
+                self.pos += 1;
+            }
+            return Ok(Token::Number(self.text[start..self.pos].to_string()));
+        }
+        if is_name_char(c) {
+            let start = self.pos;
+            while self.peek_char().is_some_and(is_name_char) {
+                self.pos += 1;
+            }
+            return Ok(Token::Name(self.text[start..self.pos].to_string()));
+        }
+        self.pos += c.len_utf8();
+        Ok(Token::Punct(c))
+    }
+    fn read_string(&mut self, quote: char, res: &mut Vec<u8>) -> Result<(), String> {
+        loop {
+            let Some(c) = self.peek_char() else { return Err(self.error("unterminated string")); };
+            self.pos += c.len_utf8();
+            match c {
+                '\n' => return Err(self.error("unterminated string")),
+                '\\' => {
+                    let Some(e) = self.peek_char() else { return Err(self.error("unterminated string")); };
+                    self.pos += e.len_utf8();
+                    match e {
+                        'n' => res.push(b'\n'),
+                        'r' => res.push(b'\r'),
+                        't' => res.push(b'\t'),
+                        'a' => res.push(0x07),
+                        'b' => res.push(0x08),
+                        'f' => res.push(0x0c),
+                        'v' => res.push(0x0b),
+                        '0'..='7' => {
+                            let start = self.pos - 1;
+                            while self.pos - start < 3 && matches!(self.peek_char(), Some('0'..='7')) { self.pos += 1; }
+                            res.push(u32::from_str_radix(&self.text[start..self.pos], 8).map_err(|_| self.error("wrong escape sequence"))? as u8);
+                        }
+                        'x' | 'u' | 'U' => {
+                            let max_len = match e { 'x' => 2, 'u' => 4, _ => 8 };
+                            let start = self.pos;
+                            while self.pos - start < max_len && self.peek_char().is_some_and(|c| c.is_ascii_hexdigit()) { self.pos += 1; }
+                            let code = u32::from_str_radix(&self.text[start..self.pos], 16).map_err(|_| self.error("wrong escape sequence"))?;
+                            if e == 'x' {
+                                res.push(code as u8);
+                            } else {
+                                let c = char::from_u32(code).ok_or_else(|| self.error("wrong unicode character"))?;
+                                res.extend_from_slice(c.to_string().as_bytes());
+                            }
+                        }
+                        other => res.extend_from_slice(other.to_string().as_bytes()), // \" \' \\ \?
+                    }
+                }
+                c if c == quote => return Ok(()),
+                c => res.extend_from_slice(c.to_string().as_bytes()),
+            }
+        }
+    }
+}
+
+pub fn text_to_message(text: &str, def: MessageProtoPtr) -> Result<MessageData, String> {
+    let mut tokenizer = Tokenizer { text, pos: 0, token_pos: 0 };
+    read_message(&mut tokenizer, def, None)
+}
+
+// a single value of the field, the fields of a message are written without braces
+pub fn text_to_value(text: &str, def: &FieldProtoPtr) -> Result<FieldValue, String> {
+    if let Some(msg_def) = def.message_proto() {
+        return Ok(FieldValue::MESSAGE(text_to_message(text, msg_def)?.into()));
+    }
+    let mut tokenizer = Tokenizer { text, pos: 0, token_pos: 0 };
+    let value = read_scalar(&mut tokenizer, def)?;
+    if tokenizer.next()? != Token::End { return Err(tokenizer.error("unexpected text after the value")); }
+    Ok(FieldValue::SCALAR(value))
+}
+
+fn read_message(tokens: &mut Tokenizer, def: MessageProtoPtr, end: Option<char>) -> Result<MessageData, String> {
+    let map_entry = is_map_entry(&def);
+    let mut fields = vec![];
+    loop {
+        let name = match tokens.next()? {
+            Token::End if end.is_none() => break,
+            Token::Punct(c) if Some(c) == end => break,
+            Token::End => return Err(tokens.error(&format!("expected '{}'", end.unwrap()))),
+            Token::Name(name) | Token::Number(name) => name,
+            Token::Punct('[') => { // an extension by its fully qualified name
+                let start = tokens.token_pos;
+                let Token::Name(name) = tokens.next()? else { return Err(tokens.error("expected an extension name")); };
+                if tokens.next()? != Token::Punct(']') { return Err(tokens.error("expected ']'")); }
+                tokens.token_pos = start; // errors of the field point to its name
+                format!("[{}]", name)
+            }
+            _ => return Err(tokens.error(&format!("expected a field name of {}", def.name))),
+        };
+        let field_def = match name.as_str() {
+            "key" if map_entry => def.get_field(1),
+            "value" if map_entry => def.get_field(2),
+            _ => def.fields.iter().find(|f| f.name() == name || (f.get_common_definition().group && f.typename() == name) || f.extension().is_some_and(|ext| format!("[{}]", ext) == name)).cloned().
+                or_else(|| def.get_field(name.parse().ok()?).filter(|f| unknown_wire_type(tokens).is_ok_and(|wire_type| declared_wire_type(f, wire_type)))),
+        };
+        let Some(field_def) = field_def else {
+            match name.parse::<i32>() {
+                Ok(id) if id > 0 => { // unknown field written by number
+                    fields.push(read_unknown(tokens, id)?);
+                    continue;
+                }
+                _ => return Err(tokens.error(&format!("unknown field \"{}\" of {}", name, def.name))),
+            }
+        };
+
+        let has_colon = tokens.peek()? == Token::Punct(':');
+        if has_colon { tokens.next()?; }
+        let list = tokens.peek()? == Token::Punct('[');
+        if list {
+            tokens.next()?;
+            if tokens.peek()? == Token::Punct(']') {
+                tokens.next()?;
+            } else {
+                loop {
+                    fields.push(read_field(tokens, &field_def, has_colon)?);
+                    match tokens.next()? {
+                        Token::Punct(',') => {}
+                        Token::Punct(']') => break,
+                        _ => return Err(tokens.error("expected ',' or ']'")),
+                    }
+                }
+            }
+        } else {
+            fields.push(read_field(tokens, &field_def, has_colon)?);
+        }
+        if let Token::Punct(',' | ';') = tokens.peek()? { tokens.next()?; }
+    }
+    Ok(MessageData { def, fields })
+}
+
+fn read_field(tokens: &mut Tokenizer, def: &FieldProtoPtr, has_colon: bool) -> Result<FieldData, String> {
+    let value = if let Some(msg_def) = def.message_proto() {
+        let end = match tokens.next()? {
+            Token::Punct('{') => '}',
+            Token::Punct('<') => '>',
+            _ => return Err(tokens.error(&format!("expected {} for field {}", msg_def.name, def.name()))),
+        };
+        FieldValue::MESSAGE(read_message(tokens, msg_def, Some(end))?.into())
+    } else {
+        if !has_colon { return Err(tokens.error(&format!("expected ':' after field {}", def.name()))); }
+        FieldValue::SCALAR(read_scalar(tokens, def)?)
+    };
+    Ok(FieldData::new(def.clone(), value))
+}
+
+fn read_scalar(tokens: &mut Tokenizer, def: &FieldProtoPtr) -> Result<ScalarValue, String> {
+    let token = tokens.next()?;
+    let error = || tokens.error(&format!("expected {} for field {}", def.typename(), def.name()));
+    let FieldValue::SCALAR(default) = def.default() else { return Err(error()); };
+    token_to_scalar(&token, &default, |name| def.get_enum_index_by_name(name)).ok_or_else(error)
+}
+
+// a value declared by the [default = ...] option of a proto2 field, zero is a value of the field type
+pub fn default_to_scalar(text: &str, zero: &ScalarValue, enum_index: impl Fn(&str) -> Option<i32>) -> Option<ScalarValue> {
+    let mut tokenizer = Tokenizer { text, pos: 0, token_pos: 0 };
+    let token = tokenizer.next().ok()?;
+    if tokenizer.next().ok()? != Token::End { return None; }
+    token_to_scalar(&token, zero, enum_index)
+}
+
+// the token converted to the type of the kind value
+fn token_to_scalar(token: &Token, kind: &ScalarValue, enum_index: impl Fn(&str) -> Option<i32>) -> Option<ScalarValue> {
+    let int = |min: i128, max: i128| match token {
+        Token::Number(text) => parse_integer(text).filter(|v| *v >= min && *v <= max),
+        _ => None,
+    };
+    Some(match kind {
+        ScalarValue::I32(_) => ScalarValue::I32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::S32(_) => ScalarValue::S32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::SF32(_) => ScalarValue::SF32(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        ScalarValue::U32(_) => ScalarValue::U32(int(0, u32::MAX as i128)? as u32),
+        ScalarValue::UF32(_) => ScalarValue::UF32(int(0, u32::MAX as i128)? as u32),
+        ScalarValue::I64(_) => ScalarValue::I64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::S64(_) => ScalarValue::S64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::SF64(_) => ScalarValue::SF64(int(i64::MIN as i128, i64::MAX as i128)? as i64),
+        ScalarValue::U64(_) => ScalarValue::U64(int(0, u64::MAX as i128)? as u64),
+        ScalarValue::UF64(_) => ScalarValue::UF64(int(0, u64::MAX as i128)? as u64),
+        ScalarValue::F32(_) => ScalarValue::F32(parse_float(token)? as f32),
+        ScalarValue::F64(_) => ScalarValue::F64(parse_float(token)?),
+        ScalarValue::BOOL(_) => match token {
+            Token::Name(v) if v == "true" || v == "True" || v == "t" => ScalarValue::BOOL(true),
+            Token::Name(v) if v == "false" || v == "False" || v == "f" => ScalarValue::BOOL(false),
+            _ => ScalarValue::BOOL(int(0, 1)? == 1),
+        },
+        ScalarValue::ENUM(_) => match token {
+            Token::Name(name) => ScalarValue::ENUM(enum_index(name)?),
+            _ => ScalarValue::ENUM(int(i32::MIN as i128, i32::MAX as i128)? as i32),
+        },
+        ScalarValue::STR(_) => match token {
+            Token::Str(v) => ScalarValue::STR(String::from_utf8(v.clone()).ok()?),
+            _ => return None,
+        },
+        ScalarValue::BYTES(_) => match token {
+            Token::Str(v) => ScalarValue::BYTES(v.clone()),
+            _ => return None,
+        },
+        ScalarValue::UNKNOWN(_, _) | ScalarValue::DELETED => return None,
+    })
+}
+
+// the wire type of the unknown field value which follows, as write_scalar writes it
+fn unknown_wire_type(tokens: &mut Tokenizer) -> Result<u8, String> {
+    let (pos, token_pos) = (tokens.pos, tokens.token_pos);
+    if tokens.peek()? == Token::Punct(':') { tokens.next()?; }
+    let token = tokens.next();
+    (tokens.pos, tokens.token_pos) = (pos, token_pos);
+    Ok(match token? {
+        Token::Number(text) => match text.strip_prefix("0x").map(|digits| digits.len()) {
+            Some(8) => WT_I32,
+            Some(16) => WT_I64,
+            _ => WT_VARINT,
+        },
+        Token::Punct('{') => WT_SGROUP,
+        _ => WT_LEN,
+    })
+}
+
+// a field written by number is the declared one if its value has the wire type of the field,
+// otherwise it is an unknown field with the same number (see MessageData::expected_wire_type)
+fn declared_wire_type(def: &FieldProtoPtr, wire_type: u8) -> bool {
+    match wire_type {
+        WT_SGROUP => def.is_message(),
+        _ if def.is_message() => false,
+        wire_type => wire_type == def.wire_type(),
+    }
+}
+
+// the value of an unknown field as write_scalar writes it: a varint, fixed32 or fixed64 in hex,
+// LEN data as a string, or a group in braces
+fn read_unknown(tokens: &mut Tokenizer, id: i32) -> Result<FieldData, String> {
+    let has_colon = tokens.peek()? == Token::Punct(':');
+    if has_colon { tokens.next()?; }
+    let value = match tokens.next()? {
+        Token::Punct('{') => {
+            let group = read_message(tokens, Rc::new(MessageProto::default()), Some('}'))?;
+            let mut bytes = vec![];
+            group.write(&mut bytes, &ProtoData::default(), group.def.clone()).map_err(|e| tokens.error(&e.to_string()))?;
+            CommonFieldProto::write_varint(&mut bytes, Tag::number(id, WT_EGROUP) as i128).map_err(|e| tokens.error(&e.to_string()))?;
+            ScalarValue::UNKNOWN(Tag { first_number: Tag::number(id, WT_SGROUP), length: 0 }, bytes)
+        }
+        _ if !has_colon => return Err(tokens.error("expected ':'")),
+        Token::Number(text) => {
+            let value = parse_integer(&text).ok_or_else(|| tokens.error("expected an integer"))?;
+            match text.strip_prefix("0x").map(|digits| digits.len()) {
+                Some(8) => ScalarValue::UNKNOWN(Tag { first_number: Tag::number(id, WT_I32), length: 4 }, (value as u32).to_le_bytes().into()),
+                Some(16) => ScalarValue::UNKNOWN(Tag { first_number: Tag::number(id, WT_I64), length: 8 }, (value as u64).to_le_bytes().into()),
+                _ => {
+                    let mut bytes: Vec<u8> = (value as u64).to_le_bytes().into();
+                    while bytes.last() == Some(&0) { bytes.pop(); } // as UnknownFieldDefinition::read_unknown does
+                    ScalarValue::UNKNOWN(Tag { first_number: Tag::number(id, WT_VARINT), length: 0 }, bytes)
+                }
+            }
+        }
+        Token::Str(bytes) => ScalarValue::UNKNOWN(Tag { first_number: Tag::number(id, WT_LEN), length: bytes.len() as u64 }, bytes),
+        _ => return Err(tokens.error("expected a number, a string or a group")),
+    };
+    Ok(FieldData::new(Rc::new(UnknownFieldDefinition::new()), FieldValue::SCALAR(value)))
+}
+
+// decimal, hexadecimal (0x) or octal (leading 0) integer
+fn parse_integer(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_float(token: &Token) -> Option<f64> {
+    let (Token::Number(text) | Token::Name(text)) = token else { return None; };
+    let (negative, value) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.as_str()),
+    };
+    let value = match value.to_ascii_lowercase().as_str() {
+        "inf" | "infinity" => f64::INFINITY,
+        "nan" => f64::NAN,
+        v => v.trim_end_matches('f').parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod text {
+    use super::*;
+    use crate::typedefs::PbReader;
+
+    const PROTO: &str = r#"
+message Root {
+  int32 id = 1;
+  string name = 2;
+  bytes raw = 3;
+  Status status = 4;
+  repeated Item items = 5;
+  map<string, int64> counts = 6;
+  repeated double values = 7;
+}
+message Item {
+  bool ok = 1;
+  sint32 delta = 2;
+}
+enum Status { UNKNOWN = 0; ACTIVE = 1; }
+"#;
+
+    fn root_def(proto: &ProtoData) -> MessageProtoPtr {
+        proto.get_message_definition("Root").unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        let text = "id: 42\n\
+                    name: \"a\\\"b\\n\"\n\
+                    raw: \"\\001\\377A\"\n\
+                    status: ACTIVE\n\
+                    items {\n  ok: true\n  delta: -3\n}\n\
+                    items {\n}\n\
+                    counts {\n  key: \"x\"\n  value: 7\n}\n\
+                    values: 1.5\n\
+                    values: -inf\n\
+                    15: 99\n";
+        let data = text_to_message(text, root_def(&proto)).unwrap();
+        assert_eq!(message_to_text(&data), text);
+
+        let mut buf = vec![];
+        data.write(&mut buf, &proto, root_def(&proto)).unwrap();
+        let mut limit = buf.len() as u64;
+        let mut reader = PbReader::new(buf.as_slice());
+        let decoded = MessageData::new(&mut reader, &proto, root_def(&proto), &mut limit).unwrap();
+        assert_eq!(message_to_text(&decoded), text);
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        let binary_input = [
+            0x65, 0x01, 0x02, 0x03, 0x04,                         // 12: fixed32
+            0x69, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // 13: fixed64
+            0x73, 0x08, 0x01, 0x12, 0x01, 0x61, 0x74,             // 14: group { 1: 1 2: "a" }
+            0x0D, 0x05, 0x00, 0x00, 0x00,                         // id as fixed32
+            0x3A, 0x00];                                          // empty packed values
+        let mut limit = binary_input.len() as u64;
+        let data = MessageData::new(&mut PbReader::new(binary_input.as_slice()), &proto, root_def(&proto), &mut limit).unwrap();
+        let text = "12: 0x04030201\n13: 0x0807060504030201\n14 {\n  1: 1\n  2: \"a\"\n}\n1: 0x00000005\n7: \"\"\n";
+        assert_eq!(message_to_text(&data), text);
+
+        let mut buf = vec![];
+        text_to_message(text, root_def(&proto)).unwrap().write(&mut buf, &proto, root_def(&proto)).unwrap();
+        assert_eq!(buf, binary_input);
+        assert_eq!(message_to_text(&text_to_message("1: 5", root_def(&proto)).unwrap()), "id: 5\n"); // a declared field by number
+    }
+
+    #[test]
+    fn syntax_variants() {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        let text = "# comment\nid: 0x10, status: 1; items < ok: t delta: 010 > values: [1, 2.5f]\ncounts: [{key: 'k' value: -1}]";
+        let data = text_to_message(text, root_def(&proto)).unwrap();
+        assert_eq!(message_to_text(&data),
+                   "id: 16\nstatus: ACTIVE\nitems {\n  ok: true\n  delta: 8\n}\nvalues: 1\nvalues: 2.5\ncounts {\n  key: \"k\"\n  value: -1\n}\n");
+    }
+
+    #[test]
+    fn groups() {
+        let proto = ProtoData::new("syntax = \"proto2\";\nmessage Root {\n  repeated group Item = 1 {\n    optional int32 id = 2;\n  }\n}").unwrap().finalize().unwrap();
+        let data = text_to_message("Item { id: 1 } item { id: 2 }", root_def(&proto)).unwrap(); // the field name is also accepted
+        assert_eq!(message_to_text(&data), "Item {\n  id: 1\n}\nItem {\n  id: 2\n}\n");
+    }
+
+    #[test]
+    fn extensions() {
+        let proto = ProtoData::new("syntax = \"proto2\";\npackage pk;\nmessage M { optional int32 a = 1; extensions 100 to 199; }\nextend M { optional int32 ext = 100; }").unwrap().finalize().unwrap();
+        let def = proto.get_message_definition("pk.M").unwrap();
+        let text = "a: 1\n[pk.ext]: 5\n";
+        assert_eq!(message_to_text(&text_to_message(text, def.clone()).unwrap()), text);
+        assert_eq!(message_to_text(&text_to_message("ext: 5", def.clone()).unwrap()), "[pk.ext]: 5\n");
+        assert_eq!(text_to_message("[pk.other]: 5", def).err().unwrap(), "line 1, column 1: unknown field \"[pk.other]\" of pk.M");
+    }
+
+    #[test]
+    fn errors() {
+        let proto = ProtoData::new(PROTO).unwrap().finalize().unwrap();
+        let check = |text: &str| text_to_message(text, root_def(&proto)).err().unwrap();
+        assert_eq!(check("id: 1\nname: 5"), "line 2, column 7: expected string for field name");
+        assert_eq!(check("id: 1\n  other: 2"), "line 2, column 3: unknown field \"other\" of Root");
+        assert_eq!(check("items { ok: true"), "line 1, column 17: expected '}'");
+        assert_eq!(check("status: BAD"), "line 1, column 9: expected Status for field status");
+        assert_eq!(check("id: 99999999999"), "line 1, column 5: expected int32 for field id");
+    }
+
+    #[test]
+    fn file_extension() {
+        assert!(is_text_format(Path::new("data.textproto")));
+        assert!(is_text_format(Path::new("dir/data.pbtxt")));
+        assert!(is_text_format(Path::new("data.txtpb")));
+        assert!(!is_text_format(Path::new("data.pb")));
+        assert!(!is_text_format(Path::new("textproto")));
+    }
+}
@@ -2,7 +2,7 @@
 // { Changes { old: vec![], new: vec![], root_message: self } }
 
 use std::path::PathBuf;
-use crate::wire::{FieldPath, FieldValue, MessageData, ScalarValue};
+use crate::wire::{FieldData, FieldPath, FieldValue, MessageData, ScalarValue};
 
 
 pub struct Change {
@@ -13,8 +13,13 @@ pub enum ChangeType {
     Overwrite(FieldValue), // overwrite field data, old value for undo or new for redo
     Insert(FieldValue),    // insert new field
     Delete,                // remove field
+    Move(usize),           // move a repeated value to the index
+    // set the member of a oneof at the path: all members are removed from the message and these fields are inserted
+    // at their positions in the fields of the message; the removed members are kept for undo
+    SwitchOneof(Vec<(usize, FieldData)>),
 }
 
+#[derive(Default)]
 pub struct History {
     pub undo: Vec<Change>,
     pub redo: Vec<Change>,
@@ -23,14 +28,24 @@ pub struct History {
 impl Change {
     pub fn change_value(path: FieldPath, value: ScalarValue) -> Self { Self { path, action: ChangeType::Overwrite(FieldValue::SCALAR(value)) } }
     pub fn insert_scalar(path: FieldPath, value: ScalarValue) -> Self { Self { path, action: ChangeType::Insert(FieldValue::SCALAR(value)) } }
-    pub fn insert_message(path: FieldPath, value: MessageData) -> Self { Self { path, action: ChangeType::Insert(FieldValue::MESSAGE(value)) } }
+    pub fn insert_message(path: FieldPath, value: MessageData) -> Self { Self { path, action: ChangeType::Insert(FieldValue::MESSAGE(value.into())) } }
     pub fn delete_value(path: FieldPath) -> Self { Self { path, action: ChangeType::Delete } }
     pub fn layout_changed(&self) -> bool {
         match self.action {
             ChangeType::Insert(_) => true,
             ChangeType::Delete => true,
+            ChangeType::Move(_) => true,
+            ChangeType::SwitchOneof(_) => true,
             ChangeType::Overwrite(_) => false,
         }
     }
 
-}
\ No newline at end of file
+}
+
+impl History {
+    // the change must be already applied, so it stores the reverse action
+    pub fn push(&mut self, change: Change) {
+        self.undo.push(change);
+        self.redo.clear();
+    }
+}
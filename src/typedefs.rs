@@ -5,7 +5,7 @@ use std::fmt::{Debug, Display, Formatter};
 use std::io::{Read};
 use std::rc::Rc;
 use crate::wire::*;
-use crate::proto::{EnumProtoPtr, MessageProto, MessageProtoPtr};
+use crate::proto::{scope_candidates, EnumProtoPtr, MessageProto, MessageProtoPtr, ProtoOption};
 
 #[derive(Default)]
 pub struct CommonFieldProto {
@@ -14,14 +14,46 @@ pub struct CommonFieldProto {
     pub repeated: bool,
     pub comment: String,
     pub oneof_name: Option<String>,
+    pub required: bool, // proto2
+    pub group: bool, // proto2 group, the message is written between start and end group tags
+    pub default_value: Option<String>, // proto2 [default = ...] option as written in the proto file
+    pub default: OnceCell<ScalarValue>, // the default_value parsed by ProtoData::finalize
+    pub packed: bool, // repeated scalars are written in one LEN record, see FieldProto::packed
+    pub optional: bool, // declared with the optional label
+    pub options: Vec<ProtoOption>, // as written in the proto file
+    pub extension: Option<String>, // the fully qualified name of an extension field, e.g. "pk.ext"
 }
 
 
 pub trait PbReaderTrait {
     fn pos(&self) -> usize;
-    fn read_tag(&mut self, limit: &mut u32) -> io::Result<Tag>;
-    fn read_varint(&mut self, limit: &mut u32) -> io::Result<i128>;
-    fn read_len(&mut self, length: u32, limit: &mut u32) -> io::Result<Vec<u8>>;
+    fn read_varint(&mut self, limit: &mut u64) -> io::Result<i128>;
+    fn read_len(&mut self, length: u64, limit: &mut u64) -> io::Result<Vec<u8>>;
+    // pass over the data, a lazy submessage is parsed later (see SubMessage)
+    fn skip(&mut self, length: u64, limit: &mut u64) -> io::Result<()>;
+
+    fn read_tag(&mut self, limit: &mut u64) -> io::Result<Tag> {
+        Ok(self.read_sized_tag(limit)?.0)
+    }
+    // the tag and the size of its first varint; the length of LEN data follows it
+    fn read_sized_tag(&mut self, limit: &mut u64) -> io::Result<(Tag, usize)> {
+        let start = self.pos();
+        let Ok(first_number) = u32::try_from(self.read_varint(limit)?) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the tag is out of 32 bits"));
+        };
+        let size = self.pos() - start;
+        let length =
+            match (first_number & 7) as u8 {
+                WT_VARINT => 0,
+                WT_I32 => 4,
+                WT_I64 => 8,
+                WT_LEN => self.read_varint(limit)? as u64,
+                WT_SGROUP | WT_EGROUP => 0, // the group ends with its end tag
+                other =>
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported length type ({}) ", other)).into()),
+            };
+        Ok((Tag { first_number, length }, size))
+    }
 }
 
 pub struct PbReader<ReaderType: io::Read> {
@@ -38,23 +70,8 @@ impl<ReaderType: io::Read> PbReaderTrait for PbReader<ReaderType> {
     fn pos(&self) -> usize {
         self.pos
     }
-    fn read_tag(&mut self, limit: &mut u32) -> io::Result<Tag> {
-        let first_number = self.read_varint(limit)? as i32;
-        let length =
-            match (first_number & 7) as u8 {
-                WT_VARINT => 0,
-                WT_I32 => 4,
-                WT_I64 => 8,
-                WT_LEN => self.read_varint(limit)? as u32,
-                WT_SGROUP | WT_EGROUP =>
-                    return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Start/end group (deprecated) is not supported")).into()),
-                other =>
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported length type ({}) ", other)).into()),
-            };
-        Ok(Tag { first_number, length })
-    }
     // read variable length integral value
-    fn read_varint(&mut self, limit: &mut u32) -> io::Result<i128> {
+    fn read_varint(&mut self, limit: &mut u64) -> io::Result<i128> {
         let mut buf: [u8; 1] = [0];
         let mut debug_str = String::new();
         let mut value: i128 = 0;
@@ -77,9 +94,9 @@ impl<ReaderType: io::Read> PbReaderTrait for PbReader<ReaderType> {
         Err(io::Error::new(io::ErrorKind::UnexpectedEof, "not completed VARINT"))
     }
     // read string or bytes with provided data length
-    fn read_len(&mut self, length: u32, limit: &mut u32) -> io::Result<Vec<u8>> {
+    fn read_len(&mut self, length: u64, limit: &mut u64) -> io::Result<Vec<u8>> {
         if *limit >= length {
-            *limit -= length as u32;
+            *limit -= length;
             let mut buf = vec![0u8; length as usize];
             self.reader.read_exact(&mut buf)?;
             self.pos += length as usize;
@@ -88,45 +105,106 @@ impl<ReaderType: io::Read> PbReaderTrait for PbReader<ReaderType> {
             Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read data out of limit"))
         }
     }
+    fn skip(&mut self, length: u64, limit: &mut u64) -> io::Result<()> {
+        self.read_len(length, limit).map(|_| ())
+    }
+}
+
+// reads the data file mapped to memory, skipped data is not touched
+pub struct PbSliceReader<'a>(PbReader<&'a [u8]>);
+
+impl<'a> PbSliceReader<'a> {
+    // pos is the position of the data in the file
+    pub fn new(data: &'a [u8], pos: usize) -> PbSliceReader<'a> {
+        PbSliceReader(PbReader { reader: data, pos })
+    }
+}
+impl PbReaderTrait for PbSliceReader<'_> {
+    fn pos(&self) -> usize {
+        self.0.pos
+    }
+    fn read_varint(&mut self, limit: &mut u64) -> io::Result<i128> {
+        self.0.read_varint(limit)
+    }
+    fn read_len(&mut self, length: u64, limit: &mut u64) -> io::Result<Vec<u8>> {
+        self.0.read_len(length, limit)
+    }
+    fn skip(&mut self, length: u64, limit: &mut u64) -> io::Result<()> {
+        if *limit < length || (self.0.reader.len() as u64) < length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read data out of limit"));
+        }
+        *limit -= length;
+        self.0.reader = &self.0.reader[length as usize..];
+        self.0.pos += length as usize;
+        Ok(())
+    }
 }
 
 
 impl CommonFieldProto {
     // read integral or real value with predefined length
-    fn read_fixed<const LEN: usize>(reader: &mut dyn PbReaderTrait, limit: &mut u32) -> io::Result<[u8; LEN]> {
+    fn read_fixed<const LEN: usize>(reader: &mut dyn PbReaderTrait, limit: &mut u64) -> io::Result<[u8; LEN]> {
         let mut buf = [0u8; LEN];
-        let vec_buf = reader.read_len(LEN as u32, limit)?;
+        let vec_buf = reader.read_len(LEN as u64, limit)?;
         for i in 0..buf.len() {
             buf[i] = vec_buf[i];
         }
         Ok(buf)
     }
 
+    // the value of the [default = ...] option, else the zero value of the type
+    fn declared_default(&self, zero: ScalarValue) -> FieldValue {
+        FieldValue::SCALAR(self.default.get().cloned().unwrap_or(zero))
+    }
+
     pub fn write_fixed<const N: usize>(writer: &mut dyn std::io::Write, data: &[u8; N]) -> io::Result<()> {
         writer.write_all(data)?;
         Ok(())
     }
 
     pub fn write_varint(writer: &mut dyn std::io::Write, data: i128) -> io::Result<()> {
-        let mut data = data;
+        let mut data = data as u64; // a negative value is extended to 64 bits
         let mut buf = vec![];
         buf.reserve(8);
-        //while data & 0x80 != 0 { // > 0x7f {
-        while (data as u128) > 0x7f {
+        while data > 0x7f {
             buf.push(((data as u8) & 0x7f) | 0x80);
-            data = (data >> 7) & 0x7fffffffffffffff;
+            data >>= 7;
         }
         buf.push(data as u8);
         writer.write_all(&buf)
     }
 
+    // a varint which was longer in the file than needed keeps its size
+    pub fn write_padded_varint(writer: &mut dyn std::io::Write, data: i128, size: usize) -> io::Result<()> {
+        let mut buf = vec![];
+        Self::write_varint(&mut buf, data)?;
+        if size > buf.len() { Self::resize_varint(&mut buf, size); }
+        writer.write_all(&buf)
+    }
+
+    // pads the varint with zero groups, or writes a negative int32 by its 32 bits instead of 10 bytes;
+    // the caller checks that the value reads the same
+    pub fn resize_varint(buf: &mut Vec<u8>, size: usize) {
+        if size > buf.len() {
+            *buf.last_mut().unwrap() |= 0x80;
+            buf.resize(size, 0x80);
+            *buf.last_mut().unwrap() = 0;
+        } else if size > 0 {
+            buf.truncate(size);
+            *buf.last_mut().unwrap() &= 0x7f >> (7 * size).saturating_sub(32);
+        }
+    }
+
     pub fn write_len(writer: &mut dyn std::io::Write, data: &[u8]) -> io::Result<()> {
         writer.write_all(&data)?;
         Ok(())
     }
 
     pub fn new_field(name: String, type_name: String, id: i32, repeated: bool, comment: String, oneof_name: Option<String>) -> Rc<dyn FieldProto> {
-        let common = CommonFieldProto { name, id, repeated, comment, oneof_name };
+        Self::with_type(CommonFieldProto { name, id, repeated, comment, oneof_name, ..Default::default() }, type_name)
+    }
+
+    pub fn with_type(common: CommonFieldProto, type_name: String) -> Rc<dyn FieldProto> {
         return
             match type_name.as_str() {
                 "int32" => Rc::new(Int32FieldProto(common)),
@@ -156,22 +234,34 @@ impl CommonFieldProto {
 }
 
 pub trait FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue>;
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue>;
     // write only data, without field name and length
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()>;
     fn name(&self) -> String { self.get_common_definition().name.clone() }
     fn typename(&self) -> String;
     fn id(&self) -> i32 { self.get_common_definition().id }
     fn repeated(&self) -> bool { self.get_common_definition().repeated }
+    fn required(&self) -> bool { self.get_common_definition().required }
     fn wire_type(&self) -> u8 { WT_VARINT }
     fn oneof_name(&self) -> &Option<String> { &self.get_common_definition().oneof_name } // only if the field belongs to an oneof
+    fn extension(&self) -> Option<&String> { self.get_common_definition().extension.as_ref() } // written as [pk.ext] in text and JSON
     fn comment(&self) -> String { self.get_common_definition().comment.clone() }
     fn default(&self) -> FieldValue;
     fn get_common_definition(&self) -> &CommonFieldProto;
     //fn message_type_name(&self) -> &str { "" } // only if the field stores a message
     fn get_enum_name_by_index(&self, i: i32) -> Option<&str> { None }
+    fn get_enum_index_by_name(&self, name: &str) -> Option<i32> { None }
     fn is_message(&self) -> bool { false }
-    fn link_user_types(&self, _: &Vec<EnumProtoPtr>, _: &Vec<MessageProtoPtr>) {}
+    // a repeated number, enum or bool, its values may be written in one record
+    fn packable(&self) -> bool { self.repeated() && !self.is_message() && self.wire_type() != WT_LEN }
+    // new values are written in one record: proto3 default or the [packed = true] option
+    fn packed(&self) -> bool { self.get_common_definition().packed && self.packable() }
+    fn damaged(&self) -> bool { false } // bytes not decoded in the repair mode
+    fn any_value(&self) -> bool { false } // bytes of google.protobuf.Any decoded as a message
+    fn message_proto(&self) -> Option<MessageProtoPtr> { None } // only if the field stores a message
+    // scope is the fully qualified name of the message where the field type name is resolved;
+    // false if the type is not found
+    fn link_user_types(&self, _scope: &str, _: &Vec<EnumProtoPtr>, _: &Vec<MessageProtoPtr>) -> bool { true }
 }
 
 impl Debug for dyn FieldProto {
@@ -180,6 +270,7 @@ impl Debug for dyn FieldProto {
             write!(f, "map<{}>", self.typename())?;
         } else {
             if self.repeated() { write!(f, "repeated ")? }
+            if self.required() { write!(f, "required ")? }
             write!(f, "{}", self.typename())?;
         }
 
@@ -195,7 +286,7 @@ impl Int32FieldProto {
     pub const MAX: i32 = i32::MAX;
 }
 impl FieldProto for Int32FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let value = reader.read_varint(limit)? as i32;
         Ok(ScalarValue::I32(value))
     }
@@ -206,7 +297,7 @@ impl FieldProto for Int32FieldProto {
         } else { unreachable!() }
     }
     fn typename(&self) -> String { "int32".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::I32(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::I32(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -216,7 +307,7 @@ impl UInt32FieldProto {
     pub const MAX: u32 = u32::MAX;
 }
 impl FieldProto for UInt32FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let value = reader.read_varint(limit)? as u32;
         Ok(ScalarValue::U32(value))
     }
@@ -226,32 +317,32 @@ impl FieldProto for UInt32FieldProto {
         } else { unreachable!() }
     }
     fn typename(&self) -> String { "uint32".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::U32(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::U32(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct SInt32FieldProto(pub CommonFieldProto);
 impl SInt32FieldProto {
-    pub const MIN: i32 = -0x7fff_ffff;
-    pub const MAX: i32 = 0x7fff_ffff;
+    pub const MIN: i32 = i32::MIN;
+    pub const MAX: i32 = i32::MAX;
 }
 
 impl FieldProto for SInt32FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        let zigzag = reader.read_varint(limit)?;
-        let value = if 0 != (zigzag & 1) { -((zigzag >> 1) & 0x7fffffff) } else { (zigzag >> 1) & 0x7fffffff } as i32;
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        let zigzag = reader.read_varint(limit)? as u32;
+        let value = (zigzag >> 1) as i32 ^ -((zigzag & 1) as i32);
         Ok(ScalarValue::S32(value))
     }
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> {
         if let ScalarValue::S32(value) = data {
-            let zigzag = if *value >= 0 { *value << 1 } else { 1 + ((-*value) << 1) };
-            return CommonFieldProto::write_varint(writer, zigzag as u32 as i128);
+            let zigzag = ((*value << 1) ^ (*value >> 31)) as u32;
+            return CommonFieldProto::write_varint(writer, zigzag as i128);
         }
         unreachable!()
     }
     fn typename(&self) -> String { "sint32".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::S32(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::S32(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -262,8 +353,8 @@ impl FixedInt32FieldProto {
     pub const MAX: i32 = i32::MAX;
 }
 impl FieldProto for FixedInt32FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<i32>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<i32>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = i32::from_le_bytes(bytes);
         Ok(ScalarValue::SF32(value))
@@ -275,7 +366,7 @@ impl FieldProto for FixedInt32FieldProto {
     }
     fn typename(&self) -> String { "sfixed32".to_string() }
     fn wire_type(&self) -> u8 { WT_I32 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::SF32(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::SF32(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -286,8 +377,8 @@ impl FixedUInt32FieldProto {
     pub const MAX: u32 = u32::MAX;
 }
 impl FieldProto for FixedUInt32FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<u32>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<u32>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = u32::from_le_bytes(bytes);
         Ok(ScalarValue::UF32(value))
@@ -299,7 +390,7 @@ impl FieldProto for FixedUInt32FieldProto {
     }
     fn typename(&self) -> String { "fixed32".to_string() }
     fn wire_type(&self) -> u8 { WT_I32 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::UF32(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::UF32(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -310,7 +401,7 @@ impl Int64FieldProto {
     pub const MAX: i64 = i64::MAX;
 }
 impl FieldProto for Int64FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let value = reader.read_varint(limit)? as i64;
         Ok(ScalarValue::I64(value))
     }
@@ -320,7 +411,7 @@ impl FieldProto for Int64FieldProto {
         } else { unreachable!() }
     }
     fn typename(&self) -> String { "int64".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::I64(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::I64(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -331,7 +422,7 @@ impl UInt64FieldProto {
     pub const MAX: u64 = u64::MAX;
 }
 impl FieldProto for UInt64FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let value = reader.read_varint(limit)? as u64;
         Ok(ScalarValue::U64(value))
     }
@@ -341,31 +432,31 @@ impl FieldProto for UInt64FieldProto {
         } else { unreachable!() }
     }
     fn typename(&self) -> String { "uint64".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::U64(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::U64(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct SInt64FieldProto(pub CommonFieldProto);
 impl SInt64FieldProto {
-    pub const MIN: i64 = -0x7fff_ffff_ffff_ffff;
-    pub const MAX: i64 = 0x7fff_ffff_ffff_ffff;
+    pub const MIN: i64 = i64::MIN;
+    pub const MAX: i64 = i64::MAX;
 }
 impl FieldProto for SInt64FieldProto {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        let zigzag = reader.read_varint(limit)?;
-        let value = if 0 != (zigzag & 1) { -(zigzag >> 1) } else { zigzag >> 1 } as i64;
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        let zigzag = reader.read_varint(limit)? as u64;
+        let value = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
         Ok(ScalarValue::S64(value))
     }
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> {
         if let ScalarValue::S64(value) = data {
-            let zigzag = if *value >= 0 { *value << 1 } else { 1 + ((-*value) << 1) };
-            return CommonFieldProto::write_varint(writer, zigzag as u64 as i128);
+            let zigzag = ((*value << 1) ^ (*value >> 63)) as u64;
+            return CommonFieldProto::write_varint(writer, zigzag as i128);
         }
         unreachable!()
     }
     fn typename(&self) -> String { "sint64".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::S64(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::S64(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -376,8 +467,8 @@ impl FixedInt64FieldDefinition {
     pub const MAX: i64 = i64::MAX;
 }
 impl FieldProto for FixedInt64FieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<i64>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<i64>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = i64::from_le_bytes(bytes);
         Ok(ScalarValue::SF64(value))
@@ -389,7 +480,7 @@ impl FieldProto for FixedInt64FieldDefinition {
     }
     fn typename(&self) -> String { "sfixed64".to_string() }
     fn wire_type(&self) -> u8 { WT_I64 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::SF64(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::SF64(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -400,8 +491,8 @@ impl FixedUInt64FieldDefinition {
     pub const MAX: u64 = u64::MAX;
 }
 impl FieldProto for FixedUInt64FieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<u64>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<u64>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = u64::from_le_bytes(bytes);
         Ok(ScalarValue::UF64(value))
@@ -413,15 +504,15 @@ impl FieldProto for FixedUInt64FieldDefinition {
     }
     fn typename(&self) -> String { "fixed64".to_string() }
     fn wire_type(&self) -> u8 { WT_I64 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::UF64(0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::UF64(0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct FloatFieldDefinition(pub CommonFieldProto);
 impl FieldProto for FloatFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<f32>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<f32>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = f32::from_le_bytes(bytes);
         Ok(ScalarValue::F32(value))
@@ -433,15 +524,15 @@ impl FieldProto for FloatFieldDefinition {
     }
     fn typename(&self) -> String { "float".to_string() }
     fn wire_type(&self) -> u8 { WT_I32 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::F32(0.0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::F32(0.0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct DoubleFieldDefinition(pub CommonFieldProto);
 impl FieldProto for DoubleFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
-        debug_assert_eq!(field_len, mem::size_of::<f64>() as u32);
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        debug_assert_eq!(field_len, mem::size_of::<f64>() as u64);
         let bytes = CommonFieldProto::read_fixed(reader, limit)?;
         let value = f64::from_le_bytes(bytes);
         Ok(ScalarValue::F64(value))
@@ -453,14 +544,14 @@ impl FieldProto for DoubleFieldDefinition {
     }
     fn typename(&self) -> String { "double".to_string() }
     fn wire_type(&self) -> u8 { WT_I64 }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::F64(0.0)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::F64(0.0)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct BoolFieldDefinition(pub CommonFieldProto);
 impl FieldProto for BoolFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let value = reader.read_varint(limit)?;
         Ok(ScalarValue::BOOL(value != 0))
     }
@@ -471,19 +562,19 @@ impl FieldProto for BoolFieldDefinition {
         unreachable!()
     }
     fn typename(&self) -> String { "bool".to_string() }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::BOOL(false)) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::BOOL(false)) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct StringFieldDefinition(pub CommonFieldProto);
 impl FieldProto for StringFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         let buf = reader.read_len(field_len, limit)?;
-        if let Ok(value) = String::from_utf8(buf) {
-            Ok(ScalarValue::STR(value))
-        } else {
-            Ok(ScalarValue::STR("wrong unicode data".into()))
+        // the bytes are kept as an unknown field, MessageData::read_field sets its tag
+        match String::from_utf8(buf) {
+            Ok(value) => Ok(ScalarValue::STR(value)),
+            Err(e) => Ok(ScalarValue::UNKNOWN(Tag { first_number: WT_LEN as u32, length: field_len }, e.into_bytes())),
         }
     }
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> {
@@ -494,14 +585,14 @@ impl FieldProto for StringFieldDefinition {
     }
     fn typename(&self) -> String { "string".to_string() }
     fn wire_type(&self) -> u8 { WT_LEN }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::STR(String::new())) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::STR(String::new())) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
 pub struct BytesFieldDefinition(pub CommonFieldProto);
 impl FieldProto for BytesFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         Ok(ScalarValue::BYTES(reader.read_len(field_len, limit)?))
     }
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> {
@@ -512,7 +603,7 @@ impl FieldProto for BytesFieldDefinition {
     }
     fn typename(&self) -> String { "bytes".to_string() }
     fn wire_type(&self) -> u8 { WT_LEN }
-    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::BYTES(Vec::new())) }
+    fn default(&self) -> FieldValue { self.0.declared_default(ScalarValue::BYTES(Vec::new())) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
@@ -520,11 +611,15 @@ impl FieldProto for BytesFieldDefinition {
 pub struct UnknownFieldDefinition(pub CommonFieldProto);
 impl UnknownFieldDefinition {
     pub fn new() -> Self {
-        Self(CommonFieldProto { name: "???".to_string(), id: 0, repeated: true, oneof_name: None, comment: String::new() })
+        Self(CommonFieldProto { name: "???".to_string(), id: 0, repeated: true, ..Default::default() })
     }
 
-    pub fn read_unknown(reader: &mut dyn PbReaderTrait, limit: &mut u32, tlv: Tag) -> io::Result<ScalarValue> {
-        if tlv.length == 0 {
+    pub fn read_unknown(reader: &mut dyn PbReaderTrait, limit: &mut u64, tlv: Tag) -> io::Result<ScalarValue> {
+        if tlv.wire_type() == WT_SGROUP {
+            let mut buf = vec![];
+            Self::read_unknown_group(reader, limit, tlv.field_id(), &mut buf)?;
+            Ok(ScalarValue::UNKNOWN(tlv, buf))
+        } else if tlv.wire_type() == WT_VARINT {
             let value = reader.read_varint(limit)? as i64;
             let mut vec: Vec<u8> = value.to_le_bytes().into();
             while vec.last() == Some(&0) { // remove insignificant zeroes
@@ -536,9 +631,35 @@ impl UnknownFieldDefinition {
             Ok(ScalarValue::UNKNOWN(tlv, buf))
         }
     }
+    // the group content is kept as it was read, including the end group tag
+    fn read_unknown_group(reader: &mut dyn PbReaderTrait, limit: &mut u64, id: i32, buf: &mut Vec<u8>) -> io::Result<()> {
+        loop {
+            if *limit == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "not completed group"));
+            }
+            let start = reader.pos();
+            let (tag, tag_size) = reader.read_sized_tag(limit)?;
+            CommonFieldProto::write_padded_varint(buf, tag.first_number as i128, tag_size)?;
+            match tag.wire_type() {
+                WT_VARINT => {
+                    let start = reader.pos();
+                    let value = reader.read_varint(limit)?;
+                    CommonFieldProto::write_padded_varint(buf, value, reader.pos() - start)?;
+                }
+                WT_LEN => {
+                    CommonFieldProto::write_padded_varint(buf, tag.length as i128, reader.pos() - start - tag_size)?;
+                    buf.extend(reader.read_len(tag.length, limit)?);
+                }
+                WT_SGROUP => Self::read_unknown_group(reader, limit, tag.field_id(), buf)?,
+                WT_EGROUP if tag.field_id() == id => return Ok(()),
+                WT_EGROUP => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected end of group {}", tag.field_id()))),
+                _ => buf.extend(reader.read_len(tag.length, limit)?),
+            }
+        }
+    }
 }
 impl FieldProto for UnknownFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         unreachable!()
     }
     fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> {
@@ -561,12 +682,61 @@ impl FieldProto for UnknownFieldDefinition {
         unreachable!()
     }
     fn typename(&self) -> String { "unknown".to_string() }
+    fn packable(&self) -> bool { false }
     fn wire_type(&self) -> u8 { panic!("wire type unknown"); } // depend on data read, but here is only type description
     fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::UNKNOWN(Tag { first_number: 0, length: 0 }, Vec::new())) }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
 }
 
 
+// bytes which cannot be decoded, kept in the repair mode (see repair.rs) and not saved;
+// the comment is the offset in the file and the error
+pub struct DamagedFieldDefinition(pub CommonFieldProto);
+impl DamagedFieldDefinition {
+    pub const ID: i32 = -1;
+    pub fn new(comment: String) -> Self {
+        Self(CommonFieldProto { name: "damaged".to_string(), id: Self::ID, repeated: true, comment, ..Default::default() })
+    }
+}
+impl FieldProto for DamagedFieldDefinition {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        unreachable!()
+    }
+    fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> { Ok(()) }
+    fn typename(&self) -> String { "damaged".to_string() }
+    fn wire_type(&self) -> u8 { WT_LEN }
+    fn default(&self) -> FieldValue { FieldValue::SCALAR(ScalarValue::BYTES(Vec::new())) }
+    fn get_common_definition(&self) -> &CommonFieldProto { &self.0 }
+    fn damaged(&self) -> bool { true }
+}
+
+// the value of google.protobuf.Any decoded as the message named by its type_url (see well_known.rs);
+// the encoding is the same as of the bytes, text and JSON keep the bytes
+pub struct AnyValueFieldDefinition {
+    pub common: CommonFieldProto,
+    pub message: MessageProtoPtr,
+}
+impl AnyValueFieldDefinition {
+    pub const ID: i32 = 2;
+    pub fn new(message: MessageProtoPtr) -> Self {
+        AnyValueFieldDefinition { common: CommonFieldProto { name: "value".to_string(), id: Self::ID, ..Default::default() }, message }
+    }
+}
+impl FieldProto for AnyValueFieldDefinition {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
+        unreachable!()
+    }
+    fn write(&self, writer: &mut dyn io::Write, data: &ScalarValue) -> io::Result<()> { unreachable!() }
+    fn typename(&self) -> String { self.message.name.clone() }
+    fn wire_type(&self) -> u8 { WT_LEN }
+    fn default(&self) -> FieldValue { FieldValue::MESSAGE(MessageData { def: self.message.clone(), fields: vec![] }.into()) }
+    fn get_common_definition(&self) -> &CommonFieldProto { &self.common }
+    fn is_message(&self) -> bool { true }
+    fn message_proto(&self) -> Option<MessageProtoPtr> { Some(self.message.clone()) }
+    fn any_value(&self) -> bool { true }
+}
+
+
 pub struct EnumOrMessageFieldDefinition {
     pub common: CommonFieldProto,
     pub enum_proto: OnceCell<EnumProtoPtr>,
@@ -584,7 +754,7 @@ impl EnumOrMessageFieldDefinition {
     }
 }
 impl FieldProto for EnumOrMessageFieldDefinition {
-    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u32, field_len: u32) -> io::Result<ScalarValue> {
+    fn read(&self, reader: &mut dyn PbReaderTrait, limit: &mut u64, field_len: u64) -> io::Result<ScalarValue> {
         if let Some(_) = self.enum_proto.get() {
             let value = reader.read_varint(limit)? as i32;
             Ok(ScalarValue::ENUM(value))
@@ -606,16 +776,25 @@ impl FieldProto for EnumOrMessageFieldDefinition {
         unreachable!()
     }
     fn typename(&self) -> String { self.typename.clone() }
-    fn wire_type(&self) -> u8 { if self.is_message.get().is_some() { WT_LEN } else { WT_VARINT } }
+    fn wire_type(&self) -> u8 {
+        match self.is_message.get() {
+            Some(_) if self.common.group => WT_SGROUP,
+            Some(_) => WT_LEN,
+            None => WT_VARINT,
+        }
+    }
     fn default(&self) -> FieldValue {
         if let Some(def) = self.is_message.get() {
-            FieldValue::MESSAGE(MessageData { def: def.clone(), fields: vec![] })
+            FieldValue::MESSAGE(MessageData { def: def.clone(), fields: vec![] }.into())
         } else {
-            FieldValue::SCALAR(ScalarValue::ENUM(0))
+            // the first declared value is the default of an enum in proto2
+            let zero = self.enum_proto.get().and_then(|e| e.variants.first()).map_or(0, |v| v.1);
+            self.common.declared_default(ScalarValue::ENUM(zero))
         }
     }
     fn get_common_definition(&self) -> &CommonFieldProto { &self.common }
     fn is_message(&self) -> bool { self.is_message.get().is_some() }
+    fn message_proto(&self) -> Option<MessageProtoPtr> { self.is_message.get().cloned() }
     fn get_enum_name_by_index(&self, i: i32) -> Option<&str> {
         for v in &self.enum_proto.get()?.variants {
             if v.1 == i {
@@ -624,16 +803,24 @@ impl FieldProto for EnumOrMessageFieldDefinition {
         }
         None
     }
-    fn link_user_types(&self, enums: &Vec<EnumProtoPtr>, messages: &Vec<MessageProtoPtr>) {
-        if let Ok(index) = messages.binary_search_by(|m| m.name.cmp(&self.typename)) {
-            self.is_message.set(messages[index].clone()); //.unwrap();
-            return;
+    fn get_enum_index_by_name(&self, name: &str) -> Option<i32> {
+        self.enum_proto.get()?.variants.iter().find(|v| v.0 == name).map(|v| v.1)
+    }
+    fn link_user_types(&self, scope: &str, enums: &Vec<EnumProtoPtr>, messages: &Vec<MessageProtoPtr>) -> bool {
+        if self.is_message.get().is_some() || self.enum_proto.get().is_some() {
+            return true; // an extension field is already linked in the scope of its extend block
         }
-        if let Ok(index) = enums.binary_search_by(|m| m.name.cmp(&self.typename)) {
-            self.enum_proto.set(enums[index].clone()).unwrap();
-            return;
+        for name in scope_candidates(scope, &self.typename) {
+            if let Ok(index) = messages.binary_search_by(|m| m.name.cmp(&name)) {
+                self.is_message.set(messages[index].clone()); //.unwrap();
+                return true;
+            }
+            if let Ok(index) = enums.binary_search_by(|m| m.name.cmp(&name)) {
+                self.enum_proto.set(enums[index].clone()).unwrap();
+                return true;
+            }
         }
-        // TODO        panic!("unknown user type: {}", self.typename);
+        false
     }
 }
 
@@ -1,4 +1,5 @@
 use std::string::String;
+use std::cell::Cell;
 use std::cmp::{Ordering, PartialEq};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
@@ -8,12 +9,18 @@ use crossterm::cursor::position;
 use crossterm::event::{KeyEvent};
 use crossterm::style;
 use crossterm::style::Color;
-use crate::proto::{FieldProtoPtr, ProtoData};
+use crate::proto::{FieldProtoPtr, MessageProtoPtr, ProtoData};
 use crate::Selection;
 use crate::trz::{Change, ChangeType};
-use crate::wire::{FieldPath, FieldValue, MessageData, ScalarValue};
-use crate::wire::ScalarValue::{BYTES, STR};
+use crate::wire::{FieldPath, FieldPos, FieldValue, MessageData, ScalarValue};
+use crate::wire::ScalarValue::{BOOL, BYTES, STR};
 use crate::text_edit::*;
+use crate::text_format;
+use crate::format;
+use crate::well_known;
+use crate::filter::RepeatedEditorConfig;
+use crate::json::is_map;
+use crate::path::{find_entries, overridden_entries, MAP_KEY_ID, MAP_VALUE_ID};
 
 pub(crate) const MARGIN_RIGHT: u16 = 1;
 pub(crate) const MARGIN_LEFT: u16 = 1;
@@ -33,43 +40,53 @@ pub enum UserCommand
     End,
     // hotkey: 'C' comments visibility (enum CommentVisibility)
     CommentsVisibility,
-    // hotkey: 'B'
-    // show/hide binary data (read only)
-    // the data shown only in tree mode, before main data line,
+    // hotkey: 'Ctrl+B'
+    // show/hide binary data (read only) of the selected field in a pane below the data,
     // binary content (tag and length) explained, for example:
-    // 002F8 : 0A 2:LEN 8C 35 2248 00 00 00 00 00 0F
+    // 002F8 : 0A 1:LEN 8C 35 (6796) 00 00 00 00 00 0F
     BinaryVisibility,
     // hotkey: 'P'
     // show/hide tyPe (by default shown only in a few cases)
     DataTypeVisibility,
     // hotkey: Enter/F5 on collapsed field name
     Exit,
+    // hotkey: Esc while a well-known value is typed, the typed text is discarded (Exit saves it)
+    CancelEdit,
     CollapsedToggle,
     // hotkey: 'T'
-    // tree / table mode switch) (vert/horiz auto select by content)
+    // tree / table mode switch of a repeated message, kept for all repeated fields of the message type
     TableTreeToggle,
     // hotkey: 'V'
-    // switch vertical or regular table, in table view
+    // switch vertical (a column for each message) or regular table, in table view
     TableVariant,
     // hotkeys: '>','<'
-    // increase or decrease column count
+    // increase or decrease column count: '<' hides the selected column, '>' shows the first hidden one
     ChangeColumnCount(i8),
+    // hotkey: 'F3'
+    // next number format of the selected field (enum FieldDataViewFormat), kept for the field of all messages of the type
+    ChangeFormat,
     // hotkey: 'F4'
     // field Order in table or message (enum FieldOrder)
     ChangeFieldOrder(FieldOrder),
-    // hotkey: 'Ctrl+←', 'Ctrl+→'
-    // shift repeated scalar or table column
-    MoveField,
+    // hotkeys: 'Alt+↑', 'Alt+↓'
+    // shift repeated value (or table column), e.g. reorder records of a stream
+    MoveField(i8),
     // hotkeys: Del/Backspace/Ins
     DeleteData(bool), // false=delete, true=backspace
     InsertData,
-    // hotkeys: 'E' ,'I'
+    // hotkeys: 'Ctrl+Z', 'Ctrl+Y'
+    // revert the last data change or apply it again
+    Undo,
+    Redo,
+    // hotkeys: 'Ctrl+E' ,'Ctrl+R'
     // supported file format depend on data types, show in UI
     // and detected by entered file name (txt, bin, pb, csv, tsv, json)
-    //ExportData,
-    //ImportData,
-    // hotkey 'S', when selected column name of a repeated message in table mode
-    // sort table by this column по (a...z|z...a|as read from file)
+    // now the selected data exported to the json file with the data file name,
+    // and the selected message is replaced by the message read from this file
+    ExportData,
+    ImportData,
+    // hotkey 'S', on a column name of a table, a field name in a repeated message or a repeated scalar
+    // sort the repeated values by this field (a...z|z...a|as read from file) in the view only, see filter.rs
     SortDataView,
     // not a command, just key pressed
     KeyPress(char),
@@ -115,7 +132,9 @@ pub enum LayoutType {
     Str,
     Message,
     Table,
+    Map,
     Collapsed,
+    WellKnown,
 }
 
 // does not store data, only params how to display it
@@ -132,6 +151,10 @@ pub trait ViewLayout {
     // get ids of children fields already shown in this layout
     fn get_consumed_fields(&self, root: &MessageData, path: &FieldPath, config: &LayoutConfig) -> HashSet<i32> { HashSet::new() }
     fn get_status_string(&self, cursor_x: u16, cursor_y: usize) -> String { String::new() }
+    // cursor position of a data item, the index is counted from the first item of the layout
+    fn cursor_at_value(&self, index: usize) -> (u16, usize) { (0, 0) }
+    // index of the data item at the cursor, the reverse of cursor_at_value
+    fn value_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> usize { 0 }
 }
 
 fn on_command_default_handler(root: &MessageData, path: &FieldPath, amount: usize, command: UserCommand, config: &LayoutConfig, width: u16, indent: u16, cursor_x: &mut u16, cursor_pos: &mut usize) -> CommandResult {
@@ -142,6 +165,9 @@ fn on_command_default_handler(root: &MessageData, path: &FieldPath, amount: usiz
             } else { CommandResult::None }
         }
 
+        // a repeated message or string is shown by a layout for each value
+        UserCommand::MoveField(delta) if amount == 1 => move_value(root, path, delta),
+
         UserCommand::ScrollVertically(mut delta) => {
             //            print!("cursor_pos (default_handler): {} -> ", cursor_pos);
 
@@ -156,10 +182,66 @@ fn on_command_default_handler(root: &MessageData, path: &FieldPath, amount: usiz
     }
 }
 
+// move the repeated value at the path to the previous or next index
+fn move_value(root: &MessageData, path: &FieldPath, delta: i8) -> CommandResult {
+    let index = path.0.last().unwrap().index;
+    match index.checked_add_signed(delta as isize) {
+        Some(to) if root.get_field(&path.with_last_index(to).0).is_some() =>
+            CommandResult::ChangeData(Change { path: path.clone(), action: ChangeType::Move(to) }),
+        _ => CommandResult::None,
+    }
+}
+
+// offsets of the shown repeated values from the one at the path, sorted and filtered in the view only
+fn view_order(root: &MessageData, path: &FieldPath, amount: usize, config: &LayoutConfig) -> Vec<usize> {
+    let last = path.0.last().unwrap();
+    let parent = root.get_submessage(&path.0[..path.0.len() - 1]);
+    match parent.and_then(|parent| Some((parent, config.repeated_config(parent, last.id)?))) {
+        Some((parent, repeated)) => repeated.view_order(parent, last.id, last.index, amount),
+        None => (0..amount).collect(),
+    }
+}
+
+// the fields of the message in the order, a oneof is shown by one member: the last one read (it replaces the others),
+// or the first one of the definition if no member is set
+fn shown_fields(msg: &MessageData, order: &FieldOrder) -> Vec<(FieldPos, usize)> {
+    let mut active: HashMap<String, i32> = HashMap::new();
+    for def in &msg.def.fields {
+        if let Some(oneof) = def.oneof_name() { active.entry(oneof.clone()).or_insert(def.id()); }
+    }
+    for field in &msg.fields {
+        if let Some(oneof) = field.def.oneof_name() { active.insert(oneof.clone(), field.def.id()); }
+    }
+    msg.get_sorted_fields(order).into_iter().filter(|(pos, _)| {
+        msg.def.get_field(pos.id).and_then(|def| def.oneof_name().clone()).is_none_or(|oneof| active[&oneof] == pos.id)
+    }).collect()
+}
+
+// data replaced by a later value when it is read: a non-repeated field set several times,
+// or a oneof with several members set (only the last one is shown)
+fn replaced(root: &MessageData, path: &FieldPath, amount: usize) -> bool {
+    let (Some(last), Some(def)) = (path.0.last(), root.get_field_definition(path)) else { return false; };
+    let Some(parent) = root.get_submessage(&path.0[..path.0.len() - 1]) else { return false; };
+    if let Some(oneof) = def.oneof_name() {
+        let members: HashSet<i32> = parent.fields.iter().filter(|f| f.def.oneof_name().as_ref() == Some(oneof)).map(|f| f.def.id()).collect();
+        if members.len() > 1 { return true; }
+    }
+    let count = parent.fields.iter().filter(|f| f.def.id() == last.id).count();
+    !def.repeated() && !def.damaged() && (amount > 1 || last.index + 1 < count)
+}
+
+// values are moved only if all of them are shown in the order of the file
+fn in_file_order(order: &[usize], amount: usize) -> bool {
+    order.len() == amount && order.iter().enumerate().all(|(index, offset)| index == *offset)
+}
+
 // bool, enum, integral, or real value: single, none or repeated
 // there are special layouts for text and hex field types
 pub struct ScalarLayout {
     line_lens: Vec<usize>, // how many scalar values of each line on the screen
+    order: Vec<usize>, // offsets of the shown values in the view order, see view_order
+    edit: Option<(usize, String)>, // index of the value and the text typed in the format of the field
+    edit_cursor: Cell<(u16, usize)>, // after the typed text, found when the layout is shown
 }
 pub struct StringLayout {
     edit: Option<TextEditor>,
@@ -207,26 +289,51 @@ impl ByteEditor {
 pub struct MessageLayout { // with columns or title only
     scroll: usize, // first visible column index
 }
-pub struct TableLayout { // for repeated messages
+pub struct TableLayout { // for repeated messages, a row for each message (a column in the vertical variant)
     vertical: bool,
-    scroll: (usize, usize), // column and row indexes of top-left visible cell
+    scroll: Cell<usize>, // index of the first visible column, moved when the layout is shown
+    columns: Vec<i32>, // ids of the fields shown
+    order: Vec<usize>, // offsets of the shown messages in the view order, see view_order
+    header: Vec<String>, // field names, or indexes of the messages in the vertical variant
+    rows: Vec<(String, Vec<(String, TextStyle)>)>, // the first column and the cells
+    widths: Vec<usize>, // of each column, the content is cut to MAX_CELL_WIDTH
+    typename_len: usize,
+    edit: Option<(u16, usize, String)>, // cursor position of the edited cell and the typed text
+}
+
+// for map fields, a `key: value` row for each entry (the entries are messages with the key @1 and the value @2)
+pub struct MapLayout {
+    order: Vec<usize>, // offsets of the shown entries in the view order, see view_order
+    rows: Vec<(String, (String, TextStyle), bool)>, // the key, the value and whether a later entry has the same key
+    edit: Option<(u16, usize, String)>, // the edited key (x=0) or value (x=1), the row and the typed text
 }
 
 pub struct CollapsedLayout {
     display_size: usize,
 }
 
+// Timestamp, Duration, wrappers, Struct, Value and ListValue shown and typed as one value (see well_known.rs)
+pub struct WellKnownLayout {
+    lines: Vec<String>, // the value, JSON may take several lines
+    edit: Option<String>, // the typed text in one line
+}
+
 pub enum CommentVisibility {
     Hidden,
     Multiline, // before data, possible multiline
     Inline,    // in the same line, after data and type, only one line of comment
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum FieldDataViewFormat {
     Formated, // show data formated according proto file
     Decimal, // show integers as decimal
     Hex, // show integers as hex
+    Octal,
+    Binary,
+    UnixSeconds, // integer seconds since 1970 as an ISO date
+    UnixMillis,
+    FullPrecision, // floating point with all significant digits
 }
 #[derive(PartialEq, Debug, Clone)]
 pub enum FieldOrder {
@@ -247,9 +354,14 @@ pub struct LayoutConfig {
 }
 
 // How to show a message or table of a certain type
+#[derive(Default)]
 pub struct MessageLayoutConfig {
-    columns: Vec<i32>,
-    columns_width: Vec<u16>,
+    table: bool, // repeated fields of the type are shown as tables
+    vertical: bool,
+    columns: Vec<i32>, // ids of the fields shown in the table, all if empty
+    formats: HashMap<i32, FieldDataViewFormat>, // by field id
+    repeated: HashMap<i32, RepeatedEditorConfig>, // sorting and filtering of repeated fields, by field id
+    entries: bool, // map entries of the type are shown as messages instead of `key: value` rows
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -270,6 +382,10 @@ pub enum TextStyle {
     SelectedTypename, // for oneof
     Divider,
     Bookmark,
+    Found, // search matches
+    Missing, // required field without data
+    Damaged, // data not decoded in the repair mode
+    Overridden, // data replaced by later data: a map entry with the same key, a value of a non-repeated field or a oneof member
     TopLine, // top line with different status information
     Unknown,
 }
@@ -290,6 +406,66 @@ impl Default for LayoutConfig {
     }
 }
 
+impl LayoutConfig {
+    // the number format of the field in the message
+    pub fn field_format(&self, msg: &MessageData, id: i32) -> FieldDataViewFormat {
+        self.messages.get(&msg.def.name).and_then(|config| config.formats.get(&id)).copied().unwrap_or(self.format)
+    }
+    // Some(vertical) if repeated messages of the type are shown as a table
+    pub fn table_view(&self, def: &MessageProtoPtr) -> Option<bool> {
+        self.messages.get(&def.name).filter(|config| config.table).map(|config| config.vertical)
+    }
+    // true if map entries of the type are shown as `key: value` rows
+    pub fn map_view(&self, def: &MessageProtoPtr) -> bool {
+        !self.messages.get(&def.name).is_some_and(|config| config.entries)
+    }
+    // switch the tree and the table, or the table variant; false if the variant cannot be switched
+    // map entries are switched between `key: value` rows and messages
+    pub fn switch_table(&mut self, def: &MessageProtoPtr, variant: bool) -> bool {
+        let config = self.messages.entry(def.name.clone()).or_default();
+        if text_format::is_map_entry(def) {
+            if !variant { config.entries = !config.entries }
+            return !variant;
+        }
+        if variant && !config.table { return false; }
+        if variant { config.vertical = !config.vertical } else { config.table = !config.table }
+        true
+    }
+    // ids of the fields shown as table columns, in the field order
+    pub fn table_columns(&self, def: &MessageProtoPtr) -> Vec<i32> {
+        let shown = self.messages.get(&def.name).map_or(&[][..], |config| &config.columns[..]);
+        let mut fields: Vec<&FieldProtoPtr> = def.fields.iter().filter(|f| shown.is_empty() || shown.contains(&f.id())).collect();
+        match self.field_order {
+            FieldOrder::ByName => fields.sort_by_key(|f| f.name()),
+            FieldOrder::ById => fields.sort_by_key(|f| f.id()),
+            FieldOrder::Proto | FieldOrder::Wire => {}
+        }
+        fields.iter().map(|f| f.id()).collect()
+    }
+    // hide the column of the field (delta < 0), or show the first hidden column
+    pub fn change_column_count(&mut self, def: &MessageProtoPtr, id: Option<i32>, delta: i8) {
+        let shown = self.table_columns(def);
+        let config = self.messages.entry(def.name.clone()).or_default();
+        if delta < 0 {
+            let Some(id) = id.or(shown.last().copied()) else { return; };
+            if shown.len() > 1 { config.columns = shown.into_iter().filter(|shown| *shown != id).collect(); }
+        } else if let Some(hidden) = def.fields.iter().map(|f| f.id()).find(|id| !shown.contains(id)) {
+            config.columns = shown;
+            config.columns.push(hidden);
+        }
+    }
+    // sorting and filtering of a repeated field of the message, None if the values are shown as read
+    pub fn repeated_config(&self, msg: &MessageData, id: i32) -> Option<&RepeatedEditorConfig> {
+        self.messages.get(&msg.def.name).and_then(|config| config.repeated.get(&id)).filter(|config| !config.shows_all())
+    }
+    pub fn repeated_config_mut(&mut self, msg: &MessageData, id: i32) -> &mut RepeatedEditorConfig {
+        self.messages.entry(msg.def.name.clone()).or_default().repeated.entry(id).or_default()
+    }
+    pub fn set_field_format(&mut self, msg: &MessageData, id: i32, format: FieldDataViewFormat) {
+        self.messages.entry(msg.def.name.clone()).or_default().formats.insert(id, format);
+    }
+}
+
 impl CommentVisibility {
     pub fn next(&self) -> CommentVisibility {
         match self {
@@ -359,15 +535,39 @@ impl ScreenLine {
         let mut text = field_def.typename();
         if field_def.repeated() { text = text + "*" }
         if empty { text = "-".to_string() + text.as_str() }
-        let max_allowed_len = (screen_width - MARGIN_RIGHT) as usize - text.len();
+        let missing = empty && field_def.required();
+        if missing { text = "!".to_string() + text.as_str() }
+        let oneof = field_def.oneof_name().as_ref().map_or(String::new(), |name| name.clone() + ":"); // the member of a oneof is picked by 'O'
+        let max_allowed_len = ((screen_width - MARGIN_RIGHT) as usize).saturating_sub(text.len() + oneof.len());
         if self.0.len() > max_allowed_len {
             self.0.truncate(max_allowed_len);
         }
-        let width = (screen_width - MARGIN_RIGHT) as usize - self.0.len();
-        self.add_string(format!("{text:>width$}"), TextStyle::Typename);
+        let width = ((screen_width - MARGIN_RIGHT) as usize).saturating_sub(self.0.len() + oneof.len());
+        let style = if missing { TextStyle::Missing } else if field_def.damaged() { TextStyle::Damaged } else { TextStyle::Typename };
+        self.add_string(" ".repeat(width.saturating_sub(text.len())), style);
+        self.add_string(oneof, TextStyle::SelectedTypename);
+        self.add_string(text, style);
         for _ in 0..MARGIN_RIGHT { self.0.push((' ', TextStyle::Typename)); }
     }
 
+    // mark text matched with the lowercase pattern, only names and values are marked
+    pub fn highlight(&mut self, pattern: &str) {
+        if pattern.is_empty() { return; }
+        let lower: Vec<char> = self.0.iter().map(|(c, _)| c.to_lowercase().next().unwrap_or(*c)).collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        let mut pos = 0;
+        while pos + pattern.len() <= lower.len() {
+            let found = lower[pos..pos + pattern.len()] == pattern[..] &&
+                self.0[pos..pos + pattern.len()].iter().all(|(_, s)| !matches!(s, TextStyle::Typename | TextStyle::Missing | TextStyle::Damaged | TextStyle::Divider | TextStyle::DataSize));
+            if found {
+                self.0[pos..pos + pattern.len()].iter_mut().for_each(|item| item.1 = TextStyle::Found);
+                pos += pattern.len();
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
     pub fn fix_length(&mut self, len: u16) {
         let len = len as usize;
         match self.0.len().cmp(&len) {
@@ -437,15 +637,24 @@ impl ScalarLayout {
     const MARGIN: u16 = MARGIN_LEFT + MARGIN_RIGHT;
 
     fn new() -> Self {
-        ScalarLayout { line_lens: vec![] }
+        ScalarLayout { line_lens: vec![], order: vec![], edit: None, edit_cursor: Cell::new((0, 0)) }
     }
-    fn add_scalar_value(line: &mut ScreenLine, value: &ScalarValue, def: &FieldProtoPtr, config: &LayoutConfig, selected: bool) {
+    fn add_scalar_value(line: &mut ScreenLine, value: &ScalarValue, def: &FieldProtoPtr, format: FieldDataViewFormat, selected: bool) {
         line.0.push((' ', TextStyle::Divider));
         let style = if selected { TextStyle::SelectedValue } else { TextStyle::Value };
-        line.add_string(Self::scalar_to_string(value, def, config), style);
+        line.add_string(Self::scalar_to_string(value, def, format), style);
     }
-    fn scalar_to_string(value: &ScalarValue, def: &FieldProtoPtr, config: &LayoutConfig) -> String {
-        if let ScalarValue::ENUM(value) = value {
+    // the typed text is shown instead of the value, in red while it is not a valid value
+    fn add_edited_value(&self, line: &mut ScreenLine, line_index: usize, indent: u16, text: &str, def: &FieldProtoPtr, format: FieldDataViewFormat) {
+        line.0.push((' ', TextStyle::Divider));
+        let style = if format::parse_scalar(text, def, format).is_ok() { TextStyle::SelectedValue } else { TextStyle::Missing };
+        line.add_string(text.to_string(), style);
+        self.edit_cursor.set(((line.0.len() as u16).saturating_sub(indent + 2), line_index));
+    }
+    fn scalar_to_string(value: &ScalarValue, def: &FieldProtoPtr, format: FieldDataViewFormat) -> String {
+        if let Some(text) = format::format_scalar(value, format) {
+            text
+        } else if let ScalarValue::ENUM(value) = value {
             if let Some(text) = def.get_enum_name_by_index(*value) {
                 text.to_string()
             } else {
@@ -474,10 +683,10 @@ impl ScalarLayout {
         let mut prv_line_end = 0;
 
         if let Some(last_pos) = path.0.last() {
-            for index in last_pos.index..last_pos.index + amount {
-                if let Some(field) = msg.get_field(&([(last_pos.id, index).into()])) {
+            for (index, offset) in self.order.iter().enumerate() {
+                if let Some(field) = msg.get_field(&([(last_pos.id, last_pos.index + offset).into()])) {
                     if let FieldValue::SCALAR(value) = &field.value {
-                        let str_value = Self::scalar_to_string(value, def, config);
+                        let str_value = Self::scalar_to_string(value, def, config.field_format(msg, def.id()));
                         let len = str_value.len();
                         cur_len += len + 1;
                         if cur_len >= avail_width {
@@ -490,7 +699,7 @@ impl ScalarLayout {
                     }
                 }
             }
-            let last_line_len = last_pos.index + amount - prv_line_end;
+            let last_line_len = self.order.len() - prv_line_end;
             if last_line_len > 0 { starts.push(last_line_len) }
         }
 
@@ -503,6 +712,11 @@ impl ScalarLayout {
         at_line_start + cursor_x as usize - 1
     }
 
+    // offset of the value from the first one in the data, the index is counted in the view order
+    fn real_offset(&self, index: usize) -> usize {
+        self.order.get(index).copied().unwrap_or(index)
+    }
+
     fn cursor_at_data_index(&self, index: usize) -> (u16, usize) {
         let mut sum = 0;
         for line_index in 0..self.line_lens.len() {
@@ -517,6 +731,24 @@ impl ScalarLayout {
 }
 impl ViewLayout for ScalarLayout {
     fn layout_type(&self) -> LayoutType { LayoutType::Scalar }
+    fn cursor_at_value(&self, index: usize) -> (u16, usize) {
+        let Some(index) = self.order.iter().position(|offset| *offset == index) else { return (0, 0); }; // filtered out
+        let mut sum = 0;
+        for (line_index, line_len) in self.line_lens.iter().enumerate() {
+            if index < sum + line_len {
+                return ((index - sum + 1) as u16, line_index);
+            }
+            sum += line_len;
+        }
+        (0, 0)
+    }
+    fn get_text_edit_cursor(&self) -> Option<(u16, usize)> {
+        self.edit.as_ref().map(|_| self.edit_cursor.get())
+    }
+    fn value_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> usize {
+        let index = self.data_index_at_cursor(cursor_x, cursor_y);
+        if index == usize::MAX { 0 } else { self.real_offset(index) } // the field name is selected
+    }
     fn get_status_string(&self, cursor_x: u16, cursor_y: usize) -> String {
         //format!("/{}", self.amount)
         String::new()
@@ -528,6 +760,7 @@ impl ViewLayout for ScalarLayout {
             let indent = negotiator.add(field_name_length, level);
 
             let mut line_count = 1;
+            self.order = view_order(root, path, amount, config);
             if amount > 0 {
                 let mut p = path.0.clone();
                 p.pop();
@@ -556,11 +789,15 @@ impl ViewLayout for ScalarLayout {
 
 
             let selected_index = cursor.map_or(usize::MAX, |(x, y)| self.data_index_at_cursor(x, y));
+            let parent = root.get_submessage(&path.0[..path.0.len() - 1]);
+            let format = parent.map_or(config.format, |msg| config.field_format(msg, field_def.id()));
 
-            if amount == 0 {
+            if let (0, Some((_, text))) = (amount, &self.edit) {
+                self.add_edited_value(&mut line, 0, indent, text, &field_def, format);
+            } else if amount == 0 {
                 // no data was read, show default value
                 if let FieldValue::SCALAR(value) = field_def.default() {
-                    Self::add_scalar_value(&mut line, &value, &field_def, config, selected_index == 0);
+                    Self::add_scalar_value(&mut line, &value, &field_def, format, selected_index == 0);
                 }
             } else {
                 let mut avail_width = (width - indent - Self::MARGIN) as usize;
@@ -571,10 +808,12 @@ impl ViewLayout for ScalarLayout {
                 let mut cur_len = 0;
                 let mut line_count = 1;
                 let mut p = path.0.clone();
-                for index in 0..amount {
+                let start = p.last().unwrap().index;
+                for (index, offset) in self.order.iter().enumerate() {
+                    p.last_mut().unwrap().index = start + offset;
                     if let Some(field) = root.get_field(&p) {
                         if let FieldValue::SCALAR(value) = &field.value {
-                            let str_value = Self::scalar_to_string(value, &field_def, config);
+                            let str_value = Self::scalar_to_string(value, &field_def, format);
                             let len = str_value.len();
                             cur_len += len + 1;
                             if cur_len >= avail_width {
@@ -588,12 +827,14 @@ impl ViewLayout for ScalarLayout {
 
                                 lines.0.push(line);
                                 line = ScreenLine::new(width);
-                                line.add_value_address(format!("{}", index), indent, &cursor, lines.0.len());
+                                line.add_value_address(format!("{}", offset), indent, &cursor, lines.0.len());
+                            }
+                            match &self.edit {
+                                Some((edit_index, text)) if *edit_index == index => self.add_edited_value(&mut line, lines.0.len(), indent, text, &field_def, format),
+                                _ => Self::add_scalar_value(&mut line, value, &field.def, format, selected_index == index),
                             }
-                            Self::add_scalar_value(&mut line, value, &field.def, config, selected_index == index);
                         }
                     }
-                    p.last_mut().unwrap().index += 1;
                 }
             }
 
@@ -608,29 +849,67 @@ impl ViewLayout for ScalarLayout {
     }
     fn on_command(&mut self, root: &MessageData, path: &FieldPath, amount: usize, command: UserCommand, config: &LayoutConfig, width: u16, indent: u16, cursor_x: &mut u16, cursor_pos: &mut usize) -> CommandResult
     {
+        if let Some((index, text)) = &mut self.edit {
+            match command {
+                UserCommand::KeyPress(c) => {
+                    text.push(c);
+                    return CommandResult::Redraw;
+                }
+                UserCommand::DeleteData(_) => {
+                    text.pop();
+                    return CommandResult::Redraw;
+                }
+                _ => { // the typed value is saved on exit, an invalid one is discarded
+                    let (index, text) = self.edit.take().unwrap();
+                    let field_path = path.with_last_index(path.0.last().unwrap().index + self.real_offset(index));
+                    let def = root.get_field_definition(path).unwrap();
+                    let parent = root.get_submessage(&path.0[..path.0.len() - 1]).unwrap();
+                    let Ok(value) = format::parse_scalar(&text, &def, config.field_format(parent, def.id())) else { return CommandResult::Redraw; };
+                    let value = FieldValue::SCALAR(value);
+                    self.line_lens.clear();
+                    let action = if amount == 0 { ChangeType::Insert(value) } else { ChangeType::Overwrite(value) };
+                    return CommandResult::ChangeData(Change { path: field_path, action });
+                }
+            }
+        }
         match command {
+            UserCommand::KeyPress(c) if *cursor_x > 0 => { // typing replaces the selected value
+                let index = if amount == 0 { 0 } else { self.data_index_at_cursor(*cursor_x, *cursor_pos) };
+                self.edit = Some((index, c.to_string()));
+                CommandResult::Redraw
+            }
             UserCommand::DeleteData(_) => {
                 if *cursor_x == 0 && *cursor_pos == 0 {
                     on_command_default_handler(root, path, amount, command, config, width, indent, cursor_x, cursor_pos)
                 } else {
                     let index = self.data_index_at_cursor(*cursor_x, *cursor_pos);
-                    if amount > 0 && index > 0 && index == amount - 1 {
+                    if amount > 0 && index > 0 && index + 1 == self.order.len() {
                         // fix the cursor position after deleting last data item
                         (*cursor_x, *cursor_pos) = self.cursor_at_data_index(index - 1);
                     }
-                    let path = path.with_last_index(path.0.last().unwrap().index + index);
+                    let path = path.with_last_index(path.0.last().unwrap().index + self.real_offset(index));
                     self.line_lens.clear();
                     CommandResult::ChangeData(Change { path, action: ChangeType::Delete })
                 }
             }
             UserCommand::InsertData => {
                 let index = self.data_index_at_cursor(*cursor_x, *cursor_pos);
-                let path = path.with_last_index(path.0.last().unwrap().index + index + 1);
+                let path = path.with_last_index(path.0.last().unwrap().index + self.real_offset(index) + 1);
                 (*cursor_x, *cursor_pos) = self.cursor_at_data_index(index + 1);
                 self.line_lens.clear();
                 let def = root.get_field_definition(&path).unwrap();
                 CommandResult::ChangeData(Change { path: path.clone(), action: ChangeType::Insert(def.default()) })
             }
+            UserCommand::MoveField(delta) => {
+                let index = self.data_index_at_cursor(*cursor_x, *cursor_pos);
+                if index >= amount { return CommandResult::None; } // the field name is selected
+                if !in_file_order(&self.order, amount) { return CommandResult::None; }
+                let result = move_value(root, &path.with_last_index(path.0.last().unwrap().index + index), delta);
+                if matches!(result, CommandResult::ChangeData(_)) {
+                    (*cursor_x, *cursor_pos) = self.cursor_at_data_index(index.wrapping_add_signed(delta as isize));
+                }
+                result
+            }
             UserCommand::ScrollHorizontally(delta) => {
                 if let Some(len) = self.line_lens.get(*cursor_pos) {
                     if delta > 0 {
@@ -1198,26 +1477,444 @@ impl ViewLayout for MessageLayout {
 }
 
 impl TableLayout {
-    fn new(path: FieldPath) -> Self {
-        TableLayout { vertical: false, scroll: (0, 0) }
+    const MAX_CELL_WIDTH: usize = 20;
+
+    fn new(vertical: bool) -> Self {
+        TableLayout { vertical, scroll: Cell::new(0), columns: vec![], order: vec![], header: vec![], rows: vec![], widths: vec![], typename_len: 0, edit: None }
+    }
+    // a long text is cut, a table row takes one screen line
+    fn cut(text: &str) -> String {
+        let text = text.replace(['\n', '\r', '\t'], " ");
+        if text.chars().count() <= Self::MAX_CELL_WIDTH { return text; }
+        text.chars().take(Self::MAX_CELL_WIDTH - 1).chain(iter::once('…')).collect()
+    }
+    // a field of a message in one cell, a nested message is shown by its size only
+    fn cell_text(msg: &MessageData, def: &FieldProtoPtr, config: &LayoutConfig) -> (String, TextStyle) {
+        let values: Vec<&FieldValue> = msg.fields.iter().filter(|f| f.def.id() == def.id()).map(|f| &f.value).collect();
+        let format = config.field_format(msg, def.id());
+        let text = match (values.last(), def.repeated()) {
+            (None, _) => return match def.default() {
+                FieldValue::SCALAR(value) if !def.repeated() => (Self::cut(&ScalarLayout::scalar_to_string(&value, def, format)), TextStyle::DefaultValue),
+                _ => ("-".to_string(), TextStyle::DefaultValue),
+            },
+            (Some(_), true) if def.is_message() => format!("[{}]", values.len()),
+            (Some(_), true) => values.iter().filter_map(|value| match value {
+                FieldValue::SCALAR(value) => Some(ScalarLayout::scalar_to_string(value, def, format)),
+                FieldValue::MESSAGE(_) => None,
+            }).collect::<Vec<_>>().join(" "),
+            (Some(FieldValue::MESSAGE(sub)), false) if well_known::is_well_known(&sub.def) => well_known::to_text(sub, false),
+            (Some(FieldValue::MESSAGE(_)), false) => "{…}".to_string(),
+            (Some(FieldValue::SCALAR(value)), false) => ScalarLayout::scalar_to_string(value, def, format),
+        };
+        (Self::cut(&text), TextStyle::Value)
+    }
+    // the edited column is widened to the typed text
+    fn column_width(&self, column: usize) -> usize {
+        match &self.edit {
+            Some((x, _, text)) if *x as usize == column + 1 => self.widths[column].max(text.chars().count()),
+            _ => self.widths[column],
+        }
+    }
+    // the columns shown, the first visible column is moved to show the selected one
+    fn visible_columns(&self, width: u16, indent: u16, selected: Option<usize>) -> std::ops::Range<usize> {
+        let avail = ((width - MARGIN_RIGHT) as usize).saturating_sub(indent as usize + 1 + self.typename_len + 2); // " …" if there are more columns
+        let end = |from: usize| {
+            let (mut len, mut end) = (0, from);
+            while end < self.widths.len() && (end == from || len + 1 + self.column_width(end) <= avail) {
+                len += 1 + self.column_width(end);
+                end += 1;
+            }
+            end
+        };
+        let mut scroll = self.scroll.get().min(self.widths.len().saturating_sub(1));
+        if let Some(selected) = selected.filter(|selected| *selected < self.widths.len()) {
+            scroll = scroll.min(selected);
+            while selected >= end(scroll) { scroll += 1; }
+        }
+        self.scroll.set(scroll);
+        scroll..end(scroll)
+    }
+    // indexes of the message and of the field column of the cell at the cursor
+    fn cell_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> (Option<usize>, Option<usize>) {
+        let column = (cursor_x as usize).checked_sub(1).filter(|column| *column < self.header.len());
+        let row = cursor_y.checked_sub(1).filter(|row| *row < self.rows.len());
+        if self.vertical { (column, row) } else { (row, column) }
+    }
+    // the path of a non-repeated field in the cell at the cursor, its definition and the message
+    fn cell_field<'a>(&self, root: &'a MessageData, path: &FieldPath, cursor_x: u16, cursor_y: usize) -> Option<(FieldPath, FieldProtoPtr, &'a MessageData)> {
+        let (Some(element), Some(column)) = self.cell_at_cursor(cursor_x, cursor_y) else { return None; };
+        let element_path = path.with_last_index(path.0.last().unwrap().index + self.order[element]);
+        let msg = root.get_submessage(&element_path.0)?;
+        let def = msg.def.get_field(self.columns[column]).filter(|def| !def.repeated())?;
+        let count = msg.fields.iter().filter(|f| f.def.id() == def.id()).count();
+        Some((element_path.add((def.id(), count.saturating_sub(1)).into()), def, msg)) // the last value is used
+    }
+    // only a number, enum or string value is typed into a cell
+    fn editable(def: &FieldProtoPtr) -> bool {
+        !def.is_message() && !matches!(def.default(), FieldValue::SCALAR(BYTES(_)))
+    }
+    fn parse_cell(text: &str, def: &FieldProtoPtr, msg: &MessageData, config: &LayoutConfig) -> Result<ScalarValue, String> {
+        match def.default() {
+            FieldValue::SCALAR(STR(_)) => Ok(STR(text.to_string())), // typed without quotes
+            _ => format::parse_scalar(text, def, config.field_format(msg, def.id())),
+        }
+    }
+    // move the cursor along the messages, to a row or to a column in the vertical variant
+    fn cursor_to_element(&self, index: usize, cursor_x: &mut u16, cursor_pos: &mut usize) {
+        if self.vertical { *cursor_x = index as u16 + 1 } else { *cursor_pos = index + 1 }
     }
 }
 impl ViewLayout for TableLayout {
     fn layout_type(&self) -> LayoutType { LayoutType::Table }
+    fn cursor_at_value(&self, index: usize) -> (u16, usize) {
+        let Some(index) = self.order.iter().position(|offset| *offset == index) else { return (0, 0); }; // filtered out
+        if self.vertical { (index as u16 + 1, 0) } else { (0, index + 1) }
+    }
+    fn value_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> usize {
+        self.cell_at_cursor(cursor_x, cursor_y).0.map_or(0, |element| self.order[element])
+    }
+    fn get_status_string(&self, cursor_x: u16, cursor_y: usize) -> String {
+        let count = if self.vertical { self.header.len() } else { self.rows.len() };
+        self.cell_at_cursor(cursor_x, cursor_y).0.map_or(String::new(), |index| format!("{}/{}", index, count))
+    }
+    fn get_text_edit_cursor(&self) -> Option<(u16, usize)> {
+        self.edit.as_ref().map(|(x, y, text)| {
+            let before: usize = (self.scroll.get()..*x as usize - 1).map(|column| 1 + self.column_width(column)).sum();
+            ((before + text.chars().count()) as u16, *y)
+        })
+    }
     fn calc_sizes(&mut self, root: &MessageData, path: &FieldPath, amount: usize, config: &LayoutConfig, width: u16, negotiator: &mut IndentsCalc) -> usize {
-        todo!()
+        let def = root.get_field_definition(path).unwrap();
+        let msg_def = def.message_proto().unwrap();
+        self.columns = config.table_columns(&msg_def);
+        let column_defs: Vec<FieldProtoPtr> = self.columns.iter().filter_map(|id| msg_def.get_field(*id)).collect();
+        let start = path.0.last().unwrap().index;
+        self.order = view_order(root, path, amount, config);
+        let cells: Vec<Vec<(String, TextStyle)>> = self.order.iter().map(|offset| {
+            let msg = root.get_submessage(&path.with_last_index(start + offset).0).unwrap();
+            column_defs.iter().map(|def| Self::cell_text(msg, def, config)).collect()
+        }).collect();
+        let names: Vec<String> = column_defs.iter().map(|def| Self::cut(&def.name())).collect();
+        let indexes: Vec<String> = self.order.iter().map(|offset| (start + offset).to_string()).collect();
+        if self.vertical { // a column for each message, a row for each field
+            self.header = indexes;
+            self.rows = names.into_iter().enumerate().map(|(column, name)| (name, cells.iter().map(|row| row[column].clone()).collect())).collect();
+        } else {
+            self.header = names;
+            self.rows = indexes.into_iter().zip(cells).collect();
+        }
+        self.widths = self.header.iter().enumerate().map(|(column, text)| {
+            self.rows.iter().map(|(_, cells)| cells[column].0.chars().count()).fold(text.chars().count(), usize::max)
+        }).collect();
+        self.typename_len = def.typename().len() + 1; // with the mark of a repeated field
+        let first_column = self.rows.iter().map(|(text, _)| text.len()).fold(def.name().len(), usize::max);
+        negotiator.add(first_column, path.0.len());
+        self.rows.len() + 1
     }
     fn get_screen(&self, root: &MessageData, path: &FieldPath, amount: usize, width: u16, indent: u16, config: &LayoutConfig, cursor: Option<(u16, usize)>) -> ScreenLines {
+        let mut lines = ScreenLines::new();
+        let Some(def) = root.get_field_definition(path) else { return lines; };
+        let visible = self.visible_columns(width, indent, cursor.and_then(|(x, _)| (x as usize).checked_sub(1)));
+        let more = if visible.end < self.header.len() { " …" } else { "" };
+        let header_styles = if self.vertical { [TextStyle::FieldIndex, TextStyle::SelectedFieldIndex] } else { [TextStyle::FieldName, TextStyle::SelectedFieldName] };
+
         let mut line = ScreenLine::new(width);
-        if let Some(field) = root.get_field(&path.0) {
-            line.add_field_name(field.def.name().clone(), indent, &cursor);
-            line.add_typename(field.def.clone(), width, amount == 0);
+        line.add_field_name(def.name(), indent, &cursor);
+        for column in visible.clone() {
+            let selected = cursor == Some((column as u16 + 1, 0));
+            line.0.push((' ', TextStyle::Divider));
+            line.add_string(format!("{:<w$}", self.header[column], w = self.column_width(column)), header_styles[selected as usize]);
+        }
+        line.add_string(more.to_string(), TextStyle::DataSize);
+        line.add_typename(def.clone(), width, amount == 0);
+        line.fix_length(width);
+        lines.0.push(line);
+
+        for (row, (address, cells)) in self.rows.iter().enumerate() {
+            let mut line = ScreenLine::new(width);
+            line.add_value_address(address.clone(), indent, &cursor, row + 1);
+            for column in visible.clone() {
+                let (text, style) = match &self.edit {
+                    Some((x, y, text)) if (*x as usize, *y) == (column + 1, row + 1) => {
+                        // the typed text is red while it is not a valid value
+                        let valid = self.cell_field(root, path, *x, *y).is_some_and(|(_, def, msg)| Self::parse_cell(text, &def, msg, config).is_ok());
+                        (text.clone(), if valid { TextStyle::SelectedValue } else { TextStyle::Missing })
+                    }
+                    _ if cursor == Some((column as u16 + 1, row + 1)) => (cells[column].0.clone(), TextStyle::SelectedValue),
+                    _ => cells[column].clone(),
+                };
+                line.0.push((' ', TextStyle::Divider));
+                line.add_string(format!("{:<w$}", text, w = self.column_width(column)), style);
+            }
+            line.add_string(more.to_string(), TextStyle::DataSize);
+            line.fix_length(width);
+            lines.0.push(line);
         }
-        ScreenLines(vec![line])
+        lines
+    }
+    fn on_command(&mut self, root: &MessageData, path: &FieldPath, amount: usize, command: UserCommand, config: &LayoutConfig, width: u16, indent: u16, cursor_x: &mut u16, cursor_pos: &mut usize) -> CommandResult
+    {
+        if let Some((_, _, text)) = &mut self.edit {
+            match command {
+                UserCommand::KeyPress(c) => {
+                    text.push(c);
+                    return CommandResult::Redraw;
+                }
+                UserCommand::DeleteData(_) => {
+                    text.pop();
+                    return CommandResult::Redraw;
+                }
+                _ => { // the typed value is saved on exit, an invalid one is discarded
+                    let (x, y, text) = self.edit.take().unwrap();
+                    let Some((field_path, def, msg)) = self.cell_field(root, path, x, y) else { return CommandResult::Redraw; };
+                    let Ok(value) = Self::parse_cell(&text, &def, msg, config) else { return CommandResult::Redraw; };
+                    let value = FieldValue::SCALAR(value);
+                    let action = if root.get_field(&field_path.0).is_some() { ChangeType::Overwrite(value) } else { ChangeType::Insert(value) };
+                    return CommandResult::ChangeData(Change { path: field_path, action });
+                }
+            }
+        }
+        let start = path.0.last().unwrap().index;
+        match command {
+            UserCommand::KeyPress(c) if self.cell_field(root, path, *cursor_x, *cursor_pos).is_some_and(|(_, def, _)| Self::editable(&def)) => {
+                self.edit = Some((*cursor_x, *cursor_pos, c.to_string())); // typing replaces the value of the cell
+                CommandResult::Redraw
+            }
+            UserCommand::ScrollHorizontally(delta) => {
+                *cursor_x = (*cursor_x as isize + delta as isize).clamp(0, self.header.len() as isize) as u16;
+                CommandResult::Redraw
+            }
+            UserCommand::Home => {
+                *cursor_x = if *cursor_x == 1 { 0 } else { 1.min(self.header.len() as u16) };
+                CommandResult::Redraw
+            }
+            UserCommand::End => {
+                *cursor_x = self.header.len() as u16;
+                CommandResult::Redraw
+            }
+            UserCommand::DeleteData(_) => match self.cell_at_cursor(*cursor_x, *cursor_pos) {
+                (Some(element), None) => { // the index of a message is selected
+                    if element > 0 && element + 1 == self.order.len() {
+                        self.cursor_to_element(element - 1, cursor_x, cursor_pos);
+                    }
+                    CommandResult::ChangeData(Change { path: path.with_last_index(start + self.order[element]), action: ChangeType::Delete })
+                }
+                (Some(_), Some(_)) => match self.cell_field(root, path, *cursor_x, *cursor_pos) {
+                    Some((field_path, _, _)) if root.get_field(&field_path.0).is_some() => CommandResult::ChangeData(Change { path: field_path, action: ChangeType::Delete }),
+                    _ => CommandResult::None,
+                },
+                _ => CommandResult::None,
+            },
+            UserCommand::InsertData => { // a new message after the selected one
+                let element = self.cell_at_cursor(*cursor_x, *cursor_pos).0;
+                let index = element.map_or(amount, |element| self.order[element] + 1);
+                let def = root.get_field_definition(path).unwrap();
+                self.cursor_to_element(element.map_or(self.order.len(), |element| element + 1), cursor_x, cursor_pos);
+                CommandResult::ChangeData(Change { path: path.with_last_index(start + index), action: ChangeType::Insert(def.default()) })
+            }
+            UserCommand::MoveField(delta) => {
+                let Some(element) = self.cell_at_cursor(*cursor_x, *cursor_pos).0 else { return CommandResult::None; };
+                if !in_file_order(&self.order, amount) { return CommandResult::None; }
+                let result = move_value(root, &path.with_last_index(start + element), delta);
+                if matches!(result, CommandResult::ChangeData(_)) {
+                    self.cursor_to_element(element.wrapping_add_signed(delta as isize), cursor_x, cursor_pos);
+                }
+                result
+            }
+            _ => on_command_default_handler(root, path, amount, command, config, width, indent, cursor_x, cursor_pos)
+        }
+    }
+}
+
+impl MapLayout {
+    fn new() -> Self {
+        MapLayout { order: vec![], rows: vec![], edit: None }
+    }
+    // the key (x=0) or the value (x=1) of the entry in the row at the cursor: its path, definition and the entry
+    fn entry_field<'a>(&self, root: &'a MessageData, path: &FieldPath, cursor_x: u16, cursor_y: usize) -> Option<(FieldPath, FieldProtoPtr, &'a MessageData)> {
+        let offset = self.order.get(cursor_y.checked_sub(1)?)?;
+        let entry_path = path.with_last_index(path.0.last().unwrap().index + offset);
+        let entry = root.get_submessage(&entry_path.0)?;
+        let def = entry.def.get_field(if cursor_x == 0 { MAP_KEY_ID } else { MAP_VALUE_ID })?;
+        let count = entry.fields.iter().filter(|f| f.def.id() == def.id()).count();
+        Some((entry_path.add((def.id(), count.saturating_sub(1)).into()), def, entry)) // the last value is used
+    }
+    // the typed key or value; a key is valid only if no other entry has it
+    fn parse_edit(&self, root: &MessageData, path: &FieldPath, cursor_x: u16, cursor_y: usize, text: &str, config: &LayoutConfig) -> Result<(FieldPath, ScalarValue), String> {
+        let (field_path, def, entry) = self.entry_field(root, path, cursor_x, cursor_y).ok_or("no entry")?;
+        let value = TableLayout::parse_cell(text, &def, entry, config)?;
+        if cursor_x == 0 {
+            let parent = root.get_submessage(&path.0[..path.0.len() - 1]).unwrap();
+            let entry_index = field_path.0[field_path.0.len() - 2].index;
+            if find_entries(parent, path.0.last().unwrap().id, &value).iter().any(|index| *index != entry_index) {
+                return Err(format!("the key {} is used by another entry", text));
+            }
+        }
+        Ok((field_path, value))
+    }
+    // a key for a new entry, not used by the entries of the map
+    fn unused_key(parent: &MessageData, def: &FieldProtoPtr) -> Option<ScalarValue> {
+        let key_def = def.message_proto()?.get_field(MAP_KEY_ID)?;
+        let FieldValue::SCALAR(default) = key_def.default() else { return None; };
+        let count = parent.fields.iter().filter(|f| f.id() == def.id()).count();
+        (0..=count).filter_map(|n| match default {
+            STR(_) if n == 0 => Some(STR(String::new())),
+            STR(_) => Some(STR(format!("key{}", n))),
+            BOOL(_) if n < 2 => Some(BOOL(n == 1)),
+            BOOL(_) => None,
+            _ => format::parse_scalar(&n.to_string(), &key_def, FieldDataViewFormat::Decimal).ok(),
+        }).find(|key| find_entries(parent, def.id(), key).is_empty())
+    }
+}
+impl ViewLayout for MapLayout {
+    fn layout_type(&self) -> LayoutType { LayoutType::Map }
+    fn cursor_at_value(&self, index: usize) -> (u16, usize) {
+        self.order.iter().position(|offset| *offset == index).map_or((0, 0), |row| (0, row + 1))
+    }
+    fn value_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> usize {
+        cursor_y.checked_sub(1).and_then(|row| self.order.get(row)).copied().unwrap_or(0)
+    }
+    fn get_status_string(&self, cursor_x: u16, cursor_y: usize) -> String {
+        match cursor_y.checked_sub(1).and_then(|row| self.rows.get(row)) {
+            Some((_, _, true)) => format!("{}/{} (duplicate key)", cursor_y - 1, self.rows.len()),
+            Some(_) => format!("{}/{}", cursor_y - 1, self.rows.len()),
+            None => String::new(),
+        }
+    }
+    fn get_text_edit_cursor(&self) -> Option<(u16, usize)> {
+        self.edit.as_ref().map(|(_, y, text)| (text.chars().count() as u16, *y))
+    }
+    fn calc_sizes(&mut self, root: &MessageData, path: &FieldPath, amount: usize, config: &LayoutConfig, width: u16, negotiator: &mut IndentsCalc) -> usize {
+        let def = root.get_field_definition(path).unwrap();
+        let start = path.0.last().unwrap().index;
+        let overridden = root.get_submessage(&path.0[..path.0.len() - 1]).map(|parent| overridden_entries(parent, def.id())).unwrap_or_default();
+        self.order = view_order(root, path, amount, config);
+        self.rows = self.order.iter().map(|offset| {
+            let entry = root.get_submessage(&path.with_last_index(start + offset).0).unwrap();
+            let [key, value] = [MAP_KEY_ID, MAP_VALUE_ID].map(|id| match entry.def.get_field(id) {
+                Some(def) => TableLayout::cell_text(entry, &def, config),
+                None => (String::new(), TextStyle::Value),
+            });
+            (key.0, value, overridden.contains(&(start + offset)))
+        }).collect();
+        let first_column = self.rows.iter().map(|(key, _, _)| key.len()).fold(def.name().len(), usize::max);
+        negotiator.add(first_column, path.0.len());
+        self.rows.len() + 1
+    }
+    fn get_screen(&self, root: &MessageData, path: &FieldPath, amount: usize, width: u16, indent: u16, config: &LayoutConfig, cursor: Option<(u16, usize)>) -> ScreenLines {
+        let mut lines = ScreenLines::new();
+        let Some(def) = root.get_field_definition(path) else { return lines; };
+        let mut line = ScreenLine::new(width);
+        line.add_field_name(def.name(), indent, &cursor);
+        line.add_typename(def.clone(), width, amount == 0);
+        lines.0.push(line);
+
+        for (index, (key, (value, style), overridden)) in self.rows.iter().enumerate() {
+            let row = index + 1;
+            let mut line = ScreenLine::new(width);
+            if *overridden { // readers use the last entry with the key
+                line.add_first_column_item([TextStyle::Overridden, TextStyle::SelectedFieldIndex], key.clone(), indent, &cursor, row);
+            } else {
+                line.add_value_address(key.clone(), indent, &cursor, row);
+            }
+            line.0.push((' ', TextStyle::Divider));
+            match &self.edit {
+                Some((x, y, text)) if *y == row => {
+                    // the typed text is red while it is not a valid value or a key of another entry
+                    let valid = self.parse_edit(root, path, *x, *y, text, config).is_ok();
+                    line.add_string(text.clone(), if valid { TextStyle::SelectedValue } else { TextStyle::Missing });
+                    if *x == 0 { line.add_string(" (new key)".to_string(), TextStyle::DataSize); }
+                }
+                _ if cursor == Some((1, row)) => line.add_string(value.clone(), TextStyle::SelectedValue),
+                _ if *overridden => line.add_string(value.clone(), TextStyle::Overridden),
+                _ => line.add_string(value.clone(), *style),
+            }
+            line.fix_length(width);
+            lines.0.push(line);
+        }
+        lines
     }
     fn on_command(&mut self, root: &MessageData, path: &FieldPath, amount: usize, command: UserCommand, config: &LayoutConfig, width: u16, indent: u16, cursor_x: &mut u16, cursor_pos: &mut usize) -> CommandResult
     {
+        if let Some((_, _, text)) = &mut self.edit {
+            match command {
+                UserCommand::KeyPress(c) => {
+                    text.push(c);
+                    return CommandResult::Redraw;
+                }
+                UserCommand::DeleteData(_) => {
+                    text.pop();
+                    return CommandResult::Redraw;
+                }
+                _ => { // the typed key or value is saved on exit, an invalid one is discarded
+                    let (x, y, text) = self.edit.take().unwrap();
+                    let Ok((field_path, value)) = self.parse_edit(root, path, x, y, &text, config) else { return CommandResult::Redraw; };
+                    let value = FieldValue::SCALAR(value);
+                    let action = if root.get_field(&field_path.0).is_some() { ChangeType::Overwrite(value) } else { ChangeType::Insert(value) };
+                    return CommandResult::ChangeData(Change { path: field_path, action });
+                }
+            }
+        }
+        let start = path.0.last().unwrap().index;
+        let row = cursor_pos.checked_sub(1).filter(|row| *row < self.order.len());
         match command {
+            UserCommand::KeyPress(c) if *cursor_x == 1 && self.entry_field(root, path, 1, *cursor_pos).is_some_and(|(_, def, _)| TableLayout::editable(&def)) => {
+                self.edit = Some((1, *cursor_pos, c.to_string())); // typing replaces the value
+                CommandResult::Redraw
+            }
+            UserCommand::CollapsedToggle => match self.entry_field(root, path, *cursor_x, *cursor_pos) { // Enter edits the key or the value
+                Some((field_path, def, entry)) if TableLayout::editable(&def) => {
+                    let text = match root.get_field(&field_path.0).map(|field| &field.value) {
+                        Some(FieldValue::SCALAR(STR(text))) => text.clone(),
+                        Some(FieldValue::SCALAR(value)) => ScalarLayout::scalar_to_string(value, &def, config.field_format(entry, def.id())),
+                        _ => String::new(),
+                    };
+                    self.edit = Some((*cursor_x, *cursor_pos, text));
+                    CommandResult::Redraw
+                }
+                _ => CommandResult::None,
+            },
+            UserCommand::ScrollHorizontally(delta) => {
+                *cursor_x = (*cursor_x as isize + delta as isize).clamp(0, row.is_some() as isize) as u16;
+                CommandResult::Redraw
+            }
+            UserCommand::Home => {
+                *cursor_x = 0;
+                CommandResult::Redraw
+            }
+            UserCommand::End => {
+                *cursor_x = row.is_some() as u16;
+                CommandResult::Redraw
+            }
+            UserCommand::DeleteData(_) => match row {
+                Some(row) if *cursor_x == 0 => { // the entry
+                    if row > 0 && row + 1 == self.order.len() { *cursor_pos -= 1; }
+                    CommandResult::ChangeData(Change { path: path.with_last_index(start + self.order[row]), action: ChangeType::Delete })
+                }
+                Some(_) => match self.entry_field(root, path, 1, *cursor_pos) {
+                    Some((field_path, _, _)) if root.get_field(&field_path.0).is_some() => CommandResult::ChangeData(Change { path: field_path, action: ChangeType::Delete }),
+                    _ => CommandResult::None,
+                },
+                None => on_command_default_handler(root, path, amount, command, config, width, indent, cursor_x, cursor_pos),
+            },
+            UserCommand::InsertData => { // a new entry after the selected one, with an unused key
+                let def = root.get_field_definition(path).unwrap();
+                let Some(parent) = root.get_submessage(&path.0[..path.0.len() - 1]) else { return CommandResult::None; };
+                let (Some(key), FieldValue::MESSAGE(mut entry)) = (Self::unused_key(parent, &def), def.default()) else { return CommandResult::None; };
+                entry.add_field(&[FieldPos { id: MAP_KEY_ID, index: 0 }]).unwrap().value = FieldValue::SCALAR(key);
+                let index = row.map_or(amount, |row| self.order[row] + 1);
+                (*cursor_x, *cursor_pos) = (0, row.map_or(self.order.len(), |row| row + 1) + 1);
+                CommandResult::ChangeData(Change { path: path.with_last_index(start + index), action: ChangeType::Insert(FieldValue::MESSAGE(entry)) })
+            }
+            UserCommand::MoveField(delta) => {
+                let Some(row) = row else { return CommandResult::None; };
+                if !in_file_order(&self.order, amount) { return CommandResult::None; }
+                let result = move_value(root, &path.with_last_index(start + row), delta);
+                if matches!(result, CommandResult::ChangeData(_)) {
+                    *cursor_pos = (row + 1).wrapping_add_signed(delta as isize);
+                }
+                result
+            }
             _ => on_command_default_handler(root, path, amount, command, config, width, indent, cursor_x, cursor_pos)
         }
     }
@@ -1261,6 +1958,106 @@ impl ViewLayout for CollapsedLayout {
     }
 }
 
+impl WellKnownLayout {
+    fn new() -> Self {
+        WellKnownLayout { lines: vec![], edit: None }
+    }
+    fn value_text(root: &MessageData, path: &FieldPath, amount: usize, pretty: bool) -> String {
+        let def = root.get_field_definition(path).and_then(|def| def.message_proto()).unwrap();
+        match root.get_submessage(&path.0) {
+            Some(msg) if amount > 0 => well_known::to_text(msg, pretty),
+            _ => well_known::to_text(&MessageData { def, fields: vec![] }, pretty), // the default value
+        }
+    }
+}
+impl ViewLayout for WellKnownLayout {
+    fn layout_type(&self) -> LayoutType { LayoutType::WellKnown }
+    fn calc_sizes(&mut self, root: &MessageData, path: &FieldPath, amount: usize, config: &LayoutConfig, width: u16, negotiator: &mut IndentsCalc) -> usize {
+        let def = root.get_field_definition(path).unwrap();
+        negotiator.add(def.name().len(), path.0.len());
+        self.lines = Self::value_text(root, path, amount, true).lines().map(str::to_string).collect();
+        if self.edit.is_some() { 1 } else { self.lines.len().max(1) }
+    }
+    fn get_screen(&self, root: &MessageData, path: &FieldPath, amount: usize, width: u16, indent: u16, config: &LayoutConfig, cursor: Option<(u16, usize)>) -> ScreenLines {
+        let mut lines = ScreenLines::new();
+        let Some(field_def) = root.get_field_definition(path) else { return lines; };
+        let texts = match &self.edit {
+            Some(text) => vec![text.clone()],
+            None if self.lines.is_empty() => vec![String::new()],
+            None => self.lines.clone(),
+        };
+        for (index, text) in texts.into_iter().enumerate() {
+            let mut line = ScreenLine::new(width);
+            if index == 0 {
+                line.add_field_name(field_def.name(), indent, &cursor);
+            } else {
+                line.add_value_address(String::new(), indent, &cursor, index);
+            }
+            line.0.push((' ', TextStyle::Divider));
+            // the typed text is red while it is not a valid value
+            let style = match (&self.edit, field_def.message_proto()) {
+                (Some(text), Some(def)) if well_known::from_text(text, def.clone()).is_err() => TextStyle::Missing,
+                (Some(_), _) => TextStyle::SelectedValue,
+                _ if cursor.is_some_and(|(x, y)| x > 0 && y == index) => TextStyle::SelectedValue,
+                _ => TextStyle::Value,
+            };
+            line.add_string(text, style);
+            if index == 0 {
+                line.add_typename(field_def.clone(), width, amount == 0);
+            }
+            line.fix_length(width);
+            lines.0.push(line);
+        }
+        lines
+    }
+    fn get_text_edit_cursor(&self) -> Option<(u16, usize)> {
+        self.edit.as_ref().map(|text| (text.chars().count() as u16, 0))
+    }
+    fn on_command(&mut self, root: &MessageData, path: &FieldPath, amount: usize, command: UserCommand, config: &LayoutConfig, width: u16, indent: u16, cursor_x: &mut u16, cursor_pos: &mut usize) -> CommandResult {
+        if let Some(text) = &mut self.edit {
+            match command {
+                UserCommand::KeyPress(c) => {
+                    text.push(c);
+                    return CommandResult::Redraw;
+                }
+                UserCommand::DeleteData(_) => {
+                    text.pop();
+                    return CommandResult::Redraw;
+                }
+                UserCommand::CancelEdit => {
+                    self.edit = None;
+                    return CommandResult::Redraw;
+                }
+                _ => { // the typed value is saved on exit, an invalid one is discarded
+                    let text = self.edit.take().unwrap();
+                    let def = root.get_field_definition(path).and_then(|def| def.message_proto()).unwrap();
+                    let Ok(msg) = well_known::from_text(&text, def) else { return CommandResult::Redraw; };
+                    let value = FieldValue::MESSAGE(msg.into());
+                    let action = if amount == 0 { ChangeType::Insert(value) } else { ChangeType::Overwrite(value) };
+                    return CommandResult::ChangeData(Change { path: path.clone(), action });
+                }
+            }
+        }
+        match command {
+            UserCommand::KeyPress(c) => { // typing replaces the value
+                self.edit = Some(c.to_string());
+                (*cursor_x, *cursor_pos) = (1, 0);
+                CommandResult::Redraw
+            }
+            UserCommand::ScrollHorizontally(delta) if delta > 0 => { // the value is edited in one line
+                self.edit = Some(Self::value_text(root, path, amount, false));
+                (*cursor_x, *cursor_pos) = (1, 0);
+                CommandResult::Redraw
+            }
+            UserCommand::ScrollHorizontally(_) => {
+                *cursor_x = 0;
+                CommandResult::Redraw
+            }
+            _ => on_command_default_handler(root, path, amount, command, config, width, indent, cursor_x, cursor_pos)
+        }
+    }
+}
+
 impl TextStyle {
     pub fn first_column(&self) -> bool {
         match self {
@@ -1289,6 +2086,11 @@ impl TextStyle {
             TextStyle::DefaultValue => Color::Grey,
             TextStyle::Typename => Color::DarkCyan,
             TextStyle::Bookmark => Color::Black,
+            TextStyle::Found => Color::Black,
+            TextStyle::Missing => Color::Red,
+            TextStyle::Overridden => Color::Magenta,
+            TextStyle::Damaged => Color::White,
+            TextStyle::Binary => Color::DarkYellow,
             TextStyle::Unknown => Color::Reset,
             _ => Color::Grey,
         };
@@ -1300,6 +2102,8 @@ impl TextStyle {
             TextStyle::SelectedFieldIndex |
             TextStyle::SelectedTypename => Color::DarkCyan,
             TextStyle::Bookmark => Color::Yellow,
+            TextStyle::Found => Color::DarkYellow,
+            TextStyle::Damaged => Color::DarkRed,
             _ => Color::Reset,
         };
 
@@ -1335,13 +2139,27 @@ impl LayoutParams {
     pub fn get_screen(&self, root: &MessageData, width: u16, indent: u16, config: &LayoutConfig, cursor: Option<(u16, usize)>) -> ScreenLines
     {
         if let Some(layout) = &self.layout {
-            layout.get_screen(root, &self.path, self.amount, width, indent, config, cursor)
+            let mut lines = layout.get_screen(root, &self.path, self.amount, width, indent, config, cursor);
+            if replaced(root, &self.path, self.amount) { // the field name is marked
+                if let Some(line) = lines.0.first_mut() {
+                    line.0.iter_mut().filter(|(_, style)| *style == TextStyle::FieldName).for_each(|item| item.1 = TextStyle::Overridden);
+                }
+            }
+            lines
         } else {
             debug_assert!(false);
             ScreenLines::new()
         }
     }
 
+    pub fn cursor_at_value(&self, index: usize) -> (u16, usize) {
+        self.layout.as_ref().map_or((0, 0), |layout| layout.cursor_at_value(index))
+    }
+
+    pub fn value_at_cursor(&self, cursor_x: u16, cursor_y: usize) -> usize {
+        self.layout.as_ref().map_or(0, |layout| layout.value_at_cursor(cursor_x, cursor_y))
+    }
+
     pub fn get_text_edit_cursor(&self) -> Option<(u16, usize)> {
         if let Some(layout) = &self.layout {
             layout.get_text_edit_cursor()
@@ -1361,7 +2179,7 @@ impl LayoutParams {
 
 impl Layouts {
     pub fn new(root: &MessageData, proto: ProtoData, config: &LayoutConfig, file_path: std::path::PathBuf, width: u16, height: u16) -> Layouts {
-        let sorted_fields = root.get_sorted_fields(&config.field_order);
+        let sorted_fields = shown_fields(root, &config.field_order);
         let mut items: Vec<LayoutParams> =
             sorted_fields.into_iter().enumerate().
                 map(|(layout_index, pos_ex)| Self::create_field_layouts(root, &config, &FieldPath([pos_ex.0].into()), pos_ex.1, false)).
@@ -1387,7 +2205,7 @@ impl Layouts {
         temp_path.set_extension("tmp");
         {
             let mut output = std::fs::File::create(temp_path.clone())?;
-            data.write(&mut output, &self.proto, data.def.clone())?;
+            text_format::write_document(&mut output, &self.file_path, data, &self.proto)?;
             output.flush()?;
         }
         std::fs::rename(temp_path, self.file_path.clone())?;
@@ -1401,11 +2219,17 @@ impl Layouts {
         if let Some(field) = root.get_field(&path.0) {
             match &field.value {
                 FieldValue::MESSAGE(msg) => {
-                    if amount == 0 {
+                    let map = is_map(&field.def) && field.def.message_proto().is_some_and(|def| config.map_view(&def));
+                    let table = field.def.message_proto().filter(|def| field.def.repeated() && !well_known::is_well_known(def)).and_then(|def| config.table_view(&def));
+                    if map { // all entries in one layout
+                        items.push(LayoutParams::new(path.clone(), amount, Box::new(MapLayout::new())));
+                    } else if let Some(vertical) = table { // all values in one layout
+                        items.push(LayoutParams::new(path.clone(), amount, Box::new(TableLayout::new(vertical))));
+                    } else if amount == 0 {
                         items.append(&mut Self::create_message_layouts(root, config, path, amount, load_all));
                     } else {
-                        for index in last_pos.index..last_pos.index + amount { // message layout does not support repeated data
-                            items.append(&mut Self::create_message_layouts(root, config, &path.with_last_index(index), 1, load_all));
+                        for offset in view_order(root, path, amount, config) { // message layout does not support repeated data
+                            items.append(&mut Self::create_message_layouts(root, config, &path.with_last_index(last_pos.index + offset), 1, load_all));
                         }
                     }
                 }
@@ -1427,13 +2251,15 @@ impl Layouts {
 
     pub fn create_message_layouts(root: &MessageData, config: &LayoutConfig, path: &FieldPath, amount: usize, load_all: bool) -> Vec<LayoutParams> {
         let mut items: Vec<LayoutParams> = vec![];
-        if load_all {
+        if root.get_field_definition(path).and_then(|def| def.message_proto()).is_some_and(|def| well_known::is_well_known(&def)) {
+            items.push(LayoutParams::new(path.clone(), amount, Box::new(WellKnownLayout::new())));
+        } else if load_all {
             let msg_layout = MessageLayout::new();
             let consumed_fields = msg_layout.get_consumed_fields(root, path, config);
             items.push(LayoutParams::new(path.clone(), amount, Box::new(msg_layout)));
             if amount > 0 {
                 let msg = root.get_submessage(&path.0).unwrap();
-                let sorted_fields = msg.get_sorted_fields(&config.field_order);
+                let sorted_fields = shown_fields(msg, &config.field_order);
                 let mut descendants = sorted_fields.into_iter().
                     filter(|(pos, _)| !consumed_fields.contains(&pos.id)).
                     map(|(pos, amount)| Self::create_field_layouts(root, config, &path.add(pos), amount, load_all)).
@@ -1451,11 +2277,11 @@ impl Layouts {
         let mut items: Vec<LayoutParams> = vec![];
         let layout_type = &field_def.typename();
         match layout_type.as_str() {
-            // repeated strings and bytes always shown as one layout for each data item
-            "bytes" | "string" => {
+            // repeated strings and bytes always shown as one layout for each data item, damaged data is shown as bytes
+            "bytes" | "string" | "damaged" => {
                 let start = path.0.last().unwrap().index;
                 for index in start..start + amount.max(1) {
-                    let layout: Box<dyn ViewLayout> = if layout_type.as_str() == "bytes" {
+                    let layout: Box<dyn ViewLayout> = if layout_type.as_str() != "string" {
                         Box::new(BytesLayout {
                             bytes_per_line: 0,
                             data_size: 0,
@@ -1639,6 +2465,37 @@ impl Layouts {
         position
     }
 
+    // index of the layout showing data at the path,
+    // if the data is hidden (collapsed message) the nearest parent layout is returned
+    pub fn find_layout(&self, path: &FieldPath) -> Option<usize> {
+        let mut path = path.0.as_slice();
+        while let Some((last, parent)) = path.split_last() {
+            let found = self.items.iter().position(|item| {
+                if let Some((item_last, item_parent)) = item.path.0.split_last() {
+                    item_parent == parent && item_last.id == last.id &&
+                        item_last.index <= last.index && last.index < item_last.index + item.amount.max(1)
+                } else { false }
+            });
+            if found.is_some() { return found; }
+            path = parent;
+        }
+        None
+    }
+
+    // expand collapsed or not loaded messages until the data at the path is shown
+    pub fn reveal(&mut self, root: &MessageData, config: &LayoutConfig, path: &FieldPath) -> Option<usize> {
+        loop {
+            let index = self.find_layout(path)?;
+            let item = &self.items[index];
+            let collapsed = match &item.layout {
+                None => true,
+                Some(layout) => layout.layout_type() == LayoutType::Collapsed && item.path.0.len() < path.0.len(),
+            };
+            if !collapsed { return Some(index); }
+            self.expand_collapsed(root, config, index);
+        }
+    }
+
     pub fn get_parent_pos(&self, mut pos: usize) -> Option<usize> {
         if let Some(current) = self.items.get(pos) {
             let parent_len = current.path.0.len() - 1;
@@ -1674,7 +2531,7 @@ impl Layouts {
                 }
             }
         } else { // if changed a field of the root message, rebuild all layouts
-            let sorted_fields = root.get_sorted_fields(&config.field_order);
+            let sorted_fields = shown_fields(root, &config.field_order);
             let mut items: Vec<LayoutParams> =
                 sorted_fields.into_iter().
                     map(|pos_ex| Self::create_field_layouts(root, &config, &FieldPath([pos_ex.0].into()), pos_ex.1, true)).
@@ -1758,7 +2615,9 @@ impl Layouts {
             }
 
             UserCommand::InsertData => {
-                if selection.x == 0 && selection.y == 0 {
+                // a new map entry needs an unused key, the map layout adds it
+                let map = self.items.get(selection.layout).and_then(|item| item.layout.as_ref()).is_some_and(|layout| layout.layout_type() == LayoutType::Map);
+                if selection.x == 0 && selection.y == 0 && !map {
                     if let Some(current) = self.items.get(selection.layout) {
                         let def = root.get_field_definition(&current.path).unwrap();
                         CommandResult::ChangeData(Change { path: current.path.clone(), action: ChangeType::Insert(def.default()) })
@@ -1793,6 +2652,9 @@ impl Layouts {
                             LayoutType::Collapsed => {
                                 self.expand_collapsed(root, config, selection.layout);
                             }
+                            LayoutType::Scalar | LayoutType::WellKnown | LayoutType::Table | LayoutType::Map => { // Enter finishes typing of a value
+                                return self.run_active_layout_command(command.clone(), root, config, selection);
+                            }
                             _ => {}
                         }
                     }
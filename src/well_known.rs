@@ -0,0 +1,414 @@
+use std::rc::Rc;
+use crate::format;
+use crate::json::{self, JsonValue};
+use crate::proto::{FieldProtoPtr, MessageProtoPtr, ProtoData};
+use crate::text_format;
+use crate::typedefs::{AnyValueFieldDefinition, PbSliceReader};
+use crate::wire::*;
+
+// Well-known types of google/protobuf. Their definitions are bundled, so the imports are found without -I.
+// Some of them are shown and typed as one value in the form of the JSON mapping:
+// Timestamp as RFC 3339 text, Duration as "1.5s", wrappers as the wrapped value, Struct, Value and ListValue as JSON.
+// The bytes of Any are decoded as the message named by its type_url, see unpack_any.
+
+const PACKAGE: &str = "google.protobuf.";
+const NANOS: i128 = 1_000_000_000;
+
+const FILES: [(&str, &str); 8] = [
+    ("google/protobuf/any.proto", include_str!("../resources/google/protobuf/any.proto")),
+    ("google/protobuf/descriptor.proto", include_str!("../resources/google/protobuf/descriptor.proto")),
+    ("google/protobuf/duration.proto", include_str!("../resources/google/protobuf/duration.proto")),
+    ("google/protobuf/empty.proto", include_str!("../resources/google/protobuf/empty.proto")),
+    ("google/protobuf/field_mask.proto", include_str!("../resources/google/protobuf/field_mask.proto")),
+    ("google/protobuf/struct.proto", include_str!("../resources/google/protobuf/struct.proto")),
+    ("google/protobuf/timestamp.proto", include_str!("../resources/google/protobuf/timestamp.proto")),
+    ("google/protobuf/wrappers.proto", include_str!("../resources/google/protobuf/wrappers.proto")),
+];
+
+const WRAPPERS: [&str; 9] = ["DoubleValue", "FloatValue", "Int64Value", "UInt64Value", "Int32Value", "UInt32Value", "BoolValue", "StringValue", "BytesValue"];
+
+// content of an imported file of the protobuf distribution
+pub fn bundled_file(name: &str) -> Option<&'static str> {
+    FILES.iter().find(|(file, _)| *file == name).map(|(_, content)| *content)
+}
+
+// the message is shown as one value instead of its fields
+pub fn is_well_known(def: &MessageProtoPtr) -> bool {
+    match def.name.strip_prefix(PACKAGE) {
+        Some("Timestamp" | "Duration" | "Struct" | "Value" | "ListValue") => true,
+        Some(name) => WRAPPERS.contains(&name),
+        None => false,
+    }
+}
+
+// JSON of Struct, Value and ListValue is indented if pretty, the other types are always one line;
+// a value which cannot be shown in the special form is written as the fields in the text format
+pub fn to_text(msg: &MessageData, pretty: bool) -> String {
+    let special = match msg.def.name.strip_prefix(PACKAGE).unwrap_or_default() {
+        "Timestamp" => timestamp_to_text(int_field(msg, 1), int_field(msg, 2)),
+        "Duration" => duration_to_text(int_field(msg, 1), int_field(msg, 2)),
+        "Struct" | "Value" | "ListValue" => {
+            let mut out = String::new();
+            write_json(&mut out, &to_json(msg), pretty.then_some(0));
+            Some(out)
+        }
+        _ => msg.def.get_field(1).map(|def| {
+            let default = def.default();
+            match last_value(msg, 1).unwrap_or(&default) {
+                FieldValue::SCALAR(value) => text_format::scalar_to_text(&def, value),
+                FieldValue::MESSAGE(_) => String::new(),
+            }
+        }),
+    };
+    special.unwrap_or_else(|| text_format::message_to_text(msg).lines().map(str::trim).collect::<Vec<_>>().join(" "))
+}
+
+// the reverse of to_text, the fields in the text format are accepted too
+pub fn from_text(text: &str, def: MessageProtoPtr) -> Result<MessageData, String> {
+    let text = text.trim();
+    let special = match def.name.strip_prefix(PACKAGE).unwrap_or_default() {
+        "Timestamp" => format::parse_date(text, NANOS).map(split_nanos).
+            ok_or_else(|| format!("\"{}\" is not a date like 1970-01-01T00:00:00.5Z", text)).
+            map(|(seconds, nanos)| new_message(def.clone(), seconds, nanos)),
+        "Duration" => parse_duration(text).
+            ok_or_else(|| format!("\"{}\" is not a duration like 1.5s", text)).
+            map(|(seconds, nanos)| new_message(def.clone(), seconds, nanos)),
+        "Struct" | "Value" | "ListValue" => JsonValue::parse(text).and_then(|value| json_to_message(&value, def.clone())),
+        _ => {
+            let value_def = def.get_field(1).ok_or("not a wrapper type")?;
+            let value = match (text_format::text_to_value(text, &value_def), value_def.default()) {
+                (Ok(value), _) => value,
+                (Err(_), FieldValue::SCALAR(ScalarValue::STR(_))) => FieldValue::SCALAR(ScalarValue::STR(text.to_string())), // without quotes
+                (Err(e), _) => return text_format::text_to_message(text, def).map_err(|_| e),
+            };
+            // the default value is not written in proto3
+            let fields = if value == value_def.default() { vec![] } else { vec![new_field(&value_def, value)] };
+            Ok(MessageData { def: def.clone(), fields })
+        }
+    };
+    special.or_else(|e| text_format::text_to_message(text, def).map_err(|_| e))
+}
+
+// the value of Any is decoded if the message named by type_url is known, wrong data is kept as bytes;
+// submessages not parsed yet (in the lazy mode) are skipped
+pub fn unpack_any(msg: &mut MessageData, proto: &ProtoData) {
+    if msg.def.name == format!("{}Any", PACKAGE) {
+        let type_name = match last_value(msg, 1) {
+            Some(FieldValue::SCALAR(ScalarValue::STR(url))) => url.rsplit('/').next().unwrap_or_default().to_string(),
+            _ => String::new(),
+        };
+        if let Some(def) = proto.get_message_definition(&type_name) {
+            let value_def: FieldProtoPtr = Rc::new(AnyValueFieldDefinition::new(def.clone()));
+            for field in msg.fields.iter_mut().filter(|f| f.def.id() == AnyValueFieldDefinition::ID) {
+                let FieldValue::SCALAR(ScalarValue::BYTES(bytes)) = &field.value else { continue; };
+                let mut limit = bytes.len() as u64;
+                let start = if field.pos == usize::MAX { 0 } else { field.pos };
+                let value = MessageData::new(&mut PbSliceReader::new(bytes, start), proto, def.clone(), &mut limit).ok();
+                // kept as bytes if the message would be saved differently
+                if let Some(value) = value.filter(|value| packed_value(value) == *bytes) {
+                    field.def = value_def.clone();
+                    field.value = FieldValue::MESSAGE(value.into());
+                }
+            }
+        }
+    }
+    for field in &mut msg.fields {
+        if let FieldValue::MESSAGE(sub) = &mut field.value {
+            if sub.is_parsed() { unpack_any(sub, proto); }
+        }
+    }
+}
+
+// the decoded value of Any as bytes, for the text format and JSON
+pub fn packed_value(value: &MessageData) -> Vec<u8> {
+    let mut buf = vec![];
+    value.write(&mut buf, &ProtoData::default(), value.def.clone()).unwrap(); // the proto data is not used for writing
+    buf
+}
+
+fn last_value(msg: &MessageData, id: i32) -> Option<&FieldValue> {
+    msg.fields.iter().rev().find(|f| f.def.id() == id).map(|f| &f.value)
+}
+
+fn int_field(msg: &MessageData, id: i32) -> i64 {
+    match last_value(msg, id) {
+        Some(FieldValue::SCALAR(ScalarValue::I64(v))) => *v,
+        Some(FieldValue::SCALAR(ScalarValue::I32(v))) => *v as i64,
+        _ => 0,
+    }
+}
+
+fn new_field(def: &FieldProtoPtr, value: FieldValue) -> FieldData {
+    FieldData::new(def.clone(), value)
+}
+
+// seconds and nanos of Timestamp or Duration, zeros are not written
+fn new_message(def: MessageProtoPtr, seconds: i64, nanos: i32) -> MessageData {
+    let mut fields = vec![];
+    if let (Some(field_def), true) = (def.get_field(1), seconds != 0) {
+        fields.push(new_field(&field_def, FieldValue::SCALAR(ScalarValue::I64(seconds))));
+    }
+    if let (Some(field_def), true) = (def.get_field(2), nanos != 0) {
+        fields.push(new_field(&field_def, FieldValue::SCALAR(ScalarValue::I32(nanos))));
+    }
+    MessageData { def, fields }
+}
+
+fn split_nanos(value: i128) -> (i64, i32) {
+    (value.div_euclid(NANOS) as i64, value.rem_euclid(NANOS) as i32)
+}
+
+// 0, 3, 6 or 9 digits after the point, as in the JSON mapping
+fn fraction(nanos: i64) -> String {
+    match nanos {
+        0 => String::new(),
+        _ if nanos % 1_000_000 == 0 => format!(".{:03}", nanos / 1_000_000),
+        _ if nanos % 1000 == 0 => format!(".{:06}", nanos / 1000),
+        _ => format!(".{:09}", nanos),
+    }
+}
+
+fn timestamp_to_text(seconds: i64, nanos: i64) -> Option<String> {
+    if !(0..NANOS as i64).contains(&nanos) { return None; }
+    let date = format::format_date(seconds as i128, 1)?;
+    Some(format!("{}{}Z", date.strip_suffix('Z')?, fraction(nanos)))
+}
+
+// seconds and nanos have the same sign
+fn duration_to_text(seconds: i64, nanos: i64) -> Option<String> {
+    if nanos.abs() >= NANOS as i64 || (seconds < 0 && nanos > 0) || (seconds > 0 && nanos < 0) { return None; }
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    Some(format!("{}{}{}s", sign, seconds.unsigned_abs(), fraction(nanos.abs())))
+}
+
+fn parse_duration(text: &str) -> Option<(i64, i32)> {
+    let text = text.strip_suffix('s')?;
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if seconds.is_empty() || !seconds.bytes().all(|b| b.is_ascii_digit()) || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: i64 = seconds.parse().ok()?;
+    let nanos: i32 = if fraction.is_empty() { 0 } else { format!("{:0<9}", fraction).parse().ok()? };
+    Some(if negative { (-seconds, -nanos) } else { (seconds, nanos) })
+}
+
+// Struct, Value or ListValue
+fn to_json(msg: &MessageData) -> JsonValue {
+    let fields_of = |id: i32| msg.fields.iter().filter(move |f| f.def.id() == id).filter_map(|f| match &f.value {
+        FieldValue::MESSAGE(sub) => Some(&**sub),
+        FieldValue::SCALAR(_) => None,
+    });
+    match msg.def.name.strip_prefix(PACKAGE).unwrap_or_default() {
+        "Struct" => JsonValue::Object(fields_of(1).map(|entry| {
+            let key = match last_value(entry, 1) {
+                Some(FieldValue::SCALAR(ScalarValue::STR(key))) => key.clone(),
+                _ => String::new(),
+            };
+            let value = match last_value(entry, 2) {
+                Some(FieldValue::MESSAGE(value)) => to_json(value),
+                _ => JsonValue::Null,
+            };
+            (key, value)
+        }).collect()),
+        "ListValue" => JsonValue::Array(fields_of(1).map(to_json).collect()),
+        _ => match msg.fields.last().map(|f| &f.value) { // the last field of the kind oneof is set
+            Some(FieldValue::SCALAR(ScalarValue::F64(v))) if v.is_finite() => JsonValue::Number(v.to_string()),
+            Some(FieldValue::SCALAR(ScalarValue::F64(v))) => JsonValue::Str(v.to_string()),
+            Some(FieldValue::SCALAR(ScalarValue::STR(v))) => JsonValue::Str(v.clone()),
+            Some(FieldValue::SCALAR(ScalarValue::BOOL(v))) => JsonValue::Bool(*v),
+            Some(FieldValue::MESSAGE(sub)) => to_json(sub),
+            _ => JsonValue::Null,
+        }
+    }
+}
+
+fn json_to_message(value: &JsonValue, def: MessageProtoPtr) -> Result<MessageData, String> {
+    let field_def = |id: i32| def.get_field(id).ok_or_else(|| format!("{} is not a well-known type", def.name));
+    let message_def = |id: i32| field_def(id)?.message_proto().ok_or_else(|| format!("{} is not a well-known type", def.name));
+    let fields = match (def.name.strip_prefix(PACKAGE).unwrap_or_default(), value) {
+        ("Struct", JsonValue::Object(members)) => {
+            let entry_def = message_def(1)?;
+            let (key_def, value_def) = (entry_def.get_field(1).unwrap(), entry_def.get_field(2).unwrap());
+            let mut fields = vec![];
+            for (key, value) in members {
+                let value = json_to_message(value, value_def.message_proto().unwrap())?;
+                let entry = MessageData { def: entry_def.clone(), fields: vec![
+                    new_field(&key_def, FieldValue::SCALAR(ScalarValue::STR(key.clone()))),
+                    new_field(&value_def, FieldValue::MESSAGE(value.into()))] };
+                fields.push(new_field(&field_def(1)?, FieldValue::MESSAGE(entry.into())));
+            }
+            fields
+        }
+        ("Struct", _) => return Err("expected a JSON object".to_string()),
+        ("ListValue", JsonValue::Array(items)) => {
+            let mut fields = vec![];
+            for item in items {
+                fields.push(new_field(&field_def(1)?, FieldValue::MESSAGE(json_to_message(item, message_def(1)?)?.into())));
+            }
+            fields
+        }
+        ("ListValue", _) => return Err("expected a JSON array".to_string()),
+        (_, value) => {
+            let (id, kind) = match value {
+                JsonValue::Null => (1, FieldValue::SCALAR(ScalarValue::ENUM(0))),
+                JsonValue::Number(text) => (2, FieldValue::SCALAR(ScalarValue::F64(text.parse().map_err(|_| format!("wrong number {}", text))?))),
+                JsonValue::Str(text) => (3, FieldValue::SCALAR(ScalarValue::STR(text.clone()))),
+                JsonValue::Bool(v) => (4, FieldValue::SCALAR(ScalarValue::BOOL(*v))),
+                JsonValue::Object(_) => (5, FieldValue::MESSAGE(json_to_message(value, message_def(5)?)?.into())),
+                JsonValue::Array(_) => (6, FieldValue::MESSAGE(json_to_message(value, message_def(6)?)?.into())),
+            };
+            vec![new_field(&field_def(id)?, kind)]
+        }
+    };
+    Ok(MessageData { def, fields })
+}
+
+// indent is None for one line
+fn write_json(out: &mut String, value: &JsonValue, indent: Option<usize>) {
+    let separator = |out: &mut String, first: bool, indent: Option<usize>| match indent {
+        Some(indent) => out.push_str(&format!("{}\n{:indent$}", if first { "" } else { "," }, "", indent = indent)),
+        None => out.push_str(if first { "" } else { ", " }),
+    };
+    let inner = indent.map(|indent| indent + 2);
+    match value {
+        JsonValue::Null => out.push_str("null"),
+        JsonValue::Bool(v) => out.push_str(&v.to_string()),
+        JsonValue::Number(text) => out.push_str(text),
+        JsonValue::Str(text) => json::write_string(out, text),
+        JsonValue::Array(items) if items.is_empty() => out.push_str("[]"),
+        JsonValue::Object(members) if members.is_empty() => out.push_str("{}"),
+        JsonValue::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                separator(out, index == 0, inner);
+                write_json(out, item, inner);
+            }
+            if let Some(indent) = indent { out.push_str(&format!("\n{:indent$}", "", indent = indent)); }
+            out.push(']');
+        }
+        JsonValue::Object(members) => {
+            out.push('{');
+            for (index, (key, value)) in members.iter().enumerate() {
+                separator(out, index == 0, inner);
+                json::write_string(out, key);
+                out.push_str(": ");
+                write_json(out, value, inner);
+            }
+            if let Some(indent) = indent { out.push_str(&format!("\n{:indent$}", "", indent = indent)); }
+            out.push('}');
+        }
+    }
+}
+
+
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+/**************************************************************************************************/
+
+
+#[cfg(test)]
+mod well_known_types {
+    use super::*;
+    use crate::text_format::message_to_text;
+
+    fn proto() -> ProtoData {
+        let mut proto = ProtoData::new(r#"
+syntax = "proto3";
+message M {
+  google.protobuf.Timestamp t = 1;
+  google.protobuf.Duration d = 2;
+  google.protobuf.Int32Value i = 3;
+  google.protobuf.StringValue s = 4;
+  google.protobuf.Struct st = 5;
+  google.protobuf.Any any = 6;
+}
+"#).unwrap();
+        for name in ["google/protobuf/timestamp.proto", "google/protobuf/duration.proto", "google/protobuf/wrappers.proto",
+            "google/protobuf/struct.proto", "google/protobuf/any.proto"] {
+            proto.append(ProtoData::new(bundled_file(name).unwrap()).unwrap());
+        }
+        proto.finalize().unwrap()
+    }
+
+    fn def(proto: &ProtoData, name: &str) -> MessageProtoPtr {
+        proto.get_message_definition(&format!("{}{}", PACKAGE, name)).unwrap()
+    }
+
+    fn round_trip(proto: &ProtoData, name: &str, text: &str) -> String {
+        to_text(&from_text(text, def(proto, name)).unwrap(), false)
+    }
+
+    #[test]
+    fn timestamp_and_duration() {
+        let proto = proto();
+        assert!(is_well_known(&def(&proto, "Timestamp")));
+        assert!(!is_well_known(&def(&proto, "Any")));
+        assert_eq!(to_text(&MessageData { def: def(&proto, "Timestamp"), fields: vec![] }, false), "1970-01-01T00:00:00Z");
+        assert_eq!(round_trip(&proto, "Timestamp", "2024-02-29T23:59:59.5Z"), "2024-02-29T23:59:59.500Z");
+        assert_eq!(round_trip(&proto, "Timestamp", "2024-03-01T01:00:00.000001+01:00"), "2024-03-01T00:00:00.000001Z");
+        assert_eq!(round_trip(&proto, "Timestamp", "1969-12-31T23:59:59.999999999Z"), "1969-12-31T23:59:59.999999999Z");
+        let msg = from_text("2024-03-01", def(&proto, "Timestamp")).unwrap();
+        assert_eq!(message_to_text(&msg), "seconds: 1709251200\n");
+        assert!(from_text("yesterday", def(&proto, "Timestamp")).is_err());
+
+        assert_eq!(round_trip(&proto, "Duration", "1.5s"), "1.500s");
+        assert_eq!(round_trip(&proto, "Duration", "-0.000000001s"), "-0.000000001s");
+        assert_eq!(message_to_text(&from_text("-1.5s", def(&proto, "Duration")).unwrap()), "seconds: -1\nnanos: -500000000\n");
+        assert_eq!(round_trip(&proto, "Duration", "seconds: 1 nanos: -1"), "seconds: 1 nanos: -1"); // not valid, shown as fields
+    }
+
+    #[test]
+    fn wrappers() {
+        let proto = proto();
+        assert_eq!(round_trip(&proto, "Int32Value", "-5"), "-5");
+        assert_eq!(from_text("0", def(&proto, "Int32Value")).unwrap().fields.len(), 0);
+        assert_eq!(round_trip(&proto, "StringValue", "abc"), "\"abc\"");
+        assert_eq!(round_trip(&proto, "StringValue", "\"a b\""), "\"a b\"");
+        assert!(from_text("x", def(&proto, "Int32Value")).is_err());
+    }
+
+    #[test]
+    fn json_values() {
+        let proto = proto();
+        let text = r#"{"a": 1.5, "b": [true, null, "x"], "c": {}}"#;
+        assert_eq!(round_trip(&proto, "Struct", text), text);
+        let msg = from_text(text, def(&proto, "Struct")).unwrap();
+        assert_eq!(to_text(&msg, true), "{\n  \"a\": 1.5,\n  \"b\": [\n    true,\n    null,\n    \"x\"\n  ],\n  \"c\": {}\n}");
+        assert_eq!(round_trip(&proto, "Value", "[]"), "[]");
+        assert_eq!(round_trip(&proto, "ListValue", "[1, {\"k\": \"v\"}]"), "[1, {\"k\": \"v\"}]");
+        assert!(from_text("[1]", def(&proto, "Struct")).is_err());
+    }
+
+    #[test]
+    fn any() {
+        let proto = proto();
+        let timestamp = [0x08, 0x96, 0x01];
+        let url = b"type.googleapis.com/google.protobuf.Timestamp";
+        let mut any = vec![0x0a, url.len() as u8];
+        any.extend_from_slice(url);
+        any.extend_from_slice(&[0x12, timestamp.len() as u8]);
+        any.extend_from_slice(&timestamp);
+        let mut data = vec![0x32, any.len() as u8];
+        data.extend_from_slice(&any);
+
+        let mut limit = data.len() as u64;
+        let mut msg = MessageData::new(&mut PbSliceReader::new(&data, 0), &proto, proto.get_message_definition("M").unwrap(), &mut limit).unwrap();
+        unpack_any(&mut msg, &proto);
+        let value = msg.get_field(&[(6, 0).into(), (2, 0).into()]).unwrap();
+        assert!(value.def.any_value());
+        assert_eq!(value.pos, 2 + 2 + url.len() + 2);
+        let FieldValue::MESSAGE(timestamp) = &value.value else { panic!() };
+        assert_eq!(to_text(timestamp, false), "1970-01-01T00:02:30Z");
+        assert_eq!(message_to_text(&msg), "any {\n  type_url: \"type.googleapis.com/google.protobuf.Timestamp\"\n  value: \"\\010\\226\\001\"\n}\n");
+
+        let mut output = vec![];
+        msg.write(&mut output, &proto, msg.def.clone()).unwrap();
+        assert_eq!(output, data);
+    }
+}
@@ -1,8 +1,12 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::{io, mem};
+use std::cell::OnceCell;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use std::collections::HashMap;
 use std::io::Read;
 use crate::proto::*;
+use crate::repair;
 use crate::trz::{Change, ChangeType};
 use crate::typedefs::*;
 use crate::view::{FieldOrder, LayoutConfig, ScreenLine, IndentsCalc, TextStyle};
@@ -10,16 +14,16 @@ use crate::view::{FieldOrder, LayoutConfig, ScreenLine, IndentsCalc, TextStyle};
 pub const WT_VARINT: u8 = 0;  // int32, int64, uint32, uint64, sint32, sint64, bool, enum
 pub const WT_I64: u8 = 1;     // fixed64, sfixed64, double
 pub const WT_LEN: u8 = 2;     // string, bytes, embedded messages, packed repeated fields
-pub const WT_SGROUP: u8 = 3;  // is not supported
-pub const WT_EGROUP: u8 = 4;  // is not supported
+pub const WT_SGROUP: u8 = 3;  // proto2 groups, deprecated
+pub const WT_EGROUP: u8 = 4;
 pub const WT_I32: u8 = 5;     // fixed32, sfixed32, float
 
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Tag
 {
-    pub first_number: i32,
-    pub length: u32,
+    pub first_number: u32, // the field number and the wire type, up to 2^32 - 1
+    pub length: u64,
 }
 
 // stores only read data, no default value
@@ -32,11 +36,50 @@ pub struct FieldData {
     pub def: FieldProtoPtr,
     pub pos: usize, // read position in file, or usize::MAX for new data
     pub value: FieldValue,
+    pub encoding: Encoding,
+}
+
+// how the field was written in the file, so the file is saved byte by byte as it was read;
+// a size is kept only if it differs from the shortest encoding, 0 otherwise
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Encoding {
+    pub tag_size: u8,
+    pub length_size: u8, // of LEN data or of the packed record
+    pub value_size: u8, // of a varint value
+    pub end_tag_size: u8, // of the end tag of a group
+    pub varint: Option<u64>, // a varint value as read, if the value is written otherwise (e.g. bool 2)
+    pub packing: Packing,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Packing {
+    #[default]
+    Default, // new data, or not a packable field; see MessageData::packed
+    No, // a value in its own record
+    First, // the first value of a packed record
+    Next, // a value in the packed record of the previous one
 }
 
 pub enum FieldValue {
     SCALAR(ScalarValue),
-    MESSAGE(MessageData),
+    MESSAGE(SubMessage),
+}
+
+// the data file mapped to memory (or any bytes), see MessageData::new_lazy
+pub type DataSource = Rc<dyn AsRef<[u8]>>;
+
+// a submessage is used as MessageData; in the lazy mode it is parsed from the data file on first access
+pub struct SubMessage {
+    data: OnceCell<MessageData>,
+    lazy: Option<LazyMessage>, // the submessage is not changed since it was read
+}
+
+struct LazyMessage {
+    source: DataSource,
+    def: MessageProtoPtr,
+    unknown_field: FieldProtoPtr,
+    start: usize, // position of the submessage data in the file
+    length: u64,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -63,6 +106,78 @@ pub enum ScalarValue {
     //    EMPTY, // a scalar without value or a message without fields
 }
 
+impl From<MessageData> for SubMessage {
+    fn from(data: MessageData) -> Self {
+        SubMessage { data: OnceCell::from(data), lazy: None }
+    }
+}
+
+impl Deref for SubMessage {
+    type Target = MessageData;
+    fn deref(&self) -> &MessageData {
+        self.parsed()
+    }
+}
+
+impl DerefMut for SubMessage {
+    fn deref_mut(&mut self) -> &mut MessageData {
+        if !self.damaged() {
+            self.lazy = None; // the submessage may be changed, it is written from the parsed data
+        }
+        self.data.get_mut().unwrap()
+    }
+}
+
+impl SubMessage {
+    fn parsed(&self) -> &MessageData {
+        self.data.get_or_init(|| {
+            let lazy = self.lazy.as_ref().unwrap();
+            let mut reader = PbSliceReader::new(&(*lazy.source).as_ref()[lazy.start..], lazy.start);
+            let mut limit = lazy.length;
+            // wrong data is shown as one damaged field (see repair.rs), the message is saved unchanged from the file
+            MessageData::read(&mut reader, &lazy.unknown_field, lazy.def.clone(), &mut limit, None, Some(&lazy.source)).unwrap_or_else(|e| {
+                let bytes = &(*lazy.source).as_ref()[lazy.start..lazy.start + lazy.length as usize];
+                MessageData { def: lazy.def.clone(), fields: vec![repair::damaged(lazy.start, bytes, &e.to_string())] }
+            })
+        })
+    }
+
+    // a lazy submessage which cannot be parsed, it cannot be changed
+    pub fn damaged(&self) -> bool {
+        self.lazy.is_some() && matches!(self.parsed().fields.as_slice(), [field] if field.def.damaged())
+    }
+
+    // a message of a stream file (see stream.rs), parsed on first access
+    pub fn new_lazy(source: DataSource, proto: &ProtoData, def: MessageProtoPtr, start: usize, length: u64) -> SubMessage {
+        SubMessage { data: OnceCell::new(), lazy: Some(LazyMessage { source, def, unknown_field: proto.unknown_field.clone(), start, length }) }
+    }
+
+    pub fn into_inner(self) -> MessageData {
+        self.parsed();
+        self.data.into_inner().unwrap()
+    }
+
+    pub fn is_parsed(&self) -> bool {
+        self.data.get().is_some()
+    }
+
+    // size of the data, a lazy submessage is not parsed
+    pub fn len(&self) -> usize {
+        match (&self.lazy, self.data.get()) {
+            (Some(lazy), None) => lazy.length as usize,
+            _ => self.parsed().len(),
+        }
+    }
+
+    // an unchanged lazy submessage is copied from the data file
+    pub fn write(&self, writer: &mut dyn io::Write, proto: &ProtoData) -> io::Result<()> {
+        match &self.lazy {
+            Some(lazy) => writer.write_all(&(*lazy.source).as_ref()[lazy.start..lazy.start + lazy.length as usize]),
+            None => self.parsed().write(writer, proto, self.def.clone()),
+        }
+    }
+}
+
 impl PartialEq for FieldValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -132,10 +247,19 @@ impl From<FieldPos> for FieldRange {
     }
 }
 
+// the size of a varint in the file if it is not the shortest one, otherwise 0
+fn kept_size(size: usize, shortest: usize) -> u8 {
+    if size == shortest { 0 } else { size as u8 }
+}
+
 impl Tag
 {
+    // the first number of a tag, field numbers go up to 2^29 - 1 so it does not fit in i32
+    pub fn number(id: i32, wire_type: u8) -> u32 {
+        (id as u32) << 3 | wire_type as u32
+    }
     pub fn field_id(&self) -> i32 {
-        self.first_number >> 3
+        (self.first_number >> 3) as i32
     }
     pub fn wire_type(&self) -> u8 {
         (self.first_number & 7) as u8
@@ -143,6 +267,7 @@ impl Tag
     pub fn auto_length(&self) -> bool {
         match (self.first_number & 7) as u8 {
             WT_VARINT | WT_I64 | WT_I32 => true,
+            WT_SGROUP => true, // unknown group data ends with the end group tag
             WT_LEN => false,
             _ => panic!()
         }
@@ -160,6 +285,11 @@ impl Debug for MessageData {
 }
 
 impl FieldData {
+    // new data, written in the shortest encoding
+    pub fn new(def: FieldProtoPtr, value: FieldValue) -> FieldData {
+        FieldData { def, pos: usize::MAX, value, encoding: Encoding::default() }
+    }
+
     pub fn id(&self) -> i32 {
         match &self.value {
             FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, _)) => { tag.field_id() }
@@ -172,7 +302,78 @@ impl FieldData {
             FieldValue::SCALAR(scalar) => scalar.len(),
             FieldValue::MESSAGE(message) => message.len(),
         };
-        ScalarValue::varint_size((self.def.id() as i128) << 3) + data_size
+        ScalarValue::varint_size(Tag::number(self.def.id(), 0) as i128) + data_size
+    }
+    // the tag, the length if any, and the value, with the sizes they had in the file
+    pub fn write(&self, writer: &mut dyn io::Write, proto: &ProtoData) -> io::Result<()> {
+        let first_number = match &self.value {
+            FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, _)) => tag.first_number,
+            _ => Tag::number(self.def.id(), self.def.wire_type()),
+        };
+        CommonFieldProto::write_padded_varint(writer, first_number as i128, self.encoding.tag_size as usize)?;
+        match (&self.value, (first_number & 7) as u8) {
+            (FieldValue::SCALAR(ScalarValue::UNKNOWN(_, data)), WT_VARINT) => {
+                let mut bytes = [0u8; 16];
+                bytes[..data.len()].copy_from_slice(data);
+                CommonFieldProto::write_padded_varint(writer, i128::from_le_bytes(bytes), self.encoding.value_size as usize)
+            }
+            (FieldValue::SCALAR(ScalarValue::UNKNOWN(_, data)), WT_LEN) => self.write_len(writer, data),
+            (FieldValue::SCALAR(ScalarValue::UNKNOWN(_, data)), _) => CommonFieldProto::write_len(writer, data),
+            (FieldValue::MESSAGE(msg), WT_SGROUP) => {
+                msg.write(writer, proto)?;
+                CommonFieldProto::write_padded_varint(writer, Tag::number(self.def.id(), WT_EGROUP) as i128, self.encoding.end_tag_size as usize)
+            }
+            (FieldValue::MESSAGE(msg), _) => {
+                // variable length data. First write to the temporary buffer to measure the length
+                let mut buf = vec![];
+                msg.write(&mut buf, proto)?;
+                self.write_len(writer, &buf)
+            }
+            (FieldValue::SCALAR(scalar), WT_LEN) => {
+                let mut buf = vec![];
+                self.def.write(&mut buf, scalar)?;
+                self.write_len(writer, &buf)
+            }
+            (FieldValue::SCALAR(scalar), _) => self.write_value(writer, scalar), // known length
+        }
+    }
+
+    fn write_len(&self, writer: &mut dyn io::Write, data: &[u8]) -> io::Result<()> {
+        CommonFieldProto::write_padded_varint(writer, data.len() as i128, self.encoding.length_size as usize)?;
+        CommonFieldProto::write_len(writer, data)
+    }
+
+    // a varint keeps its bytes or its size in the file if the value still reads the same
+    pub(crate) fn write_value(&self, writer: &mut dyn io::Write, scalar: &ScalarValue) -> io::Result<()> {
+        let size = self.encoding.value_size as usize;
+        if let Some(varint) = self.encoding.varint {
+            if MessageData::varint_value(&self.def, varint).is_ok_and(|value| value == *scalar) {
+                return CommonFieldProto::write_padded_varint(writer, varint as i128, size);
+            }
+        }
+        if size == 0 || self.def.wire_type() != WT_VARINT {
+            return self.def.write(writer, scalar);
+        }
+        let mut buf = vec![];
+        self.def.write(&mut buf, scalar)?;
+        let mut sized = buf.clone();
+        CommonFieldProto::resize_varint(&mut sized, size);
+        let mut limit = size as u64;
+        let kept = self.def.read(&mut PbSliceReader::new(&sized, 0), &mut limit, 0).is_ok_and(|value| value == *scalar);
+        writer.write_all(if kept { &sized } else { &buf })
+    }
+
+    // values of a packed field in one LEN record, with the sizes of the first value
+    pub(crate) fn write_packed(writer: &mut dyn io::Write, values: &[FieldData]) -> io::Result<()> {
+        let mut buf = vec![];
+        for field in values {
+            if let FieldValue::SCALAR(scalar) = &field.value {
+                field.write_value(&mut buf, scalar)?;
+            }
+        }
+        let first = &values[0];
+        CommonFieldProto::write_padded_varint(writer, Tag::number(first.def.id(), WT_LEN) as i128, first.encoding.tag_size as usize)?;
+        first.write_len(writer, &buf)
     }
 }
 impl Debug for FieldData {
@@ -191,7 +392,7 @@ impl Debug for FieldValue {
                 write!(f, "scalar: {}", v)
             }
             FieldValue::MESSAGE(v) => {
-                write!(f, "submessage: {}", v)
+                write!(f, "submessage: {}", **v)
             }
         }
     }
@@ -199,7 +400,7 @@ impl Debug for FieldValue {
 
 impl ScalarValue {
     pub fn varint_size(value: i128) -> usize {
-        if value < 0 { todo!() }
+        if value < 0 { return 10; } // extended to 64 bits
         if value <= 0x00_0000_0000_0000_007f { return 1; }
         if value <= 0x00_0000_0000_0000_3fff { return 2; }
         if value <= 0x00_0000_0000_001f_ffff { return 3; }
@@ -218,11 +419,11 @@ impl ScalarValue {
             ScalarValue::UF32(_) | ScalarValue::SF32(_) | ScalarValue::F32(_) => 4,
             ScalarValue::UF64(_) | ScalarValue::SF64(_) | ScalarValue::F64(_) => 8,
             ScalarValue::I32(v) => Self::varint_size(*v as i128),
-            ScalarValue::S32(v) => Self::varint_size((*v as i128) << 1),
+            ScalarValue::S32(v) => Self::varint_size(((*v << 1) ^ (*v >> 31)) as u32 as i128),
             ScalarValue::U32(v) => Self::varint_size(*v as i128),
             ScalarValue::U64(v) => Self::varint_size(*v as i128),
             ScalarValue::I64(v) => Self::varint_size(*v as i128),
-            ScalarValue::S64(v) => Self::varint_size((*v as i128) << 1),
+            ScalarValue::S64(v) => Self::varint_size(((*v << 1) ^ (*v >> 63)) as u64 as i128),
             ScalarValue::STR(v) => v.as_bytes().len(),
             ScalarValue::BYTES(v) => v.len(),
             ScalarValue::UNKNOWN(tag, bytes) => Self::varint_size(tag.first_number as i128) + bytes.len(),
@@ -230,6 +431,19 @@ impl ScalarValue {
             ScalarValue::DELETED => 0,
         }
     }
+    // the number written as a varint
+    pub fn varint(&self) -> Option<u64> {
+        match self {
+            ScalarValue::BOOL(v) => Some(*v as u64),
+            ScalarValue::I32(v) | ScalarValue::ENUM(v) => Some(*v as i64 as u64),
+            ScalarValue::S32(v) => Some(((*v << 1) ^ (*v >> 31)) as u32 as u64),
+            ScalarValue::U32(v) => Some(*v as u64),
+            ScalarValue::U64(v) => Some(*v),
+            ScalarValue::I64(v) => Some(*v as u64),
+            ScalarValue::S64(v) => Some(((*v << 1) ^ (*v >> 63)) as u64),
+            _ => None,
+        }
+    }
 }
 
 
@@ -278,47 +492,163 @@ impl Display for ScalarValue {
 
 
 impl<'proto> MessageData {
-    pub fn new(reader: &mut dyn PbReaderTrait, proto: &'proto ProtoData, def: MessageProtoPtr, limit: &mut u32) -> io::Result<Self> {
-        let mut flds = Vec::<(FieldProtoPtr, usize, FieldValue)>::new();
-        while *limit > 0 {
-            let mut tag = reader.read_tag(limit)?;
-            match def.get_field(tag.field_id()) {
-                Some(field_def) => { // read sumbessage field
-                    if field_def.is_message() {
-                        *limit -= tag.length;
-                        let submsg_def = proto.get_message_definition(&field_def.typename()).unwrap();
-                        flds.push((field_def, reader.pos(), FieldValue::MESSAGE(MessageData::new(reader, proto, submsg_def, &mut tag.length)?)));
+    pub fn new(reader: &mut dyn PbReaderTrait, proto: &'proto ProtoData, def: MessageProtoPtr, limit: &mut u64) -> io::Result<Self> {
+        Self::read(reader, &proto.unknown_field, def, limit, None, None)
+    }
+
+    // only the top level fields are read, submessages are parsed on first access;
+    // the source must not change while the data is used.
+    // The memory is not bounded: all top level fields are decoded here (a stream of many records takes as much
+    // as a parsed file), and a parsed submessage is kept until the data is dropped
+    pub fn new_lazy(source: DataSource, proto: &'proto ProtoData, def: MessageProtoPtr) -> io::Result<Self> {
+        let data = (*source).as_ref();
+        let mut limit = data.len() as u64;
+        Self::read(&mut PbSliceReader::new(data, 0), &proto.unknown_field, def, &mut limit, None, Some(&source))
+    }
+
+    // group is the field number of a group being read, the group ends with its end tag;
+    // submessages are not parsed if the source is provided (groups are always parsed)
+    pub(crate) fn read(reader: &mut dyn PbReaderTrait, unknown_field: &FieldProtoPtr, def: MessageProtoPtr, limit: &mut u64, group: Option<i32>, source: Option<&DataSource>) -> io::Result<Self> {
+        Self::read_sized(reader, unknown_field, def, limit, group, source).map(|(msg, _)| msg)
+    }
+
+    // also the size of the end tag of the group, as it is kept in Encoding
+    fn read_sized(reader: &mut dyn PbReaderTrait, unknown_field: &FieldProtoPtr, def: MessageProtoPtr, limit: &mut u64, group: Option<i32>, source: Option<&DataSource>) -> io::Result<(Self, u8)> {
+        let mut fields = Vec::new();
+        let mut end_tag_size = 0;
+        loop {
+            if *limit == 0 {
+                if group.is_some() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "not completed group")); }
+                break;
+            }
+            let start = reader.pos();
+            if !Self::read_field(reader, unknown_field, &def, limit, group, source, &mut fields)? {
+                let shortest = ScalarValue::varint_size(Tag::number(group.unwrap_or_default(), WT_EGROUP) as i128);
+                end_tag_size = kept_size(reader.pos() - start, shortest);
+                break;
+            }
+        }
+
+        // duplicated non-repeated fields and oneof members are kept, so the file is saved as it was read;
+        // the view shows the last one and marks it (see view::replaced)
+        Ok((MessageData { fields, def }, end_tag_size))
+    }
+
+    // one field, or all values of a packed field; false at the end of the group
+    pub fn read_field(reader: &mut dyn PbReaderTrait, unknown_field: &FieldProtoPtr, def: &MessageProtoPtr, limit: &mut u64, group: Option<i32>, source: Option<&DataSource>, flds: &mut Vec<FieldData>) -> io::Result<bool> {
+        let start = reader.pos();
+        let (mut tag, tag_size) = reader.read_sized_tag(limit)?;
+        if tag.wire_type() == WT_EGROUP {
+            if group == Some(tag.field_id()) { return Ok(false); }
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected end of group {}", tag.field_id())));
+        }
+        let encoding = Encoding {
+            tag_size: kept_size(tag_size, ScalarValue::varint_size(tag.first_number as i128)),
+            length_size: if tag.wire_type() == WT_LEN { kept_size(reader.pos() - start - tag_size, ScalarValue::varint_size(tag.length as i128)) } else { 0 },
+            ..Default::default()
+        };
+        match def.get_field(tag.field_id()) {
+            Some(field_def) if Self::expected_wire_type(&field_def, &tag) => { // read sumbessage field
+                if field_def.is_message() {
+                    let submsg_def = field_def.message_proto().unwrap();
+                    let pos = reader.pos();
+                    let mut end_tag_size = 0;
+                    let msg = if tag.wire_type() == WT_SGROUP {
+                        let (group, size) = MessageData::read_sized(reader, unknown_field, submsg_def, limit, Some(tag.field_id()), source)?;
+                        end_tag_size = size;
+                        group.into()
+                    } else if let Some(source) = source {
+                        reader.skip(tag.length, limit)?;
+                        let lazy = LazyMessage { source: source.clone(), def: submsg_def, unknown_field: unknown_field.clone(), start: pos, length: tag.length };
+                        SubMessage { data: OnceCell::new(), lazy: Some(lazy) }
                     } else {
-                        if !field_def.repeated() {
-                            flds.push((field_def.clone(), reader.pos(), FieldValue::SCALAR(field_def.read(reader, limit, tag.length)?)));
-                        } else {
-                            if tag.auto_length() || field_def.wire_type() == WT_LEN { // not packable
-                                flds.push((field_def.clone(), reader.pos(), FieldValue::SCALAR(field_def.read(reader, limit, tag.length)?)));
-                            } else {
-                                while *limit > 0 {
-                                    flds.push((field_def.clone(), reader.pos(), FieldValue::SCALAR(field_def.read(reader, limit, tag.length)?)));
-                                }
-                            }
+                        if tag.length > *limit {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read data out of limit"));
                         }
+                        *limit -= tag.length;
+                        MessageData::read(reader, unknown_field, submsg_def, &mut tag.length, None, None)?.into()
+                    };
+                    flds.push(FieldData { def: field_def, pos, value: FieldValue::MESSAGE(msg), encoding: Encoding { end_tag_size, ..encoding } });
+                } else if tag.wire_type() != WT_LEN || !field_def.packable() {
+                    let packing = if field_def.packable() { Packing::No } else { Packing::Default };
+                    let mut field = Self::read_scalar(reader, &field_def, limit, tag.length, Encoding { packing, ..encoding })?;
+                    if let FieldValue::SCALAR(ScalarValue::UNKNOWN(unknown_tag, _)) = &mut field.value { // a string with invalid UTF-8
+                        *unknown_tag = tag;
+                        field.def = unknown_field.clone();
+                    }
+                    flds.push(field);
+                } else {
+                    // values of a packed field up to the end of the record
+                    if tag.length > *limit {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read data out of limit"));
+                    }
+                    *limit -= tag.length;
+                    if tag.length == 0 { // kept as an unknown field, so the record is saved
+                        let pos = reader.pos();
+                        flds.push(FieldData { def: unknown_field.clone(), pos, value: FieldValue::SCALAR(ScalarValue::UNKNOWN(tag.clone(), vec![])), encoding });
+                    }
+                    let value_length = match field_def.wire_type() { WT_I32 => 4, WT_I64 => 8, _ => 0 };
+                    let mut packing = Packing::First;
+                    while tag.length > 0 {
+                        flds.push(Self::read_scalar(reader, &field_def, &mut tag.length, value_length, Encoding { packing, ..encoding })?);
+                        packing = Packing::Next;
                     }
-                }
-                None => { // field id not found in the message definition
-                    flds.push((proto.unknown_field.clone(), reader.pos(), FieldValue::SCALAR(UnknownFieldDefinition::read_unknown(reader, limit, tag)?)));
                 }
             }
+            _ => { // field id not found in the message definition, or the data of another type
+                let pos = reader.pos();
+                let value = UnknownFieldDefinition::read_unknown(reader, limit, tag)?;
+                let value_size = match &value {
+                    ScalarValue::UNKNOWN(tag, bytes) if tag.wire_type() == WT_VARINT => {
+                        let shortest = ScalarValue::varint_size(bytes.iter().rev().fold(0, |acc, b| (acc << 8) | *b as i128));
+                        kept_size(reader.pos() - pos, shortest)
+                    }
+                    _ => 0,
+                };
+                flds.push(FieldData { def: unknown_field.clone(), pos, value: FieldValue::SCALAR(value), encoding: Encoding { value_size, ..encoding } });
+            }
         }
+        Ok(true)
+    }
 
-        // remove duplicated fields
-        // if an oneof or non-repeated field duplicated we should not remove old values,
-        // instead we should save it and show the errors to the user
-        //let to_delete = Self::find_duplicated_fields(&mut flds);
-
-        let fields = flds.into_iter().enumerate().
-            // TODO filter(|m| !to_delete.contains(&m.0)).
-            map(|m| FieldData { def: m.1.0, pos: m.1.1, value: m.1.2 }).
-            collect();
+    // other data is kept as an unknown field, as protobuf libraries do
+    fn expected_wire_type(def: &FieldProtoPtr, tag: &Tag) -> bool {
+        match tag.wire_type() {
+            WT_LEN | WT_SGROUP if def.is_message() => true,
+            _ if def.is_message() => false,
+            WT_LEN if def.packable() => true,
+            wire_type => wire_type == def.wire_type(),
+        }
+    }
 
-        Ok(MessageData { fields, def })
+    // the size of a varint value is kept if it is not the shortest one,
+    // and the varint itself if the value is written otherwise
+    fn read_scalar(reader: &mut dyn PbReaderTrait, def: &FieldProtoPtr, limit: &mut u64, length: u64, encoding: Encoding) -> io::Result<FieldData> {
+        let pos = reader.pos();
+        if def.wire_type() != WT_VARINT {
+            let value = def.read(reader, limit, length)?;
+            return Ok(FieldData { def: def.clone(), pos, value: FieldValue::SCALAR(value), encoding });
+        }
+        let read = reader.read_varint(limit)? as u64;
+        let value = Self::varint_value(def, read)?;
+        let varint = Some(read).filter(|read| value.varint() != Some(*read));
+        let size = reader.pos() - pos;
+        let value_size = if varint.is_some() { size as u8 } else { kept_size(size, value.len()) };
+        Ok(FieldData { def: def.clone(), pos, value: FieldValue::SCALAR(value), encoding: Encoding { value_size, varint, ..encoding } })
+    }
+
+    // the value of a varint field from the number read
+    fn varint_value(def: &FieldProtoPtr, mut varint: u64) -> io::Result<ScalarValue> {
+        let mut bytes = [0u8; 10];
+        let mut size = 0;
+        loop {
+            bytes[size] = varint as u8 & 0x7f;
+            size += 1;
+            varint >>= 7;
+            if varint == 0 { break; }
+            bytes[size - 1] |= 0x80;
+        }
+        def.read(&mut PbSliceReader::new(&bytes[..size], 0), &mut (size as u64), 0)
     }
 
     //fn find_duplicated_fields(fields: &Vec::<(&dyn FieldDefinition, usize, FieldValue)>) -> HashSet<usize> {
@@ -361,40 +691,84 @@ impl<'proto> MessageData {
     //    ignore.into_iter().collect()
     //}
 
-    // data written as it was read
+    // data written as it was read; values of a packed field read in one record are written in one record
     pub fn write(&self, writer: &mut dyn io::Write, proto: &'proto ProtoData, _def: MessageProtoPtr) -> io::Result<()> {
-        for field in &self.fields {
-            if let FieldValue::SCALAR(ScalarValue::UNKNOWN(tag, data)) = &field.value {
-                if let FieldValue::SCALAR(scalar) = &field.value {
-                    field.def.write(writer, scalar)?;
-                }
-            } else {
-                // write field index and wire type
-                CommonFieldProto::write_varint(writer, ((field.def.id() << 3) | field.def.wire_type() as i32) as i128)?;
-                if field.def.wire_type() != WT_LEN {
-                    if let FieldValue::SCALAR(scalar) = &field.value { // write scalar with known length
-                        field.def.write(writer, scalar)?;
-                    }
-                } else {
-                    // variable length data. First write to the temporary buffer to measure the length
-                    let mut buf = vec![];
-                    match &field.value {
-                        FieldValue::MESSAGE(msg) => { msg.write(&mut buf, proto, msg.def.clone())? }
-                        FieldValue::SCALAR(scalar) => { field.def.write(&mut buf, scalar)? }
-                    }
-                    CommonFieldProto::write_varint(writer, buf.len() as i128)?;
-                    CommonFieldProto::write_len(writer, &buf)?;
-                }
+        for (range, packed) in self.records() {
+            match &self.fields[range.clone()] {
+                [field] if !packed => field.write(writer, proto)?,
+                values => FieldData::write_packed(writer, values)?,
             }
         }
         Ok(())
     }
 
+    // the range in fields of the packed record of the field at pos
+    pub fn packed_record(&self, pos: usize) -> Option<std::ops::Range<usize>> {
+        self.records().into_iter().find(|(range, packed)| *packed && range.contains(&pos)).map(|(range, _)| range)
+    }
+
+    // the ranges of the fields written in one record, true for a packed record; damaged data is not written
+    fn records(&self) -> Vec<(std::ops::Range<usize>, bool)> {
+        let read_packed: HashMap<i32, bool> = self.fields.iter().rev().
+            filter(|field| field.encoding.packing != Packing::Default).
+            map(|field| (field.def.id(), field.encoding.packing != Packing::No)).
+            collect();
+        let packed = |field: &FieldData| match field.encoding.packing {
+            Packing::No => false,
+            Packing::First | Packing::Next => true,
+            // a new value is written as the values read before, or as the proto file says
+            Packing::Default => read_packed.get(&field.def.id()).copied().unwrap_or_else(|| field.def.packed()),
+        };
+        let mut res = vec![];
+        let mut index = 0;
+        while index < self.fields.len() {
+            let field = &self.fields[index];
+            let start = index;
+            index += 1;
+            if field.def.damaged() { continue; } // dropped, so the saved file is valid
+            if packed(field) {
+                while self.fields.get(index).is_some_and(|next| next.def.id() == field.def.id() && next.encoding.packing != Packing::First && packed(next)) {
+                    index += 1;
+                }
+            }
+            res.push((start..index, packed(field)));
+        }
+        res
+    }
+
+    // paths of required fields without data, in the message and all its submessages
+    pub fn missing_required(&self) -> Vec<FieldPath> {
+        let mut res = vec![];
+        self.find_missing_required(&FieldPath::new(), &mut res);
+        res
+    }
+
+    fn find_missing_required(&self, prefix: &FieldPath, res: &mut Vec<FieldPath>) {
+        for def in self.def.fields.iter().filter(|f| f.required()) {
+            if !self.fields.iter().any(|f| f.id() == def.id() && !matches!(f.value, FieldValue::SCALAR(ScalarValue::DELETED))) {
+                res.push(prefix.add((def.id(), 0).into()));
+            }
+        }
+        let mut indexes = HashMap::new();
+        for field in &self.fields {
+            let index = indexes.entry(field.id()).or_insert(0);
+            if let FieldValue::MESSAGE(msg) = &field.value {
+                msg.find_missing_required(&prefix.add((field.id(), *index).into()), res);
+            }
+            *index += 1;
+        }
+    }
+
     pub fn get_field<'x, 'y: 'x>(&'y self, path: &[FieldPos]) -> Option<&'x FieldData> {
+        let (msg, pos) = self.get_field_place(path)?;
+        Some(&msg.fields[pos])
+    }
+    // the message with the field and the position of the field in its fields
+    pub fn get_field_place<'x, 'y: 'x>(&'y self, path: &[FieldPos]) -> Option<(&'x MessageData, usize)> {
         if let Some((first, others)) = path.split_last() {
             let msg = self.get_submessage(others)?;
             let pos = msg.get_field_pos(first.id, first.index)?;
-            Some(&msg.fields[pos])
+            Some((msg, pos))
         } else { None }
     }
     pub fn get_field_mut<'x, 'y: 'x>(&'y mut self, path: &[FieldPos]) -> Option<&'x mut FieldData> {
@@ -417,10 +791,20 @@ impl<'proto> MessageData {
     fn add_field_private<'x, 'y: 'x>(&'y mut self, id: i32, index: usize) -> Option<&'x mut FieldData> {
         if let Some(def) = self.def.fields.iter().find(|f| f.id() == id) {
             let insert_pos = if let Some(pos) = self.get_field_pos(id, index) { pos } else { self.fields.len() };
-            self.fields.insert(insert_pos, FieldData { def: def.clone(), pos: usize::MAX, value: def.default() });
+            self.fields.insert(insert_pos, FieldData::new(def.clone(), def.default()));
             Some(&mut self.fields[insert_pos])
         } else { None }
     }
+    // move a repeated value to another index of the same field
+    pub fn move_field(&mut self, path: &[FieldPos], to: usize) -> Option<()> {
+        let (last, parent) = path.split_last()?;
+        let msg = self.get_submessage_mut(parent)?;
+        let to_pos = msg.get_field_pos(last.id, to)?;
+        let from_pos = msg.get_field_pos(last.id, last.index)?;
+        let field = msg.fields.remove(from_pos);
+        msg.fields.insert(to_pos, field);
+        Some(())
+    }
     fn delete_field_private(&mut self, id: i32, index: usize) -> Option<FieldValue> {
         if let Some(del_pos) = self.get_field_pos(id, index) {
             Some(self.fields.remove(del_pos).value)
@@ -433,6 +817,7 @@ impl<'proto> MessageData {
             let split = path.split_first().unwrap();
             if let Some(pos) = self.get_field_pos(split.0.id, split.0.index) {
                 if let FieldValue::MESSAGE(msg) = &mut self.fields[pos].value {
+                    if msg.damaged() { return None; }
                     return if split.1.is_empty() {
                         Some(msg)
                     } else {
@@ -477,7 +862,10 @@ impl<'proto> MessageData {
         let mut p = path.0.clone();
         if let Some(last_path_item) = p.pop() {
             if let Some(parent) = self.get_submessage(&p.as_slice()) {
-                return parent.def.get_field(last_path_item.id);
+                // damaged data and the decoded value of Any have their own definitions
+                let own = parent.get_field(&[last_path_item.clone()]).map(|f| f.def.clone());
+                if let Some(def) = own.as_ref().filter(|def| def.any_value()) { return Some(def.clone()); }
+                return parent.def.get_field(last_path_item.id).or(own);
             }
         }
         None
@@ -552,6 +940,11 @@ impl<'proto> MessageData {
 
             res.push((FieldPos { id: fd.id(), index: 0 }, amount));
         }
+        // damaged data of the repair mode is shown after the fields
+        let damaged = self.fields.iter().filter(|f| f.def.damaged()).count();
+        if damaged > 0 {
+            res.push((FieldPos { id: DamagedFieldDefinition::ID, index: 0 }, damaged));
+        }
         return res;
     }
 
@@ -588,6 +981,30 @@ impl<'proto> MessageData {
             ChangeType::Delete => {
                 change.action = ChangeType::Insert(self.delete_field(&change.path.0)?)
             }
+
+            ChangeType::Move(to) => {
+                let from = change.path.0.last()?.index;
+                self.move_field(&change.path.0, *to)?;
+                change.path = change.path.with_last_index(*to);
+                *to = from;
+            }
+
+            ChangeType::SwitchOneof(fields) => {
+                let (last, parent) = change.path.0.split_last()?;
+                let msg = self.get_submessage_mut(parent)?;
+                let oneof = msg.def.get_field(last.id)?.oneof_name().clone()?;
+                let mut removed = vec![];
+                for pos in (0..msg.fields.len()).rev() {
+                    if msg.fields[pos].def.oneof_name().as_ref() == Some(&oneof) {
+                        removed.push((pos, msg.fields.remove(pos)));
+                    }
+                }
+                removed.reverse(); // the positions before the removal, inserted in this order they restore the fields
+                for (pos, field) in mem::take(fields) {
+                    msg.fields.insert(pos.min(msg.fields.len()), field);
+                }
+                *fields = removed;
+            }
         }
         Some(())
     }
@@ -687,16 +1104,16 @@ mod scalars {
     struct TestData {
         value: i128,
         bytes: Vec<u8>,
-        limit: u32,
+        limit: u64,
     }
 
     fn ok_data() -> [TestData; 7] {
         [
-            TestData { value: 0, bytes: vec![0], limit: u32::MAX },
-            TestData { value: 0x55, bytes: vec![0x55], limit: u32::MAX },
-            TestData { value: 0x5555, bytes: vec![0xd5, 0xaa, 0x01], limit: u32::MAX },
-            TestData { value: 150, bytes: vec![0x96, 0x01], limit: u32::MAX },
-            TestData { value: 0x7fffffffffffffff, bytes: vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f], limit: u32::MAX },
+            TestData { value: 0, bytes: vec![0], limit: u64::MAX },
+            TestData { value: 0x55, bytes: vec![0x55], limit: u64::MAX },
+            TestData { value: 0x5555, bytes: vec![0xd5, 0xaa, 0x01], limit: u64::MAX },
+            TestData { value: 150, bytes: vec![0x96, 0x01], limit: u64::MAX },
+            TestData { value: 0x7fffffffffffffff, bytes: vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f], limit: u64::MAX },
             TestData { value: 0x55, bytes: vec![0x55], limit: 1 },
             TestData { value: 0x5555, bytes: vec![0xd5, 0xaa, 0x01], limit: 3 },
         ]
@@ -704,8 +1121,8 @@ mod scalars {
 
     fn wrong_data() -> [TestData; 3] {
         [
-            TestData { value: 0, bytes: vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0], limit: u32::MAX },
-            TestData { value: 0, bytes: vec![0xff; 3], limit: u32::MAX },
+            TestData { value: 0, bytes: vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0], limit: u64::MAX },
+            TestData { value: 0, bytes: vec![0xff; 3], limit: u64::MAX },
             TestData { value: 0, bytes: vec![0x96, 0x01], limit: 1 },
         ]
     }
@@ -729,6 +1146,13 @@ mod scalars {
         }
     }
     #[test]
+    fn read_long_length() {
+        let bytes = [0x0a, 0x80, 0x80, 0x80, 0x80, 0x10]; // length 4 GiB
+        let mut limit = u64::MAX;
+        let mut read = PbReader::new(bytes.as_slice());
+        assert_eq!(read.read_tag(&mut limit).unwrap().length, 1 << 32);
+    }
+    #[test]
     fn write_varint() {
         for data in &ok_data() {
             let mut buf = vec![];
@@ -737,14 +1161,14 @@ mod scalars {
         }
     }
 
-    fn wr_scalar_fn(field: Box<dyn FieldProto>, data: ScalarValue, field_len: u32) {
+    fn wr_scalar_fn(field: Box<dyn FieldProto>, data: ScalarValue, field_len: u64) {
         let mut buf = vec![];
         assert!(field.write(&mut buf, &data).is_ok());
 
-        if field_len != 0 { assert_eq!(field_len, buf.len() as u32); }
+        if field_len != 0 { assert_eq!(field_len, buf.len() as u64); }
         if field.wire_type() == WT_VARINT { assert_eq!(field_len, 0) }
 
-        let mut counter = buf.len() as u32;
+        let mut counter = buf.len() as u64;
         let mut io_read = buf.as_slice();
         let mut read = PbReader::new(&mut io_read);
         if let Ok(data2) = field.read(&mut read, &mut counter, field_len) {
@@ -811,15 +1235,15 @@ mod scalars {
     #[test]
     fn write_and_read_bytes_fields() {
         for value in [vec![], vec![0, 0, 0], vec![0xff; 300]] {
-            let field_len = value.len() as u32;
-            wr_scalar_fn(Box::new(BytesFieldDefinition(CommonFieldProto::default())), ScalarValue::BYTES(value), field_len as u32);
+            let field_len = value.len() as u64;
+            wr_scalar_fn(Box::new(BytesFieldDefinition(CommonFieldProto::default())), ScalarValue::BYTES(value), field_len);
         }
     }
     #[test]
     fn write_and_read_string_fields() {
         for value in ["".to_string(), "abc".to_string(), "АВС".to_string(), String::new()] {
-            let field_len = value.as_bytes().len() as u32;
-            wr_scalar_fn(Box::new(StringFieldDefinition(CommonFieldProto::default())), ScalarValue::STR(value), field_len as u32);
+            let field_len = value.as_bytes().len() as u64;
+            wr_scalar_fn(Box::new(StringFieldDefinition(CommonFieldProto::default())), ScalarValue::STR(value), field_len);
         }
     }
 }
@@ -828,12 +1252,14 @@ mod scalars {
 #[cfg(test)]
 mod read_message {
     use std::io;
+    use std::rc::Rc;
     use std::io::Write;
     use crate::{App, TOP_LINE};
     use crate::proto::ProtoData;
     use crate::typedefs::PbReader;
     use crate::view::FieldOrder;
-    use crate::wire::{FieldPos, FieldValue, MessageData};
+    use crate::trz::{Change, ChangeType};
+    use crate::wire::{Encoding, FieldData, FieldPos, FieldValue, MessageData, ScalarValue, Tag};
     use crate::wire::ScalarValue::{I32, SF32, STR};
 
     fn all_scalar_proto() -> &'static str {
@@ -865,7 +1291,7 @@ bytes f_bytes = 60;
             [0x50, 0x0B, 0x58, 0x0C, 0x60, 0x1A, 0x6D, 0x0E, 0x00, 0x00, 0x00, 0x75, 0x0F, 0x00, 0x00, 0x00, 0xA0, 0x01, 0x10, 0xA8, 0x01, 0x11, 0xB0, 0x01, 0x24, 0xB9, 0x01, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC1, 0x01, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF5, 0x01, 0x00, 0x00, 0xA8, 0x41, 0xF9, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x36, 0x40, 0xC0, 0x02, 0x01, 0x92, 0x03, 0x06, 0x73, 0x74, 0x72, 0x69, 0x6E, 0x67, 0xE2, 0x03, 0x0A, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55];
 
         let proto = ProtoData::new(all_scalar_proto()).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
 
@@ -919,7 +1345,7 @@ bytes f_bytes = 60;
             0xC0, 0x02, 0x01]; // bool f_bool(#40) = true
 
         let proto = ProtoData::new(all_scalar_proto()).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
@@ -953,7 +1379,7 @@ bytes f_bytes = 60;
     fn scalars_min_values() { // all the numbers in minimal values
         let binary_input = [
             0x50, 0x80, 0x80, 0x80, 0x80, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,       // int32#11
-            0x60, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,       // sint32#13
+            0x60, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F,                                     // sint32#13
             0x75, 0x00, 0x00, 0x00, 0x80,                                           // sfixed32#15
             0xA0, 0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01, // int64#20
             0xB0, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // sint64#22
@@ -961,17 +1387,17 @@ bytes f_bytes = 60;
             0xF5, 0x01, 0xFF, 0xFF, 0x7F, 0xFF,                                     // float#30
             0xF9, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xEF, 0xFF];            // double#31
         let proto = ProtoData::new(all_scalar_proto()).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
 
         let expected = r#"message AllScalars {
   f_i32 = -2147483648
-  f_s32 = -2147483647
+  f_s32 = -2147483648
   f_fs32 = -2147483648
   f_i64 = -9223372036854775808
-  f_s64 = -9223372036854775807
+  f_s64 = -9223372036854775808
   f_fi64 = -9223372036854775808
   f_f32 = -3.4028235e38
   f_f64 = -1.7976931348623157e308
@@ -979,17 +1405,16 @@ bytes f_bytes = 60;
 "#;
         assert_eq!(data.to_string(), expected);
 
-        // TODO test data is incorrect(?) (64 bits for 32 bits fields), need to compare with other pb implementations
-        // let mut output = Vec::new();
-        // msg.write(&mut output, &proto, &root_msg).unwrap();
-        // assert_eq!(output, binary_input);
+        let mut output = Vec::new();
+        data.write(&mut output, &proto, data.def.clone()).unwrap();
+        assert_eq!(output, binary_input);
     }
 
     #[test]
     fn scalars_duplicated() {
         let binary_input = [0x50, 0x01, 0x50, 0x0B];
         let proto = ProtoData::new(all_scalar_proto()).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1006,7 +1431,7 @@ bytes f_bytes = 60;
         let proto_str = r#"message Test5 {  repeated int32 f = 6;  }"#;
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1022,7 +1447,7 @@ bytes f_bytes = 60;
         let proto_str = r#"message Test5 {  repeated int32 f = 10;  }"#;
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1045,7 +1470,7 @@ bytes f_bytes = 60;
         let proto_str = "message StrRepeated {  repeated string s = 1; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1062,14 +1487,34 @@ bytes f_bytes = 60;
         let proto_str = r#"message StrTest {  repeated string s = 1; }"#;
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let h = MessageData::new(&mut read, &proto, root_msg, &mut limit);
         let msg = h.unwrap();
 
-        let expected = "message StrTest {\n  s = wrong unicode data\n}\n";
+        // the bytes are kept as an unknown field and saved unchanged
+        let expected = "message StrTest {\n  ??? = 1.1: ff\n}\n";
         assert_eq!(msg.to_string(), expected);
+        let mut output = vec![];
+        msg.write(&mut output, &proto, msg.def.clone()).unwrap();
+        assert_eq!(output, binary_input);
+    }
+
+    #[test]
+    fn wrong_wire_type() {
+        let binary_input = [0x08, 0x01, 0x12, 0x01, 0x41, 0x1D, 0x01, 0x00, 0x00, 0x00];
+        let proto_str = r#"message WireTest { string s = 1; int32 i = 2; M m = 3; } message M { int32 i = 1; }"#;
+
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.auto_detect_root_message().unwrap();
+        let msg = MessageData::new(&mut PbReader::new(binary_input.as_slice()), &proto, root_msg, &mut limit).unwrap();
+        assert_eq!(msg.fields.iter().map(|f| f.id()).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(msg.fields.iter().all(|f| matches!(f.value, FieldValue::SCALAR(ScalarValue::UNKNOWN(..)))));
+        let mut output = vec![];
+        msg.write(&mut output, &proto, msg.def.clone()).unwrap();
+        assert_eq!(output, binary_input);
     }
 
     #[test]
@@ -1078,7 +1523,7 @@ bytes f_bytes = 60;
         let proto_str = "message EmptyStr { string s = 1; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1090,7 +1535,7 @@ bytes f_bytes = 60;
         let binary_input = [0x12, 0x00];
         let proto_str = "message EmptyMsg { M2 m = 2; }\nmessage M2 { int32 f = 3; }";
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1102,7 +1547,7 @@ bytes f_bytes = 60;
         let binary_input = [];
         let proto_str = "message EmptyMsg { }";
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
@@ -1115,7 +1560,7 @@ bytes f_bytes = 60;
         let proto_str = "message BytesRepeated { repeated bytes b = 1; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let h = MessageData::new(&mut read, &proto, root_msg, &mut limit);
@@ -1142,7 +1587,7 @@ message Pet {
 ";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
@@ -1192,7 +1637,7 @@ message House {
         assert_eq!(root_msg.name, "House");
 
 
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
 
@@ -1223,7 +1668,7 @@ message House {
 
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
@@ -1250,7 +1695,7 @@ message House {
         let proto_str = "message TestMessage { float length = 100; oneof test_oneof { string name = 101; int32 number = 102; }}";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         println!("{:?}", proto);
@@ -1278,7 +1723,7 @@ message House {
         let proto_str = r#"message TestMessage { float length = 100; oneof test_oneof { string name = 101; int32 number = 102; }}"#;
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         let mut read = PbReader::new(binary_input.as_slice());
@@ -1287,6 +1732,179 @@ message House {
         assert_eq!("message TestMessage {\n  length = 1\n  name = abc\n  number = 100\n}\n", data.to_string());
     }
 
+    #[test]
+    fn switch_oneof() {
+        let binary_input = [
+            0xAA, 0x06, 0x03, 0x61, 0x62, 0x63, // string#101 = "abc";
+            0xA5, 0x06, 0x00, 0x00, 0x80, 0x3F, // float#100 = 1.0;
+            0xB0, 0x06, 0x64];                  // int32#102 = 100
+
+        let proto_str = r#"message TestMessage { float length = 100; oneof test_oneof { string name = 101; int32 number = 102; }}"#;
+
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.auto_detect_root_message().unwrap();
+
+        let mut read = PbReader::new(binary_input.as_slice());
+        let mut data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
+        let name = FieldData::new(root_msg.get_field(101).unwrap(), FieldValue::SCALAR(STR("x".to_string())));
+        let mut change = Change { path: [(101, 0)].into(), action: ChangeType::SwitchOneof(vec![(0, name)]) };
+        data.apply(&mut change).unwrap();
+        assert_eq!("message TestMessage {\n  name = x\n  length = 1\n}\n", data.to_string());
+        data.apply(&mut change).unwrap(); // undo restores both members at their places
+        assert_eq!("message TestMessage {\n  name = abc\n  length = 1\n  number = 100\n}\n", data.to_string());
+    }
+
+    #[test]
+    fn exact_round_trip() {
+        let binary_input = [
+            0x0A, 0x04, 0x81, 0x00, 0x96, 0x01, // packed a: 1 (in 2 bytes), 150
+            0x10, 0x05, 0x10, 0x06,             // b: 5, b: 6
+            0x1A, 0x82, 0x00, 0x68, 0x69,       // s: "hi", the length in 2 bytes
+            0xA8, 0x00, 0x01,                   // z: -1, the tag in 2 bytes
+            0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // c: -1 in 5 bytes
+            0x48, 0x80, 0x00,                   // unknown 9: 0 in 2 bytes
+            0x22, 0x02, 0x08, 0x07,             // sub { a: 7 } not packed
+            0x38, 0x02,                         // t: true written as 2
+            0x40, 0x80, 0x80, 0x80, 0x80, 0x10, // h: 0 with high bits set
+            0x0A, 0x00];                        // empty packed a
+        let proto_str = "syntax = \"proto3\";\nmessage M { repeated int32 a = 1; repeated int32 b = 2 [packed = false]; string s = 3; M sub = 4; sint32 z = 5; int32 c = 6; bool t = 7; int32 h = 8; }";
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let root_msg = proto.get_message_definition("M").unwrap();
+        let mut limit = binary_input.len() as u64;
+        let mut data = MessageData::new(&mut PbReader::new(binary_input.as_slice()), &proto, root_msg.clone(), &mut limit).unwrap();
+        assert_eq!(data.get_field(&[(5, 0).into()]).unwrap().value, FieldValue::SCALAR(ScalarValue::S32(-1)));
+        assert_eq!(data.get_field(&[(6, 0).into()]).unwrap().value, FieldValue::SCALAR(I32(-1)));
+
+        let written = |data: &MessageData| {
+            let mut output = Vec::new();
+            data.write(&mut output, &proto, root_msg.clone()).unwrap();
+            output
+        };
+        assert_eq!(data.get_field(&[(7, 0).into()]).unwrap().value, FieldValue::SCALAR(ScalarValue::BOOL(true)));
+        assert_eq!(data.get_field(&[(8, 0).into()]).unwrap().value, FieldValue::SCALAR(I32(0)));
+        assert_eq!(written(&data), binary_input);
+
+        // a changed value is written as short as it can be in its place
+        data.get_field_mut(&[(6, 0).into()]).unwrap().value = FieldValue::SCALAR(I32(3));
+        data.get_field_mut(&[(3, 0).into()]).unwrap().value = FieldValue::SCALAR(STR("hello".to_string()));
+        assert_eq!(written(&data)[10..24], [0x1A, 0x85, 0x00, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0xA8, 0x00, 0x01, 0x30, 0x83, 0x80]);
+        data.get_field_mut(&[(7, 0).into()]).unwrap().value = FieldValue::SCALAR(ScalarValue::BOOL(false));
+        data.get_field_mut(&[(8, 0).into()]).unwrap().value = FieldValue::SCALAR(I32(1));
+        assert_eq!(written(&data)[34..], [0x38, 0x00, 0x40, 0x81, 0x80, 0x80, 0x80, 0x00, 0x0A, 0x00]);
+
+        // new values are written as the values read before, new fields as the proto file says
+        let mut data = MessageData::new(&mut PbReader::new(&binary_input[..10]), &proto, root_msg.clone(), &mut (10u64)).unwrap();
+        data.get_field_mut(&[(1, 0).into()]).unwrap().encoding = Encoding::default();
+        data.add_field(&[(1, 2).into()]).unwrap().value = FieldValue::SCALAR(I32(2));
+        data.add_field(&[(2, 2).into()]).unwrap().value = FieldValue::SCALAR(I32(3));
+        assert_eq!(written(&data), [0x0A, 0x03, 0x01, 0x96, 0x01, 0x10, 0x05, 0x10, 0x06, 0x0A, 0x01, 0x02, 0x10, 0x03]);
+        let new = crate::text_format::text_to_message("a: 1 a: 2 b: 3 b: 4", root_msg.clone()).unwrap();
+        assert_eq!(written(&new), [0x0A, 0x02, 0x01, 0x02, 0x10, 0x03, 0x10, 0x04]);
+    }
+
+    #[test]
+    fn big_field_numbers() {
+        let binary_input = [
+            0xF8, 0xFF, 0xFF, 0xFF, 0x0F, 0x01,  // big: 1, field 536870911
+            0xF0, 0xFF, 0xFF, 0xFF, 0x0F, 0x02]; // unknown 536870910: 2
+        let proto = ProtoData::new("message M { int32 big = 536870911; }").unwrap().finalize().unwrap();
+        let root_msg = proto.get_message_definition("M").unwrap();
+        let mut limit = binary_input.len() as u64;
+        let data = MessageData::new(&mut PbReader::new(binary_input.as_slice()), &proto, root_msg.clone(), &mut limit).unwrap();
+        assert_eq!(data.fields[0].value, FieldValue::SCALAR(I32(1)));
+        assert_eq!(data.fields[1].id(), 536870910);
+        let mut output = Vec::new();
+        data.write(&mut output, &proto, root_msg.clone()).unwrap();
+        assert_eq!(output, binary_input);
+        assert_eq!(data.fields[0].len(), 6);
+    }
+
+    #[test]
+    fn groups() {
+        let binary_input = [
+            0x08, 0x05,              // id: 5
+            0x13, 0x1a, 0x01, 0x61,  // start group 2, url: "a"
+            0x14,                    // end group 2
+            0x13, 0x14,              // empty group 2
+            0x13, 0x1a, 0x01, 0x62,  // group 2, url: "b"
+            0x94, 0x00,              // end group 2 in 2 bytes
+            0x2b, 0x30, 0x01, 0x2c]; // unknown group 5 with 6: 1
+
+        let proto_str = r#"syntax = "proto2";
+message GroupTest {
+  optional int32 id = 1;
+  repeated group Item = 2 {
+    required string url = 3;
+  }
+  required int32 count = 4;
+}"#;
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let mut limit = binary_input.len() as u64;
+        let root_msg = proto.get_message_definition("GroupTest").unwrap();
+        let mut read = PbReader::new(binary_input.as_slice());
+        let data = MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).unwrap();
+
+        assert_eq!(data.get_field(&[(2, 0).into(), (3, 0).into()]).unwrap().value, FieldValue::SCALAR(STR("a".to_string())));
+        assert!(data.get_submessage(&[(2, 1).into()]).unwrap().fields.is_empty());
+        assert_eq!(data.get_field(&[(5, 0).into()]).unwrap().value, FieldValue::SCALAR(ScalarValue::UNKNOWN(Tag { first_number: 0x2b, length: 0 }, vec![0x30, 0x01, 0x2c])));
+
+        let missing: Vec<String> = data.missing_required().iter().map(|p| crate::path::format_path(&data, p)).collect();
+        assert_eq!(missing, ["count", "item[1].url"]);
+
+        let mut output = Vec::new();
+        data.write(&mut output, &proto, root_msg.clone()).unwrap();
+        assert_eq!(output, binary_input);
+
+        for wrong_input in [&[0x13, 0x1a, 0x01, 0x61][..], &[0x14], &[0x2b, 0x30, 0x01]] {
+            let mut limit = wrong_input.len() as u64;
+            let mut read = PbReader::new(wrong_input);
+            assert!(MessageData::new(&mut read, &proto, root_msg.clone(), &mut limit).is_err());
+        }
+    }
+
+    #[test]
+    fn lazy() {
+        let binary_input = [
+            0x0a, 0x07,              // item {
+            0x08, 0x01,              //   id: 1
+            0x12, 0x03, 0x08, 0x02,  //   child { id: 2
+            0x10,                    //   wrong tag in child, never parsed
+            0x0a, 0x02, 0x08, 0x03,  // item { id: 3 }
+            0x0a, 0x01, 0xff];       // item with wrong data
+
+        let proto_str = "message Root { repeated Item items = 1; }\nmessage Item { int32 id = 1; Item child = 2; }";
+        let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
+        let root_msg = proto.get_message_definition("Root").unwrap();
+        let mut data = MessageData::new_lazy(Rc::new(binary_input.to_vec()), &proto, root_msg.clone()).unwrap();
+        assert!(crate::repair::find_damaged(&data).is_empty());
+        let FieldValue::MESSAGE(item) = &data.fields[0].value else { panic!() };
+        assert!(!item.is_parsed());
+        assert_eq!(item.len(), 7);
+
+        let mut output = Vec::new();
+        data.write(&mut output, &proto, root_msg.clone()).unwrap();
+        assert_eq!(output, binary_input); // not parsed data is copied
+
+        assert_eq!(data.get_field(&[(1, 0).into(), (1, 0).into()]).unwrap().value, FieldValue::SCALAR(I32(1)));
+        let FieldValue::MESSAGE(item) = &data.fields[0].value else { panic!() };
+        assert!(item.is_parsed());
+        let FieldValue::MESSAGE(child) = &item.fields[1].value else { panic!() };
+        assert!(!child.is_parsed());
+        // wrong data is a damaged field, it is not changed and saved as it is in the file
+        assert!(data.get_field(&[(1, 2).into(), (crate::typedefs::DamagedFieldDefinition::ID, 0).into()]).unwrap().def.damaged());
+        assert!(data.get_field_mut(&[(1, 2).into(), (1, 0).into()]).is_none());
+        assert!(data.add_field(&[(1, 2).into(), (1, 0).into()]).is_none());
+        assert_eq!(crate::repair::find_damaged(&data).len(), 1);
+
+        data.get_field_mut(&[(1, 1).into(), (1, 0).into()]).unwrap().value = FieldValue::SCALAR(I32(4));
+        let mut output = Vec::new();
+        data.write(&mut output, &proto, root_msg.clone()).unwrap();
+        assert_eq!(output[9..13], [0x0a, 0x02, 0x08, 0x04]);
+        assert_eq!(output[..9], binary_input[..9]);
+        assert_eq!(output[13..], binary_input[13..]);
+    }
+
     #[test]
     fn map() {
         let binary_input = [
@@ -1307,11 +1925,11 @@ message House {
 
         assert!(root_msg.fields[0].is_message());
 
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let mut read = PbReader::new(binary_input.as_slice());
         let data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();
 
-        let expected = "message TestMessage {\n  dict = message int32,string {\n  @1 = 1\n  @2 = foo\n}\n\n  dict = message int32,string {\n  @1 = 2\n  @2 = bar\n}\n\n}\n";
+        let expected = "message TestMessage {\n  dict = message TestMessage.int32,string {\n  @1 = 1\n  @2 = foo\n}\n\n  dict = message TestMessage.int32,string {\n  @1 = 2\n  @2 = bar\n}\n\n}\n";
         assert_eq!(data.to_string(), expected);
         assert!(data.get_field(&[(1, 0).into(), (1, 0).into()]).is_some());
         assert!(data.get_field(&[(1, 0).into(), (2, 0).into()]).is_some());
@@ -1323,7 +1941,7 @@ message House {
         let proto_str = "message M1 { repeated int32 f1 = 1; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         let mut read = PbReader::new(binary_input.as_slice());
@@ -1348,7 +1966,7 @@ message House {
         let proto_str = "message M1 { repeated int32 f1 = 1; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         let mut read = PbReader::new(binary_input.as_slice());
@@ -1380,7 +1998,7 @@ message House {
         let proto_str = "message M1 { int32 f1 = 1; M2 m2 = 2; }\nmessage M2 { int32 f2 = 3; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         let mut read = PbReader::new(binary_input.as_slice());
@@ -1400,7 +2018,7 @@ message House {
         let proto_str = "message M1 { int32 f1 = 1; M2 m2 = 2; }\nmessage M2 { int32 f2 = 3; }";
 
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
 
         let mut read = PbReader::new(binary_input.as_slice());
@@ -1430,7 +2048,7 @@ message House {
 
         let proto_str = "message M1 { repeated int32 a3 = 3; int32 c1 = 1; int32 b2 = 2; int32 d4 = 4; }";
         let proto = ProtoData::new(proto_str).unwrap().finalize().unwrap();
-        let mut limit = binary_input.len() as u32;
+        let mut limit = binary_input.len() as u64;
         let root_msg = proto.auto_detect_root_message().unwrap();
         let mut read = PbReader::new(binary_input.as_slice());
         let mut data = MessageData::new(&mut read, &proto, root_msg, &mut limit).unwrap();